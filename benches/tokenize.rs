@@ -0,0 +1,24 @@
+use bpe_tokenizer::{bench::workloads, BytePairEncoder, EmojiPolicy, EncoderOptions};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+fn tokenize(c: &mut Criterion) {
+    let mut options = EncoderOptions::default();
+    options.emoji = EmojiPolicy::Keep;
+    let encoder = BytePairEncoder::new_default_small()
+        .unwrap()
+        .with_options(options);
+
+    let mut group = c.benchmark_group("tokenize");
+    for workload in workloads() {
+        group.throughput(Throughput::Bytes(workload.text.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(workload.name),
+            &workload.text,
+            |b, text| b.iter(|| encoder.tokenize(text)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tokenize);
+criterion_main!(benches);
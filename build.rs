@@ -5,18 +5,18 @@
 ))]
 use {
     lz4_flex::block::compress_prepend_size,
-    std::{collections::HashMap, env, fs, path::PathBuf},
+    std::{env, fs, path::PathBuf},
 };
 
 fn main() {
     #[cfg(feature = "default-small")]
-    process_vocab("multi.wiki.bpe.vs100000.vocab");
+    process_vocab("multi.wiki.bpe.vs100000.vocab", "SMALL");
 
     #[cfg(feature = "default-medium")]
-    process_vocab("multi.wiki.bpe.vs320000.vocab");
+    process_vocab("multi.wiki.bpe.vs320000.vocab", "MEDIUM");
 
     #[cfg(feature = "default-large")]
-    process_vocab("multi.wiki.bpe.vs1000000.vocab");
+    process_vocab("multi.wiki.bpe.vs1000000.vocab", "LARGE");
 }
 
 #[cfg(any(
@@ -24,14 +24,15 @@ fn main() {
     feature = "default-medium",
     feature = "default-large"
 ))]
-fn process_vocab(name: &str) {
+fn process_vocab(name: &str, key: &str) {
     // Path to the vocabulary file (ensure this path is correct)
     let vocab_path = PathBuf::from(format!("vocab/{}", name));
 
-    // Load and parse the vocabulary into a HashMap.
-    let tokens: HashMap<String, isize> = load_vocab_hashmap(&vocab_path);
+    // Load and parse the vocabulary into a Vec of token-score pairs, keeping the file order since
+    // it defines the token IDs.
+    let tokens: Vec<(String, isize)> = load_vocab(&vocab_path);
 
-    // Serialize the HashMap using bincode
+    // Serialize the Vec using bincode
     let serialized = bincode::serialize(&tokens).unwrap();
 
     // Compress the serialized data using zstd (with ultra compression level)
@@ -39,8 +40,33 @@ fn process_vocab(name: &str) {
 
     // Write the compressed data to a file in the build output directory
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
-    let output_path = out_dir.join(format!("{}.hashmap.bincode.lz4", name));
-    fs::write(&output_path, compressed).unwrap();
+    let output_path = out_dir.join(format!("{}.bincode.lz4", name));
+    fs::write(&output_path, &compressed).unwrap();
+
+    // Record the uncompressed size and a checksum of the compressed data, so the crate can report
+    // them when loading the vocabulary fails, and verify the embedded data against them.
+    println!(
+        "cargo:rustc-env=BPE_TOKENIZER_DEFAULT_{}_SIZE={}",
+        key,
+        serialized.len()
+    );
+    println!(
+        "cargo:rustc-env=BPE_TOKENIZER_DEFAULT_{}_CHECKSUM={}",
+        key,
+        fnv1a(&compressed)
+    );
+}
+
+// The 64-bit FNV-1a hash of `bytes`, the same as the crate's `Fnv1a` hasher.
+#[cfg(any(
+    feature = "default-small",
+    feature = "default-medium",
+    feature = "default-large"
+))]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
 }
 
 #[cfg(any(
@@ -48,11 +74,11 @@ fn process_vocab(name: &str) {
     feature = "default-medium",
     feature = "default-large"
 ))]
-fn load_vocab_hashmap(path: &PathBuf) -> HashMap<String, isize> {
+fn load_vocab(path: &PathBuf) -> Vec<(String, isize)> {
     // Read file's contents
     let content = fs::read_to_string(path).unwrap();
 
-    let mut tokens = HashMap::new();
+    let mut tokens = Vec::new();
 
     // Process each line in the file, each being a token-score pair
     for line in content.lines() {
@@ -64,7 +90,7 @@ fn load_vocab_hashmap(path: &PathBuf) -> HashMap<String, isize> {
             Ok(score) => score,
             Err(_) => panic!("Invalid score in vocabulary file: {}", line),
         };
-        tokens.insert(token.to_string(), score);
+        tokens.push((token.to_string(), score));
     }
 
     tokens
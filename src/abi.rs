@@ -0,0 +1,163 @@
+//! # Plain-data tokenization results with a stable memory layout.
+//!
+//! The types in this module are `#[repr(C)]` and made of fixed-width integers only, so their
+//! layout is the same in every language and on every platform with the same endianness. A slice
+//! of [`AbiToken`]s can be handed to C through an [`AbiTokens`] view, or copied as-is into shared
+//! memory or a pipe and read by another process, without serializing it into another format.
+//!
+//! Each [`AbiToken`] is 24 bytes, without padding: `id` (`u32`), `flags` (`u32`), `start` (`u64`),
+//! and `end` (`u64`). The layout only ever changes together with [`ABI_VERSION`].
+//!
+//! ## Example
+//!
+//! ```
+//! use bpe_tokenizer::{abi, BytePairEncoder};
+//!
+//! let vocab = BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\n▁hello\t1\n▁world\t2").unwrap();
+//! let tokens = abi::encode(&vocab, "Hello, world!");
+//! assert_eq!(tokens.len(), 4);
+//! assert_eq!((tokens[2].id, tokens[2].start, tokens[2].end), (3, 7, 12));
+//! assert!(tokens[0].is_special());
+//!
+//! let view = abi::AbiTokens::new(&tokens);
+//! assert_eq!(view.len, 4);
+//! ```
+
+use std::ops::Range;
+
+use crate::{BytePairEncoder, Encoding};
+
+/// The version of the memory layout of the types in this module.
+pub const ABI_VERSION: u32 = 1;
+
+/// Set in [`AbiToken::flags`] if the token has an ID. Otherwise, `id` is `0` and meaningless.
+pub const TOKEN_HAS_ID: u32 = 1;
+
+/// Set in [`AbiToken::flags`] if the token was inserted by the encoder rather than matched
+/// against the text, such as a sentence marker.
+pub const TOKEN_SPECIAL: u32 = 1 << 1;
+
+/// # A token with its ID and the byte range of the text it covers.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AbiToken {
+    /// The token ID, valid if `flags` contains [`TOKEN_HAS_ID`].
+    pub id: u32,
+
+    /// A combination of [`TOKEN_HAS_ID`] and [`TOKEN_SPECIAL`].
+    pub flags: u32,
+
+    /// The byte offset in the text where the token starts.
+    pub start: u64,
+
+    /// The byte offset in the text where the token ends, exclusive.
+    pub end: u64,
+}
+
+impl AbiToken {
+    /// Creates a token from its ID, byte range, and whether it was inserted by the encoder.
+    pub fn new(id: Option<u32>, range: Range<usize>, special: bool) -> Self {
+        let mut flags = 0;
+        if id.is_some() {
+            flags |= TOKEN_HAS_ID;
+        }
+        if special {
+            flags |= TOKEN_SPECIAL;
+        }
+        AbiToken {
+            id: id.unwrap_or(0),
+            flags,
+            start: range.start as u64,
+            end: range.end as u64,
+        }
+    }
+
+    /// Returns the token ID, or `None` if the token has none.
+    pub fn id(&self) -> Option<u32> {
+        (self.flags & TOKEN_HAS_ID != 0).then_some(self.id)
+    }
+
+    /// Returns `true` if the token was inserted by the encoder rather than matched against the
+    /// text.
+    pub fn is_special(&self) -> bool {
+        self.flags & TOKEN_SPECIAL != 0
+    }
+}
+
+/// # A borrowed view of a slice of [`AbiToken`]s, to pass across an FFI boundary.
+///
+/// The view does not own the tokens: it is only valid as long as the slice it was created from.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AbiTokens {
+    /// A pointer to the first token.
+    pub ptr: *const AbiToken,
+
+    /// The number of tokens.
+    pub len: usize,
+}
+
+impl AbiTokens {
+    /// Creates a view of `tokens`.
+    pub fn new(tokens: &[AbiToken]) -> Self {
+        AbiTokens {
+            ptr: tokens.as_ptr(),
+            len: tokens.len(),
+        }
+    }
+}
+
+/// # A borrowed view of a slice of token IDs, to pass across an FFI boundary.
+///
+/// The view does not own the IDs: it is only valid as long as the slice it was created from.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AbiIds {
+    /// A pointer to the first ID.
+    pub ptr: *const u32,
+
+    /// The number of IDs.
+    pub len: usize,
+}
+
+impl AbiIds {
+    /// Creates a view of `ids`.
+    pub fn new(ids: &[u32]) -> Self {
+        AbiIds {
+            ptr: ids.as_ptr(),
+            len: ids.len(),
+        }
+    }
+}
+
+/// # Tokenizes a text into [`AbiToken`]s.
+///
+/// The tokens are those of [`BytePairEncoder::tokenize`], with offsets as in [`Encoding`].
+///
+/// ## Arguments
+///
+/// * `encoder` - The encoder to tokenize with.
+/// * `text` - A string slice containing the text to be tokenized.
+///
+/// ## Returns
+///
+/// A `Vec<AbiToken>` with the tokens of `text`.
+pub fn encode(encoder: &BytePairEncoder, text: &str) -> Vec<AbiToken> {
+    encoder
+        .token_spans(text)
+        .into_iter()
+        .map(|span| AbiToken::new(encoder.span_id(&span), span.1, span.2))
+        .collect()
+}
+
+impl From<&Encoding> for Vec<AbiToken> {
+    fn from(encoding: &Encoding) -> Self {
+        encoding
+            .ids
+            .iter()
+            .zip(&encoding.offsets)
+            .zip(&encoding.special_tokens_mask)
+            .map(|((&id, range), &special)| AbiToken::new(id, range.clone(), special))
+            .collect()
+    }
+}
@@ -0,0 +1,83 @@
+//! # Representative tokenization workloads for benchmarking.
+//!
+//! These workloads give performance work on the tokenizer a shared yardstick. They are used by the
+//! crate's own benchmarks (`cargo bench --features bench,default-small`) and are available to
+//! downstream crates through the `bench` feature.
+//!
+//! ## Example
+//!
+//! ```
+//! use bpe_tokenizer::{bench, BytePairEncoder};
+//!
+//! let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+//! for workload in bench::workloads() {
+//!     let tokens = vocab.tokenize(&workload.text);
+//!     assert!(!tokens.is_empty(), "{}", workload.name);
+//! }
+//! ```
+
+/// A named piece of text to tokenize.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workload {
+    /// A short, stable name identifying the workload in benchmark reports.
+    pub name: &'static str,
+
+    /// The text to tokenize.
+    pub text: String,
+}
+
+const ENGLISH: &str = "The quick brown fox jumps over the lazy dog. \
+    Byte pair encoding splits rare words into subword units. \
+    Tokenizers are the first stage of most language processing pipelines. \
+    This sentence contains a few uncommon words like antidisestablishmentarianism.";
+
+const CJK: &str = "こんにちは、世界！お元気ですか？\
+    東京は日本の首都です。\
+    我们今天去公园散步。\
+    한국어 문장도 포함되어 있습니다.";
+
+const EMOJI: &str = "Great job 👍🏽! See you soon 👋 \
+    Family time 👨‍👩‍👧 at the beach 🏖️. \
+    Flags 🇯🇵 🇺🇸 and hearts ❤️💙💚 everywhere.";
+
+/// Returns a handful of short English sentences.
+pub fn short_sentences() -> String {
+    ENGLISH.to_string()
+}
+
+/// Returns a long document made of many paragraphs of English text.
+pub fn long_document() -> String {
+    vec![ENGLISH; 500].join("\n\n")
+}
+
+/// Returns sentences in Japanese, Chinese, and Korean.
+pub fn cjk() -> String {
+    CJK.to_string()
+}
+
+/// Returns text with emoji, including skin tone, ZWJ, and flag sequences.
+pub fn emoji() -> String {
+    EMOJI.to_string()
+}
+
+/// Returns all workloads, in a stable order.
+pub fn workloads() -> Vec<Workload> {
+    vec![
+        Workload {
+            name: "short_sentences",
+            text: short_sentences(),
+        },
+        Workload {
+            name: "long_document",
+            text: long_document(),
+        },
+        Workload {
+            name: "cjk",
+            text: cjk(),
+        },
+        Workload {
+            name: "emoji",
+            text: emoji(),
+        },
+    ]
+}
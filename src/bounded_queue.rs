@@ -0,0 +1,125 @@
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex, MutexGuard},
+};
+
+use crate::StreamLimits;
+
+/// # A blocking queue of tokenized sentences, bounded by sentence and token counts.
+///
+/// The producer blocks in [`BoundedQueue::push`] while the queue is full, which is what propagates
+/// backpressure from a slow consumer to the producer. A sentence with more tokens than the token
+/// limit is still admitted once the queue is empty, so it cannot block the producer forever.
+pub(crate) struct BoundedQueue<T> {
+    state: Mutex<QueueState<T>>,
+    not_full: Condvar,
+    not_empty: Condvar,
+    limits: StreamLimits,
+}
+
+struct QueueState<T> {
+    items: VecDeque<(T, Vec<String>)>,
+    tokens: usize,
+    /// Set by the producer once it has pushed everything.
+    finished: bool,
+    /// Set by the consumer once it no longer accepts items.
+    closed: bool,
+}
+
+impl<T> BoundedQueue<T> {
+    pub(crate) fn new(limits: StreamLimits) -> Self {
+        BoundedQueue {
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                tokens: 0,
+                finished: false,
+                closed: false,
+            }),
+            not_full: Condvar::new(),
+            not_empty: Condvar::new(),
+            limits,
+        }
+    }
+
+    /// Appends an item, waiting while the queue is full. Returns `false`, without appending, if
+    /// the consumer closed the queue.
+    pub(crate) fn push(&self, key: T, tokens: Vec<String>) -> bool {
+        let mut state = self.lock();
+        loop {
+            if state.closed {
+                return false;
+            }
+            let sentences_full = state.items.len() >= self.limits.max_buffered_sentences.max(1);
+            let tokens_full = self.limits.max_buffered_tokens.map_or(false, |max| {
+                !state.items.is_empty() && state.tokens + tokens.len() > max
+            });
+            if !sentences_full && !tokens_full {
+                break;
+            }
+            state = self.not_full.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.tokens += tokens.len();
+        state.items.push_back((key, tokens));
+        self.not_empty.notify_one();
+        true
+    }
+
+    /// Removes the oldest item, waiting while the queue is empty. Returns `None` once the
+    /// producer has finished and the queue is drained.
+    pub(crate) fn pop(&self) -> Option<(T, Vec<String>)> {
+        let mut state = self.lock();
+        loop {
+            if let Some((key, tokens)) = state.items.pop_front() {
+                state.tokens -= tokens.len();
+                self.not_full.notify_one();
+                return Some((key, tokens));
+            }
+            if state.finished {
+                return None;
+            }
+            state = self
+                .not_empty
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Marks the producer as finished, waking the consumer.
+    fn finish(&self) {
+        self.lock().finished = true;
+        self.not_empty.notify_all();
+    }
+
+    /// Stops accepting items and drops those buffered, waking the producer.
+    pub(crate) fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        state.items.clear();
+        state.tokens = 0;
+        self.not_full.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Marks the producer of a [`BoundedQueue`] as finished when dropped, even by a panic, so the
+/// consumer never waits for items that will not come.
+pub(crate) struct FinishGuard<'a, T>(pub(crate) &'a BoundedQueue<T>);
+
+impl<T> Drop for FinishGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// Closes a [`BoundedQueue`] for its consumer when dropped, even by a panic, so the producer never
+/// waits for room that will not come.
+pub(crate) struct CloseGuard<'a, T>(pub(crate) &'a BoundedQueue<T>);
+
+impl<T> Drop for CloseGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
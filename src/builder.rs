@@ -0,0 +1,271 @@
+use crate::{
+    BytePairEncoder, BytePairEncoderError, EncoderOptions, MissingSpecialTokenPolicy, Trainer,
+    TrainerOptions, TrainerPreset, VocabStore,
+};
+
+/// # A builder for a `BytePairEncoder` with custom special tokens.
+///
+/// The special tokens are otherwise detected from the vocabulary (see
+/// [`SpecialTokens`](crate::SpecialTokens)), which covers the common conventions. The builder
+/// sets each of them explicitly instead, along with the word break character, so vocabularies
+/// with other conventions can be matched without assembling [`EncoderOptions`] by hand. It can also
+/// select the [`VocabStore`] the vocabulary is loaded into.
+///
+/// Created by [`BytePairEncoder::builder`].
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::BytePairEncoder;
+///
+/// let vocab = BytePairEncoder::builder()
+///     .sentence_start("[BOS]")
+///     .sentence_end("[EOS]")
+///     .unknown("[?]")
+///     .word_break('Ġ')
+///     .build_from_str("[BOS]\t0\n[EOS]\t0\n[?]\t0\nĠhello\t1\nĠworld\t2")
+///     .unwrap();
+/// assert_eq!(
+///     vocab.tokenize("Hello, world! Hi."),
+///     vec!["[BOS]", "Ġhello", "Ġworld", "[EOS]", "[BOS]", "[?]", "[EOS]"]
+/// );
+/// assert_eq!(vocab.decode(&[0, 3, 4, 1]).unwrap(), "hello world");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BytePairEncoderBuilder {
+    options: EncoderOptions,
+    store: Option<Box<dyn VocabStore>>,
+}
+
+impl BytePairEncoderBuilder {
+    /// # Creates a builder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Sets the options of the encoder.
+    ///
+    /// This replaces all options, including the special tokens set so far, so it should be called
+    /// before the other methods.
+    pub fn options(mut self, options: EncoderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// # Sets the token marking the start of a sentence, e.g. `[CLS]`.
+    pub fn sentence_start(mut self, token: &str) -> Self {
+        self.options.special_tokens.sentence_start = Some(token.to_string());
+        self
+    }
+
+    /// # Sets the token marking the end of a sentence, e.g. `[SEP]`.
+    pub fn sentence_end(mut self, token: &str) -> Self {
+        self.options.special_tokens.sentence_end = Some(token.to_string());
+        self
+    }
+
+    /// # Sets the token emitted for input that the vocabulary cannot cover, e.g. `[UNK]`.
+    pub fn unknown(mut self, token: &str) -> Self {
+        self.options.special_tokens.unknown = Some(token.to_string());
+        self
+    }
+
+    /// # Sets the padding token, e.g. `[PAD]`.
+    pub fn pad(mut self, token: &str) -> Self {
+        self.options.special_tokens.pad = Some(token.to_string());
+        self
+    }
+
+    /// # Sets the mask token, e.g. `[MASK]`.
+    pub fn mask(mut self, token: &str) -> Self {
+        self.options.special_tokens.mask = Some(token.to_string());
+        self
+    }
+
+    /// # Sets the character that the tokens of a new word start with, instead of `▁`.
+    pub fn word_break(mut self, c: char) -> Self {
+        self.options.special_tokens.word_break = Some(c);
+        self
+    }
+
+    /// # Sets what to do with special tokens that are not in the vocabulary.
+    pub fn missing_special_tokens(mut self, policy: MissingSpecialTokenPolicy) -> Self {
+        self.options.special_tokens.missing = policy;
+        self
+    }
+
+    /// # Sets the store the vocabulary is loaded into, instead of a `HashMapStore`.
+    ///
+    /// See [`BytePairEncoder::with_vocab_store`].
+    pub fn vocab_store<S: VocabStore + 'static>(mut self, store: S) -> Self {
+        self.store = Some(Box::new(store));
+        self
+    }
+
+    /// # Builds the encoder from a vocabulary file.
+    ///
+    /// See [`BytePairEncoder::new_from_file`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `file_path` - The path to the vocabulary file.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the configured `BytePairEncoder`, or an error if the file cannot be read
+    /// or parsed, or the options are invalid (see [`BytePairEncoder::try_with_options`]).
+    pub fn build_from_file(
+        &self,
+        file_path: &str,
+    ) -> Result<BytePairEncoder, BytePairEncoderError> {
+        self.options.validate()?;
+        self.build_from(BytePairEncoder::new_from_file(file_path)?)
+    }
+
+    /// # Builds the encoder from a vocabulary string.
+    ///
+    /// See [`BytePairEncoder::new_from_str`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `input` - The vocabulary, one tab-separated token and score per line.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the configured `BytePairEncoder`, or an error if the input cannot be
+    /// parsed, or the options are invalid (see [`BytePairEncoder::try_with_options`]).
+    pub fn build_from_str(&self, input: &str) -> Result<BytePairEncoder, BytePairEncoderError> {
+        self.options.validate()?;
+        self.build_from(BytePairEncoder::new_from_str(input)?)
+    }
+
+    /// # Applies the configuration to an existing encoder.
+    ///
+    /// The encoder's vocabulary and metadata are kept, and its options (and store, if set) are
+    /// replaced.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the configured `BytePairEncoder`, or an error if the options are invalid
+    /// for it (see [`BytePairEncoder::try_with_options`]).
+    pub fn build_from(
+        &self,
+        mut encoder: BytePairEncoder,
+    ) -> Result<BytePairEncoder, BytePairEncoderError> {
+        if let Some(store) = &self.store {
+            encoder.tokens.set_store(store.clone());
+        }
+        encoder.try_with_options(self.options.clone())
+    }
+}
+
+/// # A builder for a [`Trainer`], setting one option at a time.
+///
+/// The options mirror the main knobs of SentencePiece's trainer: the reserved special tokens,
+/// the minimum pair frequency, the maximum token length, and the character coverage. Options not
+/// set keep their [`TrainerOptions`] defaults, or those of the preset the builder started from.
+///
+/// Created by [`Trainer::builder`].
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::Trainer;
+///
+/// let mut trainer = Trainer::builder()
+///     .vocab_size(30)
+///     .special_tokens(["<unk>", "<s>", "</s>", "<sep>"])
+///     .min_pair_frequency(2)
+///     .max_token_length(4)
+///     .character_coverage(1.0)
+///     .build();
+/// trainer.feed("low lower lowest");
+/// trainer.feed("new newer newest");
+///
+/// let encoder = trainer.train();
+/// assert_eq!(encoder.token_to_id("<sep>"), Some(3));
+/// assert!((0..encoder.vocab_size() as u32)
+///     .filter_map(|id| encoder.id_to_token(id))
+///     .all(|token| token.starts_with('<') || token.chars().count() <= 4));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TrainerBuilder {
+    options: TrainerOptions,
+}
+
+impl TrainerBuilder {
+    /// # Creates a builder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Creates a builder with the options of a [`TrainerPreset`].
+    pub fn preset(preset: TrainerPreset) -> Self {
+        TrainerBuilder {
+            options: TrainerOptions::preset(preset),
+        }
+    }
+
+    /// # Sets the number of tokens to learn, including special tokens and single characters.
+    pub fn vocab_size(mut self, size: usize) -> Self {
+        self.options.vocab_size = size;
+        self
+    }
+
+    /// # Sets the special tokens every trained vocabulary contains, in ID order.
+    ///
+    /// This replaces the default special tokens, so it should include the unknown token and the
+    /// sentence markers, if the trained encoder is to have them.
+    pub fn special_tokens<I, S>(mut self, tokens: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.special_tokens = tokens.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// # Sets the minimum number of occurrences of a pair of tokens for it to be merged.
+    pub fn min_pair_frequency(mut self, frequency: u64) -> Self {
+        self.options.min_pair_frequency = frequency;
+        self
+    }
+
+    /// # Sets the maximum length of a learned token, in characters.
+    pub fn max_token_length(mut self, length: usize) -> Self {
+        self.options.max_token_length = Some(length);
+        self
+    }
+
+    /// # Sets the fraction of character occurrences that single-character tokens must cover.
+    ///
+    /// See [`TrainerOptions::character_coverage`].
+    pub fn character_coverage(mut self, coverage: f64) -> Self {
+        self.options.character_coverage = coverage;
+        self
+    }
+
+    /// # Sets the maximum number of distinct words the trainer keeps counts for.
+    ///
+    /// See [`TrainerOptions::max_distinct_words`].
+    pub fn max_distinct_words(mut self, words: usize) -> Self {
+        self.options.max_distinct_words = Some(words);
+        self
+    }
+
+    /// # Sets the number of threads that count and merge pairs of tokens.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = threads;
+        self
+    }
+
+    /// # Returns the options set so far.
+    pub fn options(&self) -> &TrainerOptions {
+        &self.options
+    }
+
+    /// # Creates a `Trainer` with the configured options and no training data.
+    pub fn build(self) -> Trainer {
+        Trainer::new(self.options)
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    mem,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// The maximum number of independently locked shards in a [`CacheStore`].
+const MAX_SHARDS: usize = 16;
+
+/// # A thread-safe, size-bounded cache of tokenized words.
+///
+/// This is the view of a [`CacheStore`] used by one encoder. The store is either private to the
+/// encoder, or shared by several encoders (see [`TokenizerPool`](crate::TokenizerPool)), in which
+/// case each one has its own namespace, so the same word never gets the tokens of another
+/// encoder.
+#[derive(Debug)]
+pub(crate) struct WordCache {
+    store: Arc<CacheStore>,
+    namespace: u64,
+    shared: bool,
+}
+
+impl WordCache {
+    /// Creates an empty cache holding up to `capacity` words, private to one encoder.
+    pub(crate) fn new(capacity: usize) -> Self {
+        WordCache {
+            store: Arc::new(CacheStore::new(capacity)),
+            namespace: 0,
+            shared: false,
+        }
+    }
+
+    /// Creates a view of `store` for the encoder with the given namespace.
+    pub(crate) fn shared(store: &Arc<CacheStore>, namespace: u64) -> Self {
+        WordCache {
+            store: Arc::clone(store),
+            namespace,
+            shared: true,
+        }
+    }
+
+    /// Returns the cached tokens of `word`, marking it as recently used.
+    pub(crate) fn get(&self, word: &str) -> Option<Vec<String>> {
+        self.store.get(self.namespace, word)
+    }
+
+    /// Caches the tokens of `word`, evicting the least recently used word of its shard if needed.
+    pub(crate) fn insert<S: AsRef<str>>(&self, word: &str, tokens: &[S]) {
+        self.store.insert(self.namespace, word, tokens);
+    }
+
+    /// Removes all cached words of this cache's namespace.
+    pub(crate) fn clear(&self) {
+        self.store.clear(self.namespace);
+    }
+
+    /// Returns the cached words and their tokens, least recently used first.
+    ///
+    /// Recency is tracked per shard, so the order across shards is approximate.
+    pub(crate) fn entries(&self) -> Vec<(String, Vec<String>)> {
+        self.store.entries(self.namespace)
+    }
+}
+
+/// Cloning a private cache creates an empty cache with the same capacity, and cloning a shared
+/// cache creates another view of the same namespace.
+impl Clone for WordCache {
+    fn clone(&self) -> Self {
+        if self.shared {
+            WordCache::shared(&self.store, self.namespace)
+        } else {
+            WordCache::new(self.store.capacity)
+        }
+    }
+}
+
+/// # The words cached by one or more [`WordCache`]s.
+///
+/// The store is split into shards, each behind its own mutex, so threads tokenizing different
+/// words rarely contend for the same lock. Each shard keeps its words in a list ordered by use,
+/// and evicts its least recently used word, from any namespace, when it is full, so every access
+/// takes constant time. A store with a capacity of 0 caches nothing.
+#[derive(Debug)]
+pub(crate) struct CacheStore {
+    capacity: usize,
+    shards: Vec<Mutex<Shard>>,
+}
+
+/// Marks the end of the list of entries in a [`Shard`].
+const NONE: usize = usize::MAX;
+
+/// A single shard of a [`CacheStore`].
+#[derive(Debug, Default)]
+struct Shard {
+    /// Maps each namespace and word to the index of its entry in `entries`.
+    slots: HashMap<u64, HashMap<String, usize>>,
+
+    /// The cached words, linked from the most to the least recently used. Evicted entries are
+    /// reused for the next word.
+    entries: Vec<Entry>,
+
+    /// The indices of the unused entries in `entries`.
+    free: Vec<usize>,
+
+    /// The index of the most recently used entry, or `NONE`.
+    head: usize,
+
+    /// The index of the least recently used entry, or `NONE`.
+    tail: usize,
+
+    /// A counter incremented on every access, used to order the entries of several shards.
+    tick: u64,
+}
+
+/// A cached word in a [`Shard`].
+#[derive(Debug)]
+struct Entry {
+    namespace: u64,
+    word: String,
+    tokens: Vec<String>,
+
+    /// The tick at which the word was last used.
+    used: u64,
+
+    /// The index of the next more recently used entry, or `NONE`.
+    prev: usize,
+
+    /// The index of the next less recently used entry, or `NONE`.
+    next: usize,
+}
+
+impl CacheStore {
+    /// Creates an empty store holding up to `capacity` words.
+    pub(crate) fn new(capacity: usize) -> Self {
+        let shards = capacity.clamp(1, MAX_SHARDS);
+        CacheStore {
+            capacity,
+            shards: (0..shards).map(|_| Mutex::new(Shard::new())).collect(),
+        }
+    }
+
+    fn get(&self, namespace: u64, word: &str) -> Option<Vec<String>> {
+        let mut shard = self.shard(word);
+        let index = *shard.slots.get(&namespace)?.get(word)?;
+        shard.touch(index);
+        Some(shard.entries[index].tokens.clone())
+    }
+
+    fn insert<S: AsRef<str>>(&self, namespace: u64, word: &str, tokens: &[S]) {
+        if self.capacity == 0 {
+            return;
+        }
+        let shard_capacity = (self.capacity / self.shards.len()).max(1);
+        let tokens = tokens.iter().map(|t| t.as_ref().to_string()).collect();
+        let mut shard = self.shard(word);
+
+        let present = shard
+            .slots
+            .get(&namespace)
+            .and_then(|words| words.get(word))
+            .copied();
+        if let Some(index) = present {
+            shard.entries[index].tokens = tokens;
+            shard.touch(index);
+            return;
+        }
+        if shard.len() >= shard_capacity {
+            let oldest = shard.tail;
+            shard.remove(oldest);
+        }
+        shard.push(namespace, word, tokens);
+    }
+
+    /// Removes all cached words of `namespace`.
+    pub(crate) fn clear(&self, namespace: u64) {
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+            let indices: Vec<usize> = shard
+                .slots
+                .get(&namespace)
+                .map(|words| words.values().copied().collect())
+                .unwrap_or_default();
+            for index in indices {
+                shard.remove(index);
+            }
+        }
+    }
+
+    /// Returns the cached words of `namespace` and their tokens, least recently used first.
+    fn entries(&self, namespace: u64) -> Vec<(String, Vec<String>)> {
+        let mut entries = vec![];
+        for shard in &self.shards {
+            let shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(words) = shard.slots.get(&namespace) {
+                entries.extend(words.values().map(|&index| {
+                    let entry = &shard.entries[index];
+                    (entry.used, entry.word.clone(), entry.tokens.clone())
+                }));
+            }
+        }
+        entries.sort_unstable();
+        entries
+            .into_iter()
+            .map(|(_, word, tokens)| (word, tokens))
+            .collect()
+    }
+
+    /// Locks and returns the shard responsible for `word`.
+    ///
+    /// A poisoned shard is still usable: its entries are only ever replaced whole.
+    fn shard(&self, word: &str) -> MutexGuard<'_, Shard> {
+        let mut hasher = DefaultHasher::new();
+        word.hash(&mut hasher);
+        let index = hasher.finish() as usize % self.shards.len();
+        self.shards[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Shard {
+    fn new() -> Self {
+        Shard {
+            head: NONE,
+            tail: NONE,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the number of cached words, across namespaces.
+    fn len(&self) -> usize {
+        self.entries.len() - self.free.len()
+    }
+
+    /// Caches a word that is not cached yet, as the most recently used one.
+    fn push(&mut self, namespace: u64, word: &str, tokens: Vec<String>) {
+        self.tick += 1;
+        let entry = Entry {
+            namespace,
+            word: word.to_string(),
+            tokens,
+            used: self.tick,
+            prev: NONE,
+            next: NONE,
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.entries[index] = entry;
+                index
+            }
+            None => {
+                self.entries.push(entry);
+                self.entries.len() - 1
+            }
+        };
+        self.link_front(index);
+        self.slots
+            .entry(namespace)
+            .or_default()
+            .insert(word.to_string(), index);
+    }
+
+    /// Marks the entry at `index` as the most recently used one.
+    fn touch(&mut self, index: usize) {
+        self.tick += 1;
+        self.entries[index].used = self.tick;
+        self.unlink(index);
+        self.link_front(index);
+    }
+
+    /// Removes the entry at `index`, which must be in use, and frees it for reuse.
+    fn remove(&mut self, index: usize) {
+        self.unlink(index);
+        let entry = &mut self.entries[index];
+        let (namespace, word) = (entry.namespace, mem::take(&mut entry.word));
+        entry.tokens = vec![];
+        if let Some(words) = self.slots.get_mut(&namespace) {
+            words.remove(&word);
+            if words.is_empty() {
+                self.slots.remove(&namespace);
+            }
+        }
+        self.free.push(index);
+    }
+
+    fn link_front(&mut self, index: usize) {
+        self.entries[index].prev = NONE;
+        self.entries[index].next = self.head;
+        match self.head {
+            NONE => self.tail = index,
+            head => self.entries[head].prev = index,
+        }
+        self.head = index;
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (prev, next) = (self.entries[index].prev, self.entries[index].next);
+        match prev {
+            NONE => self.head = next,
+            prev => self.entries[prev].next = next,
+        }
+        match next {
+            NONE => self.tail = prev,
+            next => self.entries[next].prev = prev,
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! # Reports which optional functionality is available in this build, at runtime.
+//!
+//! Most optional functionality of this crate is behind Cargo features, and the final set of
+//! features is only decided when the application is built. Libraries built on top of this crate
+//! can check here and fall back gracefully (e.g. load a vocabulary file when no default
+//! vocabulary is compiled in) instead of failing with
+//! [`BytePairEncoderError::NoDefaultVocabFeature`](crate::BytePairEncoderError::NoDefaultVocabFeature)
+//! deep in a call stack.
+//!
+//! ## Example
+//!
+//! ```
+//! use bpe_tokenizer::{capabilities, BytePairEncoder};
+//!
+//! let encoder = if capabilities::has_default_vocab() {
+//!     BytePairEncoder::best_default_for_budget(usize::MAX).unwrap().0
+//! } else {
+//!     BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap()
+//! };
+//! let texts = ["Hello", "world"];
+//! let tokens = if capabilities::supports_parallel() {
+//!     encoder.tokenize_batch(&texts)
+//! } else {
+//!     texts.iter().map(|text| encoder.tokenize(text)).collect()
+//! };
+//! assert_eq!(tokens.len(), 2);
+//! ```
+
+use std::thread;
+
+use crate::default_vocabs::DefaultVocab;
+
+/// The optional Cargo features of this crate, with whether each is enabled.
+const FEATURES: &[(&str, bool)] = &[
+    ("default-small", cfg!(feature = "default-small")),
+    ("default-medium", cfg!(feature = "default-medium")),
+    ("default-large", cfg!(feature = "default-large")),
+    ("bench", cfg!(feature = "bench")),
+    ("server", cfg!(feature = "server")),
+    ("language", cfg!(feature = "language")),
+    ("fixtures", cfg!(feature = "fixtures")),
+    ("huggingface", cfg!(feature = "huggingface")),
+    ("salted-ids", cfg!(feature = "salted-ids")),
+    ("encryption", cfg!(feature = "encryption")),
+    ("abi", cfg!(feature = "abi")),
+];
+
+/// Returns the Cargo features of the default vocabularies compiled in (e.g. `default-small`), from
+/// smallest to largest vocabulary.
+pub fn default_vocabs() -> Vec<&'static str> {
+    DefaultVocab::compiled()
+        .into_iter()
+        .map(DefaultVocab::feature)
+        .collect()
+}
+
+/// Returns `true` if at least one default vocabulary is compiled in, so
+/// [`BytePairEncoder::best_default_for_budget`](crate::BytePairEncoder::best_default_for_budget)
+/// can succeed.
+pub fn has_default_vocab() -> bool {
+    !DefaultVocab::compiled().is_empty()
+}
+
+/// Returns `true` if the batch functions (such as
+/// [`BytePairEncoder::tokenize_batch`](crate::BytePairEncoder::tokenize_batch)) can run on
+/// several threads here.
+///
+/// This is `false` on targets without threads, such as WebAssembly, where the batch functions
+/// cannot spawn their worker threads, and on machines with a single core, where they gain
+/// nothing over tokenizing one text at a time.
+pub fn supports_parallel() -> bool {
+    cfg!(not(target_family = "wasm")) && parallelism() > 1
+}
+
+/// Returns the number of threads the batch functions use, which is `1` when the available
+/// parallelism cannot be determined.
+pub fn parallelism() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Returns the optional Cargo features enabled in this build, e.g. `["huggingface"]`.
+pub fn features() -> Vec<&'static str> {
+    FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(feature, _)| *feature)
+        .collect()
+}
+
+/// Returns `true` if the optional Cargo feature `feature` is enabled in this build.
+///
+/// Unknown feature names are reported as not enabled.
+pub fn has_feature(feature: &str) -> bool {
+    FEATURES
+        .iter()
+        .any(|(name, enabled)| *name == feature && *enabled)
+}
@@ -0,0 +1,615 @@
+use std::{fs, io};
+
+use crate::{
+    json::{parse_json, JsonValue},
+    BytePairEncoder, BytePairEncoderError, CompatLevel, ConfusablePolicy, EmojiPolicy,
+    EmptySentencePolicy, EncoderOptions, HyphenationPolicy, InvisibleCharPolicy, LineBreakPolicy,
+    MissingSpecialTokenPolicy, NumberPolicy, Padding, PaddingSide, PunctuationPolicy,
+    SegmentationStrategy, SpecialTextPolicy, SpecialTokenOptions, TextMode, TokenPriors,
+    UnknownStrategy,
+};
+
+/// The version of the configuration format, written to and checked in the `version` member.
+const CONFIG_VERSION: &str = "1";
+
+const LINE_BREAKS: &[(LineBreakPolicy, &str)] = &[
+    (LineBreakPolicy::Preserve, "preserve"),
+    (LineBreakPolicy::Normalize, "normalize"),
+];
+const INVISIBLE_CHARS: &[(InvisibleCharPolicy, &str)] = &[
+    (InvisibleCharPolicy::Preserve, "preserve"),
+    (InvisibleCharPolicy::Strip, "strip"),
+];
+const HYPHENATION: &[(HyphenationPolicy, &str)] = &[
+    (HyphenationPolicy::Keep, "keep"),
+    (HyphenationPolicy::Rejoin, "rejoin"),
+];
+const NUMBERS: &[(NumberPolicy, &str)] = &[
+    (NumberPolicy::Keep, "keep"),
+    (NumberPolicy::ZeroDigits, "zero_digits"),
+    (NumberPolicy::Magnitude, "magnitude"),
+];
+const CONFUSABLES: &[(ConfusablePolicy, &str)] = &[
+    (ConfusablePolicy::Keep, "keep"),
+    (ConfusablePolicy::Fold, "fold"),
+];
+const PUNCTUATION: &[(PunctuationPolicy, &str)] = &[
+    (PunctuationPolicy::Drop, "drop"),
+    (PunctuationPolicy::Keep, "keep"),
+];
+const SPECIAL_TEXT: &[(SpecialTextPolicy, &str)] = &[
+    (SpecialTextPolicy::Text, "text"),
+    (SpecialTextPolicy::Strip, "strip"),
+    (SpecialTextPolicy::Escape, "escape"),
+];
+const SEGMENTATION: &[(SegmentationStrategy, &str)] = &[
+    (SegmentationStrategy::Greedy, "greedy"),
+    (SegmentationStrategy::Viterbi, "viterbi"),
+    (SegmentationStrategy::WordPiece, "wordpiece"),
+    (SegmentationStrategy::Merges, "merges"),
+];
+const UNKNOWN: &[(UnknownStrategy, &str)] = &[
+    (UnknownStrategy::Unk, "unk"),
+    (UnknownStrategy::Bytes, "bytes"),
+    (UnknownStrategy::Graphemes, "graphemes"),
+    (UnknownStrategy::Skip, "skip"),
+    (UnknownStrategy::Error, "error"),
+];
+const MODES: &[(TextMode, &str)] = &[
+    (TextMode::Natural, "natural"),
+    (TextMode::Code, "code"),
+    (TextMode::Lossless, "lossless"),
+];
+const COMPAT: &[(CompatLevel, &str)] = &[
+    (CompatLevel::V0_1, "0.1"),
+    (CompatLevel::V0_2, "0.2"),
+    (CompatLevel::Latest, "latest"),
+];
+const PADDING_SIDES: &[(PaddingSide, &str)] =
+    &[(PaddingSide::Right, "right"), (PaddingSide::Left, "left")];
+const MISSING: &[(MissingSpecialTokenPolicy, &str)] = &[
+    (MissingSpecialTokenPolicy::Allow, "allow"),
+    (MissingSpecialTokenPolicy::Register, "register"),
+    (MissingSpecialTokenPolicy::Error, "error"),
+];
+
+impl EncoderOptions {
+    /// # Serializes the options as a `tokenizer_config.json` document.
+    ///
+    /// The document is a JSON object with one member per option, grouped as follows:
+    ///
+    /// - `version`: The version of the format, currently `1`.
+    /// - `normalizer`: `line_breaks`, `invisible_chars`, `hyphenation`, `numbers`, and
+    ///   `confusables`.
+    /// - `pre_tokenizer`: `mode`, `emoji`, `punctuation`, `special_text`, `max_word_length`, and
+    ///   `empty_sentences`.
+    /// - `matching`: `single_char_margin`, `priors` as an object of score adjustments,
+    ///   `segmentation`, and `unknown`.
+    /// - `special_tokens`: the members of [`SpecialTokenOptions`].
+    /// - `truncation`: `max_sentence_tokens`.
+    /// - `padding`: `null`, or the `length` and `side` (`"right"` or `"left"`) of the [`Padding`].
+    /// - `runtime`: `collect_metrics` and `cache_capacity`.
+    /// - `compat`: The [`CompatLevel`], e.g. `"latest"`.
+    ///
+    /// Policies are written as their names in snake case (e.g. `"zero_digits"`), the policies
+    /// with a replacement token as an object (e.g. `{"replace": "<emoji>"}`), and unset options as
+    /// `null`. Together with the vocabulary file, the document fully reproduces the behavior of an
+    /// encoder (see [`BytePairEncoder::new_from_file_with_config`]). Members are always written
+    /// in the same order, so the output is diffable.
+    ///
+    /// ## Returns
+    ///
+    /// The options as a pretty-printed JSON document.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{EncoderOptions, NumberPolicy};
+    ///
+    /// let mut options = EncoderOptions::default();
+    /// options.numbers = NumberPolicy::ZeroDigits;
+    /// let json = options.to_json();
+    /// assert!(json.contains(r#""numbers": "zero_digits""#));
+    /// assert_eq!(EncoderOptions::from_json(&json).unwrap(), options);
+    /// ```
+    pub fn to_json(&self) -> String {
+        let special = &self.special_tokens;
+        let document = object(vec![
+            ("version", JsonValue::Number(CONFIG_VERSION.to_string())),
+            (
+                "normalizer",
+                object(vec![
+                    ("line_breaks", unit(self.line_breaks, LINE_BREAKS)),
+                    (
+                        "invisible_chars",
+                        unit(self.invisible_chars, INVISIBLE_CHARS),
+                    ),
+                    ("hyphenation", unit(self.hyphenation, HYPHENATION)),
+                    ("numbers", unit(self.numbers, NUMBERS)),
+                    ("confusables", unit(self.confusables, CONFUSABLES)),
+                ]),
+            ),
+            (
+                "pre_tokenizer",
+                object(vec![
+                    ("mode", unit(self.mode, MODES)),
+                    (
+                        "emoji",
+                        match &self.emoji {
+                            EmojiPolicy::Drop => string("drop"),
+                            EmojiPolicy::Keep => string("keep"),
+                            EmojiPolicy::Replace(token) => object(vec![("replace", string(token))]),
+                        },
+                    ),
+                    ("punctuation", unit(self.punctuation, PUNCTUATION)),
+                    ("special_text", unit(self.special_text, SPECIAL_TEXT)),
+                    ("max_word_length", number(self.max_word_length)),
+                    (
+                        "empty_sentences",
+                        match &self.empty_sentences {
+                            EmptySentencePolicy::Skip => string("skip"),
+                            EmptySentencePolicy::EmitMarkers => string("emit_markers"),
+                            EmptySentencePolicy::EmitToken(token) => {
+                                object(vec![("emit_token", string(token))])
+                            }
+                        },
+                    ),
+                ]),
+            ),
+            (
+                "matching",
+                object(vec![
+                    ("single_char_margin", number(self.single_char_margin)),
+                    (
+                        "priors",
+                        self.priors.as_ref().map_or(JsonValue::Null, |priors| {
+                            JsonValue::Object(
+                                priors
+                                    .adjustments()
+                                    .into_iter()
+                                    .map(|(token, adjustment)| {
+                                        (token.to_string(), number(Some(adjustment)))
+                                    })
+                                    .collect(),
+                            )
+                        }),
+                    ),
+                    ("segmentation", unit(self.segmentation, SEGMENTATION)),
+                    ("unknown", unit(self.unknown, UNKNOWN)),
+                ]),
+            ),
+            (
+                "special_tokens",
+                object(vec![
+                    ("sentence_start", optional_string(&special.sentence_start)),
+                    ("sentence_end", optional_string(&special.sentence_end)),
+                    ("unknown", optional_string(&special.unknown)),
+                    ("pad", optional_string(&special.pad)),
+                    ("mask", optional_string(&special.mask)),
+                    (
+                        "word_break",
+                        special
+                            .word_break
+                            .map_or(JsonValue::Null, |c| string(&c.to_string())),
+                    ),
+                    ("missing", unit(special.missing, MISSING)),
+                ]),
+            ),
+            (
+                "truncation",
+                object(vec![(
+                    "max_sentence_tokens",
+                    number(self.max_sentence_tokens),
+                )]),
+            ),
+            (
+                "padding",
+                self.padding.map_or(JsonValue::Null, |padding| {
+                    object(vec![
+                        ("length", number(Some(padding.length))),
+                        ("side", unit(padding.side, PADDING_SIDES)),
+                    ])
+                }),
+            ),
+            (
+                "runtime",
+                object(vec![
+                    ("collect_metrics", JsonValue::Bool(self.collect_metrics)),
+                    ("cache_capacity", number(self.cache_capacity)),
+                ]),
+            ),
+            ("compat", unit(self.compat, COMPAT)),
+        ]);
+
+        let mut json = String::new();
+        document.write_pretty(&mut json, 0);
+        json.push('\n');
+        json
+    }
+
+    /// # Parses options from a `tokenizer_config.json` document.
+    ///
+    /// See [`EncoderOptions::to_json`] for the format. Members that are missing keep their
+    /// default value, so a document only needs to list the options it changes. Unknown members
+    /// are rejected, so a misspelled option cannot silently fall back to its default.
+    ///
+    /// ## Arguments
+    ///
+    /// * `json` - The JSON document.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the options, or `BytePairEncoderError::InvalidConfig` if the document is
+    /// not valid JSON, has a different version, or contains an unknown member or invalid value.
+    /// Options that cannot be honored are rejected as by [`EncoderOptions::validate`].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{EncoderOptions, TextMode};
+    ///
+    /// let options = EncoderOptions::from_json(r#"{"pre_tokenizer": {"mode": "code"}}"#).unwrap();
+    /// assert_eq!(options.mode, TextMode::Code);
+    /// assert!(EncoderOptions::from_json(r#"{"pre_tokenizer": {"mdoe": "code"}}"#).is_err());
+    /// ```
+    pub fn from_json(json: &str) -> Result<Self, BytePairEncoderError> {
+        let document = parse_json(json).map_err(invalid)?;
+        let mut options = EncoderOptions::default();
+        for (key, value) in members(&document, "")? {
+            match key.as_str() {
+                "version" => match value {
+                    JsonValue::Number(version) if version == CONFIG_VERSION => {}
+                    _ => return Err(invalid(format!("unsupported version {}", describe(value)))),
+                },
+                "normalizer" => {
+                    for (key, value) in members(value, key)? {
+                        let path = format!("normalizer.{}", key);
+                        match key.as_str() {
+                            "line_breaks" => {
+                                options.line_breaks = parse_unit(value, LINE_BREAKS, &path)?
+                            }
+                            "invisible_chars" => {
+                                options.invisible_chars = parse_unit(value, INVISIBLE_CHARS, &path)?
+                            }
+                            "hyphenation" => {
+                                options.hyphenation = parse_unit(value, HYPHENATION, &path)?
+                            }
+                            "numbers" => options.numbers = parse_unit(value, NUMBERS, &path)?,
+                            "confusables" => {
+                                options.confusables = parse_unit(value, CONFUSABLES, &path)?
+                            }
+                            _ => return Err(unknown(&path)),
+                        }
+                    }
+                }
+                "pre_tokenizer" => {
+                    for (key, value) in members(value, key)? {
+                        let path = format!("pre_tokenizer.{}", key);
+                        match key.as_str() {
+                            "mode" => options.mode = parse_unit(value, MODES, &path)?,
+                            "emoji" => {
+                                options.emoji = match parse_tagged(value, &path)? {
+                                    ("drop", None) => EmojiPolicy::Drop,
+                                    ("keep", None) => EmojiPolicy::Keep,
+                                    ("replace", Some(token)) => EmojiPolicy::Replace(token),
+                                    _ => return Err(invalid_value(&path, value)),
+                                }
+                            }
+                            "punctuation" => {
+                                options.punctuation = parse_unit(value, PUNCTUATION, &path)?
+                            }
+                            "special_text" => {
+                                options.special_text = parse_unit(value, SPECIAL_TEXT, &path)?
+                            }
+                            "max_word_length" => {
+                                options.max_word_length = parse_number(value, &path)?
+                            }
+                            "empty_sentences" => {
+                                options.empty_sentences = match parse_tagged(value, &path)? {
+                                    ("skip", None) => EmptySentencePolicy::Skip,
+                                    ("emit_markers", None) => EmptySentencePolicy::EmitMarkers,
+                                    ("emit_token", Some(token)) => {
+                                        EmptySentencePolicy::EmitToken(token)
+                                    }
+                                    _ => return Err(invalid_value(&path, value)),
+                                }
+                            }
+                            _ => return Err(unknown(&path)),
+                        }
+                    }
+                }
+                "matching" => {
+                    for (key, value) in members(value, key)? {
+                        let path = format!("matching.{}", key);
+                        match key.as_str() {
+                            "single_char_margin" => {
+                                options.single_char_margin = parse_number(value, &path)?
+                            }
+                            "priors" => {
+                                options.priors = match value {
+                                    JsonValue::Null => None,
+                                    _ => {
+                                        let mut priors = vec![];
+                                        for (token, adjustment) in members(value, &path)? {
+                                            let path = format!("{}.{}", path, token);
+                                            let adjustment: isize = parse_number(
+                                                adjustment, &path,
+                                            )?
+                                            .ok_or_else(|| invalid_value(&path, adjustment))?;
+                                            priors.push((token.clone(), adjustment as f64));
+                                        }
+                                        Some(TokenPriors::new(priors, 1.0))
+                                    }
+                                }
+                            }
+                            "segmentation" => {
+                                options.segmentation = parse_unit(value, SEGMENTATION, &path)?
+                            }
+                            "unknown" => options.unknown = parse_unit(value, UNKNOWN, &path)?,
+                            _ => return Err(unknown(&path)),
+                        }
+                    }
+                }
+                "special_tokens" => {
+                    options.special_tokens = parse_special_tokens(value)?;
+                }
+                "truncation" => {
+                    for (key, value) in members(value, key)? {
+                        let path = format!("truncation.{}", key);
+                        match key.as_str() {
+                            "max_sentence_tokens" => {
+                                options.max_sentence_tokens = parse_number(value, &path)?
+                            }
+                            _ => return Err(unknown(&path)),
+                        }
+                    }
+                }
+                "padding" if *value == JsonValue::Null => options.padding = None,
+                "padding" => {
+                    let mut padding = Padding::new(0);
+                    let mut length = None;
+                    for (key, value) in members(value, key)? {
+                        let path = format!("padding.{}", key);
+                        match key.as_str() {
+                            "length" => length = parse_number(value, &path)?,
+                            "side" => padding.side = parse_unit(value, PADDING_SIDES, &path)?,
+                            _ => return Err(unknown(&path)),
+                        }
+                    }
+                    padding.length = length.ok_or_else(|| invalid_value(key, value))?;
+                    options.padding = Some(padding);
+                }
+                "runtime" => {
+                    for (key, value) in members(value, key)? {
+                        let path = format!("runtime.{}", key);
+                        match (key.as_str(), value) {
+                            ("collect_metrics", JsonValue::Bool(collect)) => {
+                                options.collect_metrics = *collect
+                            }
+                            ("collect_metrics", _) => return Err(invalid_value(&path, value)),
+                            ("cache_capacity", _) => {
+                                options.cache_capacity = parse_number(value, &path)?
+                            }
+                            _ => return Err(unknown(&path)),
+                        }
+                    }
+                }
+                "compat" => options.compat = parse_unit(value, COMPAT, key)?,
+                _ => return Err(unknown(key)),
+            }
+        }
+        options.validate()?;
+        Ok(options)
+    }
+}
+
+impl BytePairEncoder {
+    /// # Creates a new `BytePairEncoder` from a vocabulary file and a configuration file.
+    ///
+    /// The configuration file is a `tokenizer_config.json` document as written by
+    /// [`BytePairEncoder::write_config`] (see [`EncoderOptions::to_json`] for the format), so the
+    /// pair of files fully reproduces the behavior of the encoder they were written from. The
+    /// only exception is the merges of a GPT-2 style vocabulary, which neither file holds: a
+    /// configuration selecting `SegmentationStrategy::Merges` is rejected, and such encoders are
+    /// loaded with [`BytePairEncoder::new_from_gpt2_files`] instead.
+    ///
+    /// ## Arguments
+    ///
+    /// * `vocab_path` - The path to the vocabulary file.
+    /// * `config_path` - The path to the configuration file.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the configured `BytePairEncoder`, or an error if either file cannot be
+    /// read or parsed, or the options are invalid for the vocabulary (see
+    /// [`BytePairEncoder::try_with_options`]).
+    pub fn new_from_file_with_config(
+        vocab_path: &str,
+        config_path: &str,
+    ) -> Result<Self, BytePairEncoderError> {
+        let config =
+            fs::read_to_string(config_path).map_err(|e| BytePairEncoderError::InvalidFile {
+                path: config_path.to_string(),
+                source: e,
+            })?;
+        let options = EncoderOptions::from_json(&config)?;
+        Self::new_from_file(vocab_path)?.try_with_options(options)
+    }
+
+    /// # Writes the options of this encoder as a `tokenizer_config.json` document.
+    ///
+    /// See [`EncoderOptions::to_json`] for the format. Write it next to the vocabulary (e.g. with
+    /// [`BytePairEncoder::write_vocab`]) and load both with
+    /// [`BytePairEncoder::new_from_file_with_config`]. The merges of a GPT-2 style vocabulary are
+    /// not written, so load such an encoder from its original files with
+    /// [`BytePairEncoder::new_from_gpt2_files`] and apply the configuration with
+    /// [`BytePairEncoder::try_with_options`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `writer` - Where to write the configuration.
+    ///
+    /// ## Returns
+    ///
+    /// An `io::Result` indicating whether writing succeeded.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, EncoderOptions, TextMode};
+    ///
+    /// let mut options = EncoderOptions::default();
+    /// options.mode = TextMode::Code;
+    /// let vocab = BytePairEncoder::new_from_str("▁get\t1\nValue\t1")
+    ///     .unwrap()
+    ///     .with_options(options);
+    /// let mut config = vec![];
+    /// vocab.write_config(&mut config).unwrap();
+    /// let options = EncoderOptions::from_json(std::str::from_utf8(&config).unwrap()).unwrap();
+    /// assert_eq!(&options, vocab.options());
+    /// ```
+    pub fn write_config<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.options.to_json().as_bytes())
+    }
+}
+
+fn parse_special_tokens(value: &JsonValue) -> Result<SpecialTokenOptions, BytePairEncoderError> {
+    let mut special = SpecialTokenOptions::default();
+    for (key, value) in members(value, "special_tokens")? {
+        let path = format!("special_tokens.{}", key);
+        match key.as_str() {
+            "sentence_start" => special.sentence_start = parse_string(value, &path)?,
+            "sentence_end" => special.sentence_end = parse_string(value, &path)?,
+            "unknown" => special.unknown = parse_string(value, &path)?,
+            "pad" => special.pad = parse_string(value, &path)?,
+            "mask" => special.mask = parse_string(value, &path)?,
+            "word_break" => {
+                special.word_break = match parse_string(value, &path)? {
+                    None => None,
+                    Some(s) => {
+                        let mut chars = s.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(c), None) => Some(c),
+                            _ => return Err(invalid_value(&path, value)),
+                        }
+                    }
+                }
+            }
+            "missing" => special.missing = parse_unit(value, MISSING, &path)?,
+            _ => return Err(unknown(&path)),
+        }
+    }
+    Ok(special)
+}
+
+fn object(members: Vec<(&str, JsonValue)>) -> JsonValue {
+    JsonValue::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn string(s: &str) -> JsonValue {
+    JsonValue::String(s.to_string())
+}
+
+fn optional_string(s: &Option<String>) -> JsonValue {
+    s.as_deref().map_or(JsonValue::Null, string)
+}
+
+fn number<N: ToString>(n: Option<N>) -> JsonValue {
+    n.map_or(JsonValue::Null, |n| JsonValue::Number(n.to_string()))
+}
+
+/// Returns the name of a policy without data.
+fn unit<T: Copy + PartialEq>(value: T, names: &[(T, &str)]) -> JsonValue {
+    let name = names
+        .iter()
+        .find(|(v, _)| *v == value)
+        .map(|(_, name)| *name)
+        .expect("every variant has a name");
+    string(name)
+}
+
+fn parse_unit<T: Copy>(
+    value: &JsonValue,
+    names: &[(T, &str)],
+    path: &str,
+) -> Result<T, BytePairEncoderError> {
+    match value {
+        JsonValue::String(s) => names
+            .iter()
+            .find(|(_, name)| name == s)
+            .map(|(v, _)| *v)
+            .ok_or_else(|| invalid_value(path, value)),
+        _ => Err(invalid_value(path, value)),
+    }
+}
+
+/// Parses a policy written as a name, or as an object with a single member holding a token.
+fn parse_tagged<'a>(
+    value: &'a JsonValue,
+    path: &str,
+) -> Result<(&'a str, Option<String>), BytePairEncoderError> {
+    match value {
+        JsonValue::String(name) => Ok((name, None)),
+        JsonValue::Object(members) => match members.as_slice() {
+            [(name, JsonValue::String(token))] => Ok((name, Some(token.clone()))),
+            _ => Err(invalid_value(path, value)),
+        },
+        _ => Err(invalid_value(path, value)),
+    }
+}
+
+fn parse_number<N: std::str::FromStr>(
+    value: &JsonValue,
+    path: &str,
+) -> Result<Option<N>, BytePairEncoderError> {
+    match value {
+        JsonValue::Null => Ok(None),
+        JsonValue::Number(n) => n.parse().map(Some).map_err(|_| invalid_value(path, value)),
+        _ => Err(invalid_value(path, value)),
+    }
+}
+
+fn parse_string(value: &JsonValue, path: &str) -> Result<Option<String>, BytePairEncoderError> {
+    match value {
+        JsonValue::Null => Ok(None),
+        JsonValue::String(s) => Ok(Some(s.clone())),
+        _ => Err(invalid_value(path, value)),
+    }
+}
+
+fn members<'a>(
+    value: &'a JsonValue,
+    path: &str,
+) -> Result<&'a [(String, JsonValue)], BytePairEncoderError> {
+    match value {
+        JsonValue::Object(members) => Ok(members),
+        _ if path.is_empty() => Err(invalid("expected an object")),
+        _ => Err(invalid(format!("`{}` must be an object", path))),
+    }
+}
+
+fn invalid<S: Into<String>>(reason: S) -> BytePairEncoderError {
+    BytePairEncoderError::InvalidConfig {
+        reason: reason.into(),
+    }
+}
+
+fn invalid_value(path: &str, value: &JsonValue) -> BytePairEncoderError {
+    invalid(format!("invalid value for `{}`: {}", path, describe(value)))
+}
+
+/// Formats a value as JSON, for error messages.
+fn describe(value: &JsonValue) -> String {
+    let mut json = String::new();
+    value.write_pretty(&mut json, 0);
+    json
+}
+
+fn unknown(path: &str) -> BytePairEncoderError {
+    invalid(format!("unknown option `{}`", path))
+}
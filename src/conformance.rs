@@ -0,0 +1,167 @@
+//! # A check that tokenization is bit-for-bit identical across builds.
+//!
+//! Tokenization depends on more than the vocabulary: Unicode tables, case mapping, sentence and
+//! word segmentation, and platform-sized integer arithmetic all take part. This
+//! module tokenizes a built-in reference corpus with a built-in vocabulary, under several
+//! [`EncoderOptions`], and hashes every token, ID, offset, and special token flag of the results.
+//! Builds that tokenize identically return the same [`fingerprint`], so CI jobs on different
+//! operating systems and architectures, as well as FFI and WASM builds, can assert it from
+//! application code.
+//!
+//! The hash is a 64-bit FNV-1a, which is specified independently of the platform. Its expected
+//! value is pinned as [`REFERENCE_FINGERPRINT`], which changes only together with the tokenization
+//! behavior of this crate.
+//!
+//! ## Example
+//!
+//! ```
+//! use bpe_tokenizer::conformance;
+//!
+//! assert_eq!(conformance::fingerprint(), conformance::REFERENCE_FINGERPRINT);
+//! ```
+
+use crate::{
+    fnv::Fnv1a, BytePairEncoder, ConfusablePolicy, EmojiPolicy, EncoderOptions, HyphenationPolicy,
+    NumberPolicy, PunctuationPolicy, SegmentationStrategy, TextMode, UnknownStrategy,
+};
+
+/// The value of [`fingerprint`] in a correct build of this version of the crate.
+pub const REFERENCE_FINGERPRINT: u64 = 0x7a5b_dc56_21cb_72a6;
+
+/// The vocabulary used by [`fingerprint`], in the `<token>\t<score>` format of
+/// [`BytePairEncoder::new_from_str`].
+const REFERENCE_VOCAB: &str = "<unk>\t0\n<s>\t0\n</s>\t0\n<pad>\t0\n<mask>\t0\n\
+▁\t-1\n▁the\t-2\n▁quick\t-3\n▁brown\t-3\n▁fox\t-3\n▁jump\t-4\ns\t-2\ned\t-3\n\
+▁over\t-3\n▁lazy\t-4\n▁dog\t-3\n▁straße\t-5\n▁strasse\t-5\n▁café\t-5\n▁naïve\t-6\n\
+▁über\t-5\n▁привет\t-6\n▁мир\t-5\n▁γειά\t-6\n▁σου\t-5\n▁שלום\t-6\n▁مرحبا\t-6\n\
+▁नमस्ते\t-7\n▁日\t-4\n▁本\t-4\n▁語\t-4\n▁東京\t-6\n▁한국어\t-7\n▁😀\t-5\n▁👍\t-5\n\
+▁1\t-2\n▁2\t-2\n▁0\t-2\n0\t-3\n1\t-3\n2\t-3\n3\t-3\n▁,\t-2\n▁.\t-2\n▁!\t-2\n▁-\t-3\n\
+▁well\t-4\n▁known\t-4\n▁is\t-2\n▁it\t-2\n▁fn\t-4\n▁main\t-4\n▁(\t-3\n▁)\t-3\n▁{\t-3\n▁}\t-3\n\
+a\t-4\nb\t-4\nc\t-4\nd\t-4\ne\t-3\nf\t-4\ng\t-4\nh\t-4\ni\t-3\nk\t-4\nl\t-4\nm\t-4\nn\t-3\n\
+o\t-3\np\t-4\nr\t-3\nt\t-3\nu\t-4\nw\t-4\ny\t-4\nz\t-5\n▁a\t-3\n▁i\t-3\n▁o\t-3\n▁w\t-4";
+
+/// The texts tokenized by [`fingerprint`].
+///
+/// They cover the parts of the tokenizer that differ most easily between builds: case mapping,
+/// scripts with and without word separators, combining marks, emoji, digits, punctuation,
+/// invisible characters, line terminators, and words missing from the vocabulary.
+pub const REFERENCE_CORPUS: &[&str] = &[
+    "The quick brown fox jumped over the lazy dog.",
+    "THE QUICK BROWN FOX JUMPS! the lazy dogs.",
+    "STRASSE, Straße, straße. Café naïve Über.",
+    "Cafe\u{301} nai\u{308}ve, and the ﬁne ﬂow.",
+    "Привет, мир! Γειά σου. שלום. مرحبا. नमस्ते.",
+    "日本語のテキスト。東京！한국어 텍스트.",
+    "😀 👍🏽 👨‍👩‍👧 🇯🇵 it is 😀😀.",
+    "0 1 2 3 10 123 2024-01-02 3.14159 1,000,000.",
+    "well-known, well‐known, well-\nknown.",
+    "zero\u{200b}width\u{feff} and\u{00ad}soft hyphen\u{0007}.",
+    "Line one\r\nline two\u{2028}line three\u{000b}line four\n\nline five",
+    "fn main() { it(\"is\"); } // ok",
+    "Ｆｕｌｌｗｉｄｔｈ ｔｅｘｔ and ℌ𝔢𝔩𝔩𝔬 𝕎𝕠𝕣𝕝𝕕.",
+    "The\tdog   is\u{a0}lazy\u{3000}over there.",
+    "",
+    "   ",
+    "!!!",
+    "İstanbul ıi ǅemal ﬀ ΣΊΣΥΦΟΣ.",
+];
+
+/// # Returns the fingerprint of this build's tokenization of the reference corpus.
+///
+/// See the [module documentation](self) for what it covers. Compare the result with
+/// [`REFERENCE_FINGERPRINT`], or with the fingerprint of another build.
+///
+/// ## Returns
+///
+/// A `u64` hash of the tokenization of [`REFERENCE_CORPUS`] with the built-in vocabulary.
+pub fn fingerprint() -> u64 {
+    let vocab = BytePairEncoder::new_from_str(REFERENCE_VOCAB)
+        .expect("the reference vocabulary is well-formed");
+    fingerprint_with(&vocab)
+}
+
+/// # Returns the fingerprint of the tokenization of the reference corpus with the given encoder.
+///
+/// Like [`fingerprint`], but with an application's own vocabulary, so its tokenization can be
+/// compared across builds as well. Besides the encoder's own options, the corpus is also
+/// tokenized with several variations of them, so that more of the tokenizer is covered.
+///
+/// ## Arguments
+///
+/// * `encoder` - The encoder to fingerprint.
+///
+/// ## Returns
+///
+/// A `u64` hash of the tokenization of [`REFERENCE_CORPUS`] with `encoder`.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{conformance, BytePairEncoder};
+///
+/// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+/// assert_eq!(
+///     conformance::fingerprint_with(&vocab),
+///     conformance::fingerprint_with(&vocab.clone())
+/// );
+/// ```
+pub fn fingerprint_with(encoder: &BytePairEncoder) -> u64 {
+    let mut hash = Fnv1a::new();
+    for options in variants(encoder.options()) {
+        let encoder = encoder.clone().with_options(options);
+        for text in REFERENCE_CORPUS {
+            hash.write_str(text);
+            let encoding = encoder.encode_full(text);
+            hash.write(&(encoding.len() as u64).to_le_bytes());
+            for i in 0..encoding.len() {
+                hash.write_str(&encoding.tokens[i]);
+                hash.write(&encoding.ids[i].map_or(u64::MAX, u64::from).to_le_bytes());
+                for offset in [&encoding.offsets[i], &encoding.char_offsets[i]] {
+                    hash.write(&(offset.start as u64).to_le_bytes());
+                    hash.write(&(offset.end as u64).to_le_bytes());
+                }
+                hash.write(&[u8::from(encoding.special_tokens_mask[i])]);
+            }
+        }
+    }
+    hash.finish()
+}
+
+/// Returns `options`, followed by variations of it that exercise the optional text processing.
+fn variants(options: &EncoderOptions) -> Vec<EncoderOptions> {
+    let mut folded = options.clone();
+    folded.punctuation = PunctuationPolicy::Keep;
+    folded.emoji = EmojiPolicy::Keep;
+    folded.numbers = NumberPolicy::ZeroDigits;
+    folded.confusables = ConfusablePolicy::Fold;
+    folded.hyphenation = HyphenationPolicy::Rejoin;
+
+    let mut code = options.clone();
+    code.mode = TextMode::Code;
+
+    let mut lossless = options.clone();
+    lossless.mode = TextMode::Lossless;
+
+    let mut viterbi = options.clone();
+    viterbi.segmentation = SegmentationStrategy::Viterbi;
+
+    let mut bytes = options.clone();
+    bytes.unknown = UnknownStrategy::Bytes;
+
+    let mut graphemes = folded.clone();
+    graphemes.unknown = UnknownStrategy::Graphemes;
+
+    let mut skip = folded.clone();
+    skip.unknown = UnknownStrategy::Skip;
+
+    vec![
+        options.clone(),
+        folded,
+        code,
+        lossless,
+        viterbi,
+        bytes,
+        graphemes,
+        skip,
+    ]
+}
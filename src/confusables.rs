@@ -0,0 +1,128 @@
+/// Returns the ASCII character that a full-width or mathematical alphanumeric character is a
+/// styled variant of, if any.
+///
+/// These variants never carry a different meaning in running text, so they are always folded.
+fn fold_variant(c: char) -> Option<char> {
+    let code = c as u32;
+    let folded = match code {
+        // Full-width ASCII (`Ａ`, `１`, `！`, ...).
+        0xFF01..=0xFF5E => code - 0xFEE0,
+        // Mathematical alphanumeric letters: bold, italic, script, fraktur, double-struck,
+        // sans-serif, and monospace styles of A-Z followed by a-z.
+        0x1D400..=0x1D6A3 => {
+            let index = (code - 0x1D400) % 52;
+            if index < 26 {
+                'A' as u32 + index
+            } else {
+                'a' as u32 + index - 26
+            }
+        }
+        // Mathematical digits, in five styles of 0-9.
+        0x1D7CE..=0x1D7FF => '0' as u32 + (code - 0x1D7CE) % 10,
+        _ => return None,
+    };
+    char::from_u32(folded)
+}
+
+/// Returns the Latin letter that a Cyrillic or Greek letter is visually indistinguishable from, if
+/// any.
+fn fold_lookalike(c: char) -> Option<char> {
+    let folded = match c {
+        // Cyrillic.
+        'а' => 'a',
+        'в' => 'b',
+        'е' => 'e',
+        'к' => 'k',
+        'м' => 'm',
+        'н' => 'h',
+        'о' => 'o',
+        'р' => 'p',
+        'с' => 'c',
+        'т' => 't',
+        'у' => 'y',
+        'х' => 'x',
+        'і' => 'i',
+        'ј' => 'j',
+        'ѕ' => 's',
+        'ԁ' => 'd',
+        'ԛ' => 'q',
+        'ԝ' => 'w',
+        'А' => 'A',
+        'В' => 'B',
+        'Е' => 'E',
+        'К' => 'K',
+        'М' => 'M',
+        'Н' => 'H',
+        'О' => 'O',
+        'Р' => 'P',
+        'С' => 'C',
+        'Т' => 'T',
+        'У' => 'Y',
+        'Х' => 'X',
+        'І' => 'I',
+        'Ј' => 'J',
+        'Ѕ' => 'S',
+        // Greek.
+        'Α' => 'A',
+        'Β' => 'B',
+        'Ε' => 'E',
+        'Ζ' => 'Z',
+        'Η' => 'H',
+        'Ι' => 'I',
+        'Κ' => 'K',
+        'Μ' => 'M',
+        'Ν' => 'N',
+        'Ο' => 'O',
+        'Ρ' => 'P',
+        'Τ' => 'T',
+        'Υ' => 'Y',
+        'Χ' => 'X',
+        'ο' => 'o',
+        'ν' => 'v',
+        _ => return None,
+    };
+    Some(folded)
+}
+
+/// Returns `true` if `c` is in the Greek or Cyrillic blocks, which are adjacent.
+fn is_cyrillic_or_greek(c: char) -> bool {
+    matches!(c as u32, 0x0370..=0x052F)
+}
+
+/// Folds confusable characters to their canonical forms, as `ConfusablePolicy::Fold` does.
+///
+/// Characters are paired with the byte offset of the source character they came from, which is
+/// kept. Full-width and mathematical alphanumeric variants are always folded. Cyrillic and Greek
+/// lookalikes are folded only within a run of letters that also contains a Latin letter (after
+/// folding variants) and no other Cyrillic or Greek letters, so genuine Cyrillic or Greek words
+/// are left alone.
+pub(crate) fn fold_confusables(chars: Vec<(usize, char)>) -> Vec<(usize, char)> {
+    let mut chars: Vec<(usize, char)> = chars
+        .into_iter()
+        .map(|(i, c)| (i, fold_variant(c).unwrap_or(c)))
+        .collect();
+
+    let mut start = 0;
+    while start < chars.len() {
+        if !chars[start].1.is_alphabetic() {
+            start += 1;
+            continue;
+        }
+        let end = chars[start..]
+            .iter()
+            .position(|(_, c)| !c.is_alphabetic())
+            .map_or(chars.len(), |len| start + len);
+        let run = &mut chars[start..end];
+        let has_latin = run.iter().any(|(_, c)| c.is_ascii_alphabetic());
+        let all_lookalikes = run
+            .iter()
+            .all(|&(_, c)| !is_cyrillic_or_greek(c) || fold_lookalike(c).is_some());
+        if has_latin && all_lookalikes {
+            for (_, c) in run.iter_mut() {
+                *c = fold_lookalike(*c).unwrap_or(*c);
+            }
+        }
+        start = end;
+    }
+    chars
+}
@@ -0,0 +1,120 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::BytePairEncoder;
+
+/// # Token co-occurrence counts within a sliding window, as built by [`cooccurrence_counts`].
+///
+/// Counts are keyed by token ID. Each pair of tokens at most `window` tokens apart is counted once
+/// in each direction, so the counts form a symmetric matrix whose row sums are the marginals used
+/// by PMI-style weighting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CooccurrenceCounts {
+    counts: HashMap<(u32, u32), u64>,
+    window: usize,
+    total: u64,
+}
+
+impl CooccurrenceCounts {
+    /// Returns the window size the counts were built with.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Returns how often `context` occurred within the window of `center`.
+    pub fn get(&self, center: u32, context: u32) -> u64 {
+        self.counts.get(&(center, context)).copied().unwrap_or(0)
+    }
+
+    /// Iterates over the `((center, context), count)` entries with a non-zero count, in no
+    /// particular order.
+    pub fn iter(&self) -> impl Iterator<Item = ((u32, u32), u64)> + '_ {
+        self.counts.iter().map(|(&pair, &count)| (pair, count))
+    }
+
+    /// Returns the number of distinct `(center, context)` pairs.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns `true` if no pairs were counted.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Returns the sum of all counts.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+}
+
+/// # Counts how often tokens occur near each other in a corpus.
+///
+/// This feeds classical embedding pipelines (co-occurrence matrices, PPMI, SVD) straight from the
+/// tokenizer's streaming output: documents are tokenized one at a time and only the last `window`
+/// tokens are kept, so the tokenized corpus is never materialized.
+///
+/// Windows do not cross sentence boundaries, and sentence markers are not counted. Tokens without
+/// an ID (such as an unknown token missing from the vocabulary) take up a position in the window,
+/// but are not counted.
+///
+/// ## Arguments
+///
+/// * `encoder` - The encoder to tokenize the corpus with.
+/// * `corpus` - The documents to count, e.g. lines read from a file.
+/// * `window` - The maximum distance between two tokens, in tokens, for them to co-occur.
+///
+/// ## Returns
+///
+/// The [`CooccurrenceCounts`] of the corpus.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{cooccurrence_counts, BytePairEncoder};
+///
+/// let vocab = BytePairEncoder::new_from_str("▁a\t1\n▁b\t1\n▁c\t1").unwrap();
+/// let counts = cooccurrence_counts(&vocab, ["a b c! a"], 1);
+/// let id = |token| vocab.token_to_id(token).unwrap();
+/// assert_eq!(counts.get(id("▁a"), id("▁b")), 1);
+/// assert_eq!(counts.get(id("▁c"), id("▁b")), 1);
+/// assert_eq!(counts.get(id("▁a"), id("▁c")), 0);
+/// assert_eq!(counts.total(), 4);
+/// ```
+pub fn cooccurrence_counts<I, S>(
+    encoder: &BytePairEncoder,
+    corpus: I,
+    window: usize,
+) -> CooccurrenceCounts
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let special = encoder.special_tokens();
+    let mut counts = CooccurrenceCounts {
+        window,
+        ..CooccurrenceCounts::default()
+    };
+    let mut recent: VecDeque<Option<u32>> = VecDeque::with_capacity(window + 1);
+
+    for document in corpus {
+        for token in encoder.tokenize_ref_iter(document.as_ref()) {
+            if token == special.sentence_start() || token == special.sentence_end() {
+                recent.clear();
+                continue;
+            }
+            let id = encoder.token_to_id(&token);
+            if let Some(id) = id {
+                for &previous in recent.iter().flatten() {
+                    *counts.counts.entry((previous, id)).or_insert(0) += 1;
+                    *counts.counts.entry((id, previous)).or_insert(0) += 1;
+                    counts.total += 2;
+                }
+            }
+            recent.push_back(id);
+            if recent.len() > window {
+                recent.pop_front();
+            }
+        }
+    }
+    counts
+}
@@ -0,0 +1,56 @@
+use crate::fnv::Fnv1a;
+
+/// The number of rows of a [`CountMinSketch`], each with its own hash function.
+const DEPTH: usize = 4;
+
+/// # Approximate counts of any number of words, in a fixed amount of memory.
+///
+/// Every word is counted in one cell of each row, chosen by a hash of the word, and its estimate
+/// is the smallest of those cells. Words sharing a cell inflate each other's estimates, so an
+/// estimate is never below the true count, and is above it by at most a small fraction of the
+/// total count.
+#[derive(Debug, Clone)]
+pub(crate) struct CountMinSketch {
+    width: usize,
+    cells: Vec<u64>,
+}
+
+impl CountMinSketch {
+    /// Creates an empty sketch with `width` cells per row.
+    pub(crate) fn new(width: usize) -> Self {
+        let width = width.max(1);
+        CountMinSketch {
+            width,
+            cells: vec![0; DEPTH * width],
+        }
+    }
+
+    /// Adds `count` occurrences of `word`.
+    pub(crate) fn add(&mut self, word: &str, count: u64) {
+        for cell in self.cells(word) {
+            self.cells[cell] = self.cells[cell].saturating_add(count);
+        }
+    }
+
+    /// Returns the estimated number of occurrences of `word`.
+    pub(crate) fn estimate(&self, word: &str) -> u64 {
+        self.cells(word)
+            .map(|cell| self.cells[cell])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Returns the index of the cell of `word` in each row.
+    ///
+    /// The rows use the hash functions `h1 + row * h2` of two halves of a single hash, which are
+    /// as good as independent ones for a sketch.
+    fn cells(&self, word: &str) -> impl Iterator<Item = usize> {
+        let mut hasher = Fnv1a::new();
+        hasher.write_str(word);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        let width = self.width as u64;
+        (0..DEPTH as u64)
+            .map(move |row| (row * width + h1.wrapping_add(row.wrapping_mul(h2)) % width) as usize)
+    }
+}
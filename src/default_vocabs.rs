@@ -1,25 +1,214 @@
-use crate::{BytePairEncoder, BytePairEncoderError};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{fnv::Fnv1a, BytePairEncoder, BytePairEncoderError};
 
 #[cfg(any(
     feature = "default-small",
     feature = "default-medium",
     feature = "default-large"
 ))]
-use {lz4_flex::decompress_size_prepended, std::collections::HashMap};
+use lz4_flex::decompress_size_prepended;
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub(crate) enum DefaultVocab {
     Small,
     Medium,
     Large,
 }
 
+impl DefaultVocab {
+    /// The default vocabularies, from smallest to largest.
+    const ALL: [DefaultVocab; 3] = [
+        DefaultVocab::Small,
+        DefaultVocab::Medium,
+        DefaultVocab::Large,
+    ];
+
+    /// Returns the default vocabularies compiled in, from smallest to largest.
+    pub(crate) fn compiled() -> Vec<DefaultVocab> {
+        DefaultVocab::ALL
+            .into_iter()
+            .filter(|vocab| vocab.is_compiled())
+            .collect()
+    }
+
+    /// Returns the name of the Cargo feature that compiles in the vocabulary.
+    pub(crate) fn feature(self) -> &'static str {
+        match self {
+            DefaultVocab::Small => "default-small",
+            DefaultVocab::Medium => "default-medium",
+            DefaultVocab::Large => "default-large",
+        }
+    }
+
+    /// Returns `true` if the vocabulary is compiled in.
+    fn is_compiled(self) -> bool {
+        match self {
+            DefaultVocab::Small => cfg!(feature = "default-small"),
+            DefaultVocab::Medium => cfg!(feature = "default-medium"),
+            DefaultVocab::Large => cfg!(feature = "default-large"),
+        }
+    }
+
+    /// Returns the number of tokens in the vocabulary.
+    fn vocab_size(self) -> usize {
+        match self {
+            DefaultVocab::Small => 100_000,
+            DefaultVocab::Medium => 320_000,
+            DefaultVocab::Large => 1_000_000,
+        }
+    }
+
+    /// Returns the size of the decompressed data and the checksum of the compressed data, as
+    /// recorded by the build script, or zeros if the vocabulary is not compiled in.
+    fn build_info(self) -> (usize, u64) {
+        let (size, checksum) = match self {
+            DefaultVocab::Small => (
+                option_env!("BPE_TOKENIZER_DEFAULT_SMALL_SIZE"),
+                option_env!("BPE_TOKENIZER_DEFAULT_SMALL_CHECKSUM"),
+            ),
+            DefaultVocab::Medium => (
+                option_env!("BPE_TOKENIZER_DEFAULT_MEDIUM_SIZE"),
+                option_env!("BPE_TOKENIZER_DEFAULT_MEDIUM_CHECKSUM"),
+            ),
+            DefaultVocab::Large => (
+                option_env!("BPE_TOKENIZER_DEFAULT_LARGE_SIZE"),
+                option_env!("BPE_TOKENIZER_DEFAULT_LARGE_CHECKSUM"),
+            ),
+        };
+        (
+            size.and_then(|size| size.parse().ok()).unwrap_or(0),
+            checksum
+                .and_then(|checksum| checksum.parse().ok())
+                .unwrap_or(0),
+        )
+    }
+
+    /// Returns the compressed data embedded in the binary, which is empty if the vocabulary is
+    /// not compiled in.
+    pub(crate) fn data(self) -> &'static [u8] {
+        match self {
+            DefaultVocab::Small => DEFAULT_SMALL_DATA,
+            DefaultVocab::Medium => DEFAULT_MEDIUM_DATA,
+            DefaultVocab::Large => DEFAULT_LARGE_DATA,
+        }
+    }
+
+    /// Returns the heap memory used by a loaded encoder, in bytes, as measured on a 64-bit
+    /// platform. `test_default_vocab_memory_bytes` fails when these drift from
+    /// [`BytePairEncoder::memory_bytes`].
+    pub(crate) fn memory_bytes(self) -> usize {
+        match self {
+            DefaultVocab::Small => 8_440_406,
+            DefaultVocab::Medium => 32_342_854,
+            DefaultVocab::Large => 98_439_423,
+        }
+    }
+}
+
+/// # A default vocabulary chosen by
+/// [`BytePairEncoder::best_default_for_budget`](crate::BytePairEncoder::best_default_for_budget).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct DefaultVocabInfo {
+    /// The name of the Cargo feature of the vocabulary, e.g. `default-small`.
+    pub feature: &'static str,
+
+    /// The number of tokens in the vocabulary.
+    pub vocab_size: usize,
+
+    /// The heap memory used by the loaded encoder, in bytes. This was measured on a 64-bit
+    /// platform, and is an estimate elsewhere.
+    pub memory_bytes: usize,
+
+    /// The fraction of the words of a built-in multilingual sample (sentences in English, German,
+    /// French, Spanish, Russian, Arabic, Hindi, Japanese, Chinese, and Korean) that the vocabulary
+    /// matches with a single token. Larger vocabularies split fewer words, which gives shorter
+    /// token sequences.
+    pub coverage: f64,
+}
+
+/// Sentences the coverage of a default vocabulary is measured on.
+const COVERAGE_SAMPLE: &[&str] = &[
+    "The quick brown fox jumps over the lazy dog. Byte pair encoding splits rare words into \
+     subword units.",
+    "Tokenizers are the first stage of most language processing pipelines.",
+    "Der schnelle braune Fuchs springt über den faulen Hund. Die Stadtverwaltung \
+     veröffentlichte gestern ihren Jahresbericht.",
+    "Le renard brun rapide saute par-dessus le chien paresseux. Les élections municipales \
+     auront lieu au printemps.",
+    "El rápido zorro marrón salta sobre el perro perezoso. La biblioteca nacional amplió su \
+     horario de apertura.",
+    "Быстрая коричневая лиса прыгает через ленивую собаку. Правительство объявило о новых \
+     мерах поддержки.",
+    "الثعلب البني السريع يقفز فوق الكلب الكسول. افتتحت الجامعة مكتبة جديدة هذا العام.",
+    "तेज़ भूरी लोमड़ी आलसी कुत्ते के ऊपर कूदती है। सरकार ने नई शिक्षा नीति की घोषणा की।",
+    "こんにちは、世界！お元気ですか？東京は日本の首都です。",
+    "我们今天去公园散步。北京是中国的首都。",
+    "한국어 문장도 포함되어 있습니다. 서울은 한국의 수도입니다.",
+];
+
+/// Returns the fraction of the words of [`COVERAGE_SAMPLE`] that `encoder` matches with a single
+/// token.
+fn coverage(encoder: &BytePairEncoder) -> f64 {
+    let (mut words, mut whole) = (0, 0);
+    for sentence in COVERAGE_SAMPLE {
+        for word in sentence
+            .split_sentence_bounds()
+            .flat_map(|sentence| encoder.split_words(sentence))
+        {
+            let tokens = encoder.tokenize_segment(word).len();
+            if tokens > 0 {
+                words += 1;
+                whole += usize::from(tokens == 1);
+            }
+        }
+    }
+    if words == 0 {
+        0.0
+    } else {
+        whole as f64 / words as f64
+    }
+}
+
+/// Loads the largest compiled default vocabulary that fits in `budget` bytes of heap memory.
+pub(crate) fn best_default_for_budget(
+    budget: usize,
+) -> Result<(BytePairEncoder, DefaultVocabInfo), BytePairEncoderError> {
+    let compiled = DefaultVocab::compiled();
+    let vocab = match compiled
+        .iter()
+        .rev()
+        .find(|vocab| vocab.memory_bytes() <= budget)
+    {
+        Some(vocab) => *vocab,
+        None => {
+            return Err(match compiled.first() {
+                Some(smallest) => BytePairEncoderError::MemoryBudgetTooSmall {
+                    budget,
+                    minimum: smallest.memory_bytes(),
+                },
+                None => BytePairEncoderError::NoDefaultVocabFeature,
+            })
+        }
+    };
+
+    let encoder = new_default(vocab)?;
+    let info = DefaultVocabInfo {
+        feature: vocab.feature(),
+        vocab_size: vocab.vocab_size(),
+        memory_bytes: vocab.memory_bytes(),
+        coverage: coverage(&encoder),
+    };
+    Ok((encoder, info))
+}
+
 #[cfg(feature = "default-small")]
 const DEFAULT_SMALL_DATA: &[u8] = include_bytes!(concat!(
     env!("OUT_DIR"),
     "/",
-    "multi.wiki.bpe.vs100000.vocab.hashmap.bincode.lz4"
+    "multi.wiki.bpe.vs100000.vocab.bincode.lz4"
 ));
 
 #[allow(dead_code)]
@@ -30,7 +219,7 @@ const DEFAULT_SMALL_DATA: &[u8] = &[];
 const DEFAULT_MEDIUM_DATA: &[u8] = include_bytes!(concat!(
     env!("OUT_DIR"),
     "/",
-    "multi.wiki.bpe.vs320000.vocab.hashmap.bincode.lz4"
+    "multi.wiki.bpe.vs320000.vocab.bincode.lz4"
 ));
 
 #[allow(dead_code)]
@@ -41,7 +230,7 @@ const DEFAULT_MEDIUM_DATA: &[u8] = &[];
 const DEFAULT_LARGE_DATA: &[u8] = include_bytes!(concat!(
     env!("OUT_DIR"),
     "/",
-    "multi.wiki.bpe.vs1000000.vocab.hashmap.bincode.lz4"
+    "multi.wiki.bpe.vs1000000.vocab.bincode.lz4"
 ));
 
 #[allow(dead_code)]
@@ -56,22 +245,15 @@ const DEFAULT_LARGE_DATA: &[u8] = &[];
     feature = "default-large"
 ))]
 pub(crate) fn new_default(vocab: DefaultVocab) -> Result<BytePairEncoder, BytePairEncoderError> {
-    let data = match vocab {
-        DefaultVocab::Small => DEFAULT_SMALL_DATA,
-        DefaultVocab::Medium => DEFAULT_MEDIUM_DATA,
-        DefaultVocab::Large => DEFAULT_LARGE_DATA,
-    };
-
-    // Decompress the LZ4 binary data.
-    let uncompressed = decompress_size_prepended(data)
-        .map_err(|e| BytePairEncoderError::DecompressionError(e.to_string()))?;
-
-    // Deserialize the uncompressed data into a HashMap.
-    let tokens: HashMap<String, isize> = bincode::deserialize(&uncompressed)
-        .map_err(|e| BytePairEncoderError::DeserializationError(e.to_string()))?;
+    let tokens = decode_tokens(vocab, vocab.data())?;
 
     // Return the BytePairEncoder.
-    Ok(BytePairEncoder { tokens })
+    Ok(BytePairEncoder::from_vocab(
+        tokens
+            .iter()
+            .map(|(token, score)| (token.as_str(), *score))
+            .collect(),
+    ))
 }
 
 #[cfg(not(any(
@@ -82,3 +264,75 @@ pub(crate) fn new_default(vocab: DefaultVocab) -> Result<BytePairEncoder, BytePa
 pub(crate) fn new_default(_vocab: DefaultVocab) -> Result<BytePairEncoder, BytePairEncoderError> {
     Err(BytePairEncoderError::NoDefaultVocabFeature)
 }
+
+/// Decompresses and deserializes the token-score pairs of a default vocabulary from `data`, in
+/// ID order. Errors name the vocabulary, along with what the build script recorded about it.
+#[cfg(any(
+    feature = "default-small",
+    feature = "default-medium",
+    feature = "default-large"
+))]
+pub(crate) fn decode_tokens(
+    vocab: DefaultVocab,
+    data: &[u8],
+) -> Result<Vec<(String, isize)>, BytePairEncoderError> {
+    let (expected_size, checksum) = vocab.build_info();
+
+    // Decompress the LZ4 binary data.
+    let uncompressed =
+        decompress_size_prepended(data).map_err(|e| BytePairEncoderError::DecompressionError {
+            vocab: vocab.feature(),
+            expected_size,
+            checksum,
+            source: Box::new(e),
+        })?;
+    if uncompressed.len() != expected_size {
+        return Err(BytePairEncoderError::DecompressionError {
+            vocab: vocab.feature(),
+            expected_size,
+            checksum,
+            source: format!("decompressed to {} bytes", uncompressed.len()).into(),
+        });
+    }
+
+    // Deserialize the uncompressed data into a Vec of token-score pairs, in ID order.
+    bincode::deserialize(&uncompressed).map_err(|e| BytePairEncoderError::DeserializationError {
+        vocab: vocab.feature(),
+        expected_size,
+        checksum,
+        source: Box::new(e),
+    })
+}
+
+/// Checks every compiled default vocabulary: its embedded data against the checksum recorded
+/// at build time, and that it decodes to the expected number of tokens.
+pub(crate) fn verify_default_vocabs() -> Result<(), BytePairEncoderError> {
+    let compiled = DefaultVocab::compiled();
+    if compiled.is_empty() {
+        return Err(BytePairEncoderError::NoDefaultVocabFeature);
+    }
+
+    for vocab in compiled {
+        let (expected_size, checksum) = vocab.build_info();
+        let mut hasher = Fnv1a::new();
+        hasher.write(vocab.data());
+        if hasher.finish() != checksum {
+            return Err(BytePairEncoderError::DefaultVocabChecksumMismatch {
+                vocab: vocab.feature(),
+                expected: checksum,
+                actual: hasher.finish(),
+            });
+        }
+
+        let tokens = new_default(vocab)?.vocab_size();
+        if tokens != vocab.vocab_size() {
+            return Err(BytePairEncoderError::DeserializationError {
+                vocab: vocab.feature(),
+                expected_size,
+                checksum,
+                source: format!("expected {} tokens, found {}", vocab.vocab_size(), tokens).into(),
+            });
+        }
+    }
+    Ok(())
+}
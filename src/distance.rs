@@ -0,0 +1,146 @@
+use std::cmp::Reverse;
+
+/// Returns the Levenshtein distance between `a` and `b` if it is at most `max`, or `None`
+/// otherwise. The Levenshtein distance is the number of single character insertions, deletions,
+/// and substitutions needed to turn one string into the other.
+///
+/// The computation stops as soon as the distance is known to exceed `max`, so bounded searches
+/// skip most of the work for distant strings.
+pub(crate) fn edit_distance_within(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        let mut lowest = row[0];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+            lowest = lowest.min(row[j + 1]);
+        }
+        if lowest > max {
+            return None;
+        }
+    }
+    Some(row[b.len()]).filter(|&distance| distance <= max)
+}
+
+/// Returns the number of leading characters `a` and `b` have in common.
+pub(crate) fn shared_prefix(a: &[char], b: &[char]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Returns up to `k` of `candidates` closest to `query`, as `(token, distance)` pairs, best first.
+///
+/// Each candidate is a `(compared, token, score)` triple: `compared` is the text measured against
+/// the query, and `token` what is returned for it. Candidates are ranked as [`ClosestTokens`]
+/// ranks them, with their position as the final tie-breaker.
+pub(crate) fn closest_tokens<'a>(
+    query: &[char],
+    candidates: impl Iterator<Item = (&'a str, &'a str, isize)>,
+    k: usize,
+) -> Vec<(&'a str, usize)> {
+    let mut closest = ClosestTokens::new(query, k);
+    for (position, (compared, token, score)) in candidates.enumerate() {
+        closest.compare(compared, token, score, position);
+    }
+    closest.into_tokens()
+}
+
+/// The `k` tokens closest to a query found so far.
+///
+/// Tokens are ranked by edit distance, then by the length of the prefix they share with the
+/// query, then by score, and finally by position. The edit distance computation stops as soon as
+/// a token is known to be farther away than the `k` best found so far.
+pub(crate) struct ClosestTokens<'q, 'a> {
+    query: &'q [char],
+    k: usize,
+    best: Vec<(Rank, &'a str)>,
+    chars: Vec<char>,
+}
+
+/// Distance, shared prefix, score, and position, so the best tokens sort first.
+type Rank = (usize, Reverse<usize>, Reverse<isize>, usize);
+
+impl<'q, 'a> ClosestTokens<'q, 'a> {
+    pub(crate) fn new(query: &'q [char], k: usize) -> Self {
+        Self {
+            query,
+            k,
+            best: vec![],
+            chars: vec![],
+        }
+    }
+
+    /// Returns the largest edit distance a token may have and still rank among the `k` best.
+    pub(crate) fn bound(&self) -> usize {
+        match self.best.last() {
+            Some(&((distance, ..), _)) if self.best.len() == self.k => distance,
+            _ => usize::MAX,
+        }
+    }
+
+    /// Measures `compared` against the query, and keeps `token` if it ranks among the `k` best.
+    pub(crate) fn compare(
+        &mut self,
+        compared: &str,
+        token: &'a str,
+        score: isize,
+        position: usize,
+    ) {
+        if self.k == 0 {
+            return;
+        }
+        let mut chars = std::mem::take(&mut self.chars);
+        chars.clear();
+        chars.extend(compared.chars());
+        if let Some(distance) = edit_distance_within(self.query, &chars, self.bound()) {
+            self.insert(distance, &chars, token, score, position);
+        }
+        self.chars = chars;
+    }
+
+    /// Keeps `token`, already known to be `distance` edits from the query, if it ranks among the
+    /// `k` best.
+    pub(crate) fn offer(&mut self, distance: usize, token: &'a str, score: isize, position: usize) {
+        if self.k == 0 || distance > self.bound() {
+            return;
+        }
+        let mut chars = std::mem::take(&mut self.chars);
+        chars.clear();
+        chars.extend(token.chars());
+        self.insert(distance, &chars, token, score, position);
+        self.chars = chars;
+    }
+
+    fn insert(
+        &mut self,
+        distance: usize,
+        chars: &[char],
+        token: &'a str,
+        score: isize,
+        position: usize,
+    ) {
+        let rank = (
+            distance,
+            Reverse(shared_prefix(self.query, chars)),
+            Reverse(score),
+            position,
+        );
+        let candidate = (rank, token);
+        let position = self.best.partition_point(|entry| entry < &candidate);
+        self.best.insert(position, candidate);
+        self.best.truncate(self.k);
+    }
+
+    /// Returns the `(token, distance)` pairs kept, best first.
+    pub(crate) fn into_tokens(self) -> Vec<(&'a str, usize)> {
+        self.best
+            .into_iter()
+            .map(|((distance, ..), token)| (token, distance))
+            .collect()
+    }
+}
@@ -0,0 +1,137 @@
+use std::{iter, ops::Range};
+
+/// # The tokens of a text, aligned with the text they came from.
+///
+/// The vectors are parallel: position `i` of each describes the `i`th token. Offsets are byte
+/// ranges of the original text, also given as character ranges. Tokens that do not stand for any
+/// text, such as sentence markers, have an empty range at the position where they were inserted,
+/// and a token covering part of a character that normalization expanded covers the whole
+/// character.
+///
+/// Created by [`BytePairEncoder::encode_full`](crate::BytePairEncoder::encode_full) and
+/// [`BytePairEncoder::mask_spans`](crate::BytePairEncoder::mask_spans).
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::BytePairEncoder;
+///
+/// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁wörld\t2").unwrap();
+/// let text = "Hello, wörld!";
+/// let encoding = vocab.encode_full(text);
+/// assert_eq!(encoding.tokens, vec!["<s>", "▁hello", "▁wörld", "</s>"]);
+/// assert_eq!(encoding.offsets, vec![0..0, 0..5, 7..13, 14..14]);
+/// assert_eq!(encoding.char_offsets, vec![0..0, 0..5, 7..12, 13..13]);
+/// assert_eq!(encoding.special_tokens_mask, vec![true, false, false, true]);
+/// assert_eq!(encoding.attention_mask, vec![1, 1, 1, 1]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Encoding {
+    /// The tokens.
+    pub tokens: Vec<String>,
+
+    /// The ID of each token, or `None` for a token the vocabulary has no ID for.
+    pub ids: Vec<Option<u32>>,
+
+    /// The byte range of the original text each token covers.
+    pub offsets: Vec<Range<usize>>,
+
+    /// Whether each token was inserted by the encoder rather than matched against the text, such
+    /// as sentence markers and case markers.
+    pub special_tokens_mask: Vec<bool>,
+
+    /// The range of characters (Unicode scalar values) of the original text each token covers,
+    /// for consumers that index text by character rather than by byte.
+    pub char_offsets: Vec<Range<usize>>,
+
+    /// `1` for each token a model should attend to, and `0` for padding added with
+    /// [`Encoding::pad`].
+    pub attention_mask: Vec<u32>,
+}
+
+impl Encoding {
+    /// Returns the number of tokens.
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Returns `true` if there are no tokens.
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Appends a token, with an attention mask of `1`.
+    pub(crate) fn push(
+        &mut self,
+        token: &str,
+        id: Option<u32>,
+        offsets: Range<usize>,
+        char_offsets: Range<usize>,
+        special: bool,
+    ) {
+        self.tokens.push(token.to_string());
+        self.ids.push(id);
+        self.offsets.push(offsets);
+        self.char_offsets.push(char_offsets);
+        self.special_tokens_mask.push(special);
+        self.attention_mask.push(1);
+    }
+
+    /// # Pads the encoding to a given number of tokens.
+    ///
+    /// Padding tokens are appended until there are `length` tokens, with an attention mask of
+    /// `0`, an empty range at the end of the text, and the special tokens flag set. Encodings
+    /// already at least `length` tokens long are left unchanged.
+    ///
+    /// ## Arguments
+    ///
+    /// * `length` - The number of tokens to pad to.
+    /// * `token` - The padding token, e.g. from
+    ///   [`SpecialTokens::pad`](crate::SpecialTokens::pad).
+    /// * `id` - The ID of the padding token.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("<pad>\t0\n▁hello\t1").unwrap();
+    /// let mut encoding = vocab.encode_full("Hello");
+    /// let pad = vocab.special_tokens().pad().unwrap();
+    /// encoding.pad(5, pad, vocab.token_to_id(pad));
+    /// assert_eq!(encoding.tokens, vec!["<s>", "▁hello", "</s>", "<pad>", "<pad>"]);
+    /// assert_eq!(encoding.attention_mask, vec![1, 1, 1, 0, 0]);
+    /// ```
+    pub fn pad(&mut self, length: usize, token: &str, id: Option<u32>) {
+        let end = self.offsets.last().map_or(0, |range| range.end);
+        let char_end = self.char_offsets.last().map_or(0, |range| range.end);
+        while self.len() < length {
+            self.push(token, id, end..end, char_end..char_end, true);
+            *self.attention_mask.last_mut().expect("just pushed") = 0;
+        }
+    }
+
+    /// # Pads the encoding to a given number of tokens, in front of its tokens.
+    ///
+    /// This is [`Encoding::pad`], except that the padding tokens are inserted before the tokens of
+    /// the text, with an empty range at the start of the text.
+    ///
+    /// ## Arguments
+    ///
+    /// * `length` - The number of tokens to pad to.
+    /// * `token` - The padding token.
+    /// * `id` - The ID of the padding token.
+    pub fn pad_left(&mut self, length: usize, token: &str, id: Option<u32>) {
+        fn prepend<T: Clone>(values: &mut Vec<T>, value: T, count: usize) {
+            values.splice(0..0, iter::repeat(value).take(count));
+        }
+        let count = length.saturating_sub(self.len());
+        prepend(&mut self.tokens, token.to_string(), count);
+        prepend(&mut self.ids, id, count);
+        prepend(&mut self.offsets, 0..0, count);
+        prepend(&mut self.char_offsets, 0..0, count);
+        prepend(&mut self.special_tokens_mask, true, count);
+        prepend(&mut self.attention_mask, 0, count);
+    }
+}
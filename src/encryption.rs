@@ -0,0 +1,51 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+
+use crate::BytePairEncoderError;
+
+/// Identifies an encrypted vocabulary container, and its version.
+const MAGIC: &[u8; 8] = b"BPEVAES1";
+
+/// The length of the AES-GCM nonce stored after the magic bytes.
+const NONCE_LEN: usize = 12;
+
+/// Encrypts `plaintext` into a container: the magic bytes, the nonce, and the AES-256-GCM
+/// ciphertext (with its authentication tag).
+///
+/// The nonce is drawn from the operating system's random number generator for every container,
+/// so sealing many containers with the same key never reuses one.
+pub(crate) fn seal(plaintext: &[u8], key: &[u8; 32]) -> Vec<u8> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .expect("AES-GCM encryption of an in-memory buffer cannot fail");
+
+    let mut container = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    container.extend_from_slice(MAGIC);
+    container.extend_from_slice(&nonce);
+    container.extend_from_slice(&ciphertext);
+    container
+}
+
+/// Decrypts a container written by [`seal`], checking that it was not tampered with.
+pub(crate) fn open(container: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, BytePairEncoderError> {
+    let failed = |reason: &str| BytePairEncoderError::DecryptionError {
+        reason: reason.to_string(),
+    };
+
+    let body = container
+        .strip_prefix(&MAGIC[..])
+        .ok_or_else(|| failed("not an encrypted vocabulary container"))?;
+    if body.len() < NONCE_LEN {
+        return Err(failed("container is truncated"));
+    }
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| failed("wrong key, or the container is corrupted"))
+}
@@ -1,25 +1,194 @@
+use std::{error::Error as StdError, io, num::ParseIntError, ops::Range};
+
 use thiserror::Error;
 
+use crate::TestVector;
+
 /// Represents errors that can occur during BPE tokenization operations.
-#[derive(Error, Debug, Clone, PartialEq, Eq)]
+///
+/// Errors that wrap a lower-level failure (I/O, parsing, decompression, deserialization) expose it
+/// through [`std::error::Error::source`].
+///
+/// This enum is `#[non_exhaustive]`: new variants may be added in minor releases, so matches on it
+/// need a wildcard arm.
+#[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum BytePairEncoderError {
     /// Indicates an error occurred while reading the vocabulary file.
-    #[error("Error reading file: {0}")]
-    InvalidFile(String),
+    #[error("Error reading file: {path}")]
+    InvalidFile {
+        /// The path of the file that could not be read.
+        path: String,
+        /// The underlying I/O error.
+        source: io::Error,
+    },
+
+    /// Indicates that a line of the vocabulary input is not a tab-separated token-score pair.
+    #[error("Invalid vocabulary input on line {line}: expected `<token>\\t<score>`.")]
+    InvalidVocabularyInput {
+        /// The 1-based number of the offending line.
+        line: usize,
+    },
 
-    /// Indicates that the vocabulary input was invalid or could not be parsed correctly.
-    #[error("Invalid vocabulary input: Could not parse vocabulary file.")]
-    InvalidVocabularyInput,
+    /// Indicates that the score on a line of the vocabulary input is not a valid integer.
+    #[error("Invalid score in vocabulary input on line {line}.")]
+    InvalidScore {
+        /// The 1-based number of the offending line.
+        line: usize,
+        /// The underlying parse error.
+        source: ParseIntError,
+    },
+
+    /// Indicates that a line of a `merges.txt` input is not a space-separated pair of symbols.
+    #[error("Invalid merges input on line {line}: expected `<left> <right>`.")]
+    InvalidMerges {
+        /// The 1-based number of the offending line.
+        line: usize,
+    },
 
     /// Indicates an error occurred during decompression of the vocabulary data.
-    #[error("Error decompressing vocabulary data: {0}")]
-    DecompressionError(String),
+    #[error("Error decompressing default vocabulary `{vocab}` (expected {expected_size} bytes, build checksum {checksum:016x}).")]
+    DecompressionError {
+        /// The Cargo feature of the default vocabulary, e.g. `default-small`.
+        vocab: &'static str,
+        /// The size of the decompressed data recorded at build time, in bytes.
+        expected_size: usize,
+        /// The FNV-1a checksum of the compressed data recorded at build time.
+        checksum: u64,
+        /// The underlying decompression error.
+        source: Box<dyn StdError + Send + Sync>,
+    },
 
     /// Indicates an error occurred during deserialization of the vocabulary data.
-    #[error("Error deserializing vocabulary data: {0}")]
-    DeserializationError(String),
+    #[error("Error deserializing default vocabulary `{vocab}` (expected {expected_size} bytes, build checksum {checksum:016x}).")]
+    DeserializationError {
+        /// The Cargo feature of the default vocabulary, e.g. `default-small`.
+        vocab: &'static str,
+        /// The size of the decompressed data recorded at build time, in bytes.
+        expected_size: usize,
+        /// The FNV-1a checksum of the compressed data recorded at build time.
+        checksum: u64,
+        /// The underlying deserialization error.
+        source: Box<dyn StdError + Send + Sync>,
+    },
+
+    /// Indicates that the data of a default vocabulary embedded in the binary does not match the
+    /// checksum recorded when it was built.
+    #[error("Default vocabulary `{vocab}` is corrupted: build checksum {expected:016x}, embedded data checksum {actual:016x}.")]
+    DefaultVocabChecksumMismatch {
+        /// The Cargo feature of the default vocabulary, e.g. `default-small`.
+        vocab: &'static str,
+        /// The FNV-1a checksum of the compressed data recorded at build time.
+        expected: u64,
+        /// The FNV-1a checksum of the embedded data.
+        actual: u64,
+    },
 
-    // Indicates attempt to use a default vocabulary without enabling its Cargo feature.
-    #[error("Error, must enable defualt-small, default-medium, and/or default-large feature(s) to use default vocabulary.")]
+    /// Indicates attempt to use a default vocabulary without enabling its Cargo feature.
+    #[error("Error, must enable default-small, default-medium, and/or default-large feature(s) to use default vocabulary.")]
     NoDefaultVocabFeature,
+
+    /// Indicates that part of the input could not be covered by the vocabulary, and
+    /// `UnknownStrategy::Error` does not allow unknown tokens.
+    #[error("No vocabulary token covers the input at bytes {span:?}.")]
+    UnknownToken {
+        /// The byte range of the uncovered input.
+        span: Range<usize>,
+    },
+
+    /// Indicates that no compiled default vocabulary fits in a memory budget.
+    #[error("Memory budget of {budget} bytes is smaller than the {minimum} bytes of the smallest default vocabulary.")]
+    MemoryBudgetTooSmall {
+        /// The requested budget, in bytes.
+        budget: usize,
+        /// The memory used by the smallest compiled default vocabulary, in bytes.
+        minimum: usize,
+    },
+
+    /// Indicates that a `TokenizerPool` has no encoder with the requested name.
+    #[error("No encoder named `{name}` in the pool.")]
+    UnknownEncoder {
+        /// The requested name.
+        name: String,
+    },
+
+    /// Indicates that a token ID does not belong to any token.
+    #[error("Token ID {id} at index {index} is not in the vocabulary.")]
+    InvalidTokenId {
+        /// The unknown ID.
+        id: u32,
+        /// The position of the ID in the input.
+        index: usize,
+    },
+
+    /// Indicates that a truncation limit is too small to hold even the minimal output, such as an
+    /// `EncoderOptions::max_sentence_tokens` below the two sentence markers and a token.
+    #[error("Truncation limit {limit} is smaller than the minimum of {minimum}.")]
+    TruncationLimitTooSmall {
+        /// The requested limit.
+        limit: usize,
+        /// The smallest limit that can be honored.
+        minimum: usize,
+    },
+
+    /// Indicates that the format of a vocabulary input could not be recognized.
+    #[error("Unrecognized vocabulary format: {reason}")]
+    UnrecognizedFormat {
+        /// A description of why the format was not recognized.
+        reason: String,
+    },
+
+    /// Indicates that a vocabulary could not be converted to or from another library's model.
+    #[error("Error converting vocabulary: {reason}")]
+    ConversionError {
+        /// A description of why the conversion failed.
+        reason: String,
+    },
+
+    /// Indicates that a tokenizer configuration document could not be parsed, or that its options
+    /// cannot be honored.
+    #[error("Invalid tokenizer configuration: {reason}")]
+    InvalidConfig {
+        /// A description of what is invalid.
+        reason: String,
+    },
+
+    /// Indicates that an encrypted vocabulary could not be decrypted.
+    #[error("Error decrypting vocabulary: {reason}")]
+    DecryptionError {
+        /// A description of why decryption failed.
+        reason: String,
+    },
+
+    /// Indicates that a special token emitted by the encoder is not in the vocabulary.
+    #[error("Special token `{token}` is not in the vocabulary.")]
+    MissingSpecialToken {
+        /// The missing special token.
+        token: String,
+    },
+
+    /// Indicates that a line of a trainer checkpoint is malformed.
+    #[error("Invalid trainer checkpoint on line {line}.")]
+    InvalidCheckpoint {
+        /// The 1-based number of the offending line.
+        line: usize,
+    },
+
+    /// Indicates that a line of a word cache file is malformed.
+    #[error("Invalid word cache on line {line}.")]
+    InvalidCache {
+        /// The 1-based number of the offending line.
+        line: usize,
+    },
+
+    /// Indicates that a test vector no longer matches the tokenization of its text.
+    #[error("Test vector {index} does not match: expected {:?} (IDs {:?}), got {:?} (IDs {:?}).", expected.tokens, expected.ids, actual.tokens, actual.ids)]
+    TestVectorMismatch {
+        /// The index of the first mismatching test vector.
+        index: usize,
+        /// The mismatching test vector.
+        expected: Box<TestVector>,
+        /// The test vector generated for the same text now.
+        actual: Box<TestVector>,
+    },
 }
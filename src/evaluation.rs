@@ -0,0 +1,267 @@
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+use crate::BytePairEncoder;
+
+/// # How well a `BytePairEncoder` tokenizes a corpus, as measured by [`evaluate`].
+///
+/// Sentence markers are not counted as tokens, since every encoder emits the same number of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct EvalMetrics {
+    /// The number of characters in the corpus.
+    pub chars: u64,
+
+    /// The number of tokens produced, excluding sentence markers.
+    pub tokens: u64,
+
+    /// The number of unknown tokens produced.
+    pub unknown_tokens: u64,
+
+    /// The time spent tokenizing the corpus.
+    pub elapsed: Duration,
+}
+
+impl EvalMetrics {
+    /// Returns the fraction of tokens that are the unknown token, or `0.0` if there are no tokens.
+    pub fn unknown_rate(&self) -> f64 {
+        if self.tokens == 0 {
+            0.0
+        } else {
+            self.unknown_tokens as f64 / self.tokens as f64
+        }
+    }
+
+    /// Returns the average number of tokens per character, or `0.0` for an empty corpus. Lower
+    /// values mean the vocabulary covers the corpus with longer tokens.
+    pub fn tokens_per_char(&self) -> f64 {
+        if self.chars == 0 {
+            0.0
+        } else {
+            self.tokens as f64 / self.chars as f64
+        }
+    }
+
+    /// Returns the number of characters tokenized per second, or `0.0` if no time elapsed.
+    pub fn chars_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            0.0
+        } else {
+            self.chars as f64 / seconds
+        }
+    }
+}
+
+/// # Evaluates several encoders on the same corpus.
+///
+/// This helps choose between vocabularies (e.g. the default small, medium, and large ones, or
+/// custom ones) based on data rather than guesses. Each encoder tokenizes the whole corpus once,
+/// and its unknown token rate, tokens per character, and speed are measured along the way.
+///
+/// ## Arguments
+///
+/// * `corpus` - The texts to tokenize, ideally a representative sample of the application's input.
+/// * `encoders` - The encoders to compare.
+///
+/// ## Returns
+///
+/// The metrics of each encoder, in the same order as `encoders`.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{evaluate, BytePairEncoder};
+///
+/// let small = BytePairEncoder::new_from_str("▁\t0\nh\t0\ne\t0\nl\t0\no\t0").unwrap();
+/// let large = BytePairEncoder::new_from_str("▁hello\t1").unwrap();
+///
+/// let results = evaluate(&["Hello, hello!", "Hello world."], &[&small, &large]);
+/// assert!(results[1].tokens_per_char() < results[0].tokens_per_char());
+/// assert!(results[1].unknown_rate() > results[0].unknown_rate());
+/// ```
+pub fn evaluate<S: AsRef<str>>(corpus: &[S], encoders: &[&BytePairEncoder]) -> Vec<EvalMetrics> {
+    let chars: u64 = corpus
+        .iter()
+        .map(|text| text.as_ref().chars().count() as u64)
+        .sum();
+
+    encoders
+        .iter()
+        .map(|encoder| {
+            let special = encoder.special_tokens();
+            let unknown = special.unknown();
+            let mut metrics = EvalMetrics {
+                chars,
+                ..EvalMetrics::default()
+            };
+
+            let started = Instant::now();
+            for text in corpus {
+                for token in encoder.tokenize_iter(text.as_ref()) {
+                    if token == special.sentence_start() || token == special.sentence_end() {
+                        continue;
+                    }
+                    metrics.tokens += 1;
+                    if token == unknown {
+                        metrics.unknown_tokens += 1;
+                    }
+                }
+            }
+            metrics.elapsed = started.elapsed();
+            metrics
+        })
+        .collect()
+}
+
+/// # How closely a `BytePairEncoder` segments words like a reference, as measured by
+/// [`evaluate_segmentation`].
+///
+/// A boundary is a position between two characters of a word where a segmentation starts a new
+/// segment. Boundaries produced by the encoder that are also in the reference are true positives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct SegmentationMetrics {
+    /// The number of words evaluated.
+    pub words: u64,
+
+    /// The number of words segmented exactly like the reference.
+    pub exact_words: u64,
+
+    /// The number of boundaries produced that are in the reference.
+    pub true_positives: u64,
+
+    /// The number of boundaries produced that are not in the reference.
+    pub false_positives: u64,
+
+    /// The number of boundaries in the reference that were not produced.
+    pub false_negatives: u64,
+}
+
+impl SegmentationMetrics {
+    /// Returns the fraction of produced boundaries that are in the reference, or `1.0` if no
+    /// boundaries were produced.
+    pub fn precision(&self) -> f64 {
+        let produced = self.true_positives + self.false_positives;
+        if produced == 0 {
+            1.0
+        } else {
+            self.true_positives as f64 / produced as f64
+        }
+    }
+
+    /// Returns the fraction of reference boundaries that were produced, or `1.0` if the reference
+    /// has no boundaries.
+    pub fn recall(&self) -> f64 {
+        let expected = self.true_positives + self.false_negatives;
+        if expected == 0 {
+            1.0
+        } else {
+            self.true_positives as f64 / expected as f64
+        }
+    }
+
+    /// Returns the harmonic mean of precision and recall, or `0.0` if both are zero.
+    pub fn f1(&self) -> f64 {
+        let (precision, recall) = (self.precision(), self.recall());
+        if precision + recall == 0.0 {
+            0.0
+        } else {
+            2.0 * precision * recall / (precision + recall)
+        }
+    }
+
+    /// Returns the fraction of words segmented exactly like the reference, or `1.0` if no words
+    /// were evaluated, consistently with [`SegmentationMetrics::precision`] and
+    /// [`SegmentationMetrics::recall`]: with nothing to segment, nothing was segmented wrong.
+    pub fn exact_match_rate(&self) -> f64 {
+        if self.words == 0 {
+            1.0
+        } else {
+            self.exact_words as f64 / self.words as f64
+        }
+    }
+}
+
+/// # Compares how an encoder segments words to reference segmentations.
+///
+/// This quantifies vocabulary quality for morphologically rich or low-resource languages, where
+/// a good vocabulary splits words at morpheme boundaries. Each reference is the list of segments
+/// of one word, such as `["un", "believ", "able"]`. The word (the concatenation of its segments)
+/// is tokenized, and the boundaries between its tokens are compared to the boundaries between
+/// the reference segments, by position in the word's original text. Normalization therefore
+/// does not affect the comparison, and an unknown token spans the text it replaces.
+///
+/// ## Arguments
+///
+/// * `encoder` - The encoder whose segmentation is evaluated.
+/// * `references` - The reference segmentation of each word.
+///
+/// ## Returns
+///
+/// The boundary precision and recall, and the exact match rate, as [`SegmentationMetrics`].
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{evaluate_segmentation, BytePairEncoder};
+///
+/// let vocab =
+///     BytePairEncoder::new_from_str("▁un\t1\n▁unbeliev\t1\nbeliev\t1\nable\t1").unwrap();
+/// let references: Vec<Vec<&str>> = ["un+believ+able", "un+able"]
+///     .iter()
+///     .map(|word| word.split('+').collect())
+///     .collect();
+///
+/// let metrics = evaluate_segmentation(&vocab, &references);
+/// assert_eq!(
+///     (metrics.true_positives, metrics.false_positives, metrics.false_negatives),
+///     (2, 0, 1)
+/// );
+/// assert_eq!(metrics.precision(), 1.0);
+/// assert_eq!(metrics.exact_match_rate(), 0.5);
+/// ```
+pub fn evaluate_segmentation<I, W, S>(
+    encoder: &BytePairEncoder,
+    references: I,
+) -> SegmentationMetrics
+where
+    I: IntoIterator<Item = W>,
+    W: AsRef<[S]>,
+    S: AsRef<str>,
+{
+    let mut metrics = SegmentationMetrics::default();
+    for segments in references {
+        let segments = segments.as_ref();
+        let word: String = segments.iter().map(AsRef::as_ref).collect();
+        let inside = |position: &usize| 0 < *position && *position < word.len();
+
+        let expected: BTreeSet<usize> = segments
+            .iter()
+            .scan(0, |end, segment| {
+                *end += segment.as_ref().len();
+                Some(*end)
+            })
+            .filter(inside)
+            .collect();
+        let produced: BTreeSet<usize> = encoder
+            .token_spans(&word)
+            .into_iter()
+            .filter(|(_, range, inserted)| !inserted && !range.is_empty())
+            .flat_map(|(_, range, _)| [range.start, range.end])
+            .filter(inside)
+            .collect();
+
+        let matched = expected.intersection(&produced).count() as u64;
+        metrics.words += 1;
+        if expected == produced {
+            metrics.exact_words += 1;
+        }
+        metrics.true_positives += matched;
+        metrics.false_positives += produced.len() as u64 - matched;
+        metrics.false_negatives += expected.len() as u64 - matched;
+    }
+    metrics
+}
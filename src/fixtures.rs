@@ -0,0 +1,156 @@
+//! # Sample texts with their known-good tokenizations.
+//!
+//! Downstream crates can use these fixtures to check their tokenizer wiring in integration tests:
+//! load a default vocabulary, tokenize each [`Fixture::text`], and compare the result with the
+//! expected tokens for that vocabulary. A mismatch means the encoder was configured differently
+//! (options, special tokens) or the wrong vocabulary was loaded.
+//!
+//! The texts are short and cover several languages and scripts. The expected tokens were produced
+//! with the default options, so they also serve as a regression check for this crate itself.
+//!
+//! ## Example
+//!
+//! ```
+//! # #[cfg(feature = "default-small")] {
+//! use bpe_tokenizer::{fixtures, BytePairEncoder};
+//!
+//! let vocab = BytePairEncoder::new_default_small().unwrap();
+//! for fixture in fixtures::fixtures() {
+//!     assert_eq!(vocab.tokenize(fixture.text), fixture.small, "{}", fixture.name);
+//! }
+//! # }
+//! ```
+
+/// A sample text and its expected tokenization with each default vocabulary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Fixture {
+    /// A short, stable name identifying the fixture.
+    pub name: &'static str,
+
+    /// The text to tokenize.
+    pub text: &'static str,
+
+    /// The expected tokens with [`BytePairEncoder::new_default_small`](crate::BytePairEncoder).
+    pub small: &'static [&'static str],
+
+    /// The expected tokens with [`BytePairEncoder::new_default_medium`](crate::BytePairEncoder).
+    pub medium: &'static [&'static str],
+
+    /// The expected tokens with [`BytePairEncoder::new_default_large`](crate::BytePairEncoder).
+    pub large: &'static [&'static str],
+}
+
+const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "english",
+        text: "The quick brown fox jumps over the lazy dog.",
+        small: &[
+            "<s>", "▁the", "▁quick", "▁brown", "▁fox", "▁jump", "s", "▁over", "▁the", "▁laz", "y",
+            "▁dog", "</s>",
+        ],
+        medium: &[
+            "<s>", "▁the", "▁quick", "▁brown", "▁fox", "▁jump", "s", "▁over", "▁the", "▁laz", "y",
+            "▁dog", "</s>",
+        ],
+        large: &[
+            "<s>", "▁the", "▁quick", "▁brown", "▁fox", "▁jumps", "▁over", "▁the", "▁lazy", "▁dog",
+            "</s>",
+        ],
+    },
+    Fixture {
+        name: "german",
+        text: "Die Straße ist nass, weil es geregnet hat.",
+        small: &[
+            "<s>",
+            "▁die",
+            "▁straße",
+            "▁ist",
+            "▁nass",
+            "▁weil",
+            "▁es",
+            "▁gereg",
+            "net",
+            "▁hat",
+            "</s>",
+        ],
+        medium: &[
+            "<s>",
+            "▁die",
+            "▁straße",
+            "▁ist",
+            "▁nass",
+            "▁weil",
+            "▁es",
+            "▁gereg",
+            "net",
+            "▁hat",
+            "</s>",
+        ],
+        large: &[
+            "<s>",
+            "▁die",
+            "▁straße",
+            "▁ist",
+            "▁nass",
+            "▁weil",
+            "▁es",
+            "▁gereg",
+            "net",
+            "▁hat",
+            "</s>",
+        ],
+    },
+    Fixture {
+        name: "french",
+        text: "Ça va? Très bien, merci!",
+        small: &[
+            "<s>", "▁ça", "▁va", "</s>", "<s>", "▁très", "▁bien", "▁merc", "i", "</s>",
+        ],
+        medium: &[
+            "<s>", "▁ça", "▁va", "</s>", "<s>", "▁très", "▁bien", "▁merci", "</s>",
+        ],
+        large: &[
+            "<s>", "▁ça", "▁va", "</s>", "<s>", "▁très", "▁bien", "▁merci", "</s>",
+        ],
+    },
+    Fixture {
+        name: "russian",
+        text: "Москва — столица России.",
+        small: &["<s>", "▁москва", "▁столица", "▁россии", "</s>"],
+        medium: &["<s>", "▁москва", "▁столица", "▁россии", "</s>"],
+        large: &["<s>", "▁москва", "▁столица", "▁россии", "</s>"],
+    },
+    Fixture {
+        name: "arabic",
+        text: "اللغة العربية جميلة.",
+        small: &["<s>", "▁اللغة", "▁العربية", "▁جم", "يلة", "</s>"],
+        medium: &["<s>", "▁اللغة", "▁العربية", "▁جميل", "ة", "</s>"],
+        large: &["<s>", "▁اللغة", "▁العربية", "▁جميلة", "</s>"],
+    },
+    Fixture {
+        name: "japanese",
+        text: "東京は日本の首都です。",
+        small: &[
+            "<s>", "▁東", "▁", "京", "▁は", "▁日", "▁本", "▁の", "▁", "首", "▁", "都", "▁", "で",
+            "▁", "す", "</s>",
+        ],
+        medium: &[
+            "<s>", "▁東", "▁京", "▁は", "▁日", "▁本", "▁の", "▁", "首", "▁都", "▁で", "▁", "す",
+            "</s>",
+        ],
+        large: &[
+            "<s>", "▁東", "▁京", "▁は", "▁日", "▁本", "▁の", "▁首", "▁都", "▁で", "▁す", "</s>",
+        ],
+    },
+];
+
+/// Returns all fixtures, in a stable order.
+pub fn fixtures() -> &'static [Fixture] {
+    FIXTURES
+}
+
+/// Returns the fixture with the given name, if there is one.
+pub fn fixture(name: &str) -> Option<&'static Fixture> {
+    FIXTURES.iter().find(|fixture| fixture.name == name)
+}
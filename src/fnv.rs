@@ -0,0 +1,36 @@
+/// A 64-bit FNV-1a hasher.
+///
+/// Unlike the standard library's hashers, its output is specified, so hashes computed with it are
+/// identical across runs, platforms, and crate versions, and can be stored.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    /// Creates a hasher with nothing written to it.
+    pub(crate) fn new() -> Self {
+        Fnv1a(Self::OFFSET_BASIS)
+    }
+
+    /// Adds `bytes` to the hash.
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    /// Adds a string to the hash, prefixed with its length so that consecutive strings cannot
+    /// run into each other.
+    pub(crate) fn write_str(&mut self, s: &str) {
+        self.write(&(s.len() as u64).to_le_bytes());
+        self.write(s.as_bytes());
+    }
+
+    /// Returns the hash of everything written so far.
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,247 @@
+use std::{collections::HashMap, iter, mem};
+
+use crate::{
+    json::{parse_json, JsonValue},
+    vocab::Vocab,
+    BytePairEncoderError,
+};
+
+/// The prefix of the header line that starts most `merges.txt` files.
+const MERGES_HEADER: &str = "#version";
+
+/// The contractions that GPT-2's pre-tokenizer splits off as pieces of their own.
+const CONTRACTIONS: [&str; 7] = ["'s", "'t", "'re", "'ve", "'m", "'ll", "'d"];
+
+/// # The ranked merges of a GPT-2 style vocabulary.
+///
+/// A merge joins two adjacent symbols into one, and merges listed earlier are applied first. They
+/// are indexed by their left and then their right symbol, so a pair of adjacent symbols can be
+/// looked up without building a key for it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Merges {
+    ranks: HashMap<String, HashMap<String, usize>>,
+    len: usize,
+}
+
+impl Merges {
+    /// Parses a `merges.txt` file: one merge per line, as its two symbols separated by a single
+    /// space, in rank order. A leading `#version` header and empty lines are skipped.
+    pub(crate) fn parse(input: &str) -> Result<Self, BytePairEncoderError> {
+        let mut merges = Merges::default();
+        for (index, line) in input.lines().enumerate() {
+            if line.is_empty() || (index == 0 && line.starts_with(MERGES_HEADER)) {
+                continue;
+            }
+            match line.split_once(' ') {
+                Some((left, right)) if !left.is_empty() && !right.is_empty() => {
+                    if right.contains(' ') {
+                        return Err(BytePairEncoderError::InvalidMerges { line: index + 1 });
+                    }
+                    merges
+                        .ranks
+                        .entry(left.to_string())
+                        .or_default()
+                        .entry(right.to_string())
+                        .or_insert(merges.len);
+                    merges.len += 1;
+                }
+                _ => return Err(BytePairEncoderError::InvalidMerges { line: index + 1 }),
+            }
+        }
+        Ok(merges)
+    }
+
+    /// Returns the rank of the merge joining `left` and `right`, if there is one. Lower ranks are
+    /// applied first.
+    pub(crate) fn rank(&self, left: &str, right: &str) -> Option<usize> {
+        self.ranks.get(left)?.get(right).copied()
+    }
+
+    /// Returns the merges whose merged symbol `keep` returns `true` for, in the same order.
+    pub(crate) fn filter<F>(&self, mut keep: F) -> Self
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let mut ranks: HashMap<String, HashMap<String, usize>> = HashMap::new();
+        for (left, rights) in &self.ranks {
+            for (right, &rank) in rights {
+                if keep(&format!("{}{}", left, right)) {
+                    ranks
+                        .entry(left.clone())
+                        .or_default()
+                        .insert(right.clone(), rank);
+                }
+            }
+        }
+        Merges {
+            ranks,
+            len: self.len,
+        }
+    }
+
+    /// Returns an estimate of the heap memory used by the merges, in bytes.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.ranks
+            .iter()
+            .map(|(left, rights)| {
+                left.len()
+                    + rights.capacity() * mem::size_of::<(String, usize)>()
+                    + rights.keys().map(String::len).sum::<usize>()
+            })
+            .sum::<usize>()
+            + self.ranks.capacity() * mem::size_of::<(String, HashMap<String, usize>)>()
+    }
+}
+
+/// Parses a `vocab.json` file, an object mapping each token to its ID, into a vocabulary in ID
+/// order.
+///
+/// Tokens are scored by negated ID, as in the HuggingFace conversion, and the IDs must be the
+/// contiguous range `0..n`, since this crate assigns IDs by position.
+pub(crate) fn parse_vocab_json(input: &str) -> Result<Vocab, BytePairEncoderError> {
+    let conversion_error = |reason: String| BytePairEncoderError::ConversionError { reason };
+    let entries = match parse_json(input) {
+        Ok(JsonValue::Object(entries)) => entries,
+        Ok(_) => {
+            return Err(conversion_error(
+                "`vocab.json` is not an object".to_string(),
+            ))
+        }
+        Err(reason) => {
+            return Err(conversion_error(format!(
+                "invalid `vocab.json`: {}",
+                reason
+            )))
+        }
+    };
+
+    let mut ids = Vec::with_capacity(entries.len());
+    for (token, value) in entries {
+        let id = match &value {
+            JsonValue::Number(id) => id.parse::<u32>().ok(),
+            _ => None,
+        };
+        match id {
+            Some(id) => ids.push((token, id)),
+            None => {
+                return Err(conversion_error(format!(
+                    "the ID of token `{}` is not a non-negative integer",
+                    token
+                )))
+            }
+        }
+    }
+    ids.sort_unstable_by_key(|&(_, id)| id);
+    if let Some(position) = ids
+        .iter()
+        .enumerate()
+        .position(|(i, (_, id))| i as u32 != *id)
+    {
+        return Err(conversion_error(format!(
+            "token IDs are not contiguous: ID {} is missing",
+            position
+        )));
+    }
+
+    let mut tokens = Vocab::default();
+    for (token, id) in &ids {
+        tokens.insert(token, -(*id as isize));
+    }
+    Ok(tokens)
+}
+
+/// Returns the character that stands for `byte` in GPT-2's byte-level alphabet.
+///
+/// Printable bytes stand for the characters with the same code point, and the others (controls,
+/// the space, and the soft hyphen) for the characters from `U+0100` on, in byte order, so every
+/// token is printable text.
+pub(crate) fn byte_char(byte: u8) -> char {
+    let index = match byte {
+        b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF => return char::from(byte),
+        0x00..=0x20 => u32::from(byte),
+        0x7F..=0xA0 => 33 + u32::from(byte - 0x7F),
+        _ => 67,
+    };
+    char::from_u32(0x100 + index).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// Returns the byte that `c` stands for in GPT-2's byte-level alphabet, if it is part of it.
+pub(crate) fn char_byte(c: char) -> Option<u8> {
+    let code = u32::from(c);
+    match code {
+        0x21..=0x7E | 0xA1..=0xAC | 0xAE..=0xFF => Some(code as u8),
+        0x100..=0x120 => Some((code - 0x100) as u8),
+        0x121..=0x142 => Some((code - 0x121) as u8 + 0x7F),
+        0x143 => Some(0xAD),
+        _ => None,
+    }
+}
+
+/// The classes of characters that GPT-2's pre-tokenizer keeps together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Letter,
+    Number,
+    Space,
+    Other,
+}
+
+impl CharClass {
+    fn of(c: char) -> Self {
+        if c.is_alphabetic() {
+            CharClass::Letter
+        } else if c.is_numeric() {
+            CharClass::Number
+        } else if c.is_whitespace() {
+            CharClass::Space
+        } else {
+            CharClass::Other
+        }
+    }
+}
+
+/// Splits `text` into the pieces GPT-2's pre-tokenizer matches, one after the other, with the
+/// pattern `'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+`.
+///
+/// Contractions are split off, runs of letters, numbers, and other symbols are pieces of their
+/// own, each taking a single preceding space, and runs of whitespace are pieces too, except for
+/// their last character when a word follows, which goes with that word. Letters are the
+/// characters of `char::is_alphabetic`, which also counts letter-like numbers and marks.
+pub(crate) fn pretokenize(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    iter::from_fn(move || {
+        let len = piece_len(rest)?;
+        let (piece, tail) = rest.split_at(len);
+        rest = tail;
+        Some(piece)
+    })
+}
+
+/// Returns the length in bytes of the piece [`pretokenize`] splits off the start of `text`, or
+/// `None` if `text` is empty.
+fn piece_len(text: &str) -> Option<usize> {
+    text.chars().next()?;
+    if let Some(contraction) = CONTRACTIONS.iter().find(|&&c| text.starts_with(c)) {
+        return Some(contraction.len());
+    }
+
+    let body = text.strip_prefix(' ').unwrap_or(text);
+    if let Some(class) = body.chars().next().map(CharClass::of) {
+        if class != CharClass::Space {
+            let run = body
+                .find(|c| CharClass::of(c) != class)
+                .unwrap_or(body.len());
+            return Some(text.len() - body.len() + run);
+        }
+    }
+
+    let run = text
+        .find(|c: char| !c.is_whitespace())
+        .unwrap_or(text.len());
+    let last = text[..run].chars().next_back().map_or(0, char::len_utf8);
+    Some(if run < text.len() && run > last {
+        run - last
+    } else {
+        run
+    })
+}
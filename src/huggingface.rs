@@ -0,0 +1,316 @@
+use std::convert::TryFrom;
+
+use tokenizers::{
+    decoders::{
+        byte_fallback::ByteFallback, fuse::Fuse, sequence::Sequence as DecoderSequence,
+        DecoderWrapper,
+    },
+    models::bpe::{Vocab as HfVocab, BPE},
+    normalizers::{utils::Lowercase, NormalizerWrapper, Replace},
+    pre_tokenizers::{
+        metaspace::{Metaspace, PrependScheme},
+        sequence::Sequence as PreTokenizerSequence,
+        split::{Split, SplitPattern},
+        PreTokenizerWrapper,
+    },
+    processors::template::TemplateProcessing,
+    AddedToken, SplitDelimiterBehavior, Tokenizer,
+};
+
+use crate::{
+    vocab::Vocab, BytePairEncoder, BytePairEncoderError, EncoderOptions, PunctuationPolicy,
+    SpecialTokenOptions, TextMode,
+};
+
+/// The words kept by `TextMode::Natural`, approximating the Unicode word boundaries (UAX #29)
+/// this crate splits on: each ideograph, kana, or Southeast Asian letter is a word of its own
+/// (with its combining marks), and other runs of word characters are joined across `.`, `'` and
+/// `’`, across `:` and `·` between letters, and across `,` and `;` between digits, so `don't`,
+/// `U.S` and `3.14` stay whole.
+macro_rules! natural_words {
+    () => {
+        concat!(
+            r"[\p{Han}\p{Hiragana}\p{Thai}\p{Lao}\p{Khmer}\p{Myanmar}]\p{M}*",
+            r"|[^\W\p{Han}\p{Hiragana}\p{Thai}\p{Lao}\p{Khmer}\p{Myanmar}]+",
+            r"(?:(?:(?<=\p{L})[.'’:·](?=\p{L})|(?<=\d)[.'’,;](?=\d))",
+            r"[^\W\p{Han}\p{Hiragana}\p{Thai}\p{Lao}\p{Khmer}\p{Myanmar}]+)*",
+        )
+    };
+}
+
+/// The words kept by `TextMode::Natural`.
+const NATURAL_WORDS: &str = natural_words!();
+
+/// The words kept by `TextMode::Code` and `PunctuationPolicy::Keep`: the words of
+/// `TextMode::Natural`, and single symbols.
+const CODE_WORDS: &str = concat!(natural_words!(), r"|[^\w\s]");
+
+/// # Converts a `BytePairEncoder` into a HuggingFace `tokenizers` BPE model.
+///
+/// The model's vocabulary is the encoder's ID table (see
+/// [`BytePairEncoder::export_id_table`]), so token IDs agree between the two. Merges are
+/// reconstructed from the vocabulary, the same way `tokenizers` converts SentencePiece BPE models:
+/// every way of splitting a token into two vocabulary tokens becomes a merge, ranked by the score
+/// of the merged token (highest first), then by the rank of its parts.
+///
+/// Only the model is converted, with byte fallback enabled for `TextMode::Lossless`. Lowercasing,
+/// the `▁` word prefix, and sentence markers are done by this crate before and after matching,
+/// and correspond to a normalizer, pre-tokenizer, and post-processor on the `tokenizers` side;
+/// convert to a full `tokenizers::Tokenizer` to get those as well. Merge-based BPE also does not
+/// always pick the same split of a word as this crate's longest-match search, so outputs should be
+/// cross-validated rather than assumed identical.
+///
+/// ## Errors
+///
+/// Returns [`BytePairEncoderError::ConversionError`] if `tokenizers` rejects the model.
+///
+/// ## Example
+///
+/// ```
+/// use std::convert::TryFrom;
+///
+/// use bpe_tokenizer::BytePairEncoder;
+/// use tokenizers::{models::bpe::BPE, Model};
+///
+/// let vocab = BytePairEncoder::new_from_str("▁\t0\nh\t-1\ni\t-2\n▁h\t-3\n▁hi\t-4").unwrap();
+/// let model = BPE::try_from(&vocab).unwrap();
+/// assert_eq!(model.token_to_id("▁hi"), vocab.token_to_id("▁hi"));
+///
+/// let tokens: Vec<String> = model.tokenize("▁hi").unwrap().into_iter().map(|t| t.value).collect();
+/// assert_eq!(tokens, vec!["▁hi"]);
+/// ```
+impl TryFrom<&BytePairEncoder> for BPE {
+    type Error = BytePairEncoderError;
+
+    fn try_from(encoder: &BytePairEncoder) -> Result<Self, Self::Error> {
+        let table = encoder.export_id_table();
+        let vocab: HfVocab = table
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as u32))
+            .collect();
+
+        let merges = encoder
+            .tokens
+            .merges()
+            .into_iter()
+            .map(|(left, right)| (left.to_string(), right.to_string()))
+            .collect();
+
+        let mut builder = BPE::builder()
+            .vocab_and_merges(vocab, merges)
+            .byte_fallback(encoder.options.mode == TextMode::Lossless);
+        if encoder.unknown_token_id().is_some() {
+            builder = builder.unk_token(encoder.unknown_token().to_string());
+        }
+        builder
+            .build()
+            .map_err(|e| BytePairEncoderError::ConversionError {
+                reason: e.to_string(),
+            })
+    }
+}
+
+/// # Converts a `BytePairEncoder` into a complete HuggingFace `tokenizers` tokenizer.
+///
+/// The model is converted as by `BPE::try_from`, and the encoder's options are translated into
+/// the surrounding pipeline, so a `tokenizer.json` saved from the result approximates this
+/// crate's tokenization elsewhere:
+///
+/// - Normalizer: lowercasing, for `TextMode::Natural`.
+/// - Pre-tokenizer: splitting into words, dropping punctuation for `TextMode::Natural` unless
+///   `PunctuationPolicy::Keep` is set, and otherwise keeping each symbol as a word, then a
+///   `Metaspace` adding the `▁` word prefix. The words are matched with a regular expression
+///   covering the common cases of the Unicode word boundaries this crate uses (numbers such as
+///   `3.14`, contractions, and ideographs as single words), not all of them.
+///   For `TextMode::Lossless`, the `Metaspace` only turns spaces into `▁` and splits before them,
+///   so words not preceded by a space get no prefix, and the model falls back to bytes.
+/// - Special tokens: the special tokens with IDs, so decoding can skip them.
+/// - Post-processor: the sentence start and end markers around each sequence, if both have IDs.
+/// - Decoder: the inverse of the pre-tokenizer, including byte fallback.
+///
+/// Some behavior has no counterpart in `tokenizers` and is not exported: sentence splitting (the
+/// markers wrap the whole input instead of each sentence), the case markers of
+/// `TextMode::Lossless`, the identifier splitting of `TextMode::Code`, the text normalization
+/// options (line breaks, invisible characters, hyphenation, numbers, confusables), and
+/// `SpecialTextPolicy` (`tokenizers` always matches special tokens spelled out in the input).
+/// Together with the merge-based matching of the model, this means outputs should be
+/// cross-validated, e.g. against [`BytePairEncoder::generate_test_vectors`], rather than assumed
+/// identical.
+///
+/// ## Errors
+///
+/// Returns [`BytePairEncoderError::ConversionError`] if `tokenizers` rejects the model or a
+/// pipeline component.
+///
+/// ## Example
+///
+/// ```
+/// use std::convert::TryFrom;
+///
+/// use bpe_tokenizer::BytePairEncoder;
+/// use tokenizers::Tokenizer;
+///
+/// let vocab = BytePairEncoder::new_from_str(
+///     "<s>\t0\n</s>\t0\n▁\t0\nh\t-1\ni\t-2\n▁h\t-3\n▁hi\t-4"
+/// ).unwrap();
+/// let tokenizer = Tokenizer::try_from(&vocab).unwrap();
+///
+/// let encoding = tokenizer.encode("Hi, hi!", true).unwrap();
+/// assert_eq!(encoding.get_tokens(), vocab.tokenize("Hi, hi!"));
+/// assert_eq!(tokenizer.decode(encoding.get_ids(), true).unwrap(), "hi hi");
+/// ```
+impl TryFrom<&BytePairEncoder> for Tokenizer {
+    type Error = BytePairEncoderError;
+
+    fn try_from(encoder: &BytePairEncoder) -> Result<Self, Self::Error> {
+        let mode = encoder.options.mode;
+        let word_break = encoder.special_tokens().word_break();
+        let word_break_char = word_break.chars().next().unwrap_or(' ');
+        let mut tokenizer = Tokenizer::new(BPE::try_from(encoder)?);
+
+        if mode == TextMode::Natural {
+            tokenizer.with_normalizer(Some(NormalizerWrapper::Lowercase(Lowercase)));
+        }
+
+        let pre_tokenizer = match mode {
+            TextMode::Natural | TextMode::Code => {
+                let words = if mode == TextMode::Natural
+                    && encoder.options.punctuation == PunctuationPolicy::Drop
+                {
+                    NATURAL_WORDS
+                } else {
+                    CODE_WORDS
+                };
+                let words = Split::new(
+                    SplitPattern::Regex(words.to_string()),
+                    SplitDelimiterBehavior::Removed,
+                    true,
+                )
+                .map_err(conversion_error)?;
+                PreTokenizerWrapper::Sequence(PreTokenizerSequence::new(vec![
+                    words.into(),
+                    Metaspace::new(word_break_char, PrependScheme::Always, false).into(),
+                ]))
+            }
+            TextMode::Lossless => {
+                Metaspace::new(word_break_char, PrependScheme::Never, true).into()
+            }
+        };
+        tokenizer.with_pre_tokenizer(Some(pre_tokenizer));
+
+        let special = encoder.special_tokens();
+        let added: Vec<AddedToken> = special
+            .iter()
+            .filter(|token| encoder.token_to_id(token).is_some())
+            .map(|token| AddedToken::from(token.to_string(), true))
+            .collect();
+        tokenizer.add_special_tokens(&added);
+
+        if let (Some(start_id), Some(end_id)) = (
+            encoder.token_to_id(special.sentence_start()),
+            encoder.token_to_id(special.sentence_end()),
+        ) {
+            let (start, end) = (special.sentence_start(), special.sentence_end());
+            let processor = TemplateProcessing::builder()
+                .try_single(format!("{} $A {}", start, end))
+                .and_then(|builder| {
+                    builder.try_pair(format!("{} $A {} {} $B {}", start, end, start, end))
+                })
+                .map_err(|reason| BytePairEncoderError::ConversionError { reason })?
+                .special_tokens(vec![(start, start_id), (end, end_id)])
+                .build()
+                .map_err(conversion_error)?;
+            tokenizer.with_post_processor(Some(processor));
+        }
+
+        let decoder: DecoderWrapper = match mode {
+            TextMode::Natural | TextMode::Code => {
+                Metaspace::new(word_break_char, PrependScheme::Always, false).into()
+            }
+            TextMode::Lossless => DecoderSequence::new(vec![
+                Replace::new(word_break, " ")
+                    .map_err(conversion_error)?
+                    .into(),
+                ByteFallback::new().into(),
+                Fuse::new().into(),
+            ])
+            .into(),
+        };
+        tokenizer.with_decoder(Some(decoder));
+
+        Ok(tokenizer)
+    }
+}
+
+/// Wraps an error from `tokenizers` into a [`BytePairEncoderError::ConversionError`].
+fn conversion_error<E: ToString>(error: E) -> BytePairEncoderError {
+    BytePairEncoderError::ConversionError {
+        reason: error.to_string(),
+    }
+}
+
+/// # Converts a HuggingFace `tokenizers` BPE model into a `BytePairEncoder`.
+///
+/// Tokens keep their IDs, and are scored by negated ID, so tokens that `tokenizers` ranks earlier
+/// (typically the results of earlier, more frequent merges) win ties in the longest-match search.
+/// The merges themselves are not needed, and the model's unknown token, if any, becomes the
+/// encoder's unknown token. Sentence markers keep their defaults.
+///
+/// ## Errors
+///
+/// Returns [`BytePairEncoderError::ConversionError`] if the model's IDs are not the contiguous
+/// range `0..n`, since this crate assigns IDs by position.
+///
+/// ## Example
+///
+/// ```
+/// use std::convert::TryFrom;
+///
+/// use bpe_tokenizer::BytePairEncoder;
+/// use tokenizers::models::bpe::{Vocab, BPE};
+///
+/// let ids: Vocab = [("<unk>".to_string(), 0), ("▁hi".to_string(), 1)].into_iter().collect();
+/// let model = BPE::builder()
+///     .vocab_and_merges(ids, vec![])
+///     .unk_token("<unk>".to_string())
+///     .build()
+///     .unwrap();
+/// let vocab = BytePairEncoder::try_from(&model).unwrap();
+/// assert_eq!(vocab.token_to_id("▁hi"), Some(1));
+/// assert_eq!(vocab.tokenize("Hi, ho"), vec!["<s>", "▁hi", "<unk>", "</s>"]);
+/// ```
+impl TryFrom<&BPE> for BytePairEncoder {
+    type Error = BytePairEncoderError;
+
+    fn try_from(model: &BPE) -> Result<Self, Self::Error> {
+        let mut entries: Vec<(String, u32)> = model.get_vocab().into_iter().collect();
+        entries.sort_unstable_by_key(|&(_, id)| id);
+        if let Some(position) = entries
+            .iter()
+            .enumerate()
+            .position(|(i, (_, id))| i as u32 != *id)
+        {
+            return Err(BytePairEncoderError::ConversionError {
+                reason: format!("token IDs are not contiguous: ID {} is missing", position),
+            });
+        }
+
+        let mut tokens = Vocab::default();
+        for (token, id) in &entries {
+            tokens.insert(token, -(*id as isize));
+        }
+
+        let encoder = BytePairEncoder::from_vocab(tokens);
+        Ok(match model.get_unk_token() {
+            Some(unknown) => encoder.with_options(EncoderOptions {
+                special_tokens: SpecialTokenOptions {
+                    unknown: Some(unknown.clone()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            None => encoder,
+        })
+    }
+}
@@ -0,0 +1,185 @@
+use std::io::{self, Read, Write};
+
+/// The bytes every encoded ID sequence starts with.
+const MAGIC: &[u8; 4] = b"BPEI";
+
+/// The version of the encoding, written after [`MAGIC`].
+const VERSION: u8 = 1;
+
+/// Set in the flags byte if IDs are stored as differences from the previous ID.
+const FLAG_DELTA: u8 = 1;
+
+/// # Options controlling how [`write_ids`] encodes token IDs.
+///
+/// IDs are always stored as LEB128 varints, so the frequent tokens of a vocabulary sorted by
+/// frequency (small IDs) take one or two bytes instead of four. The options only add transforms
+/// on top; [`read_ids`] detects them from the data, so they need not be known when reading.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::IdStreamOptions;
+///
+/// let mut options = IdStreamOptions::default();
+/// options.delta = true;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct IdStreamOptions {
+    /// Whether each ID is stored as its (zigzag-encoded) difference from the previous ID. Disabled
+    /// by default.
+    ///
+    /// This pays off for sequences where neighboring IDs are close, such as the sorted IDs of a
+    /// bag of words or the output of a vocabulary ordered by similarity, and costs a little for
+    /// ordinary token streams.
+    pub delta: bool,
+}
+
+/// # Writes a sequence of token IDs in a compact binary encoding.
+///
+/// The encoding is a 4-byte magic number (`BPEI`), a version byte, a flags byte, the number of IDs
+/// as a varint, then one varint per ID. Sequences are self-delimiting, so a corpus can be stored
+/// by writing one sequence per document to the same writer and reading them back with repeated
+/// calls to [`read_ids`]. The output is byte-aligned and compresses well with a general-purpose
+/// compressor layered on top.
+///
+/// ## Arguments
+///
+/// * `writer` - Where to write the encoded IDs.
+/// * `ids` - The token IDs to write, e.g. from
+///   [`BytePairEncoder::encode`](crate::BytePairEncoder::encode).
+/// * `options` - The transforms to apply.
+///
+/// ## Returns
+///
+/// An `io::Result` indicating whether the IDs were written.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{read_ids, write_ids, IdStreamOptions};
+///
+/// let mut bytes = vec![];
+/// write_ids(&mut bytes, &[3, 140, 70_000], &IdStreamOptions::default()).unwrap();
+/// write_ids(&mut bytes, &[], &IdStreamOptions::default()).unwrap();
+/// assert_eq!(bytes.len(), 2 * 7 + 1 + 2 + 3);
+///
+/// let mut reader = bytes.as_slice();
+/// assert_eq!(read_ids(&mut reader).unwrap(), vec![3, 140, 70_000]);
+/// assert_eq!(read_ids(&mut reader).unwrap(), Vec::<u32>::new());
+/// ```
+pub fn write_ids<W: Write>(
+    mut writer: W,
+    ids: &[u32],
+    options: &IdStreamOptions,
+) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 2 + 10 + ids.len() * 2);
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.push(if options.delta { FLAG_DELTA } else { 0 });
+    push_varint(&mut bytes, ids.len() as u64);
+
+    let mut previous = 0;
+    for &id in ids {
+        if options.delta {
+            push_varint(&mut bytes, zigzag(i64::from(id) - i64::from(previous)));
+            previous = id;
+        } else {
+            push_varint(&mut bytes, u64::from(id));
+        }
+    }
+    writer.write_all(&bytes)
+}
+
+/// # Reads a sequence of token IDs written by [`write_ids`].
+///
+/// Exactly the bytes of one sequence are consumed, so the reader is positioned at the next
+/// sequence afterwards. Reading one byte at a time, wrapping unbuffered readers such as files in a
+/// `BufReader` is much faster.
+///
+/// ## Arguments
+///
+/// * `reader` - Where to read the encoded IDs from.
+///
+/// ## Returns
+///
+/// An `io::Result` with the IDs. Fails with `io::ErrorKind::UnexpectedEof` if the reader is
+/// exhausted, including when it has no further sequence, and with `io::ErrorKind::InvalidData` if
+/// the data is not an ID sequence of a supported version or an ID does not fit in a `u32`.
+pub fn read_ids<R: Read>(mut reader: R) -> io::Result<Vec<u32>> {
+    let mut header = [0; 6];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(invalid_data("not a token ID sequence"));
+    }
+    if header[4] != VERSION {
+        return Err(invalid_data(format!(
+            "unsupported token ID sequence version {}",
+            header[4]
+        )));
+    }
+    let flags = header[5];
+    if flags & !FLAG_DELTA != 0 {
+        return Err(invalid_data(format!(
+            "unknown token ID sequence flags {:#04x}",
+            flags
+        )));
+    }
+
+    let count = read_varint(&mut reader)?;
+    // The count is untrusted, so only a bounded capacity is reserved up front.
+    let mut ids = Vec::with_capacity(count.min(1 << 16) as usize);
+    let mut previous = 0;
+    for _ in 0..count {
+        let value = read_varint(&mut reader)?;
+        let id = if flags & FLAG_DELTA != 0 {
+            i64::from(previous).checked_add(unzigzag(value))
+        } else {
+            Some(value as i64)
+        };
+        let id = id
+            .and_then(|id| u32::try_from(id).ok())
+            .ok_or_else(|| invalid_data("token ID out of range"))?;
+        ids.push(id);
+        previous = id;
+    }
+    Ok(ids)
+}
+
+/// Appends `value` as an unsigned LEB128 varint.
+fn push_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Reads an unsigned LEB128 varint of at most 64 bits.
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("varint longer than 64 bits"))
+}
+
+/// Maps a signed value to an unsigned one, with small magnitudes staying small.
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// The inverse of [`zigzag`].
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Creates an `io::ErrorKind::InvalidData` error.
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
@@ -0,0 +1,242 @@
+//! # Lightweight script detection for choosing between encoders.
+//!
+//! Per-language vocabularies tokenize their language better than a multilingual one, but the
+//! right one has to be picked for each input. [`detect_script`] finds the dominant writing system
+//! of a text by counting the letters of each script, which is cheap and needs no models, and
+//! [`EncoderSelector`] uses it to route text to the encoder configured for that script.
+//!
+//! Detection works at the level of scripts, not languages: English and French are both
+//! [`Script::Latin`], for example.
+//!
+//! [`tokenize_sentences_tagged`] tags each tokenized sentence with its script instead, so
+//! multilingual corpus pipelines can route or filter sentences without another pass over the text.
+//!
+//! ## Example
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use bpe_tokenizer::{
+//!     language::{detect_script, EncoderSelector, Script},
+//!     BytePairEncoder,
+//! };
+//!
+//! assert_eq!(detect_script("Привет, мир!"), Some(Script::Cyrillic));
+//!
+//! let latin = Arc::new(BytePairEncoder::new_from_str("▁hello\t0").unwrap());
+//! let cyrillic = Arc::new(BytePairEncoder::new_from_str("▁привет\t0").unwrap());
+//! let selector = EncoderSelector::new(Arc::clone(&latin)).with(Script::Cyrillic, cyrillic);
+//!
+//! assert_eq!(selector.tokenize("Привет"), vec!["<s>", "▁привет", "</s>"]);
+//! assert_eq!(selector.tokenize("Hello"), vec!["<s>", "▁hello", "</s>"]);
+//! ```
+
+use std::{borrow::Cow, ops::Range, sync::Arc};
+
+use crate::BytePairEncoder;
+
+/// # A writing system recognized by [`detect_script`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Script {
+    /// Latin, including its extensions (English, French, Vietnamese, ...).
+    Latin,
+    /// Greek.
+    Greek,
+    /// Cyrillic (Russian, Ukrainian, Bulgarian, ...).
+    Cyrillic,
+    /// Armenian.
+    Armenian,
+    /// Hebrew (Hebrew, Yiddish).
+    Hebrew,
+    /// Arabic (Arabic, Persian, Urdu, ...).
+    Arabic,
+    /// Devanagari (Hindi, Marathi, Nepali, ...).
+    Devanagari,
+    /// Bengali (Bengali, Assamese).
+    Bengali,
+    /// Thai.
+    Thai,
+    /// Georgian.
+    Georgian,
+    /// Hangul (Korean).
+    Hangul,
+    /// Hiragana and katakana (Japanese). Japanese text counts as this script when it contains any
+    /// kana, even if most of it is written with Han characters.
+    Kana,
+    /// Han characters (Chinese, and Japanese without kana).
+    Han,
+}
+
+impl Script {
+    /// Returns the script of `c`, or `None` if it is not a letter of a recognized script.
+    pub fn of(c: char) -> Option<Script> {
+        let script = match c as u32 {
+            0x0041..=0x005A | 0x0061..=0x007A | 0x00C0..=0x024F | 0x1E00..=0x1EFF => Script::Latin,
+            0x0370..=0x03FF | 0x1F00..=0x1FFF => Script::Greek,
+            0x0400..=0x052F | 0x2DE0..=0x2DFF | 0xA640..=0xA69F => Script::Cyrillic,
+            0x0530..=0x058F => Script::Armenian,
+            0x0590..=0x05FF => Script::Hebrew,
+            0x0600..=0x06FF
+            | 0x0750..=0x077F
+            | 0x08A0..=0x08FF
+            | 0xFB50..=0xFDFF
+            | 0xFE70..=0xFEFF => Script::Arabic,
+            0x0900..=0x097F => Script::Devanagari,
+            0x0980..=0x09FF => Script::Bengali,
+            0x0E00..=0x0E7F => Script::Thai,
+            0x10A0..=0x10FF => Script::Georgian,
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Script::Hangul,
+            0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => Script::Kana,
+            0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F => Script::Han,
+            _ => return None,
+        };
+        if c.is_alphabetic() {
+            Some(script)
+        } else {
+            None
+        }
+    }
+}
+
+/// # Detects the dominant script of a text.
+///
+/// Letters are counted per script, and the script with the most letters wins (ties go to the
+/// script listed first in [`Script`]). As an exception, text with any kana is reported as
+/// [`Script::Kana`], since Japanese mixes kana with Han characters.
+///
+/// ## Arguments
+///
+/// * `text` - The text to inspect. For long texts, a prefix is usually enough.
+///
+/// ## Returns
+///
+/// The dominant script, or `None` if the text has no letters of a recognized script.
+pub fn detect_script(text: &str) -> Option<Script> {
+    let mut counts: Vec<(Script, usize)> = vec![];
+    for script in text.chars().filter_map(Script::of) {
+        match counts.iter_mut().find(|(s, _)| *s == script) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((script, 1)),
+        }
+    }
+
+    if counts.iter().any(|(script, _)| *script == Script::Kana) {
+        return Some(Script::Kana);
+    }
+    counts.sort();
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(script, _)| script)
+}
+
+/// # Routes text to the encoder configured for its dominant script.
+///
+/// Text whose script has no encoder configured, or with no recognizable script at all, goes to
+/// the fallback encoder (e.g. a multilingual default vocabulary).
+#[derive(Debug, Clone)]
+pub struct EncoderSelector {
+    fallback: Arc<BytePairEncoder>,
+    encoders: Vec<(Script, Arc<BytePairEncoder>)>,
+}
+
+impl EncoderSelector {
+    /// # Creates a selector that sends all text to `fallback`.
+    pub fn new(fallback: Arc<BytePairEncoder>) -> Self {
+        EncoderSelector {
+            fallback,
+            encoders: vec![],
+        }
+    }
+
+    /// # Configures the encoder for a script, replacing any previous one.
+    pub fn with(mut self, script: Script, encoder: Arc<BytePairEncoder>) -> Self {
+        self.encoders.retain(|(s, _)| *s != script);
+        self.encoders.push((script, encoder));
+        self
+    }
+
+    /// # Returns the encoder configured for the dominant script of `text`.
+    pub fn select(&self, text: &str) -> &BytePairEncoder {
+        detect_script(text)
+            .and_then(|script| self.encoders.iter().find(|(s, _)| *s == script))
+            .map_or(&self.fallback, |(_, encoder)| encoder)
+    }
+
+    /// # Tokenizes `text` with the encoder selected for it.
+    ///
+    /// See [`BytePairEncoder::tokenize`].
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        self.select(text).tokenize(text)
+    }
+}
+
+/// # A tokenized sentence, tagged with its script.
+///
+/// Created by [`tokenize_sentences_tagged`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct TaggedSentence {
+    /// The tokens of the sentence, including its markers, as in
+    /// [`BytePairEncoder::tokenize_sentences`].
+    pub tokens: Vec<String>,
+
+    /// The byte range of the sentence in the text it was tokenized from.
+    pub range: Range<usize>,
+
+    /// The dominant script of the sentence (see [`detect_script`]), or `None` if it has no letters
+    /// of a recognized script.
+    pub script: Option<Script>,
+}
+
+/// # Tokenizes a text into sentences, tagging each with its dominant script.
+///
+/// The sentences are those of [`BytePairEncoder::tokenize_sentences`], and each is tagged by
+/// [`detect_script`] over its own text, so a text mixing languages gets a tag per sentence. A
+/// sentence that `EncoderOptions::max_sentence_tokens` splits into several yields one
+/// [`TaggedSentence`] per part, all with the range and script of the whole sentence.
+///
+/// ## Arguments
+///
+/// * `encoder` - The encoder to tokenize with.
+/// * `text` - A string slice containing the text to be tokenized.
+///
+/// ## Returns
+///
+/// A `Vec` with a [`TaggedSentence`] for each sentence.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{
+///     language::{tokenize_sentences_tagged, Script},
+///     BytePairEncoder,
+/// };
+///
+/// let vocab = BytePairEncoder::new_from_str("▁hello\t0\n▁привет\t0").unwrap();
+/// let sentences = tokenize_sentences_tagged(&vocab, "Hello! Привет!");
+/// assert_eq!(sentences[0].tokens, vec!["<s>", "▁hello", "</s>"]);
+/// assert_eq!(sentences[0].script, Some(Script::Latin));
+/// assert_eq!(sentences[1].tokens, vec!["<s>", "▁привет", "</s>"]);
+/// assert_eq!(sentences[1].script, Some(Script::Cyrillic));
+/// assert_eq!(sentences[1].range, 7..20);
+/// ```
+pub fn tokenize_sentences_tagged(encoder: &BytePairEncoder, text: &str) -> Vec<TaggedSentence> {
+    let offset = |slice: &str| slice.as_ptr() as usize - text.as_ptr() as usize;
+    encoder
+        .split_sentences(text)
+        .flat_map(|sentence| {
+            let range = offset(sentence)..offset(sentence) + sentence.len();
+            let script = detect_script(sentence);
+            encoder
+                .tokenize_sentence(sentence)
+                .map(move |tokens| TaggedSentence {
+                    tokens: tokens.map(Cow::into_owned).collect(),
+                    range: range.clone(),
+                    script,
+                })
+        })
+        .collect()
+}
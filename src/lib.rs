@@ -32,6 +32,21 @@
 //! - [`BytePairEncoder::new_from_file`]: Create a `BytePairEncoder` from a file.
 //! - [`BytePairEncoder::new_from_str`]: Create a `BytePairEncoder` from a string.
 //!
+//! `BytePairEncoder` also implements `FromStr` and `Display` for the same format, so a vocabulary
+//! can be parsed with `str::parse` and written back out with `to_string`, or exported in a chosen
+//! [`VocabOrder`] with [`BytePairEncoder::write_vocab`]. Descriptive [`Metadata`] (name, version,
+//! license, ...) attached with [`BytePairEncoder::with_metadata`] is saved and restored with it.
+//! To check a file before loading it, [`BytePairEncoder::peek_vocab_file`] reads only its first
+//! tokens. Messy files can be loaded with [`BytePairEncoder::new_from_file_lenient`], which skips
+//! malformed lines and reports them in a [`ParseReport`]. Customizations of a large vocabulary can
+//! ship as a small overlay of added tokens and score overrides, applied with
+//! [`BytePairEncoder::new_with_overlay`].
+//! Services that update their vocabulary while running can wrap the encoder in a
+//! [`ReloadableEncoder`], which swaps in a new vocabulary file atomically.
+//! Multi-tenant services can keep one encoder per language or domain in a [`TokenizerPool`],
+//! which selects them by name, loads them on demand within a memory limit, and shares one word
+//! cache between them.
+//!
 //! The crate also includes default token vocabularies which support 275 languages. These are
 //! disabled by default and can be enabled with the "default-{small,medium,large}" features.
 //!
@@ -51,6 +66,46 @@
 //!
 //! - [`BytePairEncoder::tokenize`]: Tokenize text into a flat vector of BPE tokens.
 //! - [`BytePairEncoder::tokenize_sentences`]: Tokenize text into nested vectors of sentences and tokens.
+//! - [`BytePairEncoder::tokenize_batch`]: Tokenize many texts in parallel, across texts and
+//!   across the sentences of long texts.
+//! - [`BytePairEncoder::decode`]: Decode token IDs back into text, optionally keeping special
+//!   tokens with [`BytePairEncoder::decode_with_special_tokens`].
+//! - [`BytePairEncoder::detokenize`]: Reconstruct text from the tokens returned by `tokenize`.
+//! - [`BytePairEncoder::decode_batch`]: Decode many token ID sequences back into text, reusing
+//!   buffers across the batch (or in parallel, with [`BytePairEncoder::decode_batch_parallel`]).
+//! - [`BytePairEncoder::tokenize_with_boundaries`]: Tokenize text without letting tokens span
+//!   caller-supplied offsets, such as the edges of annotated entities.
+//! - [`BytePairEncoder::tokenize_pretokenized`]: Tokenize words already segmented by an upstream
+//!   NLP pipeline, skipping sentence and word segmentation.
+//! - [`BytePairEncoder::tokenize_max_bytes`]: Tokenize the beginning of a text, up to a limit on
+//!   its decoded length in bytes.
+//! - [`BytePairEncoder::split_by_token_budget`]: Split a long text into slices that each fit into
+//!   a token budget, cutting at sentence boundaries where possible.
+//! - [`BytePairEncoder::sentence_hashes`] and [`BytePairEncoder::dedup_sentences`]: Hash the
+//!   tokens of each sentence, and deduplicate a corpus by sentence.
+//! - [`BytePairEncoder::merge_word_pieces`]: Merge tokens back into whole words, keeping special
+//!   tokens as they are.
+//! - [`BytePairEncoder::collapse_runs`]: Store long runs of the same token as the token and a
+//!   count, for logging and analysis.
+//! - [`BytePairEncoder::encode_full`]: Tokenize text into an [`Encoding`] with tokens, IDs, byte
+//!   and character offsets, special token flags, and an attention mask, in one pass.
+//! - [`BytePairEncoder::tokenize_with_offsets`]: Tokenize text, pairing each token with the byte
+//!   range of the text it came from.
+//! - [`BytePairEncoder::tokenize_with_lengths`]: Tokenize text, measuring each token in bytes,
+//!   characters, and graphemes (see [`TokenLengths`]).
+//! - [`BytePairEncoder::normalize`]: Normalize text the way words are matched, with a map from
+//!   normalized offsets back to the original text.
+//! - [`BytePairEncoder::find_similar_tokens`]: Find the vocabulary tokens closest to a query by
+//!   edit distance, e.g. to resolve misspelled special tokens.
+//! - [`BytePairEncoder::is_lossless_for`]: Check that a text decodes back from its tokens
+//!   unchanged.
+//! - [`BytePairEncoder::uncovered_spans`]: Find the parts of a text that the vocabulary cannot
+//!   cover, as byte ranges of the original text.
+//! - [`BytePairEncoder::mask_spans`]: Replace the tokens of given character spans of a text with
+//!   the mask token, keeping the rest aligned, to build masked language model training data.
+//! - [`BytePairEncoder::redact`]: Replace the tokens of sensitive spans, or of tokens matching a
+//!   predicate, with a redaction token, for logging text without personal data while keeping
+//!   token counts.
 //!
 //! ### Tokenization via Iterators
 //!
@@ -58,6 +113,128 @@
 //!
 //! - [`BytePairEncoder::tokenize_iter`]: Tokenize text into a flat sequence of BPE tokens.
 //! - [`BytePairEncoder::tokenize_sentences_iter`]: Tokenize text into nested sentences and tokens.
+//! - [`BytePairEncoder::tokenize_ref_iter`] and [`BytePairEncoder::tokenize_ref`]: Tokenize text
+//!   into `Cow<str>` tokens borrowed from the vocabulary where possible, without allocating a
+//!   `String` per token.
+//! - [`BytePairEncoder::encode_iter`]: Tokenize text into token IDs, like
+//!   [`BytePairEncoder::encode`].
+//! - [`BytePairEncoder::tokenize_stream`]: Tokenize a stream of texts on a background thread into
+//!   a consumer, buffering a bounded number of sentences and tokens (see [`StreamLimits`]), so a
+//!   fast producer cannot outrun a slow consumer.
+//! - [`BytePairEncoder::tokenize_sentences_ref_iter`] and
+//!   [`BytePairEncoder::tokenize_sentences_ids_iter`]: Tokenize text into nested sentences of
+//!   borrowed tokens or token IDs.
+//!
+//! ### Configuration
+//!
+//! How text is prepared before matching can be tuned with [`EncoderOptions`], applied through
+//! [`BytePairEncoder::with_options`]. The defaults reproduce the crate's historical behavior.
+//!
+//! - [`LineBreakPolicy`]: How line terminators (`\r\n`, vertical tab, `U+2028`, ...) affect
+//!   sentence splitting.
+//! - [`InvisibleCharPolicy`]: Whether zero-width, byte order mark, and control characters are
+//!   stripped from words before matching.
+//! - [`HyphenationPolicy`]: Whether soft hyphens are removed and words hyphenated across line
+//!   breaks (common in PDF-extracted text) are rejoined.
+//! - [`NumberPolicy`]: Maps digits to `0`, or numbers to their order of magnitude, so numeric-heavy
+//!   text fragments less.
+//! - [`ConfusablePolicy`]: Folds full-width letters, mathematical alphanumerics, and Cyrillic or
+//!   Greek lookalikes in Latin words to their canonical forms, to lower `<unk>` rates on spammy
+//!   text.
+//! - [`EmojiPolicy`]: Whether emoji sequences are kept as atomic units, and how missing ones are
+//!   represented.
+//! - [`PunctuationPolicy`]: Keeps punctuation and symbols as tokens instead of dropping them, so
+//!   the output can represent them.
+//! - [`SpecialTextPolicy`]: Strips or escapes input text that spells a special token, so a literal
+//!   `<unk>` in a prompt cannot pass for a control token.
+//! - [`TextMode`]: Tunes tokenization for source code: case and symbols are kept, and identifiers
+//!   are split at `camelCase` and `snake_case` boundaries. `TextMode::Lossless` keeps whitespace,
+//!   records case with markers, and falls back to byte tokens, so text can be recovered exactly
+//!   (see [`EncoderOptions::lossless`]).
+//! - [`EncoderOptions::max_word_length`]: Splits pathologically long words into chunks before
+//!   matching.
+//! - [`EncoderOptions::single_char_margin`]: Prefers matches that do not leave single-character
+//!   fragments, for vocabularies that over-fragment words.
+//! - [`EncoderOptions::priors`]: Biases matching toward tokens common in a domain with external
+//!   frequency priors (see [`TokenPriors`]), without modifying the vocabulary.
+//! - [`SegmentationStrategy`]: Finds the segmentation of each word with the highest total score
+//!   with the Viterbi algorithm, instead of matching the longest tokens greedily, for vocabularies
+//!   scored with log-probabilities, or the way WordPiece does, with `##` continuation tokens, for
+//!   BERT-style vocabularies (see [`BytePairEncoder::new_from_wordpiece_file`]), or with ranked
+//!   merges over UTF-8 bytes, for GPT-2 and RoBERTa vocabularies loaded from `vocab.json` and
+//!   `merges.txt` (see [`BytePairEncoder::new_from_gpt2_files`]).
+//! - [`UnknownStrategy`]: Falls back to byte tokens (`<0xE3>`, as in SentencePiece) or to
+//!   grapheme clusters for text the vocabulary does not cover, instead of the
+//!   information-destroying unknown token, skips it, or reports it as an error from
+//!   [`BytePairEncoder::try_tokenize`].
+//! - [`EncoderOptions::max_sentence_tokens`]: Splits over-long sentences into several
+//!   marker-wrapped segments at word boundaries.
+//! - [`EmptySentencePolicy`]: Whether sentences without words (whitespace or punctuation only)
+//!   are skipped or emitted as markers.
+//! - [`EncoderOptions::collect_metrics`]: Enables runtime counters, retrievable with
+//!   [`BytePairEncoder::metrics`].
+//! - [`EncoderOptions::cache_capacity`]: Caches the tokens of recently seen words, safely shared
+//!   across threads. The cache can be saved with [`BytePairEncoder::save_cache`] and loaded by
+//!   the next run with [`BytePairEncoder::load_cache`], so short-lived jobs start warm.
+//! - [`SpecialTokenOptions`]: Overrides the sentence markers, unknown token, padding token, mask
+//!   token, and word break character, which are otherwise detected from the vocabulary (see
+//!   [`SpecialTokens`]). The sentence markers can also be set with
+//!   [`BytePairEncoder::with_sentence_markers`], and all of them with a
+//!   [`BytePairEncoderBuilder`] from [`BytePairEncoder::builder`].
+//! - [`VocabStore`]: How the vocabulary is indexed for lookups, set with
+//!   [`BytePairEncoder::with_vocab_store`]: a [`HashMapStore`] by default, a [`CompactStore`]
+//!   that uses a fraction of the memory, or a [`TrieStore`] that finds the tokens starting at a
+//!   position of a word in a single walk.
+//! - [`CompatLevel`]: Pins tokenization to the behavior of an earlier version, so stored token IDs
+//!   do not shift when upgrading.
+//!
+//! All options can be saved as a `tokenizer_config.json` document with
+//! [`BytePairEncoder::write_config`] (or [`EncoderOptions::to_json`]), and loaded together with
+//! the vocabulary with [`BytePairEncoder::new_from_file_with_config`], so a pair of files fully
+//! reproduces an encoder.
+//!
+//! ### Token IDs
+//!
+//! Every token has a stable ID, assigned explicitly when the vocabulary is loaded:
+//!
+//! 1. Tokens are numbered from zero in the order of the vocabulary source (the lines of a
+//!    vocabulary file, or the entries of a default vocabulary). Metadata lines get no ID.
+//! 2. A token listed more than once keeps the ID of its first occurrence, and takes the score of
+//!    its last one.
+//! 3. Special tokens registered with [`MissingSpecialTokenPolicy::Register`] follow, in the order
+//!    sentence start, sentence end, unknown, padding.
+//!
+//! IDs never depend on scores, hashing, or the platform, so the same source always yields the same
+//! IDs. Use [`BytePairEncoder::token_to_id`] and [`BytePairEncoder::id_to_token`] to map between
+//! tokens and IDs, and [`BytePairEncoder::export_id_table`] to get the whole table, e.g. to build
+//! an embedding matrix that matches, [`BytePairEncoder::export_onnx_bpe`] to export the
+//! vocabulary and merges for the onnxruntime-extensions BPE tokenizer op, and
+//! [`BytePairEncoder::write_sentencepiece_model`] to write a SentencePiece model file.
+//! [`BytePairEncoder::contains_token`] and [`BytePairEncoder::score_of`] look up single tokens
+//! without allocating. Special tokens missing from the vocabulary can be reported with
+//! [`BytePairEncoder::validate_special_tokens`], or rejected up front by applying options with
+//! [`MissingSpecialTokenPolicy::Error`] through [`BytePairEncoder::try_with_options`], which also
+//! checks the options for invalid combinations.
+//!
+//! Tokenized corpora can be stored compactly with [`write_ids`] and read back with [`read_ids`],
+//! which encode ID sequences as varints, optionally delta-coded (see [`IdStreamOptions`]).
+//!
+//! ### Training
+//!
+//! A [`Trainer`] learns a new vocabulary from a text corpus with [`Trainer::train`], or extends an
+//! existing one (keeping its token IDs) with [`Trainer::extend`]. Training is configured with
+//! [`TrainerOptions`], set directly or one at a time with a [`TrainerBuilder`], or with a
+//! [`TrainerPreset`] of common settings for vocabularies of 8k to 50k tokens. Long training runs
+//! can write checkpoints with [`Trainer::train_with_checkpoints`] and continue after an
+//! interruption with [`Trainer::resume_from_file`].
+//!
+//! ### Evaluation
+//!
+//! [`evaluate`] compares several encoders on the same corpus, reporting the unknown token rate,
+//! tokens per character, and speed of each as [`EvalMetrics`]. [`evaluate_segmentation`] compares
+//! how an encoder splits words to reference segmentations (e.g. into morphemes), reporting
+//! boundary precision and recall as [`SegmentationMetrics`]. [`cooccurrence_counts`] counts
+//! how often tokens occur near each other in a corpus, for co-occurrence based embeddings.
 //!
 //! ##  Example
 //!
@@ -128,16 +305,148 @@
 //! [`BytePairEncoder::new_default_small`], [`BytePairEncoder::new_default_medium`],
 //! [`BytePairEncoder::new_default_large`]) become available for constructing a `BytePairEncoder`.
 //! Only enable the features that you need to ensure minimized memory and binary size.
+//! [`BytePairEncoder::best_default_for_budget`] picks the largest enabled vocabulary that fits a
+//! memory budget at runtime. [`BytePairEncoder::verify_default_vocabs`] checks every enabled
+//! vocabulary against the checksum recorded at build time, for a fail-fast self-test at startup.
+//! The [`capabilities`] module reports which default vocabularies and other features are enabled,
+//! and whether the batch functions can run in parallel, so libraries built on this crate can adapt
+//! at runtime.
+//! The [`conformance`] module fingerprints this build's tokenization of a built-in reference
+//! corpus, so CI jobs across operating systems, architectures, and FFI or WASM builds can assert
+//! that they all tokenize bit-for-bit identically.
+//!
+//! ### `bench`
+//! - Exposes the `bench` module with representative workloads (short sentences, long documents,
+//!   CJK, emoji) used by the crate's benchmarks, which run with
+//!   `cargo bench --features bench,default-small`.
+//!
+//! ### `server`
+//! - Exposes the `server` module, a tiny HTTP service (`/tokenize`, `/encode`, `/count`) around a
+//!   loaded encoder, so services written in other languages can use the exact same tokenizer.
+//!
+//! ### `language`
+//! - Exposes the `language` module, which detects the dominant script of a text and selects among
+//!   per-script encoders (e.g. per-language vocabularies), falling back to a default encoder.
+//!   It can also tag each tokenized sentence with its script, for routing or filtering the
+//!   sentences of multilingual corpora.
+//!
+//! ### `fixtures`
+//! - Exposes the `fixtures` module with short multilingual sample texts and their expected
+//!   tokenizations with each default vocabulary, for validating tokenizer wiring in downstream
+//!   integration tests.
+//!
+//! ### `huggingface`
+//! - Adds `TryFrom` conversions between `BytePairEncoder` and the HuggingFace `tokenizers` crate's
+//!   BPE model (`tokenizers::models::bpe::BPE`), in both directions, so projects can migrate
+//!   either way or cross-validate outputs within Rust. Token IDs are preserved.
+//! - Adds a `TryFrom` conversion into a complete `tokenizers::Tokenizer`, whose normalizer,
+//!   pre-tokenizer, post-processor, and decoder reproduce the encoder's options (lowercasing, the
+//!   `▁` word prefix, sentence markers, byte fallback), so a saved `tokenizer.json` tokenizes the
+//!   same way elsewhere.
+//!
+//! ### `salted-ids`
+//! - Exposes the `salted` module, which maps tokens to IDs derived from an HMAC-SHA256 of their
+//!   text under a secret key, for analytics on logged ID streams that must not reveal the
+//!   vocabulary.
+//!
+//! ### `encryption`
+//! - Adds `BytePairEncoder::new_from_encrypted_file`, `new_from_encrypted_bytes`, and
+//!   `write_encrypted_vocab`, which keep proprietary vocabularies encrypted at rest with
+//!   AES-256-GCM and a caller-supplied key.
+//!
+//! ### `abi`
+//! - Exposes the `abi` module, with `#[repr(C)]` tokenization results made of fixed-width
+//!   integers, for FFI layers and for passing results to other processes through shared memory
+//!   without re-serializing them.
 
+#[cfg(feature = "abi")]
+pub mod abi;
+#[cfg(feature = "bench")]
+pub mod bench;
+mod bounded_queue;
+mod builder;
+mod cache;
+pub mod capabilities;
+mod config;
+pub mod conformance;
+mod confusables;
 mod constants;
+mod cooccurrence;
+mod count_min;
 mod default_vocabs;
+mod distance;
+mod either;
+mod encoding;
+#[cfg(feature = "encryption")]
+mod encryption;
 mod errors;
+mod evaluation;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+mod fnv;
+mod gpt2;
+#[cfg(feature = "huggingface")]
+mod huggingface;
+mod id_stream;
+mod json;
+#[cfg(feature = "language")]
+pub mod language;
+mod metadata;
+mod metrics;
+mod normalized;
+mod normalizer;
+mod options;
+mod parse_report;
+mod pool;
+mod preview;
+mod priors;
+mod redaction;
+mod reload;
+#[cfg(feature = "salted-ids")]
+pub mod salted;
+mod sentencepiece;
+#[cfg(feature = "server")]
+pub mod server;
+mod special_tokens;
+mod test_vectors;
+mod token_lengths;
 mod tokenizer;
+mod trainer;
+mod vocab;
+mod vocab_store;
+mod word_tokens;
 
 // tests
 #[cfg(test)]
 mod tests;
 
 // re-exports
+pub use builder::{BytePairEncoderBuilder, TrainerBuilder};
+pub use cooccurrence::{cooccurrence_counts, CooccurrenceCounts};
+pub use default_vocabs::DefaultVocabInfo;
+pub use encoding::Encoding;
 pub use errors::BytePairEncoderError;
+pub use evaluation::{evaluate, evaluate_segmentation, EvalMetrics, SegmentationMetrics};
+pub use id_stream::{read_ids, write_ids, IdStreamOptions};
+pub use metadata::Metadata;
+pub use metrics::Metrics;
+pub use normalized::NormalizedText;
+pub use options::{
+    CompatLevel, ConfusablePolicy, EmojiPolicy, EmptySentencePolicy, EncoderOptions,
+    HyphenationPolicy, InvisibleCharPolicy, LineBreakPolicy, MissingSpecialTokenPolicy,
+    NumberPolicy, Padding, PaddingSide, PunctuationPolicy, SegmentationStrategy, SpecialTextPolicy,
+    SpecialTokenOptions, StreamLimits, TextMode, TrainerOptions, TrainerPreset, UnknownStrategy,
+    VocabOrder,
+};
+pub use parse_report::{ParseReport, SkippedLine};
+pub use pool::TokenizerPool;
+pub use preview::{ScoreConvention, VocabPreview};
+pub use priors::TokenPriors;
+pub use redaction::{Redaction, RedactionTarget};
+pub use reload::ReloadableEncoder;
+pub use special_tokens::SpecialTokens;
+pub use test_vectors::TestVector;
+pub use token_lengths::TokenLengths;
 pub use tokenizer::BytePairEncoder;
+pub use trainer::Trainer;
+pub use vocab_store::{CompactStore, HashMapStore, TokenTable, TrieStore, VocabStore};
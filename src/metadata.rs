@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+
+use crate::constants::METADATA_PREFIX;
+
+/// # Descriptive information about a `BytePairEncoder` and its vocabulary.
+///
+/// Metadata does not affect tokenization. It is persisted in the native vocabulary format as
+/// `#@<key>: <value>` lines ahead of the tokens, which can never be mistaken for tokens since they
+/// contain no tab character, and is restored by [`BytePairEncoder::new_from_str`] and
+/// [`BytePairEncoder::new_from_file`]. Backslashes, tabs, and line breaks are escaped as `\\`,
+/// `\t`, `\n`, and `\r`, and colons in keys as `\:`, so every entry round-trips exactly.
+///
+/// [`BytePairEncoder::new_from_str`]: crate::BytePairEncoder::new_from_str
+/// [`BytePairEncoder::new_from_file`]: crate::BytePairEncoder::new_from_file
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{BytePairEncoder, Metadata};
+///
+/// let mut metadata = Metadata::default();
+/// metadata.name = Some("support-tickets".to_string());
+/// metadata.version = Some("2".to_string());
+///
+/// let vocab = BytePairEncoder::new_from_str("hello\t1")
+///     .unwrap()
+///     .with_metadata(metadata);
+/// assert_eq!(vocab.to_string(), "#@name: support-tickets\n#@version: 2\nhello\t1\n");
+///
+/// let restored = BytePairEncoder::new_from_str(&vocab.to_string()).unwrap();
+/// assert_eq!(restored.metadata().name.as_deref(), Some("support-tickets"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Metadata {
+    /// The name of the tokenizer.
+    pub name: Option<String>,
+
+    /// The version of the tokenizer.
+    pub version: Option<String>,
+
+    /// A description of the corpus the vocabulary was trained on.
+    pub corpus: Option<String>,
+
+    /// The license of the vocabulary.
+    pub license: Option<String>,
+
+    /// Any other entries, by key.
+    pub extra: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// Returns `true` if no metadata is set.
+    pub fn is_empty(&self) -> bool {
+        self.entries().next().is_none()
+    }
+
+    /// Sets the entry with the given key.
+    pub(crate) fn set(&mut self, key: &str, value: &str) {
+        let field = match key {
+            "name" => &mut self.name,
+            "version" => &mut self.version,
+            "corpus" => &mut self.corpus,
+            "license" => &mut self.license,
+            _ => {
+                self.extra.insert(key.to_string(), value.to_string());
+                return;
+            }
+        };
+        *field = Some(value.to_string());
+    }
+
+    /// Iterates over the entries that are set, known keys first.
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        [
+            ("name", &self.name),
+            ("version", &self.version),
+            ("corpus", &self.corpus),
+            ("license", &self.license),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.as_deref().map(|value| (key, value)))
+        .chain(
+            self.extra
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        )
+    }
+
+    /// Parses a `#@<key>: <value>` line, returning `None` if it is not a metadata line.
+    ///
+    /// The key ends at the first unescaped colon, which must be followed by a space.
+    pub(crate) fn parse_line(line: &str) -> Option<(String, String)> {
+        if line.contains('\t') {
+            return None;
+        }
+        let entry = line.strip_prefix(METADATA_PREFIX)?;
+        let mut escaped = false;
+        let separator = entry.char_indices().find_map(|(i, c)| {
+            let found = c == ':' && !escaped;
+            escaped = c == '\\' && !escaped;
+            found.then_some(i)
+        })?;
+        let value = entry[separator..].strip_prefix(": ")?;
+        Some((unescape(&entry[..separator]), unescape(value)))
+    }
+
+    /// Formats an entry as a `#@<key>: <value>` line, without the line break.
+    pub(crate) fn format_line(key: &str, value: &str) -> String {
+        format!(
+            "{}{}: {}",
+            METADATA_PREFIX,
+            escape(key).replace(':', "\\:"),
+            escape(value)
+        )
+    }
+}
+
+/// Escapes backslashes, tabs, and line breaks, so the text fits on one metadata line.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reverses [`escape`], and the escaping of colons in keys. Unknown escapes are kept as they are.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(c @ ('\\' | ':')) => unescaped.push(c),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,108 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// # A snapshot of the runtime counters of a `BytePairEncoder`.
+///
+/// Counters are only updated while [`EncoderOptions::collect_metrics`](crate::EncoderOptions) is
+/// enabled. They accumulate across all tokenization calls until
+/// [`BytePairEncoder::reset_metrics`](crate::BytePairEncoder::reset_metrics) is called.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{BytePairEncoder, EncoderOptions};
+///
+/// let mut options = EncoderOptions::default();
+/// options.collect_metrics = true;
+///
+/// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2")
+///     .unwrap()
+///     .with_options(options);
+/// vocab.tokenize("Hello, world!");
+///
+/// let metrics = vocab.metrics();
+/// assert_eq!(metrics.words_tokenized, 2);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Metrics {
+    /// The number of words (and emoji sequences) tokenized.
+    pub words_tokenized: u64,
+
+    /// The number of candidate substrings looked up in the vocabulary.
+    pub candidate_lookups: u64,
+
+    /// The number of unknown tokens emitted.
+    pub unknown_tokens: u64,
+
+    /// The number of words found in the word cache.
+    pub cache_hits: u64,
+
+    /// The number of words looked up in the word cache but not found.
+    pub cache_misses: u64,
+}
+
+/// The live counters behind [`Metrics`].
+///
+/// Counters are updated with relaxed atomics, so an encoder can be shared across threads while
+/// collecting metrics. Cloning takes a snapshot of the current values.
+#[derive(Debug, Default)]
+pub(crate) struct MetricsCounters {
+    words_tokenized: AtomicU64,
+    candidate_lookups: AtomicU64,
+    unknown_tokens: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+impl MetricsCounters {
+    pub(crate) fn add_words_tokenized(&self, n: u64) {
+        self.words_tokenized.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_candidate_lookups(&self, n: u64) {
+        self.candidate_lookups.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_unknown_tokens(&self, n: u64) {
+        self.unknown_tokens.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_cache_hits(&self, n: u64) {
+        self.cache_hits.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_cache_misses(&self, n: u64) {
+        self.cache_misses.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        Metrics {
+            words_tokenized: self.words_tokenized.load(Ordering::Relaxed),
+            candidate_lookups: self.candidate_lookups.load(Ordering::Relaxed),
+            unknown_tokens: self.unknown_tokens.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.words_tokenized.store(0, Ordering::Relaxed);
+        self.candidate_lookups.store(0, Ordering::Relaxed);
+        self.unknown_tokens.store(0, Ordering::Relaxed);
+        self.cache_hits.store(0, Ordering::Relaxed);
+        self.cache_misses.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Clone for MetricsCounters {
+    fn clone(&self) -> Self {
+        let metrics = self.snapshot();
+        MetricsCounters {
+            words_tokenized: AtomicU64::new(metrics.words_tokenized),
+            candidate_lookups: AtomicU64::new(metrics.candidate_lookups),
+            unknown_tokens: AtomicU64::new(metrics.unknown_tokens),
+            cache_hits: AtomicU64::new(metrics.cache_hits),
+            cache_misses: AtomicU64::new(metrics.cache_misses),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::ops::Range;
+
+/// # Text normalized for matching, with a map back to the original text.
+///
+/// Lowercasing can change the length of a string: `İ` (2 bytes) lowercases to `i̇` (3 bytes), and
+/// stripped invisible characters disappear entirely. Byte offsets into the normalized text
+/// therefore do not carry over to the original text, so this keeps track of the original
+/// character each normalized byte came from.
+///
+/// Created by [`BytePairEncoder::normalize`](crate::BytePairEncoder::normalize).
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::BytePairEncoder;
+///
+/// let vocab = BytePairEncoder::new_from_str("▁istanbul\t1").unwrap();
+/// let normalized = vocab.normalize("İstanbul!");
+/// assert_eq!(normalized.as_str(), "i\u{307}stanbul!");
+///
+/// // "stanbul" starts at byte 3 of the normalized text, but at byte 2 of the original.
+/// assert_eq!(normalized.source_range(3..10), 2..9);
+/// // Any part of the expansion of "İ" maps back to the whole character.
+/// assert_eq!(normalized.source_range(1..3), 0..2);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedText {
+    /// The normalized text.
+    text: String,
+
+    /// For each byte of `text`, the byte range of the original character it came from.
+    sources: Vec<Range<usize>>,
+
+    /// The length of the original text, in bytes.
+    source_len: usize,
+}
+
+impl NormalizedText {
+    /// Builds the normalized text from the characters kept by normalization, each paired with
+    /// the byte offset of the `source` character it came from, optionally lowercasing them.
+    pub(crate) fn new(source: &str, kept: Vec<(usize, char)>, lowercase: bool) -> Self {
+        // Lowercase the string as a whole, so context-dependent mappings (such as the final
+        // sigma) match `str::to_lowercase`. Each character still maps to as many characters as
+        // `char::to_lowercase` produces for it, which keeps the two aligned.
+        let text = kept.iter().map(|&(_, c)| c).collect::<String>();
+        let text = if lowercase { text.to_lowercase() } else { text };
+        let mut lowered = text.chars();
+        let mut sources = Vec::with_capacity(text.len());
+        for &(start, c) in &kept {
+            let len = source[start..].chars().next().map_or(0, char::len_utf8);
+            let source = start..start + len;
+            let count = if lowercase {
+                c.to_lowercase().count()
+            } else {
+                1
+            };
+            for _ in 0..count {
+                let len = lowered.next().map_or(0, char::len_utf8);
+                sources.extend((0..len).map(|_| source.clone()));
+            }
+        }
+
+        NormalizedText {
+            text,
+            sources,
+            source_len: source.len(),
+        }
+    }
+
+    /// Returns the normalized text.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// # Maps a byte range of the normalized text back to the original text.
+    ///
+    /// ## Arguments
+    ///
+    /// * `range` - A byte range into [`NormalizedText::as_str`].
+    ///
+    /// ## Returns
+    ///
+    /// The byte range of the original text covering every character that produced part of
+    /// `range`. An empty range maps to an empty range at the corresponding position.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `range` extends past the end of the normalized text.
+    pub fn source_range(&self, range: Range<usize>) -> Range<usize> {
+        assert!(
+            range.end <= self.text.len(),
+            "range out of bounds of the normalized text"
+        );
+        let position = |offset: usize| {
+            self.sources
+                .get(offset)
+                .map_or(self.source_len, |s| s.start)
+        };
+        if range.start >= range.end {
+            let start = position(range.start);
+            return start..start;
+        }
+        self.sources[range.start].start..self.sources[range.end - 1].end
+    }
+}
@@ -0,0 +1,523 @@
+use std::{iter, ops::Range};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{constants::BYTE_TOKENS, NumberPolicy};
+
+/// Returns `true` if `c` is one of the line terminators recognized by `LineBreakPolicy`.
+pub(crate) fn is_line_break(c: char) -> bool {
+    matches!(
+        c,
+        '\n' | '\r' | '\u{000B}' | '\u{000C}' | '\u{0085}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+/// Returns `true` if `c` is removed by `InvisibleCharPolicy::Strip`.
+///
+/// This covers control characters, zero-width characters, word joiners, bidirectional formatting
+/// marks, and the byte order mark.
+pub(crate) fn is_invisible(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{200B}'..='\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'..='\u{2064}'
+                | '\u{2066}'..='\u{2069}'
+                | '\u{FEFF}'
+        )
+}
+
+/// Finds the runs of invisible characters inside words in `text`, as removed by
+/// `InvisibleCharPolicy::Strip`.
+///
+/// A run is inside a word when the characters around it form a single word segment once it is
+/// removed, so merging the pieces across the ranges keeps such a word whole, as if the run had been
+/// removed before word segmentation. Whitespace control characters never count as invisible here.
+pub(crate) fn invisible_breaks(text: &str) -> Vec<Range<usize>> {
+    let is_hidden = |c: char| is_invisible(c) && !c.is_whitespace();
+    let mut breaks = vec![];
+    let mut chars = text.char_indices().peekable();
+    let mut previous = None;
+    while let Some((start, c)) = chars.next() {
+        if !is_hidden(c) {
+            previous = Some(c);
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, c)) = chars.peek().filter(|&&(_, c)| is_hidden(c)) {
+            end = i + c.len_utf8();
+            chars.next();
+        }
+        if let (Some(before), Some(&(_, after))) = (previous, chars.peek()) {
+            let joined: String = [before, after].iter().collect();
+            if joined.split_word_bounds().nth(1).is_none() {
+                breaks.push(start..end);
+            }
+        }
+    }
+    breaks
+}
+
+/// Returns `true` if `c` is punctuation, as BERT's basic tokenizer defines it.
+///
+/// This covers every ASCII symbol, which BERT treats as punctuation even where Unicode does not
+/// (such as `$` and `^`), and the punctuation of Latin-1, General Punctuation, CJK Symbols and
+/// Punctuation, and the fullwidth forms.
+pub(crate) fn is_wordpiece_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation()
+        || matches!(
+            c,
+            '\u{A1}'
+                | '\u{A7}'
+                | '\u{AB}'
+                | '\u{B6}'
+                | '\u{B7}'
+                | '\u{BB}'
+                | '\u{BF}'
+                | '\u{2010}'..='\u{2027}'
+                | '\u{2030}'..='\u{2043}'
+                | '\u{2045}'..='\u{2051}'
+                | '\u{2053}'..='\u{205E}'
+                | '\u{3001}'..='\u{3003}'
+                | '\u{3008}'..='\u{3011}'
+                | '\u{3014}'..='\u{301F}'
+                | '\u{FF01}'..='\u{FF03}'
+                | '\u{FF05}'..='\u{FF0A}'
+                | '\u{FF0C}'..='\u{FF0F}'
+                | '\u{FF1A}'
+                | '\u{FF1B}'
+                | '\u{FF1F}'
+                | '\u{FF20}'
+                | '\u{FF3B}'..='\u{FF3D}'
+                | '\u{FF3F}'
+                | '\u{FF5B}'
+                | '\u{FF5D}'
+                | '\u{FF5F}'..='\u{FF65}'
+        )
+}
+
+/// Splits `text` before and after every punctuation character, as BERT's basic tokenizer does,
+/// so each punctuation character is a piece of its own (see [`is_wordpiece_punctuation`]).
+pub(crate) fn split_punctuation(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    iter::from_fn(move || {
+        let mut chars = rest.char_indices();
+        let (_, first) = chars.next()?;
+        let end = if is_wordpiece_punctuation(first) {
+            first.len_utf8()
+        } else {
+            chars
+                .find(|&(_, c)| is_wordpiece_punctuation(c))
+                .map_or(rest.len(), |(i, _)| i)
+        };
+        let (piece, tail) = rest.split_at(end);
+        rest = tail;
+        Some(piece)
+    })
+}
+
+/// The soft hyphen, an invisible hint of where a word may be hyphenated.
+pub(crate) const SOFT_HYPHEN: char = '\u{AD}';
+
+/// Returns `true` if `c` is a hyphen that can end a line in hyphenated text.
+fn is_hyphen(c: char) -> bool {
+    matches!(c, '-' | '\u{2010}' | SOFT_HYPHEN)
+}
+
+/// Finds the hyphenated line breaks in `text`, as described by `HyphenationPolicy::Rejoin`.
+///
+/// Each range starts at the hyphen and ends where the continuation of the word starts, so removing
+/// the ranges rejoins the words.
+pub(crate) fn hyphenation_breaks(text: &str) -> Vec<Range<usize>> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let is_space = |c: char| c.is_whitespace() && !is_line_break(c);
+    let mut breaks = vec![];
+    for (i, &(start, c)) in chars.iter().enumerate() {
+        if !is_hyphen(c) || i == 0 || !chars[i - 1].1.is_alphabetic() {
+            continue;
+        }
+        let mut j = i + 1;
+        while j < chars.len() && is_space(chars[j].1) {
+            j += 1;
+        }
+        match chars.get(j) {
+            Some((_, '\r')) if chars.get(j + 1).map(|&(_, c)| c) == Some('\n') => j += 2,
+            Some(&(_, c)) if is_line_break(c) => j += 1,
+            _ => continue,
+        }
+        while j < chars.len() && is_space(chars[j].1) {
+            j += 1;
+        }
+        if let Some(&(end, c)) = chars.get(j) {
+            if c.is_lowercase() {
+                breaks.push(start..end);
+            }
+        }
+    }
+    breaks
+}
+
+/// Applies a `NumberPolicy` to a sequence of characters, each paired with the byte offset of the
+/// source character it came from.
+pub(crate) fn normalize_numbers(
+    chars: Vec<(usize, char)>,
+    policy: NumberPolicy,
+) -> Vec<(usize, char)> {
+    match policy {
+        NumberPolicy::Keep => chars,
+        NumberPolicy::ZeroDigits => chars
+            .into_iter()
+            .map(|(i, c)| (i, if c.is_ascii_digit() { '0' } else { c }))
+            .collect(),
+        NumberPolicy::Magnitude => {
+            let is_digit = |k: usize| chars.get(k).map_or(false, |(_, c)| c.is_ascii_digit());
+            let mut result = Vec::with_capacity(chars.len());
+            let mut k = 0;
+            while k < chars.len() {
+                if !is_digit(k) {
+                    result.push(chars[k]);
+                    k += 1;
+                    continue;
+                }
+                // Consume the whole number, keeping one `0` per integer digit.
+                let mut fractional = false;
+                loop {
+                    if is_digit(k) {
+                        if !fractional {
+                            result.push((chars[k].0, '0'));
+                        }
+                        k += 1;
+                    } else if matches!(chars[k].1, ',' | '.') && is_digit(k + 1) {
+                        fractional |= chars[k].1 == '.';
+                        k += 1;
+                    } else {
+                        break;
+                    }
+                    if k == chars.len() {
+                        break;
+                    }
+                }
+            }
+            result
+        }
+    }
+}
+
+/// Merges consecutive pieces of `text` (sentences or word segments) that are separated by one of
+/// `breaks`, such as the [`hyphenation_breaks`] or the [`invisible_breaks`], so a word spanning a
+/// break ends up in a single piece.
+///
+/// The pieces must be slices of `text`, in order. Pieces are merged when the gap between them, if
+/// any, and their boundary fall inside one of the breaks.
+pub(crate) fn rejoin<'a, I>(
+    text: &'a str,
+    breaks: Vec<Range<usize>>,
+    pieces: I,
+) -> impl Iterator<Item = &'a str>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let offset = move |piece: &str| piece.as_ptr() as usize - text.as_ptr() as usize;
+    let mut pieces = pieces.peekable();
+    iter::from_fn(move || {
+        let first = pieces.next()?;
+        let start = offset(first);
+        let mut end = start + first.len();
+        while let Some(next) = pieces.peek() {
+            let next_start = offset(next);
+            if !breaks.iter().any(|b| b.start <= end && next_start <= b.end) {
+                break;
+            }
+            end = next_start + next.len();
+            pieces.next();
+        }
+        Some(&text[start..end])
+    })
+}
+
+/// Splits `text` around every occurrence of any of `patterns`, as `SpecialTextPolicy::Strip`
+/// does, returning the (possibly empty) pieces between occurrences.
+///
+/// Occurrences are found as by [`find_patterns`].
+pub(crate) fn split_around<'a>(text: &'a str, patterns: &[&str]) -> Vec<&'a str> {
+    let mut pieces = vec![];
+    let mut start = 0;
+    for found in find_patterns(text, patterns) {
+        pieces.push(&text[start..found.start]);
+        start = found.end;
+    }
+    pieces.push(&text[start..]);
+    pieces
+}
+
+/// Appends `text` to `out`, escaping every occurrence of any of `patterns` with a backslash, as
+/// decoding does with `SpecialTextPolicy::Escape`. Backslashes are doubled, so the escaped text
+/// is unambiguous.
+///
+/// Occurrences are found as by [`find_patterns`].
+pub(crate) fn escape_patterns(text: &str, patterns: &[&str], out: &mut String) {
+    let mut start = 0;
+    for found in find_patterns(text, patterns) {
+        out.push_str(&text[start..found.start].replace('\\', "\\\\"));
+        out.push('\\');
+        out.push_str(&text[found.clone()]);
+        start = found.end;
+    }
+    out.push_str(&text[start..].replace('\\', "\\\\"));
+}
+
+/// Returns the byte range of every occurrence of any of `patterns` in `text`.
+///
+/// Occurrences are found from left to right, do not overlap, and the longest pattern wins at each
+/// position.
+fn find_patterns(text: &str, patterns: &[&str]) -> Vec<Range<usize>> {
+    let mut first_bytes = [false; 256];
+    for pattern in patterns.iter().filter(|pattern| !pattern.is_empty()) {
+        first_bytes[pattern.as_bytes()[0] as usize] = true;
+    }
+
+    let mut found = vec![];
+    let mut i = 0;
+    while i < text.len() {
+        let len = if first_bytes[text.as_bytes()[i] as usize] {
+            patterns
+                .iter()
+                .filter(|pattern| !pattern.is_empty() && text[i..].starts_with(*pattern))
+                .map(|pattern| pattern.len())
+                .max()
+        } else {
+            None
+        };
+        match len {
+            Some(len) => {
+                found.push(i..i + len);
+                i += len;
+            }
+            None => i += text[i..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    found
+}
+
+/// Moves the trailing whitespace of each sentence to the start of the next one, as
+/// `TextMode::Lossless` does, so a space before the first word of a sentence becomes its word
+/// break character.
+///
+/// The sentences must be slices of `text`, in order. Whitespace is only moved between adjacent
+/// sentences, and the last sentence keeps its own. Sentences may become empty.
+pub(crate) fn carry_trailing_whitespace<'a, I>(
+    text: &'a str,
+    sentences: I,
+) -> impl Iterator<Item = &'a str>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let offset = move |piece: &str| piece.as_ptr() as usize - text.as_ptr() as usize;
+    let mut sentences = sentences.peekable();
+    let mut carried: Option<usize> = None;
+    iter::from_fn(move || {
+        let sentence = sentences.next()?;
+        let start = carried.take().unwrap_or_else(|| offset(sentence));
+        let mut end = offset(sentence) + sentence.len();
+        if sentences.peek().map_or(false, |next| offset(next) == end) {
+            end = offset(sentence) + sentence.trim_end().len();
+            carried = Some(end);
+        }
+        Some(&text[start..end])
+    })
+}
+
+/// Attaches the last space of a whitespace segment to the segment that follows it, if that is
+/// not whitespace as well, as `TextMode::Lossless` does. That space becomes the word break
+/// character of the following word.
+///
+/// The segments must be slices of `sentence`, in order. Segments may become empty.
+pub(crate) fn attach_spaces<'a, I>(sentence: &'a str, segments: I) -> impl Iterator<Item = &'a str>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let offset = move |piece: &str| piece.as_ptr() as usize - sentence.as_ptr() as usize;
+    let is_space = |segment: &str| segment.chars().all(char::is_whitespace);
+    let mut segments = segments.peekable();
+    let mut carried: Option<usize> = None;
+    iter::from_fn(move || {
+        let segment = segments.next()?;
+        let start = carried.take().unwrap_or_else(|| offset(segment));
+        let mut end = offset(segment) + segment.len();
+        let attach = segment.ends_with(' ')
+            && is_space(segment)
+            && segments
+                .peek()
+                .map_or(false, |next| offset(next) == end && !is_space(next));
+        if attach {
+            end -= 1;
+            carried = Some(end);
+        }
+        Some(&sentence[start..end])
+    })
+}
+
+/// The case marker that precedes the tokens of a word in `TextMode::Lossless`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CaseMarker {
+    /// The first letter of the word is uppercase. Precedes the first token of the word.
+    Capitalized,
+
+    /// The whole word is uppercase. Precedes every token of the word.
+    Uppercase,
+}
+
+/// Lowercases a capitalized or all-uppercase word, returning the marker that restores it.
+///
+/// Words that are already lowercase, that mix cases in any other way, or whose case does not
+/// survive a round trip through lowercasing (e.g. `İ`) are returned as they are, without a
+/// marker.
+pub(crate) fn fold_case(word: &str) -> (Option<CaseMarker>, String) {
+    let lower = word.to_lowercase();
+    if lower == word {
+        return (None, lower);
+    }
+
+    let mut chars = word.chars();
+    if let Some(first) = chars.next() {
+        let rest = chars.as_str();
+        let first_lower: String = first.to_lowercase().collect();
+        let reversible =
+            first_lower.chars().count() == 1 && first_lower.to_uppercase() == first.to_string();
+        if reversible && rest.to_lowercase() == rest {
+            return (Some(CaseMarker::Capitalized), first_lower + rest);
+        }
+    }
+    if lower.to_uppercase() == word {
+        return (Some(CaseMarker::Uppercase), lower);
+    }
+    (None, word.to_string())
+}
+
+/// Appends `text` to `out`, applying a pending case marker.
+///
+/// `Uppercase` applies to all of `text`. `Capitalized` applies to its first character other than
+/// a space, and stays pending if there is none.
+pub(crate) fn push_cased(out: &mut String, text: &str, case: &mut Option<CaseMarker>) {
+    match *case {
+        None => out.push_str(text),
+        Some(CaseMarker::Uppercase) => {
+            out.push_str(&text.to_uppercase());
+            *case = None;
+        }
+        Some(CaseMarker::Capitalized) => match text.char_indices().find(|&(_, c)| c != ' ') {
+            Some((i, c)) => {
+                out.push_str(&text[..i]);
+                out.extend(c.to_uppercase());
+                out.push_str(&text[i + c.len_utf8()..]);
+                *case = None;
+            }
+            None => out.push_str(text),
+        },
+    }
+}
+
+/// Returns the byte that a byte fallback token such as `<0x41>` stands for, if `token` is one.
+pub(crate) fn byte_token(token: &str) -> Option<u8> {
+    let hex = token.strip_prefix("<0x")?.strip_suffix('>')?;
+    let byte = u8::from_str_radix(hex, 16).ok()?;
+    (BYTE_TOKENS[byte as usize] == token).then_some(byte)
+}
+
+/// Returns the length of the UTF-8 sequence starting with `lead`, or 1 if `lead` cannot start
+/// one.
+pub(crate) fn utf8_len(lead: u8) -> usize {
+    match lead {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => 1,
+    }
+}
+
+/// Splits a word into the parts `TextMode::Code` tokenizes separately.
+///
+/// Parts break at lowercase-to-uppercase transitions (`getUser` becomes `get`, `User`), before the
+/// last capital of an acronym followed by a lowercase letter (`HTTPServer` becomes `HTTP`,
+/// `Server`), and around runs of underscores and other symbols (`snake_case` becomes `snake`, `_`,
+/// `case`).
+pub(crate) fn split_identifier(word: &str) -> Vec<&str> {
+    #[derive(PartialEq)]
+    enum Class {
+        Alphanumeric,
+        Underscore,
+        Symbol,
+    }
+    let class = |c: char| match c {
+        '_' => Class::Underscore,
+        c if c.is_alphanumeric() => Class::Alphanumeric,
+        _ => Class::Symbol,
+    };
+
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = vec![];
+    let mut start = 0;
+    for i in 1..chars.len() {
+        let (at, c) = chars[i];
+        let prev = chars[i - 1].1;
+        let next = chars.get(i + 1).map(|&(_, c)| c);
+        let boundary = class(prev) != class(c)
+            || ((prev.is_lowercase() || prev.is_numeric()) && c.is_uppercase())
+            || (prev.is_uppercase() && c.is_uppercase() && next.map_or(false, char::is_lowercase));
+        if boundary {
+            parts.push(&word[start..at]);
+            start = at;
+        }
+    }
+    parts.push(&word[start..]);
+    parts
+}
+
+/// Returns `true` if `segment` is an emoji sequence.
+///
+/// A segment counts as an emoji sequence when it starts with a pictographic character or a
+/// regional indicator (used by flags). Modifiers, variation selectors, and ZWJ-joined characters
+/// that follow are part of the same sequence after word segmentation.
+pub(crate) fn is_emoji(segment: &str) -> bool {
+    segment.chars().next().map_or(false, is_pictographic)
+}
+
+/// Returns `true` if `c` is a pictographic character commonly rendered as an emoji.
+///
+/// This approximates the Unicode `Extended_Pictographic` property with the blocks that contain
+/// emoji, plus the individual emoji-capable symbols outside of those blocks.
+fn is_pictographic(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00A9}'
+            | '\u{00AE}'
+            | '\u{203C}'
+            | '\u{2049}'
+            | '\u{2122}'
+            | '\u{2139}'
+            | '\u{2194}'..='\u{2199}'
+            | '\u{21A9}'..='\u{21AA}'
+            | '\u{231A}'..='\u{231B}'
+            | '\u{2328}'
+            | '\u{23CF}'
+            | '\u{23E9}'..='\u{23F3}'
+            | '\u{23F8}'..='\u{23FA}'
+            | '\u{24C2}'
+            | '\u{25AA}'..='\u{25AB}'
+            | '\u{25B6}'
+            | '\u{25C0}'
+            | '\u{25FB}'..='\u{25FE}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2934}'..='\u{2935}'
+            | '\u{2B05}'..='\u{2B07}'
+            | '\u{2B1B}'..='\u{2B1C}'
+            | '\u{2B50}'
+            | '\u{2B55}'
+            | '\u{3030}'
+            | '\u{303D}'
+            | '\u{3297}'
+            | '\u{3299}'
+            | '\u{1F000}'..='\u{1FAFF}'
+    )
+}
@@ -0,0 +1,902 @@
+use std::thread;
+
+use crate::{
+    constants::{
+        MASK_TOKEN, MIN_SENTENCE_TOKENS, PAD_TOKEN, SENTENCE_END_TOKEN, SENTENCE_START_TOKEN,
+        UNKNOWN_TOKEN,
+    },
+    BytePairEncoderError, TokenPriors,
+};
+
+/// # Options controlling how a `BytePairEncoder` prepares text for tokenization.
+///
+/// Every option defaults to the historical behavior of the crate, except `compat`, which enables
+/// the behavior changes that need no option (see [`CompatLevel`]). With `compat` set to
+/// `CompatLevel::V0_1`, a vocabulary that version 0.1 could load tokenizes exactly as it did
+/// there, until another option is changed.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{BytePairEncoder, EncoderOptions, LineBreakPolicy};
+///
+/// let mut options = EncoderOptions::default();
+/// options.line_breaks = LineBreakPolicy::Normalize;
+///
+/// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2")
+///     .unwrap()
+///     .with_options(options);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct EncoderOptions {
+    /// How line terminators are treated by the sentence splitter.
+    pub line_breaks: LineBreakPolicy,
+
+    /// Whether zero-width, byte order mark, and control characters are kept inside words.
+    pub invisible_chars: InvisibleCharPolicy,
+
+    /// Whether soft hyphens and words hyphenated across line breaks are rejoined before matching.
+    pub hyphenation: HyphenationPolicy,
+
+    /// Whether numbers are normalized before matching, to reduce their fragmentation.
+    pub numbers: NumberPolicy,
+
+    /// Whether confusable characters (full-width letters, Cyrillic lookalikes, ...) are folded to
+    /// their canonical forms before matching.
+    pub confusables: ConfusablePolicy,
+
+    /// Whether emoji sequences take part in tokenization, and how missing ones are represented.
+    pub emoji: EmojiPolicy,
+
+    /// Whether punctuation and symbols are kept as words of their own in `TextMode::Natural`.
+    pub punctuation: PunctuationPolicy,
+
+    /// How input text that spells a special token (e.g. a literal `<unk>`) is handled.
+    pub special_text: SpecialTextPolicy,
+
+    /// Whether text is tokenized as natural language or as source code.
+    pub mode: TextMode,
+
+    /// The maximum length of a word, in characters, before it is split into chunks of this size
+    /// ahead of matching. `None` (the default) disables splitting.
+    ///
+    /// Matching a word is quadratic in its length, so a single huge "word" (minified code, base64
+    /// data) can take a very long time to tokenize. Since vocabulary tokens are short, a limit well
+    /// above the longest token (e.g. `100`) only affects such pathological input.
+    pub max_word_length: Option<usize>,
+
+    /// The score margin within which matches that avoid single-character fragments are preferred,
+    /// or `None` (the default) to always pick the highest scoring match.
+    ///
+    /// Among the longest vocabulary tokens found in a word, the highest scoring one normally wins,
+    /// even if it leaves a lone character on either side that then becomes a token of its own.
+    /// Vocabularies with inflated single-character scores make this fragmentation common. With a
+    /// margin set, every candidate scoring within the margin of the best one is considered, and
+    /// the one leaving the fewest single-character fragments wins, with ties broken by score.
+    pub single_char_margin: Option<isize>,
+
+    /// External token frequency priors combined with the vocabulary scores during matching, or
+    /// `None` (the default) to match on the scores alone.
+    pub priors: Option<TokenPriors>,
+
+    /// How each word is segmented into vocabulary tokens.
+    pub segmentation: SegmentationStrategy,
+
+    /// What is produced for the parts of words that no vocabulary token covers.
+    pub unknown: UnknownStrategy,
+
+    /// The maximum number of tokens in a sentence, including its start and end markers, or `None`
+    /// (the default) for no limit.
+    ///
+    /// Longer sentences are split at word boundaries into several segments, each wrapped in its
+    /// own markers, so every segment fits the limit. A single word with too many tokens to fit is
+    /// placed in a segment of its own, which then exceeds the limit. The limit must be at least 3,
+    /// to hold the markers and a token (see [`EncoderOptions::validate`]).
+    pub max_sentence_tokens: Option<usize>,
+
+    /// The length and side that encodings are padded to, or `None` (the default) to leave them
+    /// unpadded.
+    ///
+    /// Applies to the [`Encoding`](crate::Encoding)s created by the encoder, such as those of
+    /// [`BytePairEncoder::encode_full`](crate::BytePairEncoder::encode_full), and only if the
+    /// encoder has a padding token.
+    pub padding: Option<Padding>,
+
+    /// What is produced for sentences without any words, such as whitespace-only or
+    /// punctuation-only sentences.
+    pub empty_sentences: EmptySentencePolicy,
+
+    /// Whether runtime counters are updated during tokenization. Disabled by default.
+    ///
+    /// See [`BytePairEncoder::metrics`](crate::BytePairEncoder::metrics).
+    pub collect_metrics: bool,
+
+    /// The number of tokenized words to cache, or `None` (the default) or `Some(0)` to disable
+    /// caching.
+    ///
+    /// Natural text repeats the same words constantly, so caching their tokens avoids most of the
+    /// matching work. The cache is sharded and internally synchronized, so an encoder shared
+    /// across threads (e.g. behind an `Arc`) benefits from it without any external locking.
+    pub cache_capacity: Option<usize>,
+
+    /// Overrides for the special tokens, which are otherwise detected from the vocabulary.
+    pub special_tokens: SpecialTokenOptions,
+
+    /// The compatibility level, which can pin tokenization to the behavior of an earlier version.
+    pub compat: CompatLevel,
+}
+
+impl EncoderOptions {
+    /// # Returns options under which tokenization can be reversed exactly.
+    ///
+    /// This is `TextMode::Lossless` with [`MissingSpecialTokenPolicy::Register`], and defaults
+    /// otherwise. For these options, decoding the tokens of a text (by token or by ID, e.g. with
+    /// [`BytePairEncoder::decode_batch`](crate::BytePairEncoder::decode_batch)) gives back the
+    /// text exactly, byte for byte. The only exception is text that itself contains the word
+    /// break character `▁`, or the spelling of a case marker or byte token that is in the
+    /// vocabulary.
+    ///
+    /// Registering the case markers and byte tokens gives them IDs even when the vocabulary does
+    /// not contain them. Options that normalize text (line breaks, invisible characters,
+    /// hyphenation, numbers, confusables) defeat the guarantee when changed from these defaults.
+    /// Use [`BytePairEncoder::is_lossless_for`](crate::BytePairEncoder::is_lossless_for) to check
+    /// a given text.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, EncoderOptions};
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2")
+    ///     .unwrap()
+    ///     .with_options(EncoderOptions::lossless());
+    /// assert_eq!(
+    ///     vocab.tokenize("Hello  world!"),
+    ///     vec!["<s>", "<cap>", "<0x68>", "<0x65>", "<0x6C>", "<0x6C>", "<0x6F>", "<0x20>",
+    ///          "▁world", "<0x21>", "</s>"]
+    /// );
+    /// assert!(vocab.is_lossless_for("Hello  world!"));
+    /// ```
+    pub fn lossless() -> Self {
+        EncoderOptions {
+            mode: TextMode::Lossless,
+            special_tokens: SpecialTokenOptions {
+                missing: MissingSpecialTokenPolicy::Register,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// # Checks that the options can be honored.
+    ///
+    /// Options are validated when they are loaded with [`EncoderOptions::from_json`], applied with
+    /// [`BytePairEncoder::try_with_options`](crate::BytePairEncoder::try_with_options), or applied
+    /// by a [`BytePairEncoderBuilder`](crate::BytePairEncoderBuilder).
+    ///
+    /// ## Returns
+    ///
+    /// `Ok(())` if the options are valid, or else:
+    ///
+    /// * [`BytePairEncoderError::TruncationLimitTooSmall`] if `max_sentence_tokens` cannot hold
+    ///   the sentence markers and a token.
+    /// * [`BytePairEncoderError::InvalidConfig`] if `max_word_length` is zero, a special token
+    ///   override or the replacement token of `emoji` or `empty_sentences` is empty, or the word
+    ///   break character is whitespace, which never survives word splitting.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoderError, EncoderOptions};
+    ///
+    /// let mut options = EncoderOptions::default();
+    /// options.max_sentence_tokens = Some(2);
+    /// assert!(matches!(
+    ///     options.validate(),
+    ///     Err(BytePairEncoderError::TruncationLimitTooSmall { limit: 2, minimum: 3 })
+    /// ));
+    /// ```
+    pub fn validate(&self) -> Result<(), BytePairEncoderError> {
+        if let Some(limit) = self.max_sentence_tokens {
+            if limit < MIN_SENTENCE_TOKENS {
+                return Err(BytePairEncoderError::TruncationLimitTooSmall {
+                    limit,
+                    minimum: MIN_SENTENCE_TOKENS,
+                });
+            }
+        }
+
+        let invalid = |reason: String| Err(BytePairEncoderError::InvalidConfig { reason });
+        if self.max_word_length == Some(0) {
+            return invalid("`max_word_length` must be at least 1".to_string());
+        }
+        let special = &self.special_tokens;
+        let tokens = [
+            (
+                "special token `sentence_start`",
+                special.sentence_start.as_deref(),
+            ),
+            (
+                "special token `sentence_end`",
+                special.sentence_end.as_deref(),
+            ),
+            ("special token `unknown`", special.unknown.as_deref()),
+            ("special token `pad`", special.pad.as_deref()),
+            ("special token `mask`", special.mask.as_deref()),
+            (
+                "`emoji` replacement token",
+                match &self.emoji {
+                    EmojiPolicy::Replace(token) => Some(token.as_str()),
+                    _ => None,
+                },
+            ),
+            (
+                "`empty_sentences` token",
+                match &self.empty_sentences {
+                    EmptySentencePolicy::EmitToken(token) => Some(token.as_str()),
+                    _ => None,
+                },
+            ),
+        ];
+        if let Some((name, _)) = tokens.iter().find(|(_, token)| *token == Some("")) {
+            return invalid(format!("the {} is empty", name));
+        }
+        if let Some(c) = special.word_break.filter(|c| c.is_whitespace()) {
+            return invalid(format!("the word break character {:?} is whitespace", c));
+        }
+        Ok(())
+    }
+}
+
+/// # Pins tokenization to the behavior of a given version of this crate.
+///
+/// Improvements to matching and normalization can change the tokens (and therefore the token IDs)
+/// produced for the same input and vocabulary. Every change that applies without being opted into
+/// is tied to a compatibility level and is disabled at lower levels, so stored token IDs stay
+/// valid across upgrades for users who pin a level. Changes that are opted into with an option,
+/// which defaults to the earlier behavior, apply at every level: setting the option is the change.
+///
+/// Levels are ordered from oldest to newest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum CompatLevel {
+    /// Tokenize exactly like version 0.1 of this crate, as long as the other options keep their
+    /// defaults. The special tokens are not detected from the vocabulary.
+    V0_1,
+
+    /// Tokenize like version 0.2 of this crate, which detects the special tokens from the
+    /// vocabulary (see [`SpecialTokens`](crate::SpecialTokens)). A vocabulary with `[CLS]` and
+    /// `[SEP]` tokens then uses them as sentence markers, where version 0.1 emitted `<s>` and
+    /// `</s>` and treated them as ordinary tokens.
+    V0_2,
+
+    /// Enable all behavior changes. This is the default.
+    #[default]
+    Latest,
+}
+
+/// # Overrides for the special tokens emitted by a `BytePairEncoder`.
+///
+/// Each token left as `None` (the default) is detected from the vocabulary, as described in
+/// [`SpecialTokens`](crate::SpecialTokens).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct SpecialTokenOptions {
+    /// The token marking the start of a sentence.
+    pub sentence_start: Option<String>,
+
+    /// The token marking the end of a sentence.
+    pub sentence_end: Option<String>,
+
+    /// The token emitted for input that the vocabulary cannot cover.
+    pub unknown: Option<String>,
+
+    /// The padding token.
+    pub pad: Option<String>,
+
+    /// The mask token, used by
+    /// [`BytePairEncoder::mask_spans`](crate::BytePairEncoder::mask_spans).
+    pub mask: Option<String>,
+
+    /// The character that vocabulary tokens start with to mark the start of a word, `▁` by
+    /// default. Set it to match vocabularies with another convention, such as `Ġ`.
+    pub word_break: Option<char>,
+
+    /// What to do with special tokens that are not in the vocabulary.
+    pub missing: MissingSpecialTokenPolicy,
+}
+
+/// # Controls how special tokens missing from the vocabulary are handled.
+///
+/// The sentence markers and unknown token are always emitted, but a vocabulary may not contain
+/// them (e.g. when they fall back to `<s>`, `</s>`, and `<unk>`), in which case they have no ID.
+/// Use `Error` (or
+/// [`BytePairEncoder::validate_special_tokens`](crate::BytePairEncoder::validate_special_tokens))
+/// to turn this into an error instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MissingSpecialTokenPolicy {
+    /// Emit missing special tokens without an ID. This is the default.
+    #[default]
+    Allow,
+
+    /// Give each missing special token a reserved ID following the last vocabulary ID, in the
+    /// order sentence start, sentence end, unknown, padding, mask. In `TextMode::Lossless`, the
+    /// case markers and byte tokens follow, in the order `<cap>`, `<upper>`, `<0x00>` to `<0xFF>`,
+    /// and with `UnknownStrategy::Bytes`, the byte tokens do. Registered tokens are never matched
+    /// against input text.
+    Register,
+
+    /// Reject the options when a special token is missing: applying them with
+    /// [`BytePairEncoder::try_with_options`](crate::BytePairEncoder::try_with_options) or a
+    /// [`BytePairEncoderBuilder`](crate::BytePairEncoderBuilder) returns
+    /// [`BytePairEncoderError::MissingSpecialToken`]. Encoders configured without validation
+    /// behave as with `Allow`.
+    Error,
+}
+
+/// # Controls how line terminators are handled when splitting text into sentences.
+///
+/// Line terminators come in many flavors (`\n`, `\r\n`, `\r`, vertical tab, form feed, `U+0085`,
+/// `U+2028`, and `U+2029`), and the Unicode sentence boundary rules do not treat all of them
+/// alike. This policy decides whether they are left to those rules or handled uniformly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum LineBreakPolicy {
+    /// Leave line terminators to the Unicode sentence boundary rules. This is the default.
+    ///
+    /// Note that vertical tabs and form feeds do not end a sentence under these rules, while the
+    /// other terminators do.
+    #[default]
+    Preserve,
+
+    /// Treat every line terminator (and every `\r\n` pair) as a hard sentence boundary, so the
+    /// same logical text produces identical output regardless of the platform it came from.
+    Normalize,
+}
+
+/// # Controls how invisible characters inside words are handled before matching.
+///
+/// Zero-width characters (such as `U+200B` zero width space, `U+200C` zero width non-joiner, and
+/// `U+200D` zero width joiner), bidirectional formatting marks, the byte order mark (`U+FEFF`),
+/// and control characters can end up inside a word. Vocabularies rarely contain tokens with these
+/// characters, so leaving them in place usually turns the surrounding text into `<unk>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum InvisibleCharPolicy {
+    /// Keep invisible characters as part of the word. This is the default.
+    #[default]
+    Preserve,
+
+    /// Remove invisible characters before word segmentation, so they neither split a word
+    /// (`zero\u{200B}width` stays one word) nor reach the vocabulary.
+    Strip,
+}
+
+/// # Controls how hyphenation left over from typesetting is handled.
+///
+/// Text extracted from PDFs or scans often contains soft hyphens (`U+00AD`) and words broken
+/// across lines with a hyphen (`hyphen-` at the end of one line, `ated` at the start of the
+/// next). Both fragment words into poor tokens, or split them across sentences.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HyphenationPolicy {
+    /// Leave soft hyphens and hyphenated line breaks as they are. This is the default.
+    #[default]
+    Keep,
+
+    /// Remove soft hyphens from words, and rejoin words hyphenated across a line break into a
+    /// single word. A line break counts as hyphenated when a letter and a hyphen (`-`, `U+2010`,
+    /// or a soft hyphen) end the line, possibly followed by spaces, and the next line starts with
+    /// a lowercase letter, possibly after spaces. The hyphen, line break, and spaces are removed,
+    /// so `"hyphen-\nated"` is matched as `hyphenated`. Other hyphens, such as in `well-known`,
+    /// are left alone.
+    Rejoin,
+}
+
+/// # Controls how numbers are normalized before matching.
+///
+/// Every distinct number is a distinct word, so numeric-heavy text (financial reports, logs,
+/// tables) fragments into many rare tokens. Normalizing numbers trades their exact values for
+/// fewer, more frequent tokens. Only ASCII digits are affected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum NumberPolicy {
+    /// Leave numbers as they are. This is the default.
+    #[default]
+    Keep,
+
+    /// Replace every digit with `0`, so `$1,234.56` is matched as `$0,000.00`. This matches
+    /// vocabularies trained on text preprocessed this way, such as the default vocabularies.
+    ZeroDigits,
+
+    /// Reduce every number to its order of magnitude: its integer digits become `0`s, and its
+    /// grouping commas, decimal point, and fractional digits are removed, so `1,234.56` and
+    /// `9876` are both matched as `0000`. A number is a run of digits, possibly with single `,`
+    /// or `.` separators between digits; the first `.` starts the fractional part.
+    Magnitude,
+}
+
+/// # Controls how confusable characters are handled before matching.
+///
+/// Spam and adversarial text often replaces letters with lookalikes to evade filters: full-width
+/// forms (`ｆｒｅｅ`), mathematical alphanumerics (`𝐟𝐫𝐞𝐞`), or letters from other scripts
+/// (`pаypal` with a Cyrillic `а`). Vocabularies contain none of these spellings, so they
+/// tokenize to `<unk>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ConfusablePolicy {
+    /// Leave confusable characters as they are. This is the default.
+    #[default]
+    Keep,
+
+    /// Fold confusable characters to canonical forms before matching. Full-width ASCII and
+    /// mathematical alphanumeric characters always become their ASCII equivalents. Cyrillic and
+    /// Greek letters that look like Latin letters are replaced only in words that also contain
+    /// Latin letters and no other Cyrillic or Greek letters, so Russian or Greek text is not
+    /// affected.
+    Fold,
+}
+
+/// # Controls how emoji sequences are handled during tokenization.
+///
+/// Emoji are not words, so Unicode word segmentation alone drops them. When they are kept, each
+/// emoji sequence (including skin tone modifiers, ZWJ sequences, and flags) is treated as an atomic
+/// unit: it is either matched as a whole or replaced as a whole, but never split into fragments.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum EmojiPolicy {
+    /// Drop emoji, like any other non-word segment. This is the default.
+    #[default]
+    Drop,
+
+    /// Keep emoji sequences, emitting the unknown token for sequences missing from the vocabulary.
+    Keep,
+
+    /// Keep emoji sequences, emitting the given token (e.g. `<emoji>`) for sequences missing from
+    /// the vocabulary.
+    Replace(String),
+}
+
+/// # Controls whether punctuation and symbols take part in tokenization.
+///
+/// Unicode word segmentation yields punctuation and symbols as segments of their own, which
+/// `TextMode::Natural` drops by default, so "Hello, world!" loses its comma and exclamation mark.
+/// `TextMode::Code` and `TextMode::Lossless` always keep them, whatever this policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PunctuationPolicy {
+    /// Drop punctuation and symbols. This is the default.
+    #[default]
+    Drop,
+
+    /// Keep each punctuation mark or symbol as a word of its own, tokenized against the vocabulary
+    /// like any other word (e.g. as `▁,`, or `▁` and `,`). Sentences made only of punctuation,
+    /// such as a line of `---`, then have words, and are kept.
+    Keep,
+}
+
+/// # Controls how a word is segmented into vocabulary tokens.
+///
+/// The greedy strategy is fast and reproduces the historical behavior of the crate, but when the
+/// scores are log-probabilities (as in unigram vocabularies, such as SentencePiece's), it does not
+/// necessarily find the most probable segmentation. `Viterbi` does. `WordPiece` reproduces the
+/// segmentation of BERT-style vocabularies, and `Merges` that of GPT-2 style byte-level ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SegmentationStrategy {
+    /// Repeatedly split the word at its longest vocabulary token, preferring the highest score
+    /// among tokens of the same length (see
+    /// [`BytePairEncoder::tokenize`](crate::BytePairEncoder::tokenize)). This is the default.
+    #[default]
+    Greedy,
+
+    /// Find the segmentation with the highest total score with the Viterbi algorithm, by dynamic
+    /// programming over every vocabulary token in the word. Segmentations that leave fewer
+    /// characters to the unknown token always win, and among those with the same total score,
+    /// the one with the fewest tokens does.
+    ///
+    /// Scores are adjusted by [`EncoderOptions::priors`] as usual, while
+    /// [`EncoderOptions::single_char_margin`] only applies to the greedy strategy.
+    Viterbi,
+
+    /// Match the longest vocabulary token at the start of the word, then at the end of that
+    /// token, and so on, as BERT's WordPiece does. The first token of a word is matched without
+    /// the word break character, and the tokens continuing it with the `##` prefix. A word that
+    /// cannot be covered completely, or that is longer than 100 characters, becomes a single
+    /// unknown token. Scores are not used. Words are also split around every punctuation
+    /// character, as BERT's basic tokenizer does, so `3.14` is the three words `3`, `.`, and
+    /// `14`.
+    ///
+    /// This is set by the WordPiece constructors, such as
+    /// [`BytePairEncoder::new_from_wordpiece_file`](crate::BytePairEncoder::new_from_wordpiece_file).
+    WordPiece,
+
+    /// Spell the word with one symbol per UTF-8 byte, then repeatedly join the adjacent pair of
+    /// symbols with the lowest-ranked merge, as GPT-2's byte-level BPE does, and look up the
+    /// resulting symbols in the vocabulary. The word break character stands for a space. Without
+    /// merges, which only the GPT-2 constructors load, no symbols are joined, and
+    /// [`BytePairEncoder::try_with_options`](crate::BytePairEncoder::try_with_options) rejects
+    /// this strategy. In `TextMode::Lossless`, words keep their case instead of being folded with
+    /// case markers.
+    ///
+    /// This is set by the GPT-2 constructors, such as
+    /// [`BytePairEncoder::new_from_gpt2_files`](crate::BytePairEncoder::new_from_gpt2_files).
+    Merges,
+}
+
+/// # Controls what is produced for the parts of words that no vocabulary token covers.
+///
+/// By default, each such part becomes a single unknown token, which loses its content: nothing
+/// downstream can tell which text it stood for. Missing emoji sequences are handled the same way
+/// under `EmojiPolicy::Keep`, as a single grapheme. `TextMode::Lossless` always falls back to byte
+/// tokens, whatever this strategy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum UnknownStrategy {
+    /// Emit the unknown token for each part of a word that is not covered. This is the default.
+    #[default]
+    Unk,
+
+    /// Emit the UTF-8 bytes of the text that is not covered as byte tokens (`<0x00>` to
+    /// `<0xFF>`), as SentencePiece's byte fallback does, so no content is lost. The word break
+    /// character is emitted as the byte of a space.
+    ///
+    /// Byte tokens have IDs if the vocabulary contains them, as those of models trained with
+    /// byte fallback do, or if they are registered with [`MissingSpecialTokenPolicy::Register`].
+    Bytes,
+
+    /// Emit each grapheme cluster (user-perceived character) of the text that is not covered as
+    /// a token of its own, so at least the visible characters are preserved. The word break
+    /// character in front of a word is kept with its first grapheme.
+    ///
+    /// The graphemes are marked unknown by their IDs: they get the ID of the unknown token. The
+    /// tokenizer methods that borrow their tokens from the encoder, such as
+    /// [`BytePairEncoder::tokenize_ref_iter`](crate::BytePairEncoder::tokenize_ref_iter), yield
+    /// them owned, since they are not stored in it.
+    Graphemes,
+
+    /// Leave the text that is not covered out of the tokens, as if it were not there.
+    Skip,
+
+    /// Treat text that is not covered as an error.
+    /// [`BytePairEncoder::try_tokenize`](crate::BytePairEncoder::try_tokenize) then fails with
+    /// `BytePairEncoderError::UnknownToken` and the byte range of the text, for strict
+    /// validation pipelines. The methods that cannot fail emit the unknown token, as with `Unk`.
+    Error,
+}
+
+/// # Controls how input text that spells a special token is handled.
+///
+/// Input may literally contain the text of a special token, such as `<s>` or `<unk>` pasted into
+/// a prompt. Whether that text can come out as the special token itself depends on the
+/// vocabulary and the `TextMode`, which makes control tokens in the output untrustworthy. The
+/// special tokens covered are the sentence markers, the unknown, padding, and mask tokens, and in
+/// `TextMode::Lossless` the case markers and byte tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SpecialTextPolicy {
+    /// Treat such text like any other text. It is emitted as the special token whenever matching
+    /// produces that token. This is the default.
+    #[default]
+    Text,
+
+    /// Remove such text before tokenization. The text is removed in place, without ending the
+    /// sentence, but words on either side of it are not joined. Text that only spells a special
+    /// token once normalized, such as `BOS` lowercased to `bos`, is kept, but is never matched as
+    /// the special token, as with `Escape`.
+    Strip,
+
+    /// Keep such text, but never match it as the special token: vocabulary tokens that spell a
+    /// special token are excluded from matching, so the text is spelled by other tokens (or
+    /// becomes the unknown token, or byte tokens in `TextMode::Lossless`). Special tokens in the
+    /// output are then always control tokens, and decoding can drop them safely.
+    /// [`BytePairEncoder::decode_with_special_tokens`] keeps them, and escapes the text with a
+    /// backslash, so it stays distinct from them.
+    ///
+    /// [`BytePairEncoder::decode_with_special_tokens`]:
+    /// crate::BytePairEncoder::decode_with_special_tokens
+    Escape,
+}
+
+/// # The kind of text a `BytePairEncoder` is tuned for.
+///
+/// The natural language pipeline lowercases words and drops punctuation and symbols, which
+/// mangles source code and technical text. `Code` keeps them instead, and should be used with a
+/// case-preserving vocabulary trained on such text. `Lossless` keeps everything, so the text can
+/// be recovered from its tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TextMode {
+    /// Natural language text. Words are lowercased, and punctuation and symbols are dropped. This
+    /// is the default.
+    #[default]
+    Natural,
+
+    /// Source code and technical text, including code mixed with prose. Words keep their case,
+    /// punctuation and symbols are kept as words of their own, and identifiers are split into
+    /// their parts at `camelCase`, `snake_case`, and symbol boundaries before matching. Only the
+    /// first part starts with the word break character, so identifiers stay recoverable.
+    Code,
+
+    /// Text that must be recoverable exactly from its tokens (see
+    /// [`EncoderOptions::lossless`]). Nothing is dropped:
+    ///
+    /// - Whitespace is preserved. A single space before a word becomes its word break character,
+    ///   as usual, and all other whitespace and punctuation is tokenized like words. Words not
+    ///   preceded by a space, such as the first word of a text, have no word break character.
+    /// - Case is recorded with markers. A capitalized word is lowercased and preceded by `<cap>`,
+    ///   and each token of an all-uppercase word is lowercased and preceded by `<upper>`. Words
+    ///   with any other mix of cases are matched as they are.
+    /// - Characters the vocabulary cannot cover fall back to one token per UTF-8 byte, written
+    ///   `<0x41>` as in SentencePiece, instead of the unknown token.
+    ///
+    /// The word cache is not used in this mode.
+    Lossless,
+}
+
+/// # Controls what is produced for sentences without any words.
+///
+/// A sentence has no words when it consists only of whitespace, punctuation, or symbols (e.g. a
+/// line of `---` or a lone `...`). Input without any text at all never produces a sentence,
+/// whatever the policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum EmptySentencePolicy {
+    /// Omit such sentences entirely. This is the default.
+    #[default]
+    Skip,
+
+    /// Emit the sentence start and end markers with nothing between them.
+    EmitMarkers,
+
+    /// Emit the given token (e.g. `<empty>`) between the sentence start and end markers.
+    EmitToken(String),
+}
+
+/// # Bounds on the tokenized sentences buffered by
+/// [`BytePairEncoder::tokenize_stream`](crate::BytePairEncoder::tokenize_stream).
+///
+/// Tokenization runs ahead of the consumer until a limit is reached, then waits for the consumer
+/// to catch up, so memory use stays bounded however slow the consumer is.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::StreamLimits;
+///
+/// let mut limits = StreamLimits::default();
+/// limits.max_buffered_sentences = 64;
+/// limits.max_buffered_tokens = Some(8_192);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct StreamLimits {
+    /// The maximum number of tokenized sentences waiting for the consumer. Defaults to `1024`; a
+    /// limit of `0` is treated as `1`.
+    pub max_buffered_sentences: usize,
+
+    /// The maximum number of tokens in the sentences waiting for the consumer, or `None` (the
+    /// default) for no limit. A sentence longer than the limit is buffered on its own.
+    pub max_buffered_tokens: Option<usize>,
+}
+
+impl Default for StreamLimits {
+    fn default() -> Self {
+        StreamLimits {
+            max_buffered_sentences: 1024,
+            max_buffered_tokens: None,
+        }
+    }
+}
+
+/// # The length and side that encodings are padded to by default.
+///
+/// See [`EncoderOptions::padding`].
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{BytePairEncoder, EncoderOptions, Padding, PaddingSide};
+///
+/// let mut padding = Padding::new(4);
+/// padding.side = PaddingSide::Left;
+/// let mut options = EncoderOptions::default();
+/// options.padding = Some(padding);
+///
+/// let vocab = BytePairEncoder::new_from_str("<pad>\t0\n▁hello\t1")
+///     .unwrap()
+///     .with_options(options);
+/// let encoding = vocab.encode_full("Hello");
+/// assert_eq!(encoding.tokens, vec!["<pad>", "<s>", "▁hello", "</s>"]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Padding {
+    /// The number of tokens to pad to. Longer encodings are left unchanged.
+    pub length: usize,
+
+    /// Whether the padding tokens go after or before the tokens of the text.
+    pub side: PaddingSide,
+}
+
+impl Padding {
+    /// # Creates padding to `length` tokens, on the right.
+    pub fn new(length: usize) -> Self {
+        Padding {
+            length,
+            side: PaddingSide::default(),
+        }
+    }
+}
+
+/// # The side of an encoding that padding tokens are added to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PaddingSide {
+    /// After the tokens of the text, as [`Encoding::pad`](crate::Encoding::pad) does. This is
+    /// the default.
+    #[default]
+    Right,
+
+    /// Before the tokens of the text, as [`Encoding::pad_left`](crate::Encoding::pad_left) does.
+    Left,
+}
+
+/// # The order in which tokens are written when a vocabulary is exported.
+///
+/// Both orders are total, so exporting the same vocabulary always produces identical output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum VocabOrder {
+    /// By token ID, which is the order of the vocabulary source. Parsing the output yields the
+    /// same token IDs. This is the default.
+    #[default]
+    Id,
+
+    /// By descending score, with ties broken by token in lexicographic (byte) order.
+    Score,
+}
+
+/// # Options controlling how a [`Trainer`](crate::Trainer) learns a vocabulary.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::TrainerOptions;
+///
+/// let mut options = TrainerOptions::default();
+/// options.vocab_size = 32_000;
+/// options.special_tokens = vec!["[UNK]".into(), "[CLS]".into(), "[SEP]".into(), "[PAD]".into()];
+/// options.character_coverage = 0.9995;
+/// options.min_pair_frequency = 2;
+/// options.max_token_length = Some(16);
+/// options.threads = 8;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct TrainerOptions {
+    /// The number of tokens to learn, including special tokens and single characters. Defaults to
+    /// 8,000.
+    ///
+    /// Training stops early if the training data has no more pairs of tokens to merge.
+    pub vocab_size: usize,
+
+    /// The maximum number of distinct words the trainer keeps counts for, or `None` (the
+    /// default) for no limit.
+    ///
+    /// Word counts are the only training state that grows with the corpus, so this bounds the
+    /// memory used by training regardless of corpus size. As soon as the limit is exceeded, even
+    /// in the middle of a document, the least frequent half of the words is discarded, and their
+    /// counts are kept in a count-min sketch of a fixed size instead. A discarded word that comes
+    /// back resumes from its estimated count.
+    ///
+    /// Counts are exact until words are first discarded, and approximate afterwards: an estimate
+    /// never falls below the true count, but words sharing cells of the sketch can inflate it.
+    /// Checkpoints contain the word counts, but not the sketch.
+    pub max_distinct_words: Option<usize>,
+
+    /// The special tokens every trained vocabulary contains, in ID order. Defaults to `<unk>`,
+    /// `<s>`, and `</s>`.
+    ///
+    /// The trained encoder detects its special tokens from these, as described in
+    /// [`SpecialTokens`](crate::SpecialTokens).
+    pub special_tokens: Vec<String>,
+
+    /// The fraction of the characters in the training data, by number of occurrences, that must
+    /// be representable by single-character tokens. Defaults to `1.0`, which includes every
+    /// character.
+    ///
+    /// Lowering this (e.g. to `0.9995`, as is common for languages with large character sets)
+    /// leaves the rarest characters to the unknown token instead of spending vocabulary on them.
+    pub character_coverage: f64,
+
+    /// The minimum number of occurrences of a pair of tokens for it to be merged. Defaults to `1`.
+    ///
+    /// Training stops early once no pair occurs this often, so rare fragments never become
+    /// tokens.
+    pub min_pair_frequency: u64,
+
+    /// The maximum length of a learned token, in characters, or `None` (the default) for no limit.
+    pub max_token_length: Option<usize>,
+
+    /// The number of threads that count and merge pairs of tokens. Defaults to `1`.
+    ///
+    /// Counting pairs dominates training time on large corpora. With more than one thread, the
+    /// words are split between the threads, which count their pairs into sharded maps that are
+    /// then reduced in parallel. The trained vocabulary is the same for any number of threads.
+    pub threads: usize,
+}
+
+impl Default for TrainerOptions {
+    fn default() -> Self {
+        TrainerOptions {
+            vocab_size: 8_000,
+            max_distinct_words: None,
+            special_tokens: [UNKNOWN_TOKEN, SENTENCE_START_TOKEN, SENTENCE_END_TOKEN]
+                .iter()
+                .map(|token| token.to_string())
+                .collect(),
+            character_coverage: 1.0,
+            min_pair_frequency: 1,
+            max_token_length: None,
+            threads: 1,
+        }
+    }
+}
+
+impl TrainerOptions {
+    /// # Returns the options of a [`TrainerPreset`].
+    ///
+    /// Presets fill in every option, and individual options can still be changed afterwards.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{TrainerOptions, TrainerPreset};
+    ///
+    /// let mut options = TrainerOptions::preset(TrainerPreset::Medium16k);
+    /// assert_eq!(options.vocab_size, 16_000);
+    /// options.character_coverage = 1.0;
+    /// ```
+    pub fn preset(preset: TrainerPreset) -> Self {
+        let (vocab_size, max_distinct_words) = match preset {
+            TrainerPreset::Small8k => (8_000, 1_000_000),
+            TrainerPreset::Medium16k => (16_000, 1_000_000),
+            TrainerPreset::Large32k => (32_000, 2_000_000),
+            TrainerPreset::XLarge50k => (50_000, 2_000_000),
+        };
+        TrainerOptions {
+            vocab_size,
+            max_distinct_words: Some(max_distinct_words),
+            special_tokens: [
+                UNKNOWN_TOKEN,
+                SENTENCE_START_TOKEN,
+                SENTENCE_END_TOKEN,
+                PAD_TOKEN,
+                MASK_TOKEN,
+            ]
+            .iter()
+            .map(|token| token.to_string())
+            .collect(),
+            character_coverage: 0.9995,
+            min_pair_frequency: 2,
+            max_token_length: Some(16),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+/// # Common training setups, for use with [`TrainerOptions::preset`].
+///
+/// The presets differ in vocabulary size, and share the settings that work well for most corpora:
+///
+/// | Setting              | Value                                       |
+/// |----------------------|---------------------------------------------|
+/// | special tokens       | `<unk>`, `<s>`, `</s>`, `<pad>`, `<mask>`   |
+/// | character coverage   | `0.9995`                                    |
+/// | min pair frequency   | `2`                                         |
+/// | max token length     | 16 characters                               |
+/// | max distinct words   | 1,000,000 (2,000,000 from `Large32k`)       |
+/// | threads              | all available cores                         |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TrainerPreset {
+    /// 8,000 tokens, for small or single-domain corpora.
+    Small8k,
+
+    /// 16,000 tokens, for typical single-language corpora.
+    Medium16k,
+
+    /// 32,000 tokens, a common size for general-purpose models.
+    Large32k,
+
+    /// 50,000 tokens, the size of GPT-2 style vocabularies, for large or multilingual corpora.
+    XLarge50k,
+}
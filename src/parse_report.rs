@@ -0,0 +1,53 @@
+/// The number of skipped lines kept as samples in a [`ParseReport`].
+const MAX_SAMPLES: usize = 10;
+
+/// # A summary of the lines skipped while leniently parsing a vocabulary.
+///
+/// Produced by [`BytePairEncoder::new_from_str_lenient`] and
+/// [`BytePairEncoder::new_from_file_lenient`]. Only the first few skipped lines are kept as
+/// samples, so the report stays small however messy the input is.
+///
+/// [`BytePairEncoder::new_from_str_lenient`]: crate::BytePairEncoder::new_from_str_lenient
+/// [`BytePairEncoder::new_from_file_lenient`]: crate::BytePairEncoder::new_from_file_lenient
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseReport {
+    /// The number of lines skipped.
+    pub skipped: usize,
+
+    /// The first lines skipped, in order.
+    pub samples: Vec<SkippedLine>,
+}
+
+/// # A line skipped while leniently parsing a vocabulary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SkippedLine {
+    /// The line number, starting at 1.
+    pub line: usize,
+
+    /// The content of the line.
+    pub text: String,
+
+    /// Why the line was skipped.
+    pub reason: String,
+}
+
+impl ParseReport {
+    /// Returns `true` if no line was skipped.
+    pub fn is_clean(&self) -> bool {
+        self.skipped == 0
+    }
+
+    /// Records a skipped line.
+    pub(crate) fn skip(&mut self, line: usize, text: &str, reason: String) {
+        self.skipped += 1;
+        if self.samples.len() < MAX_SAMPLES {
+            self.samples.push(SkippedLine {
+                line,
+                text: text.to_string(),
+                reason,
+            });
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{cache::CacheStore, BytePairEncoder, BytePairEncoderError};
+
+/// Loads the encoder of a pool entry on demand.
+type Loader = Arc<dyn Fn() -> Result<BytePairEncoder, BytePairEncoderError> + Send + Sync>;
+
+/// # A set of named encoders, for services that tokenize for several tenants.
+///
+/// Multi-tenant services often need a different vocabulary per language, domain, or customer,
+/// selected per request. A pool holds them by name, and takes care of the shared concerns:
+///
+/// - Encoders added with [`TokenizerPool::insert`] stay loaded. Encoders registered with
+///   [`TokenizerPool::register`] are loaded on first use, and can be unloaded again.
+/// - With [`TokenizerPool::with_memory_limit`], the least recently used registered encoders are
+///   unloaded whenever the loaded encoders use more memory (as estimated by
+///   [`BytePairEncoder::memory_bytes`]) than the limit, and reloaded when next used.
+/// - With [`TokenizerPool::with_cache_capacity`], all encoders share a single word cache of that
+///   capacity, instead of each sizing its own, so busy tenants get more of it than idle ones.
+///
+/// The pool is internally synchronized, so it can be shared across threads (e.g. behind an
+/// `Arc`). Encoders are loaded without holding its lock, so loading one tenant's vocabulary does
+/// not hold up requests for the others. Requests that already hold an encoder returned by
+/// [`TokenizerPool::get`] keep using it even if it is unloaded meanwhile.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{BytePairEncoder, TokenizerPool};
+///
+/// let pool = TokenizerPool::new().with_cache_capacity(10_000);
+/// pool.insert("en", BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap());
+/// pool.register("de", || BytePairEncoder::new_from_str("▁hallo\t1\n▁welt\t2"));
+///
+/// assert_eq!(pool.tokenize_with("en", "Hello world").unwrap()[1], "▁hello");
+/// assert_eq!(pool.tokenize_with("de", "Hallo Welt").unwrap()[1], "▁hallo");
+/// assert!(pool.encode_with("fr", "Bonjour").is_err());
+/// ```
+pub struct TokenizerPool {
+    state: Mutex<PoolState>,
+    cache: Option<Arc<CacheStore>>,
+    memory_limit: Option<usize>,
+}
+
+#[derive(Default)]
+struct PoolState {
+    entries: HashMap<String, Entry>,
+
+    /// A counter incremented on every access, used to find the least recently used encoder.
+    tick: u64,
+
+    /// The next namespace in the shared word cache.
+    next_namespace: u64,
+}
+
+struct Entry {
+    /// The loader of a registered encoder, or `None` for an encoder that stays loaded.
+    loader: Option<Loader>,
+    encoder: Option<Arc<BytePairEncoder>>,
+    last_used: u64,
+}
+
+impl TokenizerPool {
+    /// # Creates an empty pool, without a memory limit or shared cache.
+    pub fn new() -> Self {
+        TokenizerPool {
+            state: Mutex::default(),
+            cache: None,
+            memory_limit: None,
+        }
+    }
+
+    /// # Limits the memory used by the loaded encoders, in bytes.
+    ///
+    /// Registered encoders are unloaded, least recently used first, to stay within the limit.
+    /// Encoders added with [`TokenizerPool::insert`] are never unloaded, and the encoder of the
+    /// current request is always kept, so the limit can be exceeded when those alone exceed it.
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// # Gives all encoders a shared word cache, holding up to `words` words.
+    ///
+    /// This replaces the word caches set with
+    /// [`EncoderOptions::cache_capacity`](crate::EncoderOptions::cache_capacity) for the
+    /// encoders added afterwards. Each encoder's words are cached separately, so sharing never
+    /// changes tokenization.
+    pub fn with_cache_capacity(mut self, words: usize) -> Self {
+        self.cache = Some(Arc::new(CacheStore::new(words)));
+        self
+    }
+
+    /// # Adds an encoder that stays loaded, replacing any encoder with the same name.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name to select the encoder with.
+    /// * `encoder` - The encoder.
+    pub fn insert(&self, name: &str, encoder: BytePairEncoder) {
+        let mut state = self.lock();
+        let encoder = self.prepare(&mut state, encoder);
+        let entry = Entry {
+            loader: None,
+            encoder: Some(encoder),
+            last_used: state.tick,
+        };
+        self.replace(&mut state, name, entry);
+    }
+
+    /// # Registers an encoder that is loaded on first use, replacing any with the same name.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name to select the encoder with.
+    /// * `loader` - Creates the encoder, e.g. by loading a vocabulary file. It is called again
+    ///   whenever the encoder is needed after being unloaded.
+    pub fn register<F>(&self, name: &str, loader: F)
+    where
+        F: Fn() -> Result<BytePairEncoder, BytePairEncoderError> + Send + Sync + 'static,
+    {
+        let mut state = self.lock();
+        let entry = Entry {
+            loader: Some(Arc::new(loader)),
+            encoder: None,
+            last_used: 0,
+        };
+        self.replace(&mut state, name, entry);
+    }
+
+    /// # Removes an encoder from the pool.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the pool had an encoder with that name.
+    pub fn remove(&self, name: &str) -> bool {
+        let mut state = self.lock();
+        match state.entries.remove(name) {
+            Some(entry) => {
+                self.release(&entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// # Returns the encoder with the given name, loading it if needed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name of the encoder.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the encoder, `BytePairEncoderError::UnknownEncoder` if the pool has none
+    /// with that name, or the error of its loader.
+    pub fn get(&self, name: &str) -> Result<Arc<BytePairEncoder>, BytePairEncoderError> {
+        let loader = {
+            let mut state = self.lock();
+            state.tick += 1;
+            let tick = state.tick;
+            let entry = state.entries.get_mut(name).ok_or_else(|| unknown(name))?;
+            entry.last_used = tick;
+            match (&entry.encoder, &entry.loader) {
+                (Some(encoder), _) => return Ok(Arc::clone(encoder)),
+                (None, Some(loader)) => Arc::clone(loader),
+                (None, None) => unreachable!("encoders without a loader stay loaded"),
+            }
+        };
+
+        let encoder = loader()?;
+
+        let mut state = self.lock();
+        let encoder = self.prepare(&mut state, encoder);
+        let entry = match state.entries.get_mut(name) {
+            // The entry was replaced or removed while loading.
+            Some(entry)
+                if entry
+                    .loader
+                    .as_ref()
+                    .map_or(false, |l| Arc::ptr_eq(l, &loader)) =>
+            {
+                entry
+            }
+            _ => return Ok(encoder),
+        };
+        let encoder = match &entry.encoder {
+            // Another request loaded it first.
+            Some(loaded) => Arc::clone(loaded),
+            None => {
+                entry.encoder = Some(Arc::clone(&encoder));
+                encoder
+            }
+        };
+        self.enforce_memory_limit(&mut state, name);
+        Ok(encoder)
+    }
+
+    /// # Tokenizes a text with the encoder of the given name.
+    ///
+    /// See [`BytePairEncoder::tokenize`] and [`TokenizerPool::get`].
+    pub fn tokenize_with(
+        &self,
+        name: &str,
+        text: &str,
+    ) -> Result<Vec<String>, BytePairEncoderError> {
+        Ok(self.get(name)?.tokenize(text))
+    }
+
+    /// # Tokenizes a text into token IDs with the encoder of the given name.
+    ///
+    /// See [`BytePairEncoder::encode`] and [`TokenizerPool::get`].
+    pub fn encode_with(&self, name: &str, text: &str) -> Result<Vec<u32>, BytePairEncoderError> {
+        Ok(self.get(name)?.encode(text))
+    }
+
+    /// # Returns the names of the encoders in the pool, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.lock().entries.keys().cloned().collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// # Returns `true` if the encoder with the given name is loaded.
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.lock()
+            .entries
+            .get(name)
+            .map_or(false, |entry| entry.encoder.is_some())
+    }
+
+    /// # Returns the memory used by the loaded encoders, in bytes.
+    ///
+    /// See [`BytePairEncoder::memory_bytes`].
+    pub fn memory_bytes(&self) -> usize {
+        memory_bytes(&self.lock())
+    }
+
+    /// Attaches the shared word cache to a newly loaded encoder.
+    fn prepare(&self, state: &mut PoolState, encoder: BytePairEncoder) -> Arc<BytePairEncoder> {
+        Arc::new(match &self.cache {
+            Some(cache) => {
+                state.next_namespace += 1;
+                encoder.with_shared_cache(cache, state.next_namespace)
+            }
+            None => encoder,
+        })
+    }
+
+    fn replace(&self, state: &mut PoolState, name: &str, entry: Entry) {
+        if let Some(old) = state.entries.insert(name.to_string(), entry) {
+            self.release(&old);
+        }
+        self.enforce_memory_limit(state, name);
+    }
+
+    /// Frees the cached words of an entry's encoder, if it is loaded.
+    fn release(&self, entry: &Entry) {
+        if let Some(encoder) = &entry.encoder {
+            encoder.clear_cache();
+        }
+    }
+
+    /// Unloads the least recently used registered encoders, except `keep`, until the loaded
+    /// encoders fit in the memory limit.
+    fn enforce_memory_limit(&self, state: &mut PoolState, keep: &str) {
+        let limit = match self.memory_limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        let mut used = memory_bytes(state);
+        while used > limit {
+            let victim = state
+                .entries
+                .iter_mut()
+                .filter(|(name, entry)| {
+                    name.as_str() != keep && entry.loader.is_some() && entry.encoder.is_some()
+                })
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(_, entry)| entry);
+            let victim = match victim {
+                Some(victim) => victim,
+                None => return,
+            };
+            let encoder = victim.encoder.take().expect("filtered on loaded encoders");
+            used -= encoder.memory_bytes();
+            encoder.clear_cache();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for TokenizerPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TokenizerPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenizerPool")
+            .field("names", &self.names())
+            .field("memory_limit", &self.memory_limit)
+            .field("shared_cache", &self.cache.is_some())
+            .finish()
+    }
+}
+
+fn memory_bytes(state: &PoolState) -> usize {
+    state
+        .entries
+        .values()
+        .filter_map(|entry| entry.encoder.as_ref())
+        .map(|encoder| encoder.memory_bytes())
+        .sum()
+}
+
+fn unknown(name: &str) -> BytePairEncoderError {
+    BytePairEncoderError::UnknownEncoder {
+        name: name.to_string(),
+    }
+}
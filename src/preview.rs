@@ -0,0 +1,140 @@
+use std::io::BufRead;
+
+use crate::{vocab::Vocab, BytePairEncoder, BytePairEncoderError, Metadata};
+
+/// # The beginning of a vocabulary file, as read by [`BytePairEncoder::peek_vocab_file`].
+///
+/// [`BytePairEncoder::peek_vocab_file`]: crate::BytePairEncoder::peek_vocab_file
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VocabPreview {
+    /// The first tokens and their scores, in ID order.
+    pub entries: Vec<(String, isize)>,
+
+    /// The metadata found ahead of (or between) those tokens.
+    pub metadata: Metadata,
+
+    /// How the scores of those tokens are ordered.
+    pub scores: ScoreConvention,
+}
+
+/// # How the scores of a vocabulary are ordered, by ID.
+///
+/// Vocabularies use different conventions: log-probabilities and negated merge ranks decrease
+/// with each token, while frequency counts sorted the other way increase. Scores only break ties
+/// between candidates of the same length, so every convention works, but a surprising one (or no
+/// order at all) often points at a file that was converted incorrectly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ScoreConvention {
+    /// Scores never increase from one token to the next, like log-probabilities or negated ranks.
+    Descending,
+
+    /// Scores never decrease from one token to the next.
+    Ascending,
+
+    /// All scores are equal (or there are fewer than two tokens).
+    Constant,
+
+    /// Scores go both up and down.
+    Unordered,
+}
+
+impl ScoreConvention {
+    /// Detects the convention followed by `scores`, in ID order.
+    fn detect(scores: impl Iterator<Item = isize> + Clone) -> Self {
+        let pairs = || scores.clone().zip(scores.clone().skip(1));
+        let ascending = pairs().all(|(a, b)| a <= b);
+        let descending = pairs().all(|(a, b)| a >= b);
+        match (ascending, descending) {
+            (true, true) => ScoreConvention::Constant,
+            (false, true) => ScoreConvention::Descending,
+            (true, false) => ScoreConvention::Ascending,
+            (false, false) => ScoreConvention::Unordered,
+        }
+    }
+}
+
+impl VocabPreview {
+    /// Reads up to `n` tokens from a vocabulary in the native format.
+    ///
+    /// Errors are reported like a full load would, except that a first token line that does not
+    /// look like the native format at all is reported as
+    /// [`BytePairEncoderError::UnrecognizedFormat`], with a hint at what it looks like instead.
+    pub(crate) fn read<R: BufRead>(
+        reader: R,
+        n: usize,
+        path: &str,
+    ) -> Result<Self, BytePairEncoderError> {
+        let (mut tokens, mut metadata) = (Vocab::default(), Metadata::default());
+        let lines = reader.lines().map(|line| {
+            line.map_err(|e| BytePairEncoderError::InvalidFile {
+                path: path.to_string(),
+                source: e,
+            })
+        });
+        let mut failed_line = String::new();
+        let parsed = BytePairEncoder::parse_onto(
+            &mut tokens,
+            &mut metadata,
+            lines,
+            Some(n),
+            |_, line, e| {
+                failed_line = line.to_string();
+                Err(e)
+            },
+        );
+        if let Err(e) = parsed {
+            return Err(if tokens.is_empty() {
+                unrecognized(&failed_line, e)
+            } else {
+                e
+            });
+        }
+
+        let entries: Vec<_> = tokens
+            .iter()
+            .map(|(token, score)| (token.to_string(), score))
+            .collect();
+        let scores = ScoreConvention::detect(entries.iter().map(|(_, score)| *score));
+        Ok(VocabPreview {
+            entries,
+            metadata,
+            scores,
+        })
+    }
+}
+
+/// Describes why `line`, the first token line of a vocabulary, is not in the native format, if it
+/// does not look like it at all. Otherwise, `error` is returned unchanged.
+fn unrecognized(line: &str, error: BytePairEncoderError) -> BytePairEncoderError {
+    let float_score = || {
+        let score = line.split_once('\t').map_or("", |(_, score)| score);
+        score.parse::<f64>().is_ok()
+    };
+    match error {
+        BytePairEncoderError::InvalidVocabularyInput { .. } => {}
+        BytePairEncoderError::InvalidScore { .. } if float_score() => {
+            return BytePairEncoderError::UnrecognizedFormat {
+                reason: "scores are not integers, as in a SentencePiece `.vocab` file; scale and \
+                         round them to integers first"
+                    .to_string(),
+            }
+        }
+        error => return error,
+    }
+
+    let trimmed = line.trim_start();
+    let reason = if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        "the input looks like JSON (e.g. a `vocab.json` or `tokenizer.json` file)"
+    } else if trimmed.starts_with("#version") {
+        "the input looks like a GPT-2 style `merges.txt` file"
+    } else if line.contains(' ') {
+        "tokens and scores are separated by spaces instead of a tab"
+    } else {
+        "the first token line has no tab-separated score"
+    };
+    BytePairEncoderError::UnrecognizedFormat {
+        reason: format!("{}; expected `<token>\\t<score>` lines", reason),
+    }
+}
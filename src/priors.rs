@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use crate::fnv::Fnv1a;
+
+/// # External token frequency priors, combined with vocabulary scores during matching.
+///
+/// Among candidate tokens of the same length, matching picks the one with the highest score (see
+/// [`BytePairEncoder::tokenize`](crate::BytePairEncoder::tokenize)). With priors set through
+/// [`EncoderOptions::priors`](crate::EncoderOptions::priors), the score of each candidate is
+/// `score + weight * prior` instead, which biases segmentation toward tokens that are common in a
+/// deployment's domain without modifying the vocabulary (its scores, IDs, and fingerprint stay
+/// the same).
+///
+/// Priors are typically log frequencies counted on domain text. Adjustments are rounded to whole
+/// score units, so `weight` should bring the priors to the scale of the vocabulary's scores.
+/// Tokens without a prior are not adjusted.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{BytePairEncoder, EncoderOptions, TokenPriors};
+///
+/// let vocab = BytePairEncoder::new_from_str("▁ab\t5\nabc\t1\n▁\t0\nc\t0").unwrap();
+/// assert_eq!(vocab.tokenize("abc"), vec!["<s>", "▁ab", "c", "</s>"]);
+///
+/// let mut options = EncoderOptions::default();
+/// options.priors = Some(TokenPriors::new([("abc", 2.0)], 5.0));
+/// let vocab = vocab.with_options(options);
+/// assert_eq!(vocab.tokenize("abc"), vec!["<s>", "▁", "abc", "</s>"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TokenPriors {
+    /// The score adjustment of each token with a prior.
+    adjustments: Arc<HashMap<String, isize>>,
+
+    /// A hash of `adjustments`, independent of their order.
+    fingerprint: u64,
+}
+
+impl TokenPriors {
+    /// # Creates priors from per-token values and the weight they are combined with.
+    ///
+    /// ## Arguments
+    ///
+    /// * `priors` - `(token, prior)` pairs. Tokens are given as they appear in the vocabulary,
+    ///   including any word break character. A token listed twice keeps its last prior.
+    /// * `weight` - The weight of the priors relative to the vocabulary scores.
+    pub fn new<I, S>(priors: I, weight: f64) -> Self
+    where
+        I: IntoIterator<Item = (S, f64)>,
+        S: Into<String>,
+    {
+        let adjustments: HashMap<String, isize> = priors
+            .into_iter()
+            .map(|(token, prior)| (token.into(), (weight * prior).round() as isize))
+            .collect();
+
+        let mut sorted: Vec<(&String, &isize)> = adjustments.iter().collect();
+        sorted.sort_unstable();
+        let mut hash = Fnv1a::new();
+        for (token, adjustment) in sorted {
+            hash.write_str(token);
+            hash.write(&(*adjustment as i64).to_le_bytes());
+        }
+
+        TokenPriors {
+            adjustments: Arc::new(adjustments),
+            fingerprint: hash.finish(),
+        }
+    }
+
+    /// Returns the score adjustment of each token with a prior, sorted by token.
+    pub(crate) fn adjustments(&self) -> Vec<(&str, isize)> {
+        let mut adjustments: Vec<(&str, isize)> = self
+            .adjustments
+            .iter()
+            .map(|(token, &adjustment)| (token.as_str(), adjustment))
+            .collect();
+        adjustments.sort_unstable();
+        adjustments
+    }
+
+    /// Returns the score adjustment of `token`, which is zero for tokens without a prior.
+    pub(crate) fn adjustment(&self, token: &str) -> isize {
+        self.adjustments.get(token).copied().unwrap_or(0)
+    }
+}
+
+impl PartialEq for TokenPriors {
+    fn eq(&self, other: &Self) -> bool {
+        self.fingerprint == other.fingerprint && self.adjustments == other.adjustments
+    }
+}
+
+impl Eq for TokenPriors {}
+
+impl Hash for TokenPriors {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.fingerprint.hash(state);
+    }
+}
@@ -0,0 +1,140 @@
+use std::{borrow::Cow, ops::Range};
+
+use crate::{constants::MASK_TOKEN, BytePairEncoder, Encoding};
+
+/// # The parts of a text to redact, for [`BytePairEncoder::redact`].
+///
+/// This is implemented for lists of byte ranges of the text (slices, arrays, and `Vec`s of
+/// `Range<usize>`), which redact every token that overlaps one of them, and for predicates
+/// `Fn(&str) -> bool`, which are called with the text covered by each token and redact the
+/// tokens they return `true` for.
+pub trait RedactionTarget {
+    /// Returns `true` if the token covering `range` of `text` is to be redacted.
+    fn is_sensitive(&self, text: &str, range: Range<usize>) -> bool;
+}
+
+fn overlaps(spans: &[Range<usize>], range: &Range<usize>) -> bool {
+    spans
+        .iter()
+        .any(|span| span.start < range.end && range.start < span.end)
+}
+
+impl RedactionTarget for &[Range<usize>] {
+    fn is_sensitive(&self, _text: &str, range: Range<usize>) -> bool {
+        overlaps(self, &range)
+    }
+}
+
+impl<const N: usize> RedactionTarget for &[Range<usize>; N] {
+    fn is_sensitive(&self, _text: &str, range: Range<usize>) -> bool {
+        overlaps(&self[..], &range)
+    }
+}
+
+impl RedactionTarget for &Vec<Range<usize>> {
+    fn is_sensitive(&self, _text: &str, range: Range<usize>) -> bool {
+        overlaps(self, &range)
+    }
+}
+
+impl<F: Fn(&str) -> bool> RedactionTarget for F {
+    fn is_sensitive(&self, text: &str, range: Range<usize>) -> bool {
+        self(&text[range])
+    }
+}
+
+/// # A tokenized text with its sensitive tokens redacted.
+///
+/// Produced by [`BytePairEncoder::redact`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Redaction {
+    /// The text decoded from the redacted tokens, with the redaction token in place of each
+    /// redacted token. A redacted token that starts a word keeps the space in front of it.
+    pub text: String,
+
+    /// The redacted tokens, with their IDs and offsets into the original text.
+    pub encoding: Encoding,
+
+    /// The number of tokens that were redacted.
+    pub redacted: usize,
+}
+
+impl BytePairEncoder {
+    /// # Tokenizes a text, replacing its sensitive tokens with a redaction token.
+    ///
+    /// This is meant for logging prompts without leaking personal data: every token selected by
+    /// `target` is replaced by the redaction token, one for one, so the token count and the
+    /// offsets of the rest of the text stay exactly as [`BytePairEncoder::encode_full`] would
+    /// produce them. Tokens inserted by the encoder, such as sentence markers, are never
+    /// redacted.
+    ///
+    /// The redaction token is the mask token, as for [`BytePairEncoder::mask_spans`]: the one
+    /// configured in `SpecialTokenOptions::mask` or detected from the vocabulary, and `<mask>`
+    /// otherwise.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be redacted.
+    /// * `target` - The byte ranges of `text` to redact, or a predicate on the text of each
+    ///   token (see [`RedactionTarget`]).
+    ///
+    /// ## Returns
+    ///
+    /// A [`Redaction`] with the redacted text, tokens, and IDs.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab =
+    ///     BytePairEncoder::new_from_str("<mask>\t0\n▁call\t1\n▁me\t1\n▁at\t1\n▁555\t1\n▁alice\t1")
+    ///         .unwrap();
+    /// let text = "Call me at 555, Alice.";
+    ///
+    /// let redaction = vocab.redact(text, &[16..21]);
+    /// assert_eq!(redaction.text, "call me at 555 <mask>");
+    /// assert_eq!(redaction.encoding.ids[5], Some(0));
+    ///
+    /// let redaction = vocab.redact(text, |token: &str| token.chars().all(|c| c.is_ascii_digit()));
+    /// assert_eq!(redaction.text, "call me at <mask> alice");
+    /// assert_eq!(redaction.redacted, 1);
+    /// ```
+    pub fn redact<T: RedactionTarget>(&self, text: &str, target: T) -> Redaction {
+        let mask = self.special_tokens.mask().unwrap_or(MASK_TOKEN);
+        let word_break = self.special_tokens.word_break();
+        let spaced_mask = format!("{}{}", word_break, mask);
+        let mut redacted = 0;
+        let mut decoded_tokens = vec![];
+        let tokens: Vec<_> = self
+            .token_spans(text)
+            .into_iter()
+            .map(|(token, range, inserted)| {
+                if !inserted && target.is_sensitive(text, range.clone()) {
+                    redacted += 1;
+                    // Keep the space in front of a redacted word in the decoded text.
+                    let shown = if token.starts_with(word_break) {
+                        spaced_mask.as_str()
+                    } else {
+                        mask
+                    };
+                    decoded_tokens.push(Cow::Borrowed(shown));
+                    (Cow::Borrowed(mask), range, inserted)
+                } else {
+                    decoded_tokens.push(token.clone());
+                    (token, range, inserted)
+                }
+            })
+            .collect();
+
+        let encoding = self.encoding(text, tokens);
+        let mut decoded = String::new();
+        self.decode_into(decoded_tokens.iter().map(AsRef::as_ref), true, &mut decoded);
+        Redaction {
+            text: decoded,
+            encoding,
+            redacted,
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use crate::{BytePairEncoder, BytePairEncoderError};
+
+/// # A `BytePairEncoder` whose vocabulary can be replaced while it is in use.
+///
+/// Long-running services can update their vocabulary without a restart: readers take a cheap
+/// snapshot with [`ReloadableEncoder::load`] for each request, and a reload swaps in the new
+/// encoder atomically. Requests already holding a snapshot finish with the encoder they started
+/// with, and the old encoder is dropped once the last of them is done. Reloads and stores are
+/// serialized, so concurrent reloads never race: each one reads the file after the previous one
+/// is swapped in, and the last to start serves the newest file.
+///
+/// ## Example
+///
+/// ```no_run
+/// use bpe_tokenizer::{BytePairEncoder, ReloadableEncoder};
+///
+/// let encoder = ReloadableEncoder::new(BytePairEncoder::new_from_file("vocab.txt").unwrap());
+///
+/// // For each request:
+/// let tokens = encoder.load().tokenize("Hello, world!");
+///
+/// // When the vocabulary file changes:
+/// encoder.reload_from_file("vocab.txt").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ReloadableEncoder {
+    current: RwLock<Arc<BytePairEncoder>>,
+    /// Held for the whole of a reload or store, so they happen one at a time without blocking
+    /// readers.
+    writer: Mutex<()>,
+}
+
+impl ReloadableEncoder {
+    /// # Creates a reloadable encoder, initially serving `encoder`.
+    pub fn new(encoder: BytePairEncoder) -> Self {
+        ReloadableEncoder {
+            current: RwLock::new(Arc::new(encoder)),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// # Returns a snapshot of the current encoder.
+    ///
+    /// The snapshot is unaffected by later reloads, so all the work of one request should use
+    /// the same snapshot.
+    pub fn load(&self) -> Arc<BytePairEncoder> {
+        Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// # Replaces the current encoder.
+    ///
+    /// ## Arguments
+    ///
+    /// * `encoder` - The encoder to serve from now on.
+    ///
+    /// ## Returns
+    ///
+    /// The encoder that was replaced.
+    pub fn store(&self, encoder: BytePairEncoder) -> Arc<BytePairEncoder> {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        self.swap(encoder)
+    }
+
+    /// Swaps in `encoder`, returning the one it replaces. Callers hold the writer lock.
+    fn swap(&self, encoder: BytePairEncoder) -> Arc<BytePairEncoder> {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        std::mem::replace(&mut *current, Arc::new(encoder))
+    }
+
+    /// # Loads a vocabulary file and swaps it in, keeping the current configuration.
+    ///
+    /// The file is loaded before the swap, so readers are never blocked while it is parsed, and
+    /// a file that fails to load leaves the current encoder in place. The new encoder is
+    /// configured with [`BytePairEncoder::with_config_from`] the current one, so options and
+    /// special tokens carry over. Other reloads and stores wait until this one is done.
+    ///
+    /// ## Arguments
+    ///
+    /// * `file_path` - The path to the new vocabulary file.
+    ///
+    /// ## Returns
+    ///
+    /// The encoder that was replaced, or the error that prevented loading the file.
+    pub fn reload_from_file(
+        &self,
+        file_path: &str,
+    ) -> Result<Arc<BytePairEncoder>, BytePairEncoderError> {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let encoder = BytePairEncoder::new_from_file(file_path)?.with_config_from(&self.load());
+        Ok(self.swap(encoder))
+    }
+}
@@ -0,0 +1,78 @@
+//! # Keyed-hash token IDs for privacy-preserving analytics.
+//!
+//! Dictionary IDs can be mapped back to tokens by anyone who has (or can guess) the vocabulary, so
+//! logging them is close to logging the text itself. [`SaltedIds`] instead derives each ID from an
+//! HMAC-SHA256 of the token text under a secret key: the same token always gets the same ID, so
+//! frequencies and co-occurrences can still be analyzed, but without the key the IDs cannot be
+//! linked back to tokens, even by hashing a candidate vocabulary.
+//!
+//! An ID is the first 8 bytes of `HMAC-SHA256(key, token)`, read as a big-endian `u64`, so it can
+//! be reproduced by any HMAC implementation. Collisions are possible but negligible for
+//! vocabulary-sized token sets.
+//!
+//! ## Example
+//!
+//! ```
+//! use bpe_tokenizer::{salted::SaltedIds, BytePairEncoder};
+//!
+//! let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+//! let salted = SaltedIds::new(b"secret key");
+//!
+//! let ids = salted.ids(&vocab.tokenize("Hello world. Hello!"));
+//! assert_eq!(ids.len(), 7);
+//! assert_eq!(ids[1], ids[5]); // "▁hello" both times
+//! assert_ne!(ids[1], ids[2]);
+//!
+//! // A different key gives unrelated IDs.
+//! assert_ne!(SaltedIds::new(b"other key").id("▁hello"), ids[1]);
+//! ```
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// # Maps tokens to IDs derived from a keyed hash of their text.
+///
+/// See the [module documentation](self) for how IDs are computed.
+#[derive(Clone)]
+pub struct SaltedIds {
+    /// The HMAC state after absorbing the key, cloned for each token.
+    mac: Hmac<Sha256>,
+}
+
+impl SaltedIds {
+    /// # Creates a mapping keyed with `key`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The secret key. Anyone holding it can link IDs back to tokens, so keep it out of
+    ///   the logs it protects. Keys of any length are accepted; 32 random bytes are plenty.
+    pub fn new(key: &[u8]) -> Self {
+        SaltedIds {
+            mac: Hmac::new_from_slice(key).expect("HMAC accepts keys of any length"),
+        }
+    }
+
+    /// # Returns the salted ID of a token.
+    ///
+    /// Any string has an ID, including special tokens and tokens outside the vocabulary.
+    pub fn id(&self, token: &str) -> u64 {
+        let mut mac = self.mac.clone();
+        mac.update(token.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        let mut id = [0; 8];
+        id.copy_from_slice(&digest[..8]);
+        u64::from_be_bytes(id)
+    }
+
+    /// # Returns the salted IDs of a sequence of tokens, in order.
+    pub fn ids<S: AsRef<str>>(&self, tokens: &[S]) -> Vec<u64> {
+        tokens.iter().map(|token| self.id(token.as_ref())).collect()
+    }
+}
+
+/// Only shows that this is a `SaltedIds`, so the key never ends up in logs.
+impl std::fmt::Debug for SaltedIds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SaltedIds").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,275 @@
+use std::{collections::BTreeMap, io};
+
+use crate::{constants::BYTE_TOKENS, BytePairEncoder, TextMode};
+
+/// The word break character SentencePiece models always use.
+const SENTENCEPIECE_WORD_BREAK: &str = "▁";
+
+/// `ModelProto.SentencePiece.Type` values.
+const PIECE_NORMAL: u64 = 1;
+const PIECE_UNKNOWN: u64 = 2;
+const PIECE_CONTROL: u64 = 3;
+const PIECE_BYTE: u64 = 6;
+
+/// `TrainerSpec.ModelType.BPE`.
+const MODEL_TYPE_BPE: u64 = 2;
+
+/// Builds a serialized SentencePiece `ModelProto` for `encoder`.
+///
+/// The pieces are the encoder's ID table, scored like the vocabulary. Registered and sentence
+/// marker tokens become control pieces, the unknown token the unknown piece, and, when
+/// `TextMode::Lossless` has all 256 byte tokens, those become byte pieces with byte fallback
+/// enabled. `TextMode::Natural` gets a normalizer lowercasing each character, and every mode but
+/// `TextMode::Lossless` adds a dummy `▁` prefix and collapses whitespace.
+pub(crate) fn model_proto(encoder: &BytePairEncoder) -> io::Result<Vec<u8>> {
+    let special = encoder.special_tokens();
+    if special.word_break() != SENTENCEPIECE_WORD_BREAK {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "cannot write word break `{}`, SentencePiece only uses `{}`",
+                special.word_break(),
+                SENTENCEPIECE_WORD_BREAK
+            ),
+        ));
+    }
+    let unknown_id = encoder.unknown_token_id().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "cannot write unknown token `{}` without an ID, SentencePiece requires one",
+                special.unknown()
+            ),
+        )
+    })?;
+
+    let mode = encoder.options.mode;
+    let byte_fallback = mode == TextMode::Lossless
+        && BYTE_TOKENS
+            .iter()
+            .all(|token| encoder.token_to_id(token).is_some());
+    let control: Vec<&str> = special
+        .iter()
+        .filter(|&token| token != special.unknown())
+        .chain(special.registered().iter().map(String::as_str))
+        .collect();
+
+    let mut model = Message::default();
+    let scores = encoder.tokens.iter().map(|(_, score)| score as f32);
+    for (token, score) in encoder
+        .export_id_table()
+        .into_iter()
+        .zip(scores.chain(std::iter::repeat(0.0)))
+    {
+        let kind = if token == special.unknown() {
+            PIECE_UNKNOWN
+        } else if byte_fallback && BYTE_TOKENS.contains(&token) {
+            PIECE_BYTE
+        } else if control.contains(&token) {
+            PIECE_CONTROL
+        } else {
+            PIECE_NORMAL
+        };
+        let mut piece = Message::default();
+        piece.bytes(1, token.as_bytes());
+        piece.float(2, score);
+        piece.int(3, kind);
+        model.bytes(1, &piece.0);
+    }
+
+    let id = |token: Option<&str>| {
+        token
+            .and_then(|token| encoder.token_to_id(token))
+            .map_or(-1, i64::from)
+    };
+    let mut trainer = Message::default();
+    trainer.int(3, MODEL_TYPE_BPE);
+    trainer.int(4, encoder.vocab_size() as u64);
+    trainer.int(35, u64::from(byte_fallback));
+    trainer.int(40, u64::from(unknown_id));
+    // Negative IDs, meaning "none", are sign-extended to 64 bits, as protobuf encodes `int32`.
+    trainer.int(41, id(Some(special.sentence_start())) as u64);
+    trainer.int(42, id(Some(special.sentence_end())) as u64);
+    trainer.int(43, id(special.pad()) as u64);
+    trainer.bytes(45, special.unknown().as_bytes());
+    trainer.bytes(46, special.sentence_start().as_bytes());
+    trainer.bytes(47, special.sentence_end().as_bytes());
+    if let Some(pad) = special.pad() {
+        trainer.bytes(48, pad.as_bytes());
+    }
+    model.bytes(2, &trainer.0);
+
+    let lossless = mode == TextMode::Lossless;
+    let mut normalizer = Message::default();
+    if mode == TextMode::Natural {
+        normalizer.bytes(1, b"lowercase");
+        normalizer.bytes(2, &lowercase_charsmap());
+    } else {
+        normalizer.bytes(1, b"identity");
+    }
+    normalizer.int(3, u64::from(!lossless));
+    normalizer.int(4, u64::from(!lossless));
+    normalizer.int(5, 1);
+    model.bytes(3, &normalizer.0);
+
+    Ok(model.0)
+}
+
+/// A protobuf message being serialized, field by field.
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(u64::from(field << 3 | wire_type));
+    }
+
+    /// Writes a varint field: an integer, boolean, or enum.
+    fn int(&mut self, field: u32, value: u64) {
+        self.key(field, 0);
+        self.varint(value);
+    }
+
+    fn float(&mut self, field: u32, value: f32) {
+        self.key(field, 5);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a length-delimited field: a string, bytes, or an embedded message.
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, 2);
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+    }
+}
+
+/// Builds a SentencePiece `precompiled_charsmap` lowercasing each character.
+///
+/// The charsmap is the size of a darts-clone double-array trie in bytes (`u32`, little-endian),
+/// the trie's units, and the replacement strings, each terminated by a NUL byte. The trie maps the
+/// UTF-8 bytes of every character that lowercases to something else to the offset of its
+/// replacement. Characters are lowercased on their own, so a final `Σ` becomes `σ`, where
+/// `str::to_lowercase` makes it `ς`.
+fn lowercase_charsmap() -> Vec<u8> {
+    let mut keys = vec![];
+    let mut replacements = vec![];
+    for c in (0..=char::MAX as u32).filter_map(char::from_u32) {
+        let lower: String = c.to_lowercase().collect();
+        if lower.chars().ne(std::iter::once(c)) {
+            keys.push((c.to_string(), replacements.len() as u32));
+            replacements.extend_from_slice(lower.as_bytes());
+            replacements.push(0);
+        }
+    }
+
+    let units = double_array(&keys);
+    let mut charsmap = Vec::with_capacity(4 + units.len() * 4 + replacements.len());
+    charsmap.extend_from_slice(&(units.len() as u32 * 4).to_le_bytes());
+    for unit in units {
+        charsmap.extend_from_slice(&unit.to_le_bytes());
+    }
+    charsmap.extend_from_slice(&replacements);
+    charsmap
+}
+
+/// A node of the byte trie that [`double_array`] lays out.
+#[derive(Default)]
+struct TrieNode {
+    children: BTreeMap<u8, usize>,
+    value: Option<u32>,
+}
+
+/// Lays out a darts-clone double-array trie mapping each key to its value.
+///
+/// Each unit stores its label in the low byte, whether it has a value in bit 8, and the XOR
+/// offset from its position to its children's base in bits 10 to 30. A node's child labeled `c`
+/// is at `base ^ c`, and its value, if any, at `base` itself, in a unit with bit 31 set. Every
+/// base is used by one node only, so a unit found at `base ^ c` with label `c` is that node's
+/// child, and the array is padded to whole blocks of 256 units, so every `base ^ c` is in it.
+fn double_array(keys: &[(String, u32)]) -> Vec<u32> {
+    let mut nodes = vec![TrieNode::default()];
+    for (key, value) in keys {
+        let mut node = 0;
+        for &byte in key.as_bytes() {
+            node = match nodes[node].children.get(&byte) {
+                Some(&child) => child,
+                None => {
+                    nodes.push(TrieNode::default());
+                    let child = nodes.len() - 1;
+                    nodes[node].children.insert(byte, child);
+                    child
+                }
+            };
+        }
+        nodes[node].value = Some(*value);
+    }
+
+    let mut units = vec![0u32];
+    let mut occupied = vec![true];
+    let mut is_base = vec![];
+    let mut first_free = 1;
+    let mut pending = vec![(0, 0)];
+    while let Some((node, position)) = pending.pop() {
+        let node = &nodes[node];
+        let labels: Vec<usize> = node
+            .value
+            .map(|_| 0)
+            .into_iter()
+            .chain(node.children.keys().map(|&byte| usize::from(byte)))
+            .collect();
+        if labels.is_empty() {
+            continue;
+        }
+
+        let free = |slot: usize, occupied: &[bool]| !occupied.get(slot).copied().unwrap_or(false);
+        let base = (first_free..)
+            .filter(|&slot| free(slot, &occupied))
+            .map(|slot| slot ^ labels[0])
+            .find(|&base| {
+                !is_base.get(base).copied().unwrap_or(false)
+                    && labels.iter().all(|&label| free(base ^ label, &occupied))
+            })
+            .expect("the slots past the end of the array are free");
+
+        let end = labels
+            .iter()
+            .map(|&label| base ^ label)
+            .max()
+            .unwrap_or(base)
+            + 1;
+        if units.len() < end {
+            units.resize(end, 0);
+            occupied.resize(end, false);
+        }
+        if is_base.len() <= base {
+            is_base.resize(base + 1, false);
+        }
+        is_base[base] = true;
+        units[position] |= ((position ^ base) as u32) << 10;
+        if let Some(value) = node.value {
+            units[position] |= 1 << 8;
+            units[base] = 1 << 31 | value;
+            occupied[base] = true;
+        }
+        for (&byte, &child) in &node.children {
+            let slot = base ^ usize::from(byte);
+            units[slot] = u32::from(byte);
+            occupied[slot] = true;
+            pending.push((child, slot));
+        }
+        while occupied.get(first_free).copied().unwrap_or(false) {
+            first_free += 1;
+        }
+    }
+    // Lookups read `base ^ c` for any byte `c`, which stays within the 256-unit block of `base`.
+    units.resize((units.len() + 255) / 256 * 256, 0);
+    units
+}
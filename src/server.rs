@@ -0,0 +1,329 @@
+//! # A tiny HTTP service exposing a `BytePairEncoder`.
+//!
+//! This lets services written in other languages use the exact same tokenizer without
+//! reimplementing it. The server only depends on the standard library and handles connections on
+//! a fixed pool of worker threads, with a timeout on every read and write, so slow or idle
+//! clients can neither exhaust threads nor hold one forever.
+//!
+//! Every endpoint takes a `POST` request whose body is the UTF-8 text to process, and answers
+//! with JSON:
+//!
+//! | Endpoint    | Response                                                        |
+//! |-------------|-----------------------------------------------------------------|
+//! | `/tokenize` | The tokens, e.g. `["<s>","▁hello","</s>"]`                      |
+//! | `/encode`   | The token IDs, as [`BytePairEncoder::encode`] returns them      |
+//! | `/count`    | The number of tokens, e.g. `3`                                  |
+//!
+//! Errors are answered with a plain text body and the usual status codes (`400`, `404`, `405`,
+//! and `431` for a request line and headers over 64 KiB, or more than 100 headers).
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use bpe_tokenizer::{server::Server, BytePairEncoder};
+//!
+//! let encoder = BytePairEncoder::new_from_file("vocab.txt").unwrap();
+//! let server = Server::bind(Arc::new(encoder), "127.0.0.1:8080").unwrap();
+//! server.run().unwrap();
+//! ```
+//!
+//! ```text
+//! $ curl -d 'Hello, world!' http://127.0.0.1:8080/tokenize
+//! ["<s>","▁hello","▁world","</s>"]
+//! ```
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Take, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    thread,
+    time::Duration,
+};
+
+use crate::{capabilities, json::json_string, BytePairEncoder};
+
+/// The largest request body accepted, in bytes.
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// The largest request line and headers accepted, in bytes, including their line breaks.
+const MAX_HEADER_SIZE: u64 = 64 * 1024;
+
+/// The largest number of headers accepted.
+const MAX_HEADERS: usize = 100;
+
+/// How long a read or write on a connection may block before the connection is dropped, unless
+/// changed with [`Server::with_timeout`].
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// # An HTTP server exposing a `BytePairEncoder`.
+///
+/// See the [module documentation](self) for the endpoints.
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    encoder: Arc<BytePairEncoder>,
+    workers: usize,
+    timeout: Duration,
+}
+
+impl Server {
+    /// # Creates a server listening on the given address.
+    ///
+    /// The server has one worker thread per available CPU (see
+    /// [`capabilities::parallelism`]) and a timeout of 30 seconds on each read and write, which
+    /// [`Server::with_workers`] and [`Server::with_timeout`] change.
+    ///
+    /// ## Arguments
+    ///
+    /// * `encoder` - The encoder to expose.
+    /// * `addr` - The address to listen on. Use port `0` to let the OS choose a free port.
+    ///
+    /// ## Returns
+    ///
+    /// The server, or the I/O error that prevented binding the address.
+    pub fn bind<A: ToSocketAddrs>(encoder: Arc<BytePairEncoder>, addr: A) -> io::Result<Self> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            encoder,
+            workers: capabilities::parallelism(),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// # Sets the number of worker threads, and so the number of connections served at once.
+    ///
+    /// Connections beyond that many wait in a queue of the same size, and then in the backlog of
+    /// the operating system, until a worker is free. A value of `0` is treated as `1`.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// # Sets how long a read or write on a connection may block before it is dropped.
+    ///
+    /// A zero duration is treated as one millisecond, since the standard library rejects it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout.max(Duration::from_millis(1));
+        self
+    }
+
+    /// # Returns the address the server is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// # Serves requests until accepting a connection fails.
+    ///
+    /// Connections are handed to the worker threads, one at a time each. Errors on individual
+    /// connections, including timeouts, are not reported, since they only affect that client.
+    pub fn run(self) -> io::Result<()> {
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(self.workers);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..self.workers {
+            let receiver = Arc::clone(&receiver);
+            let encoder = Arc::clone(&self.encoder);
+            let timeout = self.timeout;
+            thread::spawn(move || loop {
+                let next = receiver
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .recv();
+                match next {
+                    Ok(stream) => {
+                        let _ = handle_connection(&encoder, stream, timeout);
+                    }
+                    Err(_) => break,
+                }
+            });
+        }
+
+        // Sending blocks while every worker is busy and the queue is full, which leaves further
+        // connections in the backlog of the operating system.
+        for stream in self.listener.incoming() {
+            if sender.send(stream?).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a single request from `stream` and writes the response, giving up on any read or write
+/// that blocks for longer than `timeout`.
+fn handle_connection(
+    encoder: &BytePairEncoder,
+    stream: TcpStream,
+    timeout: Duration,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        Ok((method, path, body)) => respond(encoder, &method, &path, body),
+        Err(RequestError::HeadersTooLarge) => Response::error(
+            431,
+            "Request Header Fields Too Large",
+            "request line or headers too large",
+        ),
+        Err(RequestError::Io(e)) => Response::error(400, "Bad Request", &e.to_string()),
+    };
+    response.write_to(&stream)?;
+
+    // Closing a connection with unread input resets it, which can discard the response before the
+    // client reads it, so the client is told that nothing more is coming and its input is drained.
+    stream.shutdown(Shutdown::Write)?;
+    io::copy(&mut reader.take(MAX_BODY_SIZE as u64), &mut io::sink())?;
+    Ok(())
+}
+
+/// Why a request could not be read.
+enum RequestError {
+    /// The request line and headers are longer than `MAX_HEADER_SIZE`, or there are more than
+    /// `MAX_HEADERS` headers.
+    HeadersTooLarge,
+    /// The request is malformed, or reading it failed.
+    Io(io::Error),
+}
+
+impl From<io::Error> for RequestError {
+    fn from(e: io::Error) -> Self {
+        RequestError::Io(e)
+    }
+}
+
+/// Reads a line of the request head, failing once the head is longer than the limit of `head`.
+fn read_head_line<R: BufRead>(
+    head: &mut Take<R>,
+    line: &mut String,
+) -> Result<usize, RequestError> {
+    let read = head.read_line(line)?;
+    if !line.ends_with('\n') && head.limit() == 0 {
+        return Err(RequestError::HeadersTooLarge);
+    }
+    Ok(read)
+}
+
+/// Reads the method, path, and body of an HTTP/1.x request.
+fn read_request<R: BufRead>(reader: &mut R) -> Result<(String, String, Vec<u8>), RequestError> {
+    let invalid = |message: &str| {
+        RequestError::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            message.to_string(),
+        ))
+    };
+
+    let mut head = reader.by_ref().take(MAX_HEADER_SIZE);
+    let mut request_line = String::new();
+    read_head_line(&mut head, &mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(invalid("malformed request line")),
+    };
+
+    let mut content_length = 0;
+    let mut headers = 0;
+    loop {
+        let mut header = String::new();
+        if read_head_line(&mut head, &mut header)? == 0 {
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADERS {
+            return Err(RequestError::HeadersTooLarge);
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("invalid Content-Length"))?;
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok((method, path, body))
+}
+
+/// Computes the response to a request.
+fn respond(encoder: &BytePairEncoder, method: &str, path: &str, body: Vec<u8>) -> Response {
+    if !matches!(path, "/tokenize" | "/encode" | "/count") {
+        return Response::error(404, "Not Found", "unknown endpoint");
+    }
+    if method != "POST" {
+        return Response::error(405, "Method Not Allowed", "use POST");
+    }
+    let text = match String::from_utf8(body) {
+        Ok(text) => text,
+        Err(_) => return Response::error(400, "Bad Request", "body is not valid UTF-8"),
+    };
+
+    let json = match path {
+        "/tokenize" => {
+            let tokens: Vec<String> = encoder
+                .tokenize_iter(&text)
+                .map(|t| json_string(&t))
+                .collect();
+            format!("[{}]", tokens.join(","))
+        }
+        "/encode" => {
+            let ids: Vec<String> = encoder
+                .encode_iter(&text)
+                .map(|id| id.to_string())
+                .collect();
+            format!("[{}]", ids.join(","))
+        }
+        _ => encoder.tokenize_iter(&text).count().to_string(),
+    };
+    Response {
+        status: 200,
+        reason: "OK",
+        content_type: "application/json",
+        body: json,
+    }
+}
+
+/// An HTTP response.
+struct Response {
+    status: u16,
+    reason: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    /// Creates a plain text error response.
+    fn error(status: u16, reason: &'static str, message: &str) -> Self {
+        Response {
+            status,
+            reason,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", message),
+        }
+    }
+
+    /// Writes the response, closing the connection afterwards.
+    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.reason,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )?;
+        writer.flush()
+    }
+}
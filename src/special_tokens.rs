@@ -0,0 +1,164 @@
+use crate::{
+    constants::*,
+    options::{CompatLevel, EncoderOptions, MissingSpecialTokenPolicy, TextMode, UnknownStrategy},
+    vocab::Vocab,
+};
+
+/// # The special tokens emitted by a `BytePairEncoder`.
+///
+/// Special tokens are resolved when an encoder is created, and again whenever its options change.
+/// Each one comes from [`SpecialTokenOptions`](crate::SpecialTokenOptions) when set there, and is
+/// otherwise detected from the vocabulary by looking for common conventions:
+///
+/// | Token          | Candidates, in order of preference | Fallback |
+/// |----------------|------------------------------------|----------|
+/// | sentence start | `<s>`, `[CLS]`, `<bos>`            | `<s>`    |
+/// | sentence end   | `</s>`, `[SEP]`, `<eos>`           | `</s>`   |
+/// | unknown        | `<unk>`, `[UNK]`, `<UNK>`, `⁇`     | `<unk>`  |
+/// | padding        | `<pad>`, `[PAD]`                   | none     |
+/// | mask           | `<mask>`, `[MASK]`                 | none     |
+///
+/// The word break character that starts the tokens of a new word is `▁`, unless overridden.
+///
+/// Detection was added in version 0.2 and changes the tokens of vocabularies that contain the
+/// candidates, so it requires [`CompatLevel::V0_2`] or later. With [`CompatLevel::V0_1`], nothing
+/// is detected, and each token is its override or fallback, as in version 0.1.
+///
+/// A fallback or override may name a token that is not in the vocabulary, in which case it has no
+/// ID. [`MissingSpecialTokenPolicy::Register`] gives such tokens reserved IDs following the last
+/// vocabulary ID, and [`BytePairEncoder::validate_special_tokens`](crate::BytePairEncoder::validate_special_tokens)
+/// reports them as an error.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::BytePairEncoder;
+///
+/// let vocab = BytePairEncoder::new_from_str("[CLS]\t0\n[SEP]\t0\n[UNK]\t0\n[PAD]\t0").unwrap();
+/// let special = vocab.special_tokens();
+/// assert_eq!(special.sentence_start(), "[CLS]");
+/// assert_eq!(special.sentence_end(), "[SEP]");
+/// assert_eq!(special.unknown(), "[UNK]");
+/// assert_eq!(special.pad(), Some("[PAD]"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpecialTokens {
+    sentence_start: String,
+    sentence_end: String,
+    unknown: String,
+    pad: Option<String>,
+    mask: Option<String>,
+    word_break: String,
+
+    /// Special tokens missing from the vocabulary that were given reserved IDs, in ID order.
+    registered: Vec<String>,
+}
+
+impl SpecialTokens {
+    /// Resolves the special tokens from the given options and vocabulary.
+    pub(crate) fn resolve(encoder_options: &EncoderOptions, vocab: &Vocab) -> Self {
+        let options = &encoder_options.special_tokens;
+        let detection = encoder_options.compat >= CompatLevel::V0_2;
+        let detect = |candidates: &[&str]| {
+            candidates
+                .iter()
+                .filter(|_| detection)
+                .find(|candidate| vocab.contains_key(candidate))
+                .map(|candidate| candidate.to_string())
+        };
+
+        let mut special_tokens = SpecialTokens {
+            sentence_start: options
+                .sentence_start
+                .clone()
+                .or_else(|| detect(SENTENCE_START_TOKEN_CANDIDATES))
+                .unwrap_or_else(|| SENTENCE_START_TOKEN.to_string()),
+            sentence_end: options
+                .sentence_end
+                .clone()
+                .or_else(|| detect(SENTENCE_END_TOKEN_CANDIDATES))
+                .unwrap_or_else(|| SENTENCE_END_TOKEN.to_string()),
+            unknown: options
+                .unknown
+                .clone()
+                .or_else(|| detect(UNKNOWN_TOKEN_CANDIDATES))
+                .unwrap_or_else(|| UNKNOWN_TOKEN.to_string()),
+            pad: options.pad.clone().or_else(|| detect(PAD_TOKEN_CANDIDATES)),
+            mask: options
+                .mask
+                .clone()
+                .or_else(|| detect(MASK_TOKEN_CANDIDATES)),
+            word_break: options
+                .word_break
+                .map_or_else(|| WORD_BREAK_CHAR.to_string(), String::from),
+            registered: Vec::new(),
+        };
+
+        if options.missing == MissingSpecialTokenPolicy::Register {
+            let mut registered: Vec<String> = Vec::new();
+            let lossless = encoder_options.mode == TextMode::Lossless;
+            let bytes = lossless || encoder_options.unknown == UnknownStrategy::Bytes;
+            let case_markers = [CAPITALIZED_TOKEN, UPPERCASE_TOKEN]
+                .into_iter()
+                .filter(|_| lossless);
+            let byte_tokens = BYTE_TOKENS.into_iter().filter(|_| bytes);
+            let tokens = special_tokens.iter().chain(case_markers).chain(byte_tokens);
+            for token in tokens {
+                if !vocab.contains_key(token) && !registered.iter().any(|r| r == token) {
+                    registered.push(token.to_string());
+                }
+            }
+            special_tokens.registered = registered;
+        }
+
+        special_tokens
+    }
+
+    /// Iterates over the special tokens: sentence start, sentence end, unknown, padding, and mask.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &str> {
+        [
+            Some(self.sentence_start()),
+            Some(self.sentence_end()),
+            Some(self.unknown()),
+            self.pad(),
+            self.mask(),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Returns the special tokens that were given reserved IDs, in ID order.
+    pub(crate) fn registered(&self) -> &[String] {
+        &self.registered
+    }
+
+    /// Returns the token marking the start of a sentence.
+    pub fn sentence_start(&self) -> &str {
+        &self.sentence_start
+    }
+
+    /// Returns the token marking the end of a sentence.
+    pub fn sentence_end(&self) -> &str {
+        &self.sentence_end
+    }
+
+    /// Returns the token emitted for input that the vocabulary cannot cover.
+    pub fn unknown(&self) -> &str {
+        &self.unknown
+    }
+
+    /// Returns the padding token, if the vocabulary has one or one was configured.
+    pub fn pad(&self) -> Option<&str> {
+        self.pad.as_deref()
+    }
+
+    /// Returns the mask token, if the vocabulary has one or one was configured.
+    pub fn mask(&self) -> Option<&str> {
+        self.mask.as_deref()
+    }
+
+    /// Returns the word break character that the tokens of a new word start with.
+    pub fn word_break(&self) -> &str {
+        &self.word_break
+    }
+}
@@ -0,0 +1,20 @@
+/// # A pinned tokenization of a text, used to detect behavior changes.
+///
+/// Test vectors are produced by [`BytePairEncoder::generate_test_vectors`] and checked with
+/// [`BytePairEncoder::verify_test_vectors`]. Storing them alongside an application (e.g. in its
+/// test suite) makes any change in tokenization visible, whether it comes from upgrading this
+/// crate, changing options, or swapping vocabularies.
+///
+/// [`BytePairEncoder::generate_test_vectors`]: crate::BytePairEncoder::generate_test_vectors
+/// [`BytePairEncoder::verify_test_vectors`]: crate::BytePairEncoder::verify_test_vectors
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TestVector {
+    /// The input text.
+    pub text: String,
+
+    /// The expected tokens.
+    pub tokens: Vec<String>,
+
+    /// The expected ID of each token, or `None` for tokens without an ID.
+    pub ids: Vec<Option<u32>>,
+}
@@ -1,6 +1,24 @@
-use std::{fs::File, io::Write};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
-use crate::{BytePairEncoder, BytePairEncoderError};
+use crate::{
+    capabilities, cooccurrence_counts, evaluate, evaluate_segmentation, read_ids, write_ids,
+    BytePairEncoder, BytePairEncoderError, CompactStore, CompatLevel, ConfusablePolicy,
+    EmojiPolicy, EmptySentencePolicy, EncoderOptions, Encoding, HashMapStore, HyphenationPolicy,
+    IdStreamOptions, InvisibleCharPolicy, LineBreakPolicy, Metadata, Metrics,
+    MissingSpecialTokenPolicy, NumberPolicy, Padding, PaddingSide, PunctuationPolicy,
+    ReloadableEncoder, ScoreConvention, SegmentationStrategy, SpecialTextPolicy,
+    SpecialTokenOptions, StreamLimits, TextMode, TokenPriors, TokenizerPool, Trainer,
+    TrainerBuilder, TrainerOptions, TrainerPreset, TrieStore, UnknownStrategy, VocabOrder,
+};
 
 #[test]
 fn test_new_valid_file() {
@@ -27,10 +45,12 @@ fn test_new_invalid_file() {
     // Test with a non-existent file
     let result = BytePairEncoder::new_from_file("non_existent_file.txt");
     assert!(result.is_err());
-    assert!(matches!(
-        result.unwrap_err(),
-        BytePairEncoderError::InvalidFile(_)
-    ));
+    let err = result.unwrap_err();
+    assert!(matches!(err, BytePairEncoderError::InvalidFile { .. }));
+
+    // The underlying I/O error is preserved as the source.
+    let source = std::error::Error::source(&err).unwrap();
+    assert!(source.downcast_ref::<std::io::Error>().is_some());
 }
 
 #[test]
@@ -64,10 +84,10 @@ fn test_new_from_str_invalid_format() {
     let result = BytePairEncoder::new_from_str(input);
 
     assert!(result.is_err());
-    assert_eq!(
+    assert!(matches!(
         result.unwrap_err(),
-        BytePairEncoderError::InvalidVocabularyInput
-    );
+        BytePairEncoderError::InvalidVocabularyInput { line: 1 }
+    ));
 }
 
 #[test]
@@ -76,10 +96,10 @@ fn test_new_from_str_invalid_score() {
     let result = BytePairEncoder::new_from_str(input);
 
     assert!(result.is_err());
-    assert_eq!(
+    assert!(matches!(
         result.unwrap_err(),
-        BytePairEncoderError::InvalidVocabularyInput
-    );
+        BytePairEncoderError::InvalidScore { line: 2, .. }
+    ));
 }
 
 #[test]
@@ -92,6 +112,11 @@ fn test_new_default_small_with_tokenization() {
     let vocab = result.unwrap();
     assert!(!vocab.tokens.is_empty());
 
+    // IDs follow the order of the vocabulary file.
+    assert_eq!(vocab.token_to_id("<unk>"), Some(0));
+    assert_eq!(vocab.token_to_id("<s>"), Some(1));
+    assert_eq!(vocab.token_to_id("</s>"), Some(2));
+
     // Test tokenizing a phrase
     let text = "This is a test sentence.";
     let tokenized = vocab.tokenize(text);
@@ -437,9 +462,7 @@ fn test_tokenize_with_sentence_markers() {
     let vocab = BytePairEncoder::new_from_str(vocab_str).unwrap();
 
     let sentence = "Hello, World!";
-    let tokenized: Vec<String> = vocab
-        .tokenize_with_sentence_markers_iter(sentence)
-        .collect();
+    let tokenized: Vec<_> = vocab.tokenize_sentence(sentence).flatten().collect();
 
     assert_eq!(
         tokenized,
@@ -460,9 +483,7 @@ fn test_tokenize_with_sentence_markers_unicode() {
     let vocab = BytePairEncoder::new_from_str(vocab_str).unwrap();
 
     let sentence = "こんにちは、世界！";
-    let tokenized: Vec<String> = vocab
-        .tokenize_with_sentence_markers_iter(sentence)
-        .collect();
+    let tokenized: Vec<_> = vocab.tokenize_sentence(sentence).flatten().collect();
 
     assert_eq!(
         tokenized,
@@ -531,3 +552,4437 @@ fn test_tokenize_word_partial_match() {
         vec!["▁".to_string(), "partial".to_string(), "<unk>".to_string()]
     );
 }
+
+#[test]
+fn test_line_break_policy_normalize() {
+    let vocab_str = "hello\t1\nworld\t2\n▁\t3";
+    let vocab = BytePairEncoder::new_from_str(vocab_str)
+        .unwrap()
+        .with_options(EncoderOptions {
+            line_breaks: LineBreakPolicy::Normalize,
+            ..Default::default()
+        });
+
+    let expected = vec![
+        vec![
+            "<s>".to_string(),
+            "▁".to_string(),
+            "hello".to_string(),
+            "</s>".to_string(),
+        ],
+        vec![
+            "<s>".to_string(),
+            "▁".to_string(),
+            "world".to_string(),
+            "</s>".to_string(),
+        ],
+    ];
+
+    for separator in [
+        "\n", "\r\n", "\r", "\u{000B}", "\u{000C}", "\u{2028}", "\u{2029}",
+    ] {
+        let text = format!("Hello{}world", separator);
+        assert_eq!(vocab.tokenize_sentences(&text), expected, "{:?}", separator);
+    }
+}
+
+#[test]
+fn test_line_break_policy_preserve() {
+    let vocab_str = "hello\t1\nworld\t2\n▁\t3";
+    let vocab = BytePairEncoder::new_from_str(vocab_str).unwrap();
+    assert_eq!(vocab.options().line_breaks, LineBreakPolicy::Preserve);
+
+    // A vertical tab does not end a sentence under the Unicode rules.
+    assert_eq!(vocab.tokenize_sentences("Hello\u{000B}world").len(), 1);
+    assert_eq!(vocab.tokenize_sentences("Hello\r\nworld").len(), 2);
+}
+
+#[test]
+fn test_invisible_char_policy() {
+    let vocab_str = "hello\t1\nworld\t2\n▁\t3";
+    let text = "\u{FEFF}Hel\u{200D}lo wor\u{200C}ld";
+
+    let vocab = BytePairEncoder::new_from_str(vocab_str).unwrap();
+    assert_eq!(
+        vocab.options().invisible_chars,
+        InvisibleCharPolicy::Preserve
+    );
+    assert!(vocab.tokenize(text).contains(&"<unk>".to_string()));
+
+    let vocab = vocab.with_options(EncoderOptions {
+        invisible_chars: InvisibleCharPolicy::Strip,
+        ..Default::default()
+    });
+    assert_eq!(
+        vocab.tokenize(text),
+        vec![
+            "<s>".to_string(),
+            "▁".to_string(),
+            "hello".to_string(),
+            "▁".to_string(),
+            "world".to_string(),
+            "</s>".to_string(),
+        ]
+    );
+
+    // Invisible characters are removed before word segmentation, so they do not split words.
+    let vocab = BytePairEncoder::new_from_str("▁zerowidth\t1\n▁zero\t2\n▁width\t3")
+        .unwrap()
+        .with_options(EncoderOptions {
+            invisible_chars: InvisibleCharPolicy::Strip,
+            ..Default::default()
+        });
+    for text in [
+        "zero\u{200B}width",
+        "zero\u{2060}\u{FEFF}width",
+        "zero\u{1}width",
+    ] {
+        assert_eq!(vocab.tokenize(text), vec!["<s>", "▁zerowidth", "</s>"]);
+    }
+    assert_eq!(
+        vocab.tokenize("zero \u{200B}width"),
+        vec!["<s>", "▁zero", "▁width", "</s>"]
+    );
+}
+
+#[test]
+fn test_emoji_policy() {
+    let vocab_str = "hi\t1\n▁\t2\n▁🔗\t3\n👍🏽\t4";
+    let text = "Hi 🔗 👍🏽 👨‍👩‍👧";
+
+    // By default, emoji are dropped.
+    let vocab = BytePairEncoder::new_from_str(vocab_str).unwrap();
+    assert_eq!(
+        vocab.tokenize(text),
+        vec![
+            "<s>".to_string(),
+            "▁".to_string(),
+            "hi".to_string(),
+            "</s>".to_string(),
+        ]
+    );
+
+    // Kept emoji are matched whole, never split into fragments.
+    let vocab = vocab.with_options(EncoderOptions {
+        emoji: EmojiPolicy::Keep,
+        ..Default::default()
+    });
+    assert_eq!(
+        vocab.tokenize(text),
+        vec![
+            "<s>".to_string(),
+            "▁".to_string(),
+            "hi".to_string(),
+            "▁🔗".to_string(),
+            "▁".to_string(),
+            "👍🏽".to_string(),
+            "▁".to_string(),
+            "<unk>".to_string(),
+            "</s>".to_string(),
+        ]
+    );
+
+    let vocab = vocab.with_options(EncoderOptions {
+        emoji: EmojiPolicy::Replace("<emoji>".to_string()),
+        ..Default::default()
+    });
+    assert_eq!(
+        vocab.tokenize("👨‍👩‍👧"),
+        vec![
+            "<s>".to_string(),
+            "▁".to_string(),
+            "<emoji>".to_string(),
+            "</s>".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_max_word_length() {
+    let vocab_str = "ab\t1\n▁ab\t2\n▁abab\t3\n▁\t4";
+    let vocab = BytePairEncoder::new_from_str(vocab_str).unwrap();
+    assert_eq!(
+        vocab.tokenize_word("▁ababab"),
+        vec!["▁abab".to_string(), "ab".to_string()]
+    );
+
+    let vocab = vocab.with_options(EncoderOptions {
+        max_word_length: Some(2),
+        ..Default::default()
+    });
+    assert_eq!(
+        vocab.tokenize("ababab"),
+        vec![
+            "<s>".to_string(),
+            "▁ab".to_string(),
+            "ab".to_string(),
+            "ab".to_string(),
+            "</s>".to_string(),
+        ]
+    );
+
+    // Words within the limit are not affected.
+    assert_eq!(
+        vocab.tokenize("ab"),
+        vec!["<s>".to_string(), "▁ab".to_string(), "</s>".to_string()]
+    );
+}
+
+#[test]
+fn test_metrics() {
+    let vocab_str = "hello\t1\nworld\t2\n▁\t3";
+    let text = "Hello, world! How are you?";
+
+    // Counters stay at zero unless enabled.
+    let vocab = BytePairEncoder::new_from_str(vocab_str).unwrap();
+    vocab.tokenize(text);
+    assert_eq!(vocab.metrics(), Metrics::default());
+
+    let vocab = vocab.with_options(EncoderOptions {
+        collect_metrics: true,
+        ..Default::default()
+    });
+    vocab.tokenize(text);
+    let metrics = vocab.metrics();
+    assert_eq!(metrics.words_tokenized, 5);
+    assert_eq!(metrics.unknown_tokens, 3);
+    assert!(metrics.candidate_lookups > 0);
+
+    // Metrics do not take part in equality.
+    assert_eq!(vocab, vocab.clone().with_options(vocab.options().clone()));
+    vocab.reset_metrics();
+    assert_eq!(vocab.metrics(), Metrics::default());
+}
+
+#[test]
+fn test_word_cache() {
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\n▁\t3\nhe\t1")
+        .unwrap()
+        .with_options(EncoderOptions {
+            collect_metrics: true,
+            cache_capacity: Some(100),
+            ..Default::default()
+        });
+    let uncached = BytePairEncoder::new_from_str(&vocab.to_string()).unwrap();
+
+    let text = "Hello world, hello hello heh.";
+    assert_eq!(vocab.tokenize(text), uncached.tokenize(text));
+    let metrics = vocab.metrics();
+    assert_eq!(metrics.cache_misses, 3);
+    assert_eq!(metrics.cache_hits, 2);
+
+    // The cache is shared safely between threads.
+    let vocab = std::sync::Arc::new(vocab);
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let vocab = std::sync::Arc::clone(&vocab);
+            std::thread::spawn(move || vocab.tokenize("Hello world, heh."))
+        })
+        .collect();
+    for handle in handles {
+        assert_eq!(
+            handle.join().unwrap(),
+            uncached.tokenize("Hello world, heh.")
+        );
+    }
+
+    vocab.clear_cache();
+    vocab.reset_metrics();
+    vocab.tokenize("hello");
+    assert_eq!(vocab.metrics().cache_misses, 1);
+
+    // Words found in the cache count their unknown tokens, as when they were tokenized.
+    for unknown in [UnknownStrategy::Unk, UnknownStrategy::Graphemes] {
+        let options = EncoderOptions {
+            collect_metrics: true,
+            unknown,
+            ..Default::default()
+        };
+        let uncached = uncached.clone().with_options(options.clone());
+        let cached = uncached.clone().with_options(EncoderOptions {
+            cache_capacity: Some(100),
+            ..options
+        });
+        for encoder in [&uncached, &cached] {
+            encoder.tokenize("xyz hello xyz xyz");
+        }
+        assert_eq!(cached.metrics().cache_hits, 2);
+        assert_eq!(
+            cached.metrics().unknown_tokens,
+            uncached.metrics().unknown_tokens
+        );
+    }
+
+    // The least recently used word is evicted from a full cache.
+    let vocab = uncached.clone().with_options(EncoderOptions {
+        collect_metrics: true,
+        cache_capacity: Some(1),
+        ..Default::default()
+    });
+    vocab.tokenize("hello hello world hello");
+    assert_eq!(vocab.metrics().cache_hits, 1);
+    assert_eq!(vocab.metrics().cache_misses, 3);
+
+    // A capacity of 0 disables the cache.
+    let vocab = uncached.with_options(EncoderOptions {
+        collect_metrics: true,
+        cache_capacity: Some(0),
+        ..Default::default()
+    });
+    vocab.tokenize("hello hello");
+    assert_eq!(vocab.metrics().cache_hits + vocab.metrics().cache_misses, 0);
+}
+
+#[test]
+fn test_display_and_from_str() {
+    let vocab: BytePairEncoder = "hello\t1\nworld\t2\n▁\t-3\nabc\t1".parse().unwrap();
+    assert_eq!(vocab.tokens.len(), 4);
+    assert_eq!(vocab.to_string(), "hello\t1\nworld\t2\n▁\t-3\nabc\t1\n");
+
+    let reparsed: BytePairEncoder = vocab.to_string().parse().unwrap();
+    assert_eq!(reparsed, vocab);
+
+    assert!("hello 1".parse::<BytePairEncoder>().is_err());
+}
+
+#[test]
+fn test_metadata() {
+    let input = "#@name: test\n#@license: MIT\n#@built-by: ci\nhello\t1\n#@notmeta\t2\n";
+    let vocab = BytePairEncoder::new_from_str(input).unwrap();
+    let metadata = vocab.metadata();
+    assert_eq!(metadata.name.as_deref(), Some("test"));
+    assert_eq!(metadata.license.as_deref(), Some("MIT"));
+    assert_eq!(metadata.version, None);
+    assert_eq!(
+        metadata.extra.get("built-by").map(String::as_str),
+        Some("ci")
+    );
+
+    // Lines with a tab are always tokens.
+    assert_eq!(vocab.token_to_id("#@notmeta"), Some(1));
+    assert_eq!(vocab.to_string(), input);
+
+    // Entries are escaped onto a single line, and round-trip exactly.
+    let mut metadata = Metadata {
+        corpus: Some("news\n2024".to_string()),
+        license: Some("see: LICENSE\t(C:\\dir)".to_string()),
+        ..Default::default()
+    };
+    metadata
+        .extra
+        .insert("source: url".to_string(), "https://example.com".to_string());
+    let vocab = vocab.with_metadata(metadata.clone());
+    assert_eq!(
+        vocab.to_string(),
+        "#@corpus: news\\n2024\n#@license: see: LICENSE\\t(C:\\\\dir)\n\
+         #@source\\: url: https://example.com\nhello\t1\n#@notmeta\t2\n"
+    );
+    let restored = BytePairEncoder::new_from_str(&vocab.to_string()).unwrap();
+    assert_eq!(restored.metadata(), &metadata);
+    assert!(Metadata::default().is_empty());
+}
+
+#[test]
+fn test_token_ids() {
+    let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2\nhello\t3").unwrap();
+
+    // Duplicates keep their first ID and take the last score.
+    assert_eq!(vocab.vocab_size(), 2);
+    assert_eq!(vocab.token_to_id("hello"), Some(0));
+    assert_eq!(vocab.token_to_id("world"), Some(1));
+    assert_eq!(vocab.tokens.get("hello"), Some(&3));
+    assert_eq!(vocab.id_to_token(1), Some("world"));
+    assert_eq!(vocab.id_to_token(2), None);
+}
+
+#[test]
+fn test_unknown_token() {
+    // Detected from the vocabulary.
+    let vocab = BytePairEncoder::new_from_str("hello\t1\n[UNK]\t0\n▁\t2").unwrap();
+    assert_eq!(vocab.unknown_token(), "[UNK]");
+    assert_eq!(vocab.unknown_token_id(), Some(1));
+    assert_eq!(
+        vocab.tokenize("hello you"),
+        vec![
+            "<s>".to_string(),
+            "▁".to_string(),
+            "hello".to_string(),
+            "▁".to_string(),
+            "[UNK]".to_string(),
+            "</s>".to_string(),
+        ]
+    );
+
+    // Falls back to `<unk>`.
+    let vocab = BytePairEncoder::new_from_str("hello\t1").unwrap();
+    assert_eq!(vocab.unknown_token(), "<unk>");
+    assert_eq!(vocab.unknown_token_id(), None);
+
+    // Configured explicitly.
+    let vocab = vocab.with_options(EncoderOptions {
+        special_tokens: SpecialTokenOptions {
+            unknown: Some("⁇".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    assert_eq!(vocab.unknown_token(), "⁇");
+    assert_eq!(vocab.tokenize_word("x"), vec!["⁇".to_string()]);
+}
+
+#[test]
+fn test_special_tokens_detection() {
+    let vocab_str = "[PAD]\t0\n[UNK]\t0\n[CLS]\t0\n[SEP]\t0\nhello\t-1\n▁\t-2";
+    let vocab = BytePairEncoder::new_from_str(vocab_str).unwrap();
+
+    let special = vocab.special_tokens();
+    assert_eq!(special.sentence_start(), "[CLS]");
+    assert_eq!(special.sentence_end(), "[SEP]");
+    assert_eq!(special.unknown(), "[UNK]");
+    assert_eq!(special.pad(), Some("[PAD]"));
+    assert_eq!(
+        vocab.tokenize("Hello"),
+        vec![
+            "[CLS]".to_string(),
+            "▁".to_string(),
+            "hello".to_string(),
+            "[SEP]".to_string(),
+        ]
+    );
+
+    // Overrides take precedence over detection.
+    let vocab = vocab.with_options(EncoderOptions {
+        special_tokens: SpecialTokenOptions {
+            sentence_start: Some("<bos>".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    assert_eq!(vocab.special_tokens().sentence_start(), "<bos>");
+    assert_eq!(vocab.special_tokens().sentence_end(), "[SEP]");
+
+    // Without any known conventions, the historical defaults are used.
+    let vocab = BytePairEncoder::new_from_str("hello\t1").unwrap();
+    let special = vocab.special_tokens();
+    assert_eq!(special.sentence_start(), "<s>");
+    assert_eq!(special.sentence_end(), "</s>");
+    assert_eq!(special.unknown(), "<unk>");
+    assert_eq!(special.pad(), None);
+
+    // Detection came with version 0.2, so pinning 0.1 keeps the tokens of 0.1.
+    let pinned = BytePairEncoder::new_from_str(vocab_str)
+        .unwrap()
+        .with_options(EncoderOptions {
+            compat: CompatLevel::V0_1,
+            ..Default::default()
+        });
+    let special = pinned.special_tokens();
+    assert_eq!(special.sentence_start(), "<s>");
+    assert_eq!(special.unknown(), "<unk>");
+    assert_eq!(special.pad(), None);
+    assert_eq!(pinned.tokenize("Hello"), vec!["<s>", "▁", "hello", "</s>"]);
+    let pinned = pinned.with_options(EncoderOptions {
+        compat: CompatLevel::V0_2,
+        ..Default::default()
+    });
+    assert_eq!(pinned.special_tokens().sentence_start(), "[CLS]");
+}
+
+#[test]
+fn test_missing_special_tokens() {
+    let vocab = BytePairEncoder::new_from_str("<s>\t0\nhello\t1").unwrap();
+    assert!(matches!(
+        vocab.validate_special_tokens(),
+        Err(BytePairEncoderError::MissingSpecialToken { token }) if token == "</s>"
+    ));
+    assert_eq!(vocab.token_to_id("</s>"), None);
+    assert_eq!(vocab.vocab_size(), 2);
+
+    // Registered tokens get reserved IDs after the vocabulary, in a fixed order.
+    let vocab = vocab.with_options(EncoderOptions {
+        special_tokens: SpecialTokenOptions {
+            missing: MissingSpecialTokenPolicy::Register,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    assert!(vocab.validate_special_tokens().is_ok());
+    assert_eq!(vocab.vocab_size(), 4);
+    assert_eq!(vocab.token_to_id("<s>"), Some(0));
+    assert_eq!(vocab.token_to_id("</s>"), Some(2));
+    assert_eq!(vocab.token_to_id("<unk>"), Some(3));
+    assert_eq!(vocab.unknown_token_id(), Some(3));
+    assert_eq!(vocab.id_to_token(3), Some("<unk>"));
+    assert_eq!(vocab.id_to_token(4), None);
+
+    // Registered tokens are not part of the vocabulary source.
+    assert_eq!(vocab.to_string(), "<s>\t0\nhello\t1\n");
+
+    // With the `Error` policy, missing tokens are reported when the options are applied.
+    let result = BytePairEncoder::builder()
+        .missing_special_tokens(MissingSpecialTokenPolicy::Error)
+        .build_from_str("<s>\t0\n</s>\t0\nhello\t1");
+    assert!(matches!(
+        result,
+        Err(BytePairEncoderError::MissingSpecialToken { token }) if token == "<unk>"
+    ));
+    assert!(BytePairEncoder::builder()
+        .missing_special_tokens(MissingSpecialTokenPolicy::Error)
+        .build_from_str("<s>\t0\n</s>\t0\n<unk>\t0\nhello\t1")
+        .is_ok());
+}
+
+#[test]
+fn test_try_with_options() {
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1").unwrap();
+    let invalid_config = |options: EncoderOptions| {
+        matches!(
+            vocab.clone().try_with_options(options),
+            Err(BytePairEncoderError::InvalidConfig { .. })
+        )
+    };
+    let special = |special_tokens: SpecialTokenOptions| EncoderOptions {
+        special_tokens,
+        ..Default::default()
+    };
+
+    assert!(invalid_config(EncoderOptions {
+        max_word_length: Some(0),
+        ..Default::default()
+    }));
+    assert!(invalid_config(EncoderOptions {
+        emoji: EmojiPolicy::Replace(String::new()),
+        ..Default::default()
+    }));
+    assert!(invalid_config(EncoderOptions {
+        empty_sentences: EmptySentencePolicy::EmitToken(String::new()),
+        ..Default::default()
+    }));
+    assert!(invalid_config(special(SpecialTokenOptions {
+        unknown: Some(String::new()),
+        ..Default::default()
+    })));
+    assert!(invalid_config(special(SpecialTokenOptions {
+        word_break: Some(' '),
+        ..Default::default()
+    })));
+    // Only the GPT-2 constructors load the merges this strategy needs.
+    assert!(invalid_config(EncoderOptions {
+        segmentation: SegmentationStrategy::Merges,
+        ..Default::default()
+    }));
+    assert!(matches!(
+        vocab.clone().try_with_options(EncoderOptions {
+            max_sentence_tokens: Some(1),
+            ..Default::default()
+        }),
+        Err(BytePairEncoderError::TruncationLimitTooSmall { .. })
+    ));
+
+    let options = EncoderOptions {
+        max_word_length: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(
+        vocab.clone().try_with_options(options.clone()).unwrap(),
+        vocab.with_options(options)
+    );
+}
+
+#[test]
+fn test_write_vocab_order() {
+    let vocab = BytePairEncoder::new_from_str("b\t1\nd\t-2\nc\t5\na\t1").unwrap();
+
+    let mut by_id = Vec::new();
+    vocab.write_vocab(&mut by_id, VocabOrder::Id).unwrap();
+    assert_eq!(String::from_utf8(by_id).unwrap(), vocab.to_string());
+
+    let mut by_score = Vec::new();
+    vocab.write_vocab(&mut by_score, VocabOrder::Score).unwrap();
+    assert_eq!(
+        String::from_utf8(by_score).unwrap(),
+        "c\t5\na\t1\nb\t1\nd\t-2\n"
+    );
+}
+
+#[test]
+fn test_rescore_from_corpus() {
+    let mut vocab = BytePairEncoder::new_from_str("▁\t-6\n▁a\t-1\nab\t-2\nb\t-3\n▁c\t-5").unwrap();
+    assert_eq!(vocab.tokenize("ab"), vec!["<s>", "▁a", "b", "</s>"]);
+
+    // "ab" is frequent in the corpus, so it now wins over "▁a".
+    vocab.rescore_from_corpus(vec!["cab cab".to_string(), "cab".to_string()]);
+    assert_eq!(vocab.tokenize("ab"), vec!["<s>", "▁", "ab", "</s>"]);
+    assert_eq!(vocab.to_string(), "▁\t-4\n▁a\t-2\nab\t0\nb\t-3\n▁c\t-1\n");
+
+    // Rescoring leaves the metrics and the cache untouched.
+    let mut vocab = vocab.with_options(EncoderOptions {
+        collect_metrics: true,
+        cache_capacity: Some(10),
+        ..Default::default()
+    });
+    vocab.rescore_from_corpus(["ab ab c"]);
+    assert_eq!(vocab.metrics(), Metrics::default());
+    let mut cache = Vec::new();
+    vocab.write_cache(&mut cache).unwrap();
+    assert_eq!(String::from_utf8(cache).unwrap().lines().count(), 2);
+}
+
+#[test]
+fn test_trainer_extend() {
+    let corpus = "the cat sat on the mat. the cat ate the rat.";
+    let mut trainer = Trainer::new(TrainerOptions {
+        vocab_size: 20,
+        ..Default::default()
+    });
+    trainer.feed(corpus);
+
+    let base = trainer.train();
+    assert_eq!(base.vocab_size(), 20);
+    assert_eq!(base.id_to_token(0), Some("<unk>"));
+    assert_eq!(base.tokenize("the"), vec!["<s>", "▁the", "</s>"]);
+    // Training is deterministic.
+    assert_eq!(base, trainer.train());
+
+    // Extending keeps every existing token ID and learns the new domain on top.
+    let mut trainer = Trainer::new(TrainerOptions {
+        vocab_size: 36,
+        ..Default::default()
+    });
+    trainer.feed("quantum quanta quantized quantum");
+    let extended = trainer.extend(&base);
+    assert_eq!(extended.vocab_size(), 36);
+    for id in 0..20 {
+        assert_eq!(extended.id_to_token(id), base.id_to_token(id));
+    }
+    assert_eq!(
+        extended.tokenize("quantum"),
+        vec!["<s>", "▁quantum", "</s>"]
+    );
+    assert_eq!(extended.tokenize("the"), base.tokenize("the"));
+}
+
+#[test]
+fn test_trainer_streaming() {
+    let corpus = "the cat sat on the mat\nthe cat ate the rat\n";
+
+    let mut from_text = Trainer::new(TrainerOptions {
+        vocab_size: 20,
+        ..Default::default()
+    });
+    from_text.feed(corpus);
+
+    let mut from_reader = Trainer::new(TrainerOptions {
+        vocab_size: 20,
+        ..Default::default()
+    });
+    from_reader.feed_reader(corpus.as_bytes()).unwrap();
+    assert_eq!(from_text.train(), from_reader.train());
+
+    // With a bounded word table, rare words are dropped but frequent ones survive.
+    let mut bounded = Trainer::new(TrainerOptions {
+        vocab_size: 100,
+        max_distinct_words: Some(10),
+        ..Default::default()
+    });
+    bounded.feed_iter((0..1_000).map(|i| format!("common words {}", i)));
+    let encoder = bounded.train();
+    assert_eq!(encoder.tokenize("common"), vec!["<s>", "▁common", "</s>"]);
+    assert_eq!(encoder.tokenize("words"), vec!["<s>", "▁words", "</s>"]);
+
+    // The limit holds within a single document, and discarded words resume from their
+    // estimated counts.
+    let mut bounded = Trainer::new(TrainerOptions {
+        max_distinct_words: Some(4),
+        ..Default::default()
+    });
+    let numbers: Vec<String> = (0..100).map(|i| format!("n{}", i)).collect();
+    bounded.feed(&format!("rare {} rare rare", numbers.join(" ")));
+    let mut checkpoint = vec![];
+    bounded.write_checkpoint(&mut checkpoint).unwrap();
+    let checkpoint = String::from_utf8(checkpoint).unwrap();
+    assert!(
+        checkpoint
+            .lines()
+            .filter(|line| line.starts_with("w\t"))
+            .count()
+            <= 4
+    );
+    let rare = checkpoint
+        .lines()
+        .find_map(|line| line.strip_suffix("\t▁rare")?.strip_prefix("w\t"))
+        .unwrap();
+    assert!(rare.parse::<u64>().unwrap() >= 3);
+}
+
+#[test]
+fn test_trainer_options() {
+    let corpus = "aaab aaab aaab aaab aaab aaab aaab aaab aaab aaab aaab aaab aaab aaab aaab ab x";
+    let train = |options: TrainerOptions| {
+        let mut trainer = Trainer::new(options);
+        trainer.feed(corpus);
+        trainer.train()
+    };
+
+    // Custom special tokens come first and are detected by the trained encoder.
+    let encoder = train(TrainerOptions {
+        special_tokens: vec!["[UNK]".into(), "[CLS]".into(), "[SEP]".into()],
+        ..Default::default()
+    });
+    assert_eq!(encoder.id_to_token(0), Some("[UNK]"));
+    assert_eq!(encoder.special_tokens().sentence_start(), "[CLS]");
+
+    // The rare "x" falls outside the character coverage.
+    let encoder = train(TrainerOptions {
+        character_coverage: 0.98,
+        ..Default::default()
+    });
+    assert_eq!(encoder.token_to_id("x"), None);
+    assert_eq!(encoder.tokenize("x"), vec!["<s>", "▁", "<unk>", "</s>"]);
+
+    // Merges stop at the minimum pair frequency and the maximum token length.
+    let encoder = train(TrainerOptions {
+        min_pair_frequency: 16,
+        ..Default::default()
+    });
+    assert!(encoder.token_to_id("aa").is_some());
+    assert!(encoder.token_to_id("ab").is_some());
+    assert_eq!(encoder.token_to_id("▁aa"), None);
+
+    let encoder = train(TrainerOptions {
+        max_token_length: Some(3),
+        ..Default::default()
+    });
+    assert!(encoder.token_to_id("▁aa").is_some());
+    // The special tokens come first; every learned token is at most three characters long.
+    let longest = encoder.export_id_table()[3..]
+        .iter()
+        .map(|token| token.chars().count())
+        .max();
+    assert_eq!(longest, Some(3));
+    assert_eq!(encoder.vocab_size(), 12);
+    assert_eq!(encoder.token_to_id("▁aaab"), None);
+}
+
+#[test]
+fn test_encoder_hash_and_eq() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let hash = |vocab: &BytePairEncoder| {
+        let mut hasher = DefaultHasher::new();
+        vocab.hash(&mut hasher);
+        hasher.finish()
+    };
+
+    let a = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    let b = BytePairEncoder::new_from_str("#@name: b\nhello\t1\nworld\t2").unwrap();
+    assert_eq!(a, b);
+    assert_eq!(hash(&a), hash(&b));
+    // The fingerprint is stable across runs and platforms.
+    assert_eq!(a.fingerprint(), 0xe15c_e5e4_7582_ce8a);
+
+    // Scores, IDs, and options all matter.
+    let c = BytePairEncoder::new_from_str("hello\t1\nworld\t3").unwrap();
+    assert_ne!(a, c);
+    assert_ne!(a.fingerprint(), c.fingerprint());
+    let d = a.clone().with_options(EncoderOptions {
+        emoji: EmojiPolicy::Keep,
+        ..Default::default()
+    });
+    assert_ne!(a, d);
+    assert_ne!(hash(&a), hash(&d));
+}
+
+#[test]
+fn test_max_sentence_tokens() {
+    let vocab =
+        BytePairEncoder::new_from_str("▁one\t1\n▁two\t1\n▁three\t1\n▁\t0\nf\t0\no\t0\nu\t0\nr\t0")
+            .unwrap()
+            .with_options(EncoderOptions {
+                max_sentence_tokens: Some(4),
+                ..Default::default()
+            });
+
+    assert_eq!(
+        vocab.tokenize_sentences("One two three. One."),
+        vec![
+            vec!["<s>", "▁one", "▁two", "</s>"],
+            vec!["<s>", "▁three", "</s>"],
+            vec!["<s>", "▁one", "</s>"],
+        ]
+    );
+
+    // A word that cannot fit gets a segment of its own.
+    assert_eq!(
+        vocab.tokenize_sentences("one four two"),
+        vec![
+            vec!["<s>", "▁one", "</s>"],
+            vec!["<s>", "▁", "f", "o", "u", "r", "</s>"],
+            vec!["<s>", "▁two", "</s>"],
+        ]
+    );
+
+    // A limit that cannot hold the markers and a token is rejected.
+    let too_small = |result: Result<(), BytePairEncoderError>| {
+        matches!(
+            result,
+            Err(BytePairEncoderError::TruncationLimitTooSmall {
+                limit: 2,
+                minimum: 3
+            })
+        )
+    };
+    assert!(too_small(
+        EncoderOptions::from_json(r#"{"truncation": {"max_sentence_tokens": 2}}"#).map(drop)
+    ));
+    assert!(too_small(
+        BytePairEncoder::builder()
+            .options(EncoderOptions {
+                max_sentence_tokens: Some(2),
+                ..Default::default()
+            })
+            .build_from_str("▁one\t1")
+            .map(drop)
+    ));
+}
+
+#[test]
+fn test_tokenize_last_n() {
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\n▁\t0\nh\t0").unwrap();
+    let text = "Hello world. Hello!\r\n\nWorld, hello.\nHello\u{2029}world hh";
+    let all = vocab.tokenize(text);
+
+    for n in 0..=all.len() + 2 {
+        let skip = all.len().saturating_sub(n);
+        assert_eq!(vocab.tokenize_last_n(text, n), all[skip..].to_vec());
+    }
+
+    let vocab = vocab.with_options(EncoderOptions {
+        line_breaks: LineBreakPolicy::Normalize,
+        max_sentence_tokens: Some(3),
+        ..Default::default()
+    });
+    let all = vocab.tokenize(text);
+    assert_eq!(
+        vocab.tokenize_last_n(text, 5),
+        all[all.len() - 5..].to_vec()
+    );
+
+    // Tokens without an ID are not counted, as they are left out of `encode`.
+    let vocab = BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\n▁hello\t1\n▁world\t2").unwrap();
+    let all = vocab.encode(text);
+    for n in 0..=all.len() + 2 {
+        let skip = all.len().saturating_sub(n);
+        assert_eq!(vocab.encode_last_n(text, n), all[skip..].to_vec());
+    }
+
+    // Lines are not tokenized on their own when line breaks are tokens, words are rejoined
+    // across them, or GPT-2's pre-tokenizer attaches them to neighbouring whitespace.
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\nhello\t1")
+        .unwrap()
+        .with_options(EncoderOptions::lossless());
+    let text = "hello\n\nhello world\n";
+    assert_eq!(
+        vocab.tokenize_last_n(text, 5),
+        vec!["<0x0A>", "hello", "▁world", "<0x0A>", "</s>"]
+    );
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁hyphenated\t1")
+        .unwrap()
+        .with_options(EncoderOptions {
+            hyphenation: HyphenationPolicy::Rejoin,
+            ..Default::default()
+        });
+    let text = "hello hyphen-\nated hello";
+    assert_eq!(
+        vocab.tokenize_last_n(text, 3),
+        vec!["▁hyphenated", "▁hello", "</s>"]
+    );
+    let vocab =
+        BytePairEncoder::new_from_gpt2_str(r#"{"a": 0, "b": 1, "Ġ": 2, "Ċ": 3}"#, "").unwrap();
+    let text = "a  \n  b";
+    let all = vocab.encode(text);
+    for n in 0..=all.len() + 2 {
+        let skip = all.len().saturating_sub(n);
+        assert_eq!(vocab.encode_last_n(text, n), all[skip..].to_vec());
+    }
+}
+
+#[test]
+fn test_tokenize_iter_is_lazy() {
+    let vocab = BytePairEncoder::new_from_str("▁hel\t1\nlo\t1\n▁wor\t1\nld\t1")
+        .unwrap()
+        .with_options(EncoderOptions {
+            collect_metrics: true,
+            ..Default::default()
+        });
+
+    // Only the first word is matched, and only far enough to yield its first token.
+    let first: Vec<String> = vocab.tokenize_iter("hello world").take(2).collect();
+    assert_eq!(first, vec!["<s>", "▁hel"]);
+    assert_eq!(vocab.metrics().words_tokenized, 1);
+
+    assert_eq!(
+        vocab.tokenize("hello world"),
+        vec!["<s>", "▁hel", "lo", "▁wor", "ld", "</s>"]
+    );
+}
+
+#[test]
+fn test_filter_tokens() {
+    let vocab = BytePairEncoder::new_from_str("<unk>\t0\n▁héllo\t1\n▁hello\t1\n▁\t0\nh\t0\né\t0")
+        .unwrap()
+        .with_options(EncoderOptions {
+            emoji: EmojiPolicy::Keep,
+            ..Default::default()
+        });
+
+    // Keep ASCII tokens (plus the word break character).
+    let ascii = vocab.filter_tokens(|token, _| token.trim_start_matches('▁').is_ascii());
+    assert_eq!(ascii.vocab_size(), 4);
+    assert_eq!(ascii.token_to_id("▁hello"), Some(1));
+    assert_eq!(ascii.token_to_id("é"), None);
+    assert_eq!(ascii.options(), vocab.options());
+    assert_eq!(
+        ascii.tokenize("héllo"),
+        vec!["<s>", "▁", "h", "<unk>", "</s>"]
+    );
+
+    // The original encoder is untouched.
+    assert_eq!(vocab.tokenize("héllo"), vec!["<s>", "▁héllo", "</s>"]);
+
+    // The merges of a GPT-2 style vocabulary are kept, except those whose token is dropped.
+    let vocab_json = r#"{"H": 0, "e": 1, "l": 2, "o": 3, "Ġ": 4, "w": 5, "r": 6, "d": 7, "!": 8,
+        "ll": 9, "llo": 10, "He": 11, "Hello": 12, "Ġw": 13, "or": 14, "orl": 15, "Ġworl": 16,
+        "Ġworld": 17}"#;
+    let merges = "#version: 0.2\nl l\nll o\nH e\nHe llo\nĠ w\no r\nor l\nĠw orl\nĠworl d";
+    let gpt2 = BytePairEncoder::new_from_gpt2_str(vocab_json, merges).unwrap();
+    let kept = gpt2.filter_tokens(|_, _| true);
+    assert_eq!(kept, gpt2);
+    assert_eq!(kept.tokenize("Hello world!"), vec!["Hello", "Ġworld", "!"]);
+    let no_world = gpt2.filter_tokens(|token, _| token != "Ġworld");
+    assert_eq!(
+        no_world.tokenize("Hello world!"),
+        vec!["Hello", "Ġworl", "d", "!"]
+    );
+}
+
+#[test]
+fn test_test_vectors() {
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\n▁\t0").unwrap();
+    let texts = ["Hello, world!", "", "World hello. Hello?"];
+    let vectors = vocab.generate_test_vectors(texts);
+    assert_eq!(vectors.len(), 3);
+    assert!(vectors[1].tokens.is_empty());
+    assert!(vocab.verify_test_vectors(&vectors).is_ok());
+
+    // Changing the tokens is detected.
+    let changed = BytePairEncoder::new_from_str("▁hello\t1\n▁\t0").unwrap();
+    assert!(matches!(
+        changed.verify_test_vectors(&vectors),
+        Err(BytePairEncoderError::TestVectorMismatch { index: 0, .. })
+    ));
+
+    // So is shifting the IDs, even with the same tokens.
+    let shifted = BytePairEncoder::new_from_str("▁world\t2\n▁hello\t1\n▁\t0").unwrap();
+    assert!(matches!(
+        shifted.verify_test_vectors(&vectors),
+        Err(BytePairEncoderError::TestVectorMismatch { index: 0, expected, actual })
+            if expected.tokens == actual.tokens && expected.ids != actual.ids
+    ));
+}
+
+#[test]
+fn test_compat_level() {
+    assert!(CompatLevel::V0_1 < CompatLevel::Latest);
+    assert_eq!(EncoderOptions::default().compat, CompatLevel::Latest);
+
+    // The tokens of version 0.1 for this vocabulary and text, as produced by that version.
+    let vocab = BytePairEncoder::new_from_str(
+        "[CLS]\t0\n[SEP]\t0\n[UNK]\t0\n<pad>\t0\n▁hello\t5\n▁wor\t2\nld\t3\n▁\t0\nl\t1\no\t1\n\
+         😀\t2\nσ\t1\nς\t1",
+    )
+    .unwrap();
+    let text =
+        "Hello, world! ... 😀 Hello\u{200d}world.\n\n---\nHELLO wor\u{ad}ld ΟΣ [CLS] xyz lol.";
+    let expected = [
+        vec!["<s>", "▁hello", "▁wor", "ld", "</s>"],
+        vec!["<s>", "▁hello", "<unk>", "o", "<unk>", "ld", "</s>"],
+        vec![
+            "<s>", "▁hello", "▁wor", "<unk>", "ld", "▁", "<unk>", "ς", "▁", "<unk>", "l", "<unk>",
+            "▁", "<unk>", "▁", "l", "o", "l", "</s>",
+        ],
+    ];
+
+    let pinned = vocab.clone().with_options(EncoderOptions {
+        compat: CompatLevel::V0_1,
+        ..Default::default()
+    });
+    assert_eq!(pinned.tokenize_sentences(text), expected);
+    assert_eq!(pinned.tokenize(text), expected.concat());
+    assert_ne!(pinned, vocab);
+
+    // Later levels detect the special tokens, and so change the tokens.
+    assert_eq!(vocab.tokenize("Hello"), vec!["[CLS]", "▁hello", "[SEP]"]);
+    assert_ne!(vocab.tokenize(text), expected.concat());
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_server() {
+    use std::{io::Read, net::TcpStream, sync::Arc};
+
+    use crate::server::Server;
+
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁\t0\n\"\t0").unwrap();
+    let ids = vocab.encode("Hello there");
+    let server = Server::bind(Arc::new(vocab), "127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || server.run());
+
+    let request = |method: &str, path: &str, body: &str| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    };
+
+    let (status, body) = request("POST", "/tokenize", "Hello there");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(body, r#"["<s>","▁hello","▁","<unk>","</s>"]"#);
+
+    // IDs are those of `encode`, which leaves out tokens without one.
+    let (_, body) = request("POST", "/encode", "Hello there");
+    assert_eq!(body, format!("{:?}", ids).replace(' ', ""));
+    assert_eq!(body, "[0,1]");
+
+    let (_, body) = request("POST", "/count", "Hello there");
+    assert_eq!(body, "5");
+
+    assert_eq!(
+        request("GET", "/count", "").0,
+        "HTTP/1.1 405 Method Not Allowed"
+    );
+    assert_eq!(request("POST", "/nope", "").0, "HTTP/1.1 404 Not Found");
+
+    // Oversized request heads are refused, whether by length or by number of headers.
+    let head = |head: String| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        // The server may close the connection before reading the whole head.
+        let _ = write!(stream, "{}\r\n\r\n", head);
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response.lines().next().unwrap().to_string()
+    };
+    let too_large = "HTTP/1.1 431 Request Header Fields Too Large";
+    let path = "a".repeat(64 * 1024);
+    assert_eq!(head(format!("POST /{} HTTP/1.1", path)), too_large);
+    let headers = "X-Header: 1\r\n".repeat(101);
+    assert_eq!(
+        head(format!("POST /count HTTP/1.1\r\n{}", headers)),
+        too_large
+    );
+    let headers = "X-Header: 1\r\n".repeat(100);
+    assert_eq!(
+        head(format!("POST /count HTTP/1.1\r\n{}", headers)),
+        "HTTP/1.1 200 OK"
+    );
+
+    // An idle client holds its worker only until the timeout, so the next client is served.
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1").unwrap();
+    let server = Server::bind(Arc::new(vocab), "127.0.0.1:0")
+        .unwrap()
+        .with_workers(1)
+        .with_timeout(Duration::from_millis(50));
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || server.run());
+    let _idle = TcpStream::connect(addr).unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "POST /count HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello"
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+}
+
+#[cfg(feature = "language")]
+#[test]
+fn test_language_detection() {
+    use std::sync::Arc;
+
+    use crate::language::{detect_script, EncoderSelector, Script};
+
+    assert_eq!(detect_script("Hello, world!"), Some(Script::Latin));
+    assert_eq!(detect_script("Γειά σου κόσμε"), Some(Script::Greek));
+    assert_eq!(detect_script("مرحبا بالعالم"), Some(Script::Arabic));
+    assert_eq!(detect_script("你好世界"), Some(Script::Han));
+    assert_eq!(detect_script("日本語のテキスト"), Some(Script::Kana));
+    assert_eq!(detect_script("안녕하세요"), Some(Script::Hangul));
+    assert_eq!(
+        detect_script("The word мир is Russian"),
+        Some(Script::Latin)
+    );
+    assert_eq!(detect_script("123 !?"), None);
+
+    let fallback = Arc::new(BytePairEncoder::new_from_str("▁\t0").unwrap());
+    let greek = Arc::new(BytePairEncoder::new_from_str("▁κόσμε\t0").unwrap());
+    let selector =
+        EncoderSelector::new(Arc::clone(&fallback)).with(Script::Greek, Arc::clone(&greek));
+    assert_eq!(selector.select("κόσμε"), &*greek);
+    assert_eq!(selector.select("world"), &*fallback);
+    assert_eq!(selector.select("42"), &*fallback);
+}
+
+#[test]
+fn test_tokenize_batch() {
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\n▁\t0\nh\t0").unwrap();
+    let long = "Hello world. ".repeat(500) + "Hh!";
+    let texts = vec![
+        "".to_string(),
+        long,
+        "World?".to_string(),
+        "...".to_string(),
+    ];
+
+    let batch = vocab.tokenize_batch(&texts);
+    assert_eq!(batch.len(), texts.len());
+    for (text, tokens) in texts.iter().zip(&batch) {
+        assert_eq!(tokens, &vocab.tokenize(text));
+    }
+    assert!(vocab.tokenize_batch::<&str>(&[]).is_empty());
+}
+
+#[test]
+fn test_empty_sentence_policy() {
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t1").unwrap();
+    let with_policy = |policy| {
+        vocab.clone().with_options(EncoderOptions {
+            line_breaks: LineBreakPolicy::Normalize,
+            empty_sentences: policy,
+            ..Default::default()
+        })
+    };
+    let text = "Hello\n---\nworld";
+
+    assert_eq!(
+        with_policy(EmptySentencePolicy::Skip).tokenize_sentences(text),
+        vec![vec!["<s>", "▁hello", "</s>"], vec!["<s>", "▁world", "</s>"]]
+    );
+    assert_eq!(
+        with_policy(EmptySentencePolicy::EmitMarkers).tokenize_sentences(text),
+        vec![
+            vec!["<s>", "▁hello", "</s>"],
+            vec!["<s>", "</s>"],
+            vec!["<s>", "▁world", "</s>"],
+        ]
+    );
+
+    let emit_token = with_policy(EmptySentencePolicy::EmitToken("<empty>".to_string()));
+    assert_eq!(emit_token.tokenize("   "), vec!["<s>", "<empty>", "</s>"]);
+    assert!(emit_token.tokenize("").is_empty());
+}
+
+#[test]
+fn test_single_char_margin() {
+    let vocab = BytePairEncoder::new_from_str("abc\t5\ncde\t0\n▁ab\t0\n▁\t0\nd\t0\ne\t0").unwrap();
+    let with_margin = |margin| {
+        vocab.clone().with_options(EncoderOptions {
+            single_char_margin: margin,
+            ..Default::default()
+        })
+    };
+
+    assert_eq!(
+        with_margin(None).tokenize("abcde"),
+        vec!["<s>", "▁", "abc", "d", "e", "</s>"]
+    );
+    assert_eq!(
+        with_margin(Some(5)).tokenize("abcde"),
+        vec!["<s>", "▁ab", "cde", "</s>"]
+    );
+    // Candidates outside the margin are not considered.
+    assert_eq!(
+        with_margin(Some(4)).tokenize("abcde"),
+        vec!["<s>", "▁", "abc", "d", "e", "</s>"]
+    );
+}
+
+#[test]
+fn test_merge_word_pieces() {
+    let vocab = BytePairEncoder::new_from_str("▁\t0\n▁the\t1\nx\t0\ny\t0\nz\t0").unwrap();
+
+    let tokens = vocab.tokenize("The xyz. Zz?");
+    assert_eq!(
+        tokens,
+        vec!["<s>", "▁the", "▁", "x", "y", "z", "</s>", "<s>", "▁", "z", "z", "</s>"]
+    );
+    assert_eq!(
+        vocab.merge_word_pieces(&tokens),
+        vec!["<s>", "the", "xyz", "</s>", "<s>", "zz", "</s>"]
+    );
+
+    // Unknown tokens are kept as units of their own.
+    assert_eq!(
+        vocab.merge_word_pieces(&["▁", "x", "<unk>", "y", "▁the"]),
+        vec!["x", "<unk>", "y", "the"]
+    );
+}
+
+#[test]
+fn test_normalize_offsets() {
+    let vocab = BytePairEncoder::new_from_str("▁a\t1").unwrap();
+    let strip = vocab.clone().with_options(EncoderOptions {
+        invisible_chars: InvisibleCharPolicy::Strip,
+        ..Default::default()
+    });
+
+    // The normalized text matches what words are prepared as for matching.
+    for word in ["İSTANBUL", "ΟΔΟΣ", "Straße", "A\u{200B}B", "ǅ"] {
+        for encoder in [&vocab, &strip] {
+            let normalized = encoder.normalize(word);
+            assert_eq!(
+                Some(format!("▁{}", normalized.as_str())),
+                encoder.prepare_word(word)
+            );
+        }
+    }
+
+    let text = "A\u{200B}İ!";
+    let normalized = strip.normalize(text);
+    assert_eq!(normalized.as_str(), "ai\u{307}!");
+    assert_eq!(&text[normalized.source_range(0..1)], "A");
+    assert_eq!(&text[normalized.source_range(1..4)], "İ");
+    assert_eq!(&text[normalized.source_range(4..5)], "!");
+    assert_eq!(normalized.source_range(5..5), text.len()..text.len());
+}
+
+#[test]
+fn test_tokenize_with_boundaries() {
+    let vocab =
+        BytePairEncoder::new_from_str("▁newyork\t5\n▁new\t1\nyork\t1\n▁is\t1\nis\t1").unwrap();
+    let text = "NewYork is. NewYork";
+
+    assert_eq!(
+        vocab.tokenize_with_boundaries(text, &[]),
+        vocab.tokenize(text)
+    );
+    // Boundaries between words, past the end, or inside a character change nothing.
+    assert_eq!(
+        vocab.tokenize_with_boundaries("NewYork is", &[7, 8, 100]),
+        vocab.tokenize("NewYork is")
+    );
+    assert_eq!(
+        vocab.tokenize_with_boundaries(text, &[15, 12]),
+        vec![
+            "<s>",
+            "▁newyork",
+            "▁is",
+            "</s>",
+            "<s>",
+            "▁new",
+            "york",
+            "</s>"
+        ]
+    );
+
+    let capped = vocab.clone().with_options(EncoderOptions {
+        max_sentence_tokens: Some(3),
+        ..Default::default()
+    });
+    assert_eq!(
+        capped.tokenize_with_boundaries("NewYork is", &[3]),
+        vec!["<s>", "▁new", "york", "</s>", "<s>", "▁is", "</s>"]
+    );
+
+    // Sentences with boundaries go through the same pipeline: empty sentence tokens, the word
+    // cache, and metrics all apply, and each piece of a word is cached on its own.
+    let cached = vocab.with_options(EncoderOptions {
+        cache_capacity: Some(64),
+        collect_metrics: true,
+        empty_sentences: EmptySentencePolicy::EmitToken("<empty>".to_string()),
+        ..Default::default()
+    });
+    for _ in 0..2 {
+        assert_eq!(
+            cached.tokenize_with_boundaries("NewYork\n?!\nNewYork", &[3, 9]),
+            vec![
+                "<s>",
+                "▁new",
+                "york",
+                "</s>",
+                "<s>",
+                "<empty>",
+                "</s>",
+                "<s>",
+                "▁newyork",
+                "</s>"
+            ]
+        );
+    }
+    let metrics = cached.metrics();
+    assert_eq!(metrics.words_tokenized, 4);
+    assert_eq!((metrics.cache_hits, metrics.cache_misses), (3, 3));
+}
+
+#[test]
+fn test_code_mode() {
+    let vocab = BytePairEncoder::new_from_str(
+        "▁get\t1\nUser\t1\nName\t1\n▁snake\t1\n_\t1\ncase\t1\n▁(\t1\n▁)\t1\n▁HTTP\t1\nServer\t1\n\
+         ▁foo\t1\n.\t1\nbar\t1",
+    )
+    .unwrap()
+    .with_options(EncoderOptions {
+        mode: TextMode::Code,
+        ..Default::default()
+    });
+
+    assert_eq!(
+        vocab.tokenize("getUserName(snake_case) HTTPServer foo.bar"),
+        vec![
+            "<s>", "▁get", "User", "Name", "▁(", "▁snake", "_", "case", "▁)", "▁HTTP", "Server",
+            "▁foo", ".", "bar", "</s>"
+        ]
+    );
+    // Lines of nothing but symbols are kept.
+    assert_eq!(vocab.tokenize(")"), vec!["<s>", "▁)", "</s>"]);
+}
+
+#[test]
+fn test_suggest_alternatives() {
+    let vocab =
+        BytePairEncoder::new_from_str("<unk>\t0\n▁cat\t1\n▁car\t2\n▁cart\t3\nca\t4\n▁dog\t5")
+            .unwrap();
+
+    // Ties on distance are broken by shared prefix, then score.
+    assert_eq!(
+        vocab.suggest_alternatives("cax", 4),
+        vec![("ca", 1), ("▁car", 1), ("▁cat", 1), ("▁cart", 2)]
+    );
+    assert!(vocab.suggest_alternatives("cat", 0).is_empty());
+    assert!(vocab
+        .suggest_alternatives("unk", 10)
+        .iter()
+        .all(|(token, _)| *token != "<unk>"));
+}
+
+#[test]
+fn test_collapse_runs() {
+    let mut tokens = vec!["<s>"];
+    tokens.extend(["<unk>"; 1000]);
+    tokens.extend(["▁a", "<unk>", "<unk>", "</s>"]);
+
+    let runs = BytePairEncoder::collapse_runs(&tokens, 3);
+    assert_eq!(
+        runs,
+        vec![
+            ("<s>".to_string(), 1),
+            ("<unk>".to_string(), 1000),
+            ("▁a".to_string(), 1),
+            ("<unk>".to_string(), 1),
+            ("<unk>".to_string(), 1),
+            ("</s>".to_string(), 1),
+        ]
+    );
+    assert_eq!(BytePairEncoder::expand_runs(&runs), tokens);
+
+    assert!(BytePairEncoder::collapse_runs::<&str>(&[], 3).is_empty());
+}
+
+#[test]
+fn test_export_id_table() {
+    let vocab = BytePairEncoder::new_from_str("#@name: ids\n<s>\t0\nb\t1\na\t2\nb\t3")
+        .unwrap()
+        .with_options(EncoderOptions {
+            special_tokens: SpecialTokenOptions {
+                missing: MissingSpecialTokenPolicy::Register,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+    // Duplicates keep their first ID, and registered special tokens come last.
+    let table = vocab.export_id_table();
+    assert_eq!(table, vec!["<s>", "b", "a", "</s>", "<unk>"]);
+    assert_eq!(table.len(), vocab.vocab_size());
+    for (id, token) in table.iter().enumerate() {
+        assert_eq!(vocab.token_to_id(token), Some(id as u32));
+        assert_eq!(vocab.id_to_token(id as u32), Some(*token));
+    }
+}
+
+#[test]
+fn test_tokenize_max_bytes() {
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\n▁\t0\né\t0").unwrap();
+    let text = "Hello world. Hello é!";
+
+    // A sentence cut short is closed.
+    assert_eq!(
+        vocab.tokenize_max_bytes(text, 10),
+        vec!["<s>", "▁hello", "</s>"]
+    );
+    // The space that would start the text is not counted, as in decoding.
+    assert_eq!(
+        vocab.tokenize_max_bytes("Hello world", 11),
+        vec!["<s>", "▁hello", "▁world", "</s>"]
+    );
+    // A dangling sentence start is dropped.
+    assert_eq!(
+        vocab.tokenize_max_bytes(text, 12),
+        vec!["<s>", "▁hello", "▁world", "</s>"]
+    );
+    // Multi-byte characters count in full.
+    assert_eq!(
+        vocab.tokenize_max_bytes(text, 19),
+        vec!["<s>", "▁hello", "▁world", "</s>", "<s>", "▁hello", "▁", "</s>"]
+    );
+    assert_eq!(vocab.tokenize_max_bytes(text, 1000), vocab.tokenize(text));
+    assert!(vocab.tokenize_max_bytes(text, 0).is_empty());
+    for max_bytes in 0..=vocab.detokenize(&vocab.tokenize(text)).len() {
+        let tokens = vocab.tokenize_max_bytes(text, max_bytes);
+        assert!(vocab.detokenize(&tokens).len() <= max_bytes);
+    }
+}
+
+#[test]
+fn test_dedup_sentences() {
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    let mut seen = HashSet::new();
+
+    assert_eq!(
+        vocab.dedup_sentences("Hello world. HELLO, world! World.", &mut seen),
+        vec![
+            vec!["<s>", "▁hello", "▁world", "</s>"],
+            vec!["<s>", "▁world", "</s>"]
+        ]
+    );
+    assert_eq!(
+        vocab.dedup_sentences("Hello. Hello world.", &mut seen),
+        vec![vec!["<s>", "▁hello", "</s>"]]
+    );
+    assert_eq!(seen.len(), 3);
+    let hashes = vocab.sentence_hashes("Hello. World world.");
+    assert!(seen.contains(&hashes[0]));
+    assert!(!seen.contains(&hashes[1]));
+}
+
+#[test]
+fn test_evaluate() {
+    let chars = BytePairEncoder::new_from_str("▁\t0\na\t0\nb\t0").unwrap();
+    let words = BytePairEncoder::new_from_str("▁ab\t1").unwrap();
+    let corpus = ["ab ab.", "ab c"];
+
+    let results = evaluate(&corpus, &[&chars, &words]);
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].chars, 10);
+    assert_eq!((results[0].tokens, results[0].unknown_tokens), (11, 1));
+    assert_eq!((results[1].tokens, results[1].unknown_tokens), (4, 1));
+    assert_eq!(results[1].unknown_rate(), 0.25);
+    assert_eq!(results[1].tokens_per_char(), 0.4);
+
+    assert!(evaluate::<&str>(&[], &[&chars])[0].unknown_rate() == 0.0);
+}
+
+#[test]
+fn test_contains_token_and_score_of() {
+    let vocab = BytePairEncoder::new_from_str("<s>\t0\n▁hello\t-3")
+        .unwrap()
+        .with_options(EncoderOptions {
+            special_tokens: SpecialTokenOptions {
+                missing: MissingSpecialTokenPolicy::Register,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+    assert!(vocab.contains_token("▁hello"));
+    assert_eq!(vocab.score_of("▁hello"), Some(-3));
+    // Registered special tokens are present, but have no score.
+    assert!(vocab.contains_token("<unk>"));
+    assert_eq!(vocab.score_of("<unk>"), None);
+    assert!(!vocab.contains_token("hello"));
+    assert_eq!(vocab.score_of("hello"), None);
+}
+
+#[test]
+fn test_with_sentence_markers() {
+    let vocab = BytePairEncoder::new_from_str("▁hi\t1\n[BOS]\t0")
+        .unwrap()
+        .with_options(EncoderOptions {
+            max_sentence_tokens: Some(5),
+            ..Default::default()
+        })
+        .with_sentence_markers("[BOS]", "[EOS]");
+
+    assert_eq!(vocab.tokenize("Hi."), vec!["[BOS]", "▁hi", "[EOS]"]);
+    assert_eq!(vocab.token_to_id("[BOS]"), Some(1));
+    assert_eq!(vocab.token_to_id("[EOS]"), None);
+    // Other options are kept.
+    assert_eq!(vocab.options().max_sentence_tokens, Some(5));
+}
+
+#[test]
+fn test_tokenize_ref() {
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\n▁\t0\n<s>\t0").unwrap();
+    let text = "Hello 👋 world! Hellooo. ...";
+    let configs = [
+        EncoderOptions::default(),
+        EncoderOptions {
+            cache_capacity: Some(10),
+            emoji: EmojiPolicy::Replace("<emoji>".to_string()),
+            ..Default::default()
+        },
+        EncoderOptions {
+            max_sentence_tokens: Some(3),
+            empty_sentences: EmptySentencePolicy::EmitToken("<empty>".to_string()),
+            ..Default::default()
+        },
+    ];
+
+    for options in configs {
+        let vocab = vocab.clone().with_options(options);
+        // Twice, to exercise the word cache.
+        for _ in 0..2 {
+            assert_eq!(vocab.tokenize_ref(text), vocab.tokenize(text));
+        }
+    }
+}
+
+#[test]
+fn test_with_config_from() {
+    let current = BytePairEncoder::new_from_str("[CLS]\t0\n[SEP]\t0\n[UNK]\t0\n▁hi\t1")
+        .unwrap()
+        .with_options(EncoderOptions {
+            line_breaks: LineBreakPolicy::Normalize,
+            cache_capacity: Some(8),
+            ..Default::default()
+        })
+        .with_metadata(Metadata {
+            name: Some("current".to_string()),
+            ..Default::default()
+        });
+
+    let next = BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\n<unk>\t0\n<pad>\t0\n▁hi\t1")
+        .unwrap()
+        .with_config_from(&current);
+
+    assert_eq!(next.options().line_breaks, LineBreakPolicy::Normalize);
+    assert_eq!(next.options().cache_capacity, Some(8));
+    assert_eq!(next.special_tokens().unknown(), "[UNK]");
+    // Without a padding token to pin, it is detected from the new vocabulary.
+    assert_eq!(next.special_tokens().pad(), Some("<pad>"));
+    assert_eq!(
+        next.tokenize("Hi\nho"),
+        vec!["[CLS]", "▁hi", "[SEP]", "[CLS]", "[UNK]", "[SEP]"]
+    );
+    assert!(next.metadata().is_empty());
+}
+
+#[test]
+fn test_peek_vocab_file() {
+    let file_path = "test_peek_vocab.txt";
+    let mut file = File::create(file_path).unwrap();
+    file.write_all(b"#@name: peek\n<unk>\t0\n\xe2\x96\x81the\t-1\n\xe2\x96\x81a\t-2\nbroken")
+        .unwrap();
+
+    // Lines past the first `n` tokens are not read, so the broken line goes unnoticed.
+    let preview = BytePairEncoder::peek_vocab_file(file_path, 3).unwrap();
+    assert_eq!(preview.metadata.name.as_deref(), Some("peek"));
+    assert_eq!(preview.entries[1], ("▁the".to_string(), -1));
+    assert_eq!(preview.entries.len(), 3);
+    assert_eq!(preview.scores, ScoreConvention::Descending);
+    assert!(matches!(
+        BytePairEncoder::peek_vocab_file(file_path, 4),
+        Err(BytePairEncoderError::InvalidVocabularyInput { line: 5 })
+    ));
+
+    let mut file = File::create(file_path).unwrap();
+    file.write_all(b"{\"hello\": 0}").unwrap();
+    assert!(matches!(
+        BytePairEncoder::peek_vocab_file(file_path, 4),
+        Err(BytePairEncoderError::UnrecognizedFormat { .. })
+    ));
+
+    // Fractional scores are only a format problem on the first token line.
+    let mut file = File::create(file_path).unwrap();
+    file.write_all(
+        b"<unk>	-1.5
+",
+    )
+    .unwrap();
+    assert!(matches!(
+        BytePairEncoder::peek_vocab_file(file_path, 4),
+        Err(BytePairEncoderError::UnrecognizedFormat { .. })
+    ));
+    let mut file = File::create(file_path).unwrap();
+    file.write_all(
+        b"<unk>	0
+\xe2\x96\x81the	-1.5
+",
+    )
+    .unwrap();
+    assert!(matches!(
+        BytePairEncoder::peek_vocab_file(file_path, 4),
+        Err(BytePairEncoderError::InvalidScore { line: 2, .. })
+    ));
+
+    std::fs::remove_file(file_path).unwrap();
+}
+
+#[test]
+fn test_reloadable_encoder() {
+    let file_path = "test_reload_vocab.txt";
+    let encoder = ReloadableEncoder::new(
+        BytePairEncoder::new_from_str("▁hello\t1")
+            .unwrap()
+            .with_sentence_markers("[BOS]", "[EOS]"),
+    );
+    let before = encoder.load();
+
+    // A file that fails to load leaves the current encoder in place.
+    assert!(encoder.reload_from_file("non_existent_file.txt").is_err());
+    assert!(Arc::ptr_eq(&before, &encoder.load()));
+
+    let mut file = File::create(file_path).unwrap();
+    file.write_all("▁hello\t1\n▁world\t1".as_bytes()).unwrap();
+    let replaced = encoder.reload_from_file(file_path).unwrap();
+    assert!(Arc::ptr_eq(&before, &replaced));
+
+    // Concurrent reloads run one at a time, each replacing the encoder the previous one stored.
+    let replaced: Vec<_> = thread::scope(|scope| {
+        let reloads: Vec<_> = (0..4)
+            .map(|_| scope.spawn(|| encoder.reload_from_file(file_path).unwrap()))
+            .collect();
+        reloads.into_iter().map(|r| r.join().unwrap()).collect()
+    });
+    std::fs::remove_file(file_path).unwrap();
+    let distinct: HashSet<_> = replaced.iter().map(Arc::as_ptr).collect();
+    assert_eq!(distinct.len(), replaced.len());
+
+    assert_eq!(before.tokenize("world"), vec!["[BOS]", "<unk>", "[EOS]"]);
+    assert_eq!(
+        encoder.load().tokenize("world"),
+        vec!["[BOS]", "▁world", "[EOS]"]
+    );
+}
+
+#[test]
+fn test_lenient_parsing() {
+    let mut input = String::from("#@name: messy\n");
+    for i in 0..100 {
+        input.push_str(&format!("token{}\t{}\n", i, -i));
+        if i % 5 == 0 {
+            input.push_str("no score here\n");
+        }
+    }
+
+    assert!(matches!(
+        BytePairEncoder::new_from_str(&input),
+        Err(BytePairEncoderError::InvalidVocabularyInput { line: 3 })
+    ));
+
+    let (vocab, report) = BytePairEncoder::new_from_str_lenient(&input);
+    assert_eq!(vocab.vocab_size(), 100);
+    assert_eq!(vocab.metadata().name.as_deref(), Some("messy"));
+    assert_eq!(report.skipped, 20);
+    assert_eq!(report.samples.len(), 10);
+    assert_eq!(report.samples[1].line, 9);
+    assert_eq!(report.samples[1].text, "no score here");
+    assert!(!report.is_clean());
+
+    let (_, report) = BytePairEncoder::new_from_str_lenient("hello\t1");
+    assert!(report.is_clean());
+}
+
+#[test]
+fn test_tokenize_with_lengths() {
+    let vocab = BytePairEncoder::new_from_str("▁\t0\n🇯🇵\t1\n▁ok\t1")
+        .unwrap()
+        .with_options(EncoderOptions {
+            emoji: EmojiPolicy::Keep,
+            ..Default::default()
+        });
+
+    let tokens = vocab.tokenize_with_lengths("OK 🇯🇵");
+    let lengths: Vec<(&str, usize, usize, usize)> = tokens
+        .iter()
+        .map(|(token, l)| (token.as_str(), l.bytes, l.chars, l.graphemes))
+        .collect();
+    assert_eq!(
+        lengths,
+        vec![
+            ("<s>", 3, 3, 3),
+            ("▁ok", 5, 3, 3),
+            ("▁", 3, 1, 1),
+            ("🇯🇵", 8, 2, 1),
+            ("</s>", 4, 4, 4),
+        ]
+    );
+}
+
+#[cfg(feature = "fixtures")]
+#[test]
+fn test_fixtures() {
+    use crate::fixtures::{fixture, fixtures};
+
+    let all = fixtures();
+    assert!(!all.is_empty());
+    for f in all {
+        assert_eq!(fixture(f.name), Some(f));
+        for expected in [f.small, f.medium, f.large] {
+            assert_eq!(expected.first(), Some(&"<s>"), "{}", f.name);
+            assert_eq!(expected.last(), Some(&"</s>"), "{}", f.name);
+        }
+    }
+    assert_eq!(fixture("klingon"), None);
+
+    #[cfg(feature = "default-small")]
+    {
+        let vocab = BytePairEncoder::new_default_small().unwrap();
+        for f in all {
+            assert_eq!(vocab.tokenize(f.text), f.small, "{}", f.name);
+        }
+    }
+    #[cfg(feature = "default-medium")]
+    {
+        let vocab = BytePairEncoder::new_default_medium().unwrap();
+        for f in all {
+            assert_eq!(vocab.tokenize(f.text), f.medium, "{}", f.name);
+        }
+    }
+    #[cfg(feature = "default-large")]
+    {
+        let vocab = BytePairEncoder::new_default_large().unwrap();
+        for f in all {
+            assert_eq!(vocab.tokenize(f.text), f.large, "{}", f.name);
+        }
+    }
+}
+
+#[cfg(feature = "huggingface")]
+#[test]
+fn test_huggingface_conversion() {
+    use std::convert::TryFrom;
+
+    use tokenizers::{
+        models::bpe::{Vocab, BPE},
+        Model,
+    };
+
+    let vocab = BytePairEncoder::new_from_str(
+        "<unk>\t0\n▁\t-1\nl\t-2\no\t-3\nw\t-4\ne\t-5\nr\t-6\n▁l\t-7\now\t-8\n▁low\t-9\ner\t-10\n▁lower\t-11",
+    )
+    .unwrap();
+    let model = BPE::try_from(&vocab).unwrap();
+    assert_eq!(model.get_vocab_size(), vocab.vocab_size());
+    for (id, token) in vocab.export_id_table().into_iter().enumerate() {
+        assert_eq!(model.token_to_id(token), Some(id as u32));
+    }
+    assert_eq!(model.get_unk_token().as_deref(), Some("<unk>"));
+
+    // Both sides agree on these words.
+    for word in ["▁low", "▁lower", "▁lowe"] {
+        let theirs: Vec<String> = model
+            .tokenize(word)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.value)
+            .collect();
+        assert_eq!(theirs, vocab.tokenize_word(word), "{}", word);
+    }
+
+    // Converting back keeps the IDs and the unknown token.
+    let back = BytePairEncoder::try_from(&model).unwrap();
+    assert_eq!(back.export_id_table(), vocab.export_id_table());
+    assert_eq!(back.unknown_token(), "<unk>");
+    assert_eq!(back.tokenize("lower"), vocab.tokenize("lower"));
+
+    // IDs with gaps can't be represented.
+    let ids: Vocab = [("a".to_string(), 0), ("b".to_string(), 2)]
+        .into_iter()
+        .collect();
+    let gapped = BPE::builder()
+        .vocab_and_merges(ids, vec![])
+        .build()
+        .unwrap();
+    assert!(matches!(
+        BytePairEncoder::try_from(&gapped),
+        Err(BytePairEncoderError::ConversionError { .. })
+    ));
+}
+
+#[test]
+fn test_export_onnx_bpe() {
+    let vocab = BytePairEncoder::new_from_str("a\t0\nb\t-1\nab\t-2\n\"\t-3\n\"ab\t-4")
+        .unwrap()
+        .with_options(EncoderOptions {
+            special_tokens: SpecialTokenOptions {
+                missing: MissingSpecialTokenPolicy::Register,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    let (mut ids, mut merges) = (Vec::new(), Vec::new());
+    vocab.export_onnx_bpe(&mut ids, &mut merges).unwrap();
+    assert_eq!(
+        String::from_utf8(ids).unwrap(),
+        "{\"a\":0,\"b\":1,\"ab\":2,\"\\\"\":3,\"\\\"ab\":4,\"<s>\":5,\"</s>\":6,\"<unk>\":7}\n"
+    );
+    assert_eq!(
+        String::from_utf8(merges).unwrap(),
+        "#version: 0.2\na b\n\" ab\n"
+    );
+
+    // Tokens with whitespace can't be written as merges.
+    let vocab = BytePairEncoder::new_from_str("a\t0\n \t-1\na \t-2").unwrap();
+    let err = vocab.export_onnx_bpe(Vec::new(), Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+/// Splits a protobuf message into its fields, as (number, varint value, length-delimited bytes).
+fn protobuf_fields(mut bytes: &[u8]) -> Vec<(u64, u64, &[u8])> {
+    fn varint(bytes: &mut &[u8]) -> u64 {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = bytes[0];
+            *bytes = &bytes[1..];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                break;
+            }
+        }
+        value
+    }
+
+    let mut fields = vec![];
+    while !bytes.is_empty() {
+        let key = varint(&mut bytes);
+        let field = match key & 7 {
+            0 => (key >> 3, varint(&mut bytes), &[][..]),
+            2 => {
+                let len = varint(&mut bytes) as usize;
+                let (value, rest) = bytes.split_at(len);
+                bytes = rest;
+                (key >> 3, 0, value)
+            }
+            5 => {
+                let (value, rest) = bytes.split_at(4);
+                bytes = rest;
+                (key >> 3, 0, value)
+            }
+            wire_type => panic!("unexpected wire type {}", wire_type),
+        };
+        fields.push(field);
+    }
+    fields
+}
+
+#[test]
+fn test_write_sentencepiece_model() {
+    let vocab =
+        BytePairEncoder::new_from_str("<unk>\t0\n<s>\t0\n</s>\t0\n▁\t0\nh\t-1\ni\t-2\n▁hi\t-3")
+            .unwrap();
+    let mut model = Vec::new();
+    vocab.write_sentencepiece_model(&mut model).unwrap();
+    let fields = protobuf_fields(&model);
+    let section = |number| {
+        fields
+            .iter()
+            .filter(move |&&(field, _, _)| field == number)
+            .map(|&(_, _, value)| protobuf_fields(value))
+    };
+
+    // Pieces keep their IDs and scores, and special tokens get their types.
+    let pieces: Vec<(String, f32, u64)> = section(1)
+        .map(|piece| {
+            let score = f32::from_le_bytes(piece[1].2.try_into().unwrap());
+            (
+                String::from_utf8(piece[0].2.to_vec()).unwrap(),
+                score,
+                piece[2].1,
+            )
+        })
+        .collect();
+    let names: Vec<&str> = pieces.iter().map(|(name, _, _)| name.as_str()).collect();
+    assert_eq!(names, vocab.export_id_table());
+    let types: Vec<u64> = pieces.iter().map(|&(_, _, kind)| kind).collect();
+    assert_eq!(types, [2, 3, 3, 1, 1, 1, 1]);
+    assert_eq!(pieces[6].1, -3.0);
+
+    let trainer = section(2).next().unwrap();
+    let trainer_field = |number| trainer.iter().find(|field| field.0 == number).unwrap().1;
+    assert_eq!(trainer_field(3), 2); // BPE
+    assert_eq!(trainer_field(4), 7);
+    assert_eq!(trainer_field(35), 0);
+    assert_eq!(
+        [40, 41, 42, 43].map(trainer_field),
+        [0, 1, 2, u64::MAX] // no padding token
+    );
+
+    // Natural mode lowercases each character and adds the dummy prefix.
+    let normalizer = section(3).next().unwrap();
+    assert_eq!(normalizer[0].2, b"lowercase");
+    assert_eq!(
+        [normalizer[2].1, normalizer[3].1, normalizer[4].1],
+        [1, 1, 1]
+    );
+    #[cfg(feature = "huggingface")]
+    {
+        let charsmap =
+            tokenizers::normalizers::precompiled::Precompiled::from(normalizer[1].2).unwrap();
+        for c in (0..=char::MAX as u32).filter_map(char::from_u32) {
+            let lower: String = c.to_lowercase().collect();
+            assert_eq!(charsmap.normalize_string(&c.to_string()), lower, "{:?}", c);
+        }
+        assert_eq!(charsmap.normalize_string("HÉLLO, Wörld"), "héllo, wörld");
+    }
+
+    // Lossless mode keeps case and whitespace, and falls back to bytes.
+    let lossless = vocab.clone().with_options(EncoderOptions::lossless());
+    let mut model = Vec::new();
+    lossless.write_sentencepiece_model(&mut model).unwrap();
+    let fields = protobuf_fields(&model);
+    let section = |number| {
+        fields
+            .iter()
+            .filter(move |&&(field, _, _)| field == number)
+            .map(|&(_, _, value)| protobuf_fields(value))
+    };
+    let byte = section(1).find(|piece| piece[0].2 == b"<0x41>").unwrap();
+    assert_eq!(byte[2].1, 6);
+    let trainer = section(2).next().unwrap();
+    assert!(trainer.contains(&(35, 1, &[][..])));
+    let normalizer = section(3).next().unwrap();
+    assert_eq!(normalizer[0].2, b"identity");
+    assert_eq!(
+        [normalizer[1].1, normalizer[2].1, normalizer[3].1],
+        [0, 0, 1]
+    );
+
+    // SentencePiece requires an unknown piece and the `▁` word break.
+    let err = BytePairEncoder::new_from_str("▁hi\t0")
+        .unwrap()
+        .write_sentencepiece_model(Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    let custom = vocab.with_options(EncoderOptions {
+        special_tokens: SpecialTokenOptions {
+            word_break: Some('_'),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let err = custom.write_sentencepiece_model(Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[cfg(feature = "salted-ids")]
+#[test]
+fn test_salted_ids() {
+    use crate::salted::SaltedIds;
+
+    // Reference values computed independently with HMAC-SHA256.
+    let salted = SaltedIds::new(b"secret");
+    assert_eq!(salted.id("▁hello"), 13_825_035_657_659_361_725);
+    assert_eq!(salted.id("<s>"), 10_706_655_840_355_475_840);
+    assert_eq!(
+        salted.ids(&["</s>", "▁world"]),
+        vec![3_905_928_994_503_091_548, 8_472_647_112_453_209_428]
+    );
+
+    // The key must not leak through Debug.
+    assert_eq!(format!("{:?}", salted), "SaltedIds { .. }");
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn test_encrypted_vocab() {
+    let key = [42; 32];
+    let vocab = BytePairEncoder::new_from_str("#@domain: legal\n▁tort\t5\n▁law\t3").unwrap();
+    let mut encrypted = Vec::new();
+    vocab
+        .write_encrypted_vocab(&mut encrypted, VocabOrder::Id, &key)
+        .unwrap();
+    assert!(!encrypted.windows(4).any(|w| w == "tort".as_bytes()));
+
+    // Every write uses a fresh nonce, so writing the same vocabulary twice gives different bytes.
+    let mut again = Vec::new();
+    vocab
+        .write_encrypted_vocab(&mut again, VocabOrder::Id, &key)
+        .unwrap();
+    assert_ne!(again, encrypted);
+    assert_eq!(
+        BytePairEncoder::new_from_encrypted_bytes(&again, &key).unwrap(),
+        vocab
+    );
+
+    let file_path = "test_encrypted_vocab.bin";
+    File::create(file_path)
+        .unwrap()
+        .write_all(&encrypted)
+        .unwrap();
+    let loaded = BytePairEncoder::new_from_encrypted_file(file_path, &key);
+    std::fs::remove_file(file_path).unwrap();
+    let loaded = loaded.unwrap();
+    assert_eq!(loaded, vocab);
+    assert_eq!(loaded.metadata(), vocab.metadata());
+
+    let decryption_fails = |container: &[u8], key: &[u8; 32]| {
+        matches!(
+            BytePairEncoder::new_from_encrypted_bytes(container, key),
+            Err(BytePairEncoderError::DecryptionError { .. })
+        )
+    };
+    assert!(decryption_fails(&encrypted, &[0; 32]));
+    let mut tampered = encrypted.clone();
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(decryption_fails(&tampered, &key));
+    assert!(decryption_fails(&encrypted[..15], &key));
+    assert!(decryption_fails(b"hello\t1", &key));
+}
+
+#[test]
+fn test_hyphenation_policy() {
+    let vocab = BytePairEncoder::new_from_str(
+        "▁hyphen\t5\n▁hyphenated\t9\n▁well\t3\n▁known\t3\n▁word\t4\n▁a\t1\nated\t2",
+    )
+    .unwrap();
+    let rejoin = vocab.clone().with_options(EncoderOptions {
+        hyphenation: HyphenationPolicy::Rejoin,
+        ..Default::default()
+    });
+
+    // Line breaks split sentences, so by default the word ends up in two of them.
+    let text = "A hyphen-\nated word.";
+    assert_eq!(
+        vocab.tokenize(text),
+        vec![
+            "<s>",
+            "▁a",
+            "▁hyphen",
+            "</s>",
+            "<s>",
+            "<unk>",
+            "ated",
+            "▁word",
+            "</s>"
+        ]
+    );
+    let expected = vec!["<s>", "▁a", "▁hyphenated", "▁word", "</s>"];
+    assert_eq!(rejoin.tokenize(text), expected);
+    assert_eq!(rejoin.tokenize("A hyphen- \r\n  ated word."), expected);
+    assert_eq!(rejoin.tokenize("A hyphen\u{AD}\nated word."), expected);
+    assert_eq!(
+        rejoin
+            .clone()
+            .with_options(EncoderOptions {
+                hyphenation: HyphenationPolicy::Rejoin,
+                line_breaks: LineBreakPolicy::Normalize,
+                ..Default::default()
+            })
+            .tokenize(text),
+        expected
+    );
+
+    // Soft hyphens inside a line are removed.
+    assert_eq!(
+        rejoin.tokenize("hy\u{AD}phen\u{AD}ated"),
+        vec!["<s>", "▁hyphenated", "</s>"]
+    );
+
+    // Ordinary hyphens, and line breaks before a capital, are left alone.
+    assert_eq!(
+        rejoin.tokenize("well-known"),
+        vec!["<s>", "▁well", "▁known", "</s>"]
+    );
+    assert_eq!(
+        rejoin.tokenize("A hyphen-\nWord."),
+        vec!["<s>", "▁a", "▁hyphen", "</s>", "<s>", "▁word", "</s>"]
+    );
+
+    // Offsets of the normalized text still point into the original.
+    let normalized = rejoin.normalize("Hyphen-\nated");
+    assert_eq!(normalized.as_str(), "hyphenated");
+    assert_eq!(normalized.source_range(6..10), 8..12);
+}
+
+#[test]
+fn test_number_policy() {
+    let vocab = BytePairEncoder::new_from_str(
+        "▁revenue\t1\n▁in\t1\n▁0\t1\n▁00\t2\n▁0000\t3\n▁0,000.00\t4\n▁q0\t2",
+    )
+    .unwrap();
+    let with_numbers = |numbers| {
+        vocab.clone().with_options(EncoderOptions {
+            numbers,
+            ..Default::default()
+        })
+    };
+
+    let text = "Revenue $1,234.56 in Q3";
+    assert_eq!(
+        vocab.tokenize(text),
+        vec!["<s>", "▁revenue", "<unk>", "▁in", "<unk>", "</s>"]
+    );
+    assert_eq!(
+        with_numbers(NumberPolicy::ZeroDigits).tokenize(text),
+        vec!["<s>", "▁revenue", "▁0,000.00", "▁in", "▁q0", "</s>"]
+    );
+    assert_eq!(
+        with_numbers(NumberPolicy::Magnitude).tokenize(text),
+        vec!["<s>", "▁revenue", "▁0000", "▁in", "▁q0", "</s>"]
+    );
+    assert_eq!(
+        with_numbers(NumberPolicy::Magnitude).tokenize("9876 42 0.5"),
+        vec!["<s>", "▁0000", "▁00", "▁0", "</s>"]
+    );
+
+    // Offsets still point into the original text.
+    let normalized = with_numbers(NumberPolicy::Magnitude).normalize("x 12.75 y");
+    assert_eq!(normalized.as_str(), "x 00 y");
+    assert_eq!(normalized.source_range(2..4), 2..4);
+    assert_eq!(normalized.source_range(5..6), 8..9);
+}
+
+#[test]
+fn test_confusable_policy() {
+    let vocab = BytePairEncoder::new_from_str("▁free\t1\n▁paypal\t1\n▁москва\t1\n▁win\t1\n▁100\t1")
+        .unwrap();
+    let fold = vocab.clone().with_options(EncoderOptions {
+        confusables: ConfusablePolicy::Fold,
+        ..Default::default()
+    });
+
+    // Full-width, mathematical bold, and a Cyrillic `а` inside a Latin word.
+    let text = "ＦＲＥＥ 𝐰𝐢𝐧 pаypal １００";
+    assert_eq!(
+        vocab.tokenize(text),
+        vec!["<s>", "<unk>", "<unk>", "<unk>", "<unk>", "</s>"]
+    );
+    assert_eq!(
+        fold.tokenize(text),
+        vec!["<s>", "▁free", "▁win", "▁paypal", "▁100", "</s>"]
+    );
+
+    // Genuine Cyrillic words are not folded, even when every letter has a lookalike.
+    assert_eq!(fold.tokenize("Москва"), vec!["<s>", "▁москва", "</s>"]);
+    assert_eq!(fold.normalize("Рома").as_str(), "рома");
+
+    // Folding keeps offsets into the original text.
+    let normalized = fold.normalize("ＦＲＥＥ!");
+    assert_eq!(normalized.as_str(), "free!");
+    assert_eq!(normalized.source_range(1..2), 3..6);
+}
+
+#[test]
+fn test_uncovered_spans() {
+    let vocab = BytePairEncoder::new_from_str("▁the\t1\n▁cat\t1\n▁sat\t1\n▁\t0\nzz\t1").unwrap();
+
+    // Whole unknown words, and unknown parts inside words.
+    let text = "The qat sat. The cat xzzy!";
+    let spans = vocab.uncovered_spans(text);
+    let parts: Vec<&str> = spans.iter().map(|span| &text[span.clone()]).collect();
+    assert_eq!(parts, vec!["qat", "x", "y"]);
+
+    // Spans cover whole source characters, even when lowercasing expands them.
+    assert_eq!(vocab.uncovered_spans("İ cat"), vec![0..2]);
+
+    // Nothing to report for covered text, or text without words.
+    assert!(vocab.uncovered_spans("the cat sat").is_empty());
+    assert!(vocab.uncovered_spans("... !!!").is_empty());
+
+    // Missing emoji are reported when they take part in tokenization.
+    let emoji = vocab.clone().with_options(EncoderOptions {
+        emoji: EmojiPolicy::Replace("<emoji>".to_string()),
+        ..Default::default()
+    });
+    assert!(vocab.uncovered_spans("cat 🐈").is_empty());
+    assert_eq!(emoji.uncovered_spans("cat 🐈"), vec![4..8]);
+
+    // Words split into chunks (`▁zz`, `qz`, `z`) are still mapped back to the original text.
+    let chunked = vocab.clone().with_options(EncoderOptions {
+        max_word_length: Some(2),
+        ..Default::default()
+    });
+    assert_eq!(
+        chunked.tokenize("zzqzz"),
+        vec!["<s>", "▁", "zz", "<unk>", "<unk>", "</s>"]
+    );
+    assert_eq!(chunked.uncovered_spans("zzqzz"), vec![2..5]);
+}
+
+#[test]
+fn test_split_by_token_budget() {
+    let vocab = BytePairEncoder::new_from_str(
+        "▁the\t1\n▁cat\t1\n▁sat\t1\n▁on\t1\n▁mat\t1\n▁a\t1\n▁\t0\nc\t0\na\t0\nt\t0",
+    )
+    .unwrap();
+    let text =
+        "The cat sat on the mat. A cat! The cat sat on a mat, the cat sat on the cat.\n\nCat.";
+
+    for budget in [3, 5, 8, 12, 100] {
+        let slices = vocab.split_by_token_budget(text, budget);
+        assert_eq!(slices.concat(), text, "budget {}", budget);
+        for slice in &slices {
+            assert!(vocab.tokenize(slice).len() <= budget, "{:?}", slice);
+        }
+    }
+
+    // Sentences are kept whole when they fit, and packed together.
+    assert_eq!(
+        vocab.split_by_token_budget(text, 12),
+        vec![
+            "The cat sat on the mat. A cat! ",
+            "The cat sat on a mat, the cat sat on ",
+            "the cat.\n\nCat."
+        ]
+    );
+    assert_eq!(vocab.split_by_token_budget(text, 100), vec![text]);
+
+    // A word that can never fit is returned on its own.
+    assert_eq!(
+        vocab.split_by_token_budget("cat catcat cat", 4),
+        vec!["cat ", "catcat ", "cat"]
+    );
+    assert!(vocab.split_by_token_budget("", 10).is_empty());
+
+    // Slices are checked on their own, since they can tokenize differently than in context.
+    for mode in [TextMode::Lossless, TextMode::Code] {
+        let vocab = vocab.clone().with_options(EncoderOptions {
+            mode,
+            ..Default::default()
+        });
+        for text in [".A", ".▁", "a.A b.  C", text] {
+            for budget in 1..12 {
+                let slices = vocab.split_by_token_budget(text, budget);
+                assert_eq!(slices.concat(), text);
+                for slice in &slices {
+                    let count = vocab.tokenize(slice).len();
+                    assert!(
+                        count <= budget || vocab.split_words(slice).nth(1).is_none(),
+                        "{:?} with budget {} in {:?}",
+                        slice,
+                        budget,
+                        mode
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decode_batch() {
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\n!\t3").unwrap();
+    let texts = ["Hello world!", "World, hello.", "Hello! World!"];
+    let batch: Vec<Vec<u32>> = texts
+        .iter()
+        .map(|text| {
+            vocab
+                .tokenize(text)
+                .iter()
+                .filter_map(|token| vocab.token_to_id(token))
+                .collect()
+        })
+        .collect();
+
+    // Sentence markers have no IDs here, and sentences run together with single spaces.
+    let decoded = vocab.decode_batch(&batch);
+    assert_eq!(decoded, vec!["hello world", "world hello", "hello world"]);
+    assert_eq!(vocab.decode_batch_parallel(&batch), decoded);
+
+    // Out-of-range IDs decode as the unknown token; empty sequences as empty strings.
+    assert_eq!(
+        vocab.decode_batch(&[vec![0, 2, 99], vec![]]),
+        vec!["hello!<unk>", ""]
+    );
+    assert!(vocab.decode_batch(&[]).is_empty());
+    assert!(vocab.decode_batch_parallel(&[]).is_empty());
+}
+
+#[test]
+fn test_lossless_mode() {
+    let vocab = BytePairEncoder::new_from_str("▁\t0\n▁hello\t1\n▁world\t2\n,\t3\n.\t4")
+        .unwrap()
+        .with_options(EncoderOptions::lossless());
+
+    assert_eq!(
+        vocab.tokenize("Hello, WORLD.\n"),
+        vec![
+            "<s>", "<cap>", "<0x68>", "<0x65>", "<0x6C>", "<0x6C>", "<0x6F>", ",", "<upper>",
+            "▁world", ".", "<0x0A>", "</s>"
+        ]
+    );
+    // Whitespace between sentences starts the next sentence.
+    assert_eq!(
+        vocab.tokenize_sentences("hello! world"),
+        vec![
+            vec!["<s>", "<0x68>", "<0x65>", "<0x6C>", "<0x6C>", "<0x6F>", "<0x21>", "</s>"],
+            vec!["<s>", "▁world", "</s>"],
+        ]
+    );
+
+    let texts = [
+        "",
+        "Hello, World!",
+        "  leading and trailing  \t\n",
+        "HELLO WORLD. Hello world.\r\n\r\nNew paragraph",
+        "iPhone McDonald ÉCOLE Ünïcödé",
+        "日本語のテキスト。 Mixed 😀 emoji\u{200B}",
+        "İstanbul ΣΟΦΟΣ straße",
+    ];
+    for text in texts {
+        assert!(vocab.is_lossless_for(text), "{:?}", text);
+        assert_eq!(vocab.tokenize_batch(&[text])[0], vocab.tokenize(text));
+    }
+
+    // Registered case markers and byte tokens have IDs, so IDs decode losslessly too.
+    let ids: Vec<Vec<u32>> = texts
+        .iter()
+        .map(|text| {
+            vocab
+                .tokenize(text)
+                .iter()
+                .map(|token| vocab.token_to_id(token).unwrap())
+                .collect()
+        })
+        .collect();
+    assert_eq!(vocab.decode_batch(&ids), texts);
+
+    // Capped sentences and word chunks keep the guarantee.
+    let capped = vocab.clone().with_options(EncoderOptions {
+        max_sentence_tokens: Some(4),
+        max_word_length: Some(2),
+        ..EncoderOptions::lossless()
+    });
+    for text in texts {
+        assert!(capped.is_lossless_for(text), "{:?}", text);
+    }
+
+    // The default pipeline drops case and punctuation.
+    let natural = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    assert!(natural.is_lossless_for("hello world"));
+    assert!(!natural.is_lossless_for("Hello world"));
+    assert!(!natural.is_lossless_for("hello, world"));
+}
+
+#[test]
+fn test_find_similar_tokens() {
+    let vocab = BytePairEncoder::new_from_str(
+        "<unk>\t0\n<s>\t0\n</s>\t0\n▁hello\t1\n▁help\t2\n▁hell\t3\nhello\t1\n▁world\t4",
+    )
+    .unwrap();
+
+    // Special tokens are candidates, and tokens are compared as stored.
+    assert_eq!(vocab.find_similar_tokens("</s", 1), vec![("</s>", 1)]);
+    assert_eq!(vocab.find_similar_tokens("hello", 1), vec![("hello", 0)]);
+    assert_eq!(
+        vocab.find_similar_tokens("▁hello", 3),
+        vec![("▁hello", 0), ("▁hell", 1), ("hello", 1)]
+    );
+
+    // Equal distances are ranked by shared prefix, then score.
+    assert_eq!(
+        vocab.find_similar_tokens("▁helo", 3),
+        vec![("▁hell", 1), ("▁help", 1), ("▁hello", 1)]
+    );
+
+    assert!(vocab.find_similar_tokens("hello", 0).is_empty());
+    assert_eq!(vocab.find_similar_tokens("x", 100).len(), 8);
+
+    // Registered special tokens are found too.
+    let registered = BytePairEncoder::new_from_str("▁hello\t1")
+        .unwrap()
+        .with_options(EncoderOptions {
+            special_tokens: SpecialTokenOptions {
+                missing: MissingSpecialTokenPolicy::Register,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    assert_eq!(
+        registered.find_similar_tokens("<unk", 1),
+        vec![("<unk>", 1)]
+    );
+
+    // Searching a trie finds the same tokens as comparing every token.
+    let trie = vocab.clone().with_vocab_store(TrieStore::new());
+    for query in [
+        "", "x", "</s", "hello", "▁hello", "▁helo", "▁wrold", "héllo", "▁▁",
+    ] {
+        for k in [0, 1, 2, 3, 5, 100] {
+            assert_eq!(
+                trie.find_similar_tokens(query, k),
+                vocab.find_similar_tokens(query, k),
+                "{query:?}, {k}"
+            );
+        }
+    }
+    let registered_trie = registered.with_vocab_store(TrieStore::new());
+    assert_eq!(
+        registered_trie.find_similar_tokens("<unk", 1),
+        vec![("<unk>", 1)]
+    );
+}
+
+#[test]
+fn test_token_priors() {
+    let vocab =
+        BytePairEncoder::new_from_str("▁ab\t5\nabc\t1\n▁\t0\nc\t0\n▁x\t0\nxy\t0\ny\t0").unwrap();
+    assert_eq!(vocab.tokenize("abc"), vec!["<s>", "▁ab", "c", "</s>"]);
+
+    let with_priors = |priors: TokenPriors| {
+        vocab.clone().with_options(EncoderOptions {
+            priors: Some(priors),
+            ..Default::default()
+        })
+    };
+
+    // The weighted prior must make up the score difference of 4 (ties go to the last candidate).
+    let weak = with_priors(TokenPriors::new([("abc", 1.0)], 3.0));
+    assert_eq!(weak.tokenize("abc"), vec!["<s>", "▁ab", "c", "</s>"]);
+    let strong = with_priors(TokenPriors::new([("abc", 1.0)], 4.6));
+    assert_eq!(strong.tokenize("abc"), vec!["<s>", "▁", "abc", "</s>"]);
+
+    // Negative priors push tokens down.
+    assert_eq!(vocab.tokenize("xy"), vec!["<s>", "▁", "xy", "</s>"]);
+    let penalized = with_priors(TokenPriors::new([("xy", -1.0)], 1.0));
+    assert_eq!(penalized.tokenize("xy"), vec!["<s>", "▁x", "y", "</s>"]);
+
+    // The vocabulary itself is unchanged, but the configuration differs.
+    assert_eq!(strong.fingerprint(), vocab.fingerprint());
+    assert_eq!(strong.score_of("abc"), Some(1));
+    assert_ne!(strong, vocab);
+    assert_ne!(strong, weak);
+    assert_eq!(
+        strong,
+        with_priors(TokenPriors::new(vec![("abc".to_string(), 0.5)], 9.2))
+    );
+}
+
+#[test]
+fn test_special_text_policy() {
+    let vocab = BytePairEncoder::new_from_str(
+        "BOS\t0\nEOS\t0\n<unk>\t0\n▁say\t1\n▁now\t1\n▁\t0\nB\t0\nO\t0\nS\t0\n<\t0\nunk\t0\n>\t0",
+    )
+    .unwrap();
+    let with_policy = |policy: SpecialTextPolicy| {
+        vocab.clone().with_options(EncoderOptions {
+            mode: TextMode::Code,
+            special_text: policy,
+            special_tokens: SpecialTokenOptions {
+                sentence_start: Some("BOS".to_string()),
+                sentence_end: Some("EOS".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    };
+
+    // By default, the literal text comes out as the control token.
+    let text = with_policy(SpecialTextPolicy::Text);
+    assert_eq!(
+        text.tokenize("say BOS now"),
+        vec!["BOS", "▁say", "▁", "BOS", "▁now", "EOS"]
+    );
+
+    // Escaped text is spelled by other tokens.
+    let escape = with_policy(SpecialTextPolicy::Escape);
+    assert_eq!(
+        escape.tokenize("say BOS now"),
+        vec!["BOS", "▁say", "▁", "B", "O", "S", "▁now", "EOS"]
+    );
+
+    // Stripped text is removed in place, and separates the words around it.
+    let strip = with_policy(SpecialTextPolicy::Strip);
+    assert_eq!(
+        strip.tokenize("say BOS now"),
+        vec!["BOS", "▁say", "▁now", "EOS"]
+    );
+    assert_eq!(
+        strip.tokenize("say<unk>now"),
+        vec!["BOS", "▁say", "▁now", "EOS"]
+    );
+    assert_eq!(
+        strip.tokenize("Say <unk> now. Then."),
+        strip.tokenize("Say now. Then.")
+    );
+    assert!(strip.tokenize("<unk>BOS").is_empty());
+
+    // Text that spells a control token only once normalized is never matched as it.
+    let natural = |policy: SpecialTextPolicy| {
+        BytePairEncoder::new_from_str(
+            "bos\t0\neos\t0\n▁say\t1\n▁now\t1\n▁b\t0\n▁\t0\nb\t0\no\t0\ns\t0",
+        )
+        .unwrap()
+        .with_options(EncoderOptions {
+            special_text: policy,
+            special_tokens: SpecialTokenOptions {
+                sentence_start: Some("bos".to_string()),
+                sentence_end: Some("eos".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    };
+    assert_eq!(
+        natural(SpecialTextPolicy::Text).tokenize("say BOS now"),
+        vec!["bos", "▁say", "▁", "bos", "▁now", "eos"]
+    );
+    assert_eq!(
+        natural(SpecialTextPolicy::Strip).tokenize("say BOS now"),
+        vec!["bos", "▁say", "▁b", "o", "s", "▁now", "eos"]
+    );
+    assert_eq!(
+        natural(SpecialTextPolicy::Strip).tokenize("say bos now<unk>"),
+        vec!["bos", "▁say", "▁now", "eos"]
+    );
+
+    // Decoding with special tokens escapes the text that spells one.
+    let ids = escape.encode("say BOS now \\");
+    assert_eq!(
+        escape.decode_with_special_tokens(&ids).unwrap(),
+        "BOS say \\BOS now <unk>EOS"
+    );
+    assert_eq!(escape.decode(&ids).unwrap(), "say BOS now <unk>");
+
+    // In lossless mode, escaped text still round-trips, but never as a control token.
+    let lossless = vocab.with_options(EncoderOptions {
+        special_text: SpecialTextPolicy::Escape,
+        ..EncoderOptions::lossless()
+    });
+    let tokens = lossless.tokenize("a <unk> b");
+    assert!(!tokens.contains(&"<unk>".to_string()));
+    assert!(lossless.is_lossless_for("a <unk> b"));
+}
+
+#[test]
+fn test_mask_spans() {
+    let vocab = BytePairEncoder::new_from_str(
+        "▁the\t1\n▁cat\t1\n▁sat\t1\n▁s\t0\nat\t0\n▁\t0\n[MASK]\t0\n😀\t0",
+    )
+    .unwrap();
+    let text = "The cat sat. The CAT 😀!";
+
+    // Without spans, the tokens are exactly those of `tokenize`, in every configuration.
+    let configurations = [
+        EncoderOptions::default(),
+        EncoderOptions {
+            mode: TextMode::Code,
+            ..Default::default()
+        },
+        EncoderOptions {
+            max_sentence_tokens: Some(4),
+            ..Default::default()
+        },
+        EncoderOptions {
+            emoji: EmojiPolicy::Keep,
+            empty_sentences: EmptySentencePolicy::EmitToken("<empty>".to_string()),
+            ..Default::default()
+        },
+        EncoderOptions {
+            unknown: UnknownStrategy::Graphemes,
+            cache_capacity: Some(4),
+            max_sentence_tokens: Some(3),
+            ..Default::default()
+        },
+        EncoderOptions::lossless(),
+    ];
+    for options in configurations {
+        let vocab = vocab.clone().with_options(options);
+        for text in [text, "", "İstanbul ist!  ?  ", "sat\u{200B}sat", text] {
+            let encoding = vocab.mask_spans(text, &[]);
+            assert_eq!(encoding.tokens, vocab.tokenize(text), "{:?}", text);
+            assert_eq!(encoding.len(), encoding.offsets.len());
+            assert!(encoding.offsets.iter().all(|range| range.end <= text.len()));
+        }
+    }
+
+    // Masked tokens keep their offsets, and the detected mask token is used.
+    let encoding = vocab.mask_spans(text, &[4..6, 17..18]);
+    assert_eq!(
+        encoding.tokens,
+        vec!["<s>", "▁the", "[MASK]", "▁sat", "</s>", "<s>", "▁the", "[MASK]", "</s>"]
+    );
+    assert_eq!(
+        encoding.offsets,
+        vec![
+            0..0,
+            0..3,
+            4..7,
+            8..11,
+            13..13,
+            13..13,
+            13..16,
+            17..20,
+            26..26
+        ]
+    );
+    assert_eq!(encoding.ids[2], vocab.token_to_id("[MASK]"));
+    assert_eq!(
+        encoding.special_tokens_mask,
+        vec![true, false, false, false, true, true, false, false, true]
+    );
+
+    // Sentence markers are never masked, and spans only touching a token leave it alone.
+    let encoding = vocab.mask_spans(text, &[0..0, 3..4, 11..11, 23..23]);
+    assert_eq!(encoding.tokens, vocab.tokenize(text));
+
+    // Spans count characters, not bytes.
+    let encoding = vocab.mask_spans("😀 sat CAT", &[2..5, 6..6]);
+    assert_eq!(encoding.tokens, vec!["<s>", "[MASK]", "▁cat", "</s>"]);
+    assert_eq!(encoding.offsets[1], 5..8);
+    assert_eq!(encoding.char_offsets[1], 2..5);
+
+    // Without a mask token in the vocabulary, `<mask>` is used.
+    let vocab = BytePairEncoder::new_from_str("▁the\t1\n▁cat\t1").unwrap();
+    let encoding = vocab.mask_spans("the cat", &[4..5, 6..7]);
+    assert_eq!(encoding.tokens, vec!["<s>", "▁the", "<mask>", "</s>"]);
+    assert_eq!(encoding.ids[2], None);
+}
+
+#[test]
+fn test_cooccurrence_counts() {
+    let vocab = BytePairEncoder::new_from_str("▁the\t1\n▁cat\t1\n▁sat\t1\n▁mat\t1").unwrap();
+    let id = |token| vocab.token_to_id(token).unwrap();
+    let corpus = vec!["The cat sat.", "The mat"];
+
+    let counts = cooccurrence_counts(&vocab, &corpus, 2);
+    assert_eq!(counts.window(), 2);
+    assert_eq!(counts.get(id("▁the"), id("▁cat")), 1);
+    assert_eq!(counts.get(id("▁the"), id("▁sat")), 1);
+    assert_eq!(counts.get(id("▁sat"), id("▁the")), 1);
+    assert_eq!(counts.get(id("▁the"), id("▁mat")), 1);
+    // Windows do not cross documents.
+    assert_eq!(counts.get(id("▁sat"), id("▁mat")), 0);
+    assert_eq!(counts.total(), 8);
+    assert_eq!(counts.iter().map(|(_, count)| count).sum::<u64>(), 8);
+
+    // Unknown tokens take up a position in the window.
+    let counts = cooccurrence_counts(&vocab, ["the dog cat"], 1);
+    assert!(counts.is_empty());
+    let counts = cooccurrence_counts(&vocab, ["the dog cat"], 2);
+    assert_eq!(counts.len(), 2);
+
+    assert!(cooccurrence_counts(&vocab, &corpus, 0).is_empty());
+}
+
+#[test]
+fn test_trainer_presets() {
+    let sizes = [
+        (TrainerPreset::Small8k, 8_000),
+        (TrainerPreset::Medium16k, 16_000),
+        (TrainerPreset::Large32k, 32_000),
+        (TrainerPreset::XLarge50k, 50_000),
+    ];
+    for (preset, size) in sizes {
+        let options = TrainerOptions::preset(preset);
+        assert_eq!(options.vocab_size, size);
+        assert_eq!(
+            options.special_tokens,
+            vec!["<unk>", "<s>", "</s>", "<pad>", "<mask>"]
+        );
+    }
+
+    let mut trainer = Trainer::preset(TrainerPreset::Small8k);
+    trainer.feed("the cat sat on the mat, the cat sat");
+    let encoder = trainer.train();
+    assert_eq!(encoder.special_tokens().pad(), Some("<pad>"));
+    assert_eq!(encoder.special_tokens().mask(), Some("<mask>"));
+    assert_eq!(
+        encoder.tokenize("the cat"),
+        vec!["<s>", "▁the", "▁cat", "</s>"]
+    );
+    // Pairs that occur only once are not merged.
+    assert!(!encoder.contains_token("▁on"));
+}
+
+#[test]
+fn test_tokenize_sentences_ids_iter() {
+    let vocab =
+        BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\n▁the\t1\n▁cat\t1\n▁sat\t1").unwrap();
+    let text = "The cat sat. The dog! Cat";
+
+    for options in [
+        EncoderOptions::default(),
+        EncoderOptions {
+            max_sentence_tokens: Some(3),
+            ..Default::default()
+        },
+    ] {
+        let vocab = vocab.clone().with_options(options);
+        let tokens: Vec<Vec<_>> = vocab
+            .tokenize_sentences_ref_iter(text)
+            .map(Iterator::collect)
+            .collect();
+        assert_eq!(tokens, vocab.tokenize_sentences(text));
+
+        let ids: Vec<Vec<u32>> = vocab
+            .tokenize_sentences_ids_iter(text)
+            .map(Iterator::collect)
+            .collect();
+        let expected: Vec<Vec<u32>> = tokens
+            .iter()
+            .map(|sentence| {
+                sentence
+                    .iter()
+                    .filter_map(|token| vocab.token_to_id(token))
+                    .collect()
+            })
+            .collect();
+        // The unknown token has no ID, so it is left out.
+        assert_eq!(ids, expected);
+    }
+
+    // With an unknown token ID, every token has an ID.
+    let vocab = BytePairEncoder::new_from_str("<unk>\t0\n▁the\t1").unwrap();
+    let ids: Vec<Vec<u32>> = vocab
+        .tokenize_sentences_ids_iter("the dog")
+        .map(Iterator::collect)
+        .collect();
+    assert_eq!(ids, vec![vec![0, 1, 0, 0]]);
+}
+
+#[cfg(feature = "abi")]
+#[test]
+fn test_abi_tokens() {
+    use crate::abi::{self, AbiToken, TOKEN_HAS_ID, TOKEN_SPECIAL};
+
+    assert_eq!(std::mem::size_of::<AbiToken>(), 24);
+    assert_eq!(std::mem::align_of::<AbiToken>(), 8);
+
+    let vocab = BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\n▁the\t1\n▁cat\t1").unwrap();
+    let text = "The dog cat";
+    let tokens = abi::encode(&vocab, text);
+    assert_eq!(tokens, Vec::<AbiToken>::from(&vocab.mask_spans(text, &[])));
+    assert_eq!(
+        tokens,
+        vec![
+            AbiToken::new(Some(0), 0..0, true),
+            AbiToken::new(Some(2), 0..3, false),
+            AbiToken::new(None, 4..7, false),
+            AbiToken::new(Some(3), 8..11, false),
+            AbiToken::new(Some(1), 11..11, true),
+        ]
+    );
+    assert_eq!(tokens[0].flags, TOKEN_HAS_ID | TOKEN_SPECIAL);
+    assert_eq!(tokens[2].flags, 0);
+    assert_eq!(tokens[2].id(), None);
+    assert_eq!(tokens[3].id(), Some(3));
+
+    let ids: Vec<u32> = tokens.iter().filter_map(AbiToken::id).collect();
+    let view = abi::AbiIds::new(&ids);
+    assert_eq!((view.ptr, view.len), (ids.as_ptr(), 4));
+}
+
+#[test]
+fn test_parallel_training() {
+    let corpus: Vec<String> = (0..200)
+        .map(|i| format!("token {} tokens tokenizer {} lower lowest newer", i, i * 7))
+        .collect();
+    let train = |threads: usize| {
+        let mut trainer = Trainer::new(TrainerOptions {
+            vocab_size: 120,
+            threads,
+            ..Default::default()
+        });
+        trainer.feed_iter(&corpus);
+        trainer.train()
+    };
+
+    let single = train(1);
+    assert_eq!(single.vocab_size(), 120);
+    for threads in [2, 3, 8] {
+        let parallel = train(threads);
+        assert_eq!(parallel.export_id_table(), single.export_id_table());
+        assert_eq!(parallel, single);
+    }
+}
+
+#[test]
+fn test_trainer_checkpoints() {
+    let corpus: Vec<String> = (0..50)
+        .map(|i| format!("token {} tokens tokenizer lower lowest newer", i))
+        .collect();
+    let options = |vocab_size: usize| TrainerOptions {
+        vocab_size,
+        ..Default::default()
+    };
+    let mut trainer = Trainer::new(options(60));
+    trainer.feed_iter(&corpus);
+    let expected = trainer.train();
+
+    // An interrupted run, simulated by a smaller target, resumes to the same vocabulary.
+    let path = "test_trainer_checkpoint.txt";
+    let mut interrupted = Trainer::new(options(40));
+    interrupted.feed_iter(&corpus);
+    let partial = interrupted.train_with_checkpoints(path, 7).unwrap();
+    assert_eq!(partial.vocab_size(), 40);
+    let resumed = Trainer::resume_from_file(options(60), path).unwrap();
+    assert_eq!(resumed.train(), expected);
+    assert_eq!(resumed.train_with_checkpoints(path, 7).unwrap(), expected);
+    fs::remove_file(path).unwrap();
+
+    // The state before training round-trips too.
+    let mut buffer = vec![];
+    trainer.write_checkpoint(&mut buffer).unwrap();
+    let input = String::from_utf8(buffer).unwrap();
+    let restored = Trainer::resume_from_str(options(60), &input).unwrap();
+    assert_eq!(restored.train(), expected);
+
+    assert!(matches!(
+        Trainer::resume_from_str(options(60), ""),
+        Err(BytePairEncoderError::InvalidCheckpoint { line: 1 })
+    ));
+    assert!(input.contains("\t▁lower\n"));
+    let corrupt = input.replacen("\t▁lower\n", "\t▁lower\textra\n", 1);
+    assert!(matches!(
+        Trainer::resume_from_str(options(60), &corrupt),
+        Err(BytePairEncoderError::InvalidCheckpoint { .. })
+    ));
+}
+
+#[test]
+fn test_evaluate_segmentation() {
+    let vocab =
+        BytePairEncoder::new_from_str("▁walk\t1\ned\t1\n▁talk\t1\ning\t1\n▁ta\t0\nlking\t0")
+            .unwrap();
+    let references = vec![
+        vec!["walk", "ed"],
+        vec!["talk", "ing"],
+        vec!["Walk", "ing"],
+        vec!["jump"],
+    ];
+    let metrics = evaluate_segmentation(&vocab, &references);
+    // "jump" is one unknown token, so no boundary is produced inside it.
+    assert_eq!(metrics.words, 4);
+    assert_eq!(metrics.exact_words, 4);
+    assert_eq!(metrics.true_positives, 3);
+    assert_eq!(
+        (metrics.precision(), metrics.recall(), metrics.f1()),
+        (1.0, 1.0, 1.0)
+    );
+
+    // Boundaries in the wrong place count against both precision and recall.
+    let metrics = evaluate_segmentation(&vocab, [["tal", "king"]]);
+    assert_eq!(
+        (
+            metrics.true_positives,
+            metrics.false_positives,
+            metrics.false_negatives
+        ),
+        (0, 1, 1)
+    );
+    assert_eq!(metrics.f1(), 0.0);
+    assert_eq!(metrics.exact_match_rate(), 0.0);
+
+    // Positions are in the original text, even if normalization changes its length.
+    let vocab = BytePairEncoder::new_from_str("▁i\u{307}\t1\nstanbul\t1").unwrap();
+    let metrics = evaluate_segmentation(&vocab, [["İ", "stanbul"]]);
+    assert_eq!(metrics.exact_words, 1);
+
+    // Without any words, every rate is vacuously perfect.
+    let metrics = evaluate_segmentation(&vocab, Vec::<Vec<&str>>::new());
+    assert_eq!(
+        (
+            metrics.precision(),
+            metrics.recall(),
+            metrics.f1(),
+            metrics.exact_match_rate()
+        ),
+        (1.0, 1.0, 1.0, 1.0)
+    );
+}
+
+#[test]
+fn test_best_default_for_budget() {
+    let result = BytePairEncoder::best_default_for_budget(usize::MAX);
+    if cfg!(not(any(
+        feature = "default-small",
+        feature = "default-medium",
+        feature = "default-large"
+    ))) {
+        assert!(matches!(
+            result,
+            Err(BytePairEncoderError::NoDefaultVocabFeature)
+        ));
+        return;
+    }
+    let (encoder, info) = result.unwrap();
+    assert_eq!(encoder.vocab_size(), info.vocab_size);
+    assert!(info.coverage > 0.0 && info.coverage <= 1.0);
+
+    assert!(matches!(
+        BytePairEncoder::best_default_for_budget(1_000),
+        Err(BytePairEncoderError::MemoryBudgetTooSmall { budget: 1_000, .. })
+    ));
+
+    #[cfg(feature = "default-small")]
+    {
+        let (_, info) = BytePairEncoder::best_default_for_budget(20_000_000).unwrap();
+        assert_eq!((info.feature, info.vocab_size), ("default-small", 100_000));
+    }
+}
+
+#[test]
+fn test_encode() {
+    let vocab =
+        BytePairEncoder::new_from_str("<unk>\t0\n<s>\t0\n</s>\t0\n▁the\t1\n▁cat\t1").unwrap();
+    let text = "The cat. The dog!";
+    let ids = vocab.encode(text);
+    assert_eq!(ids, vec![1, 3, 4, 2, 1, 3, 0, 2]);
+    assert_eq!(vocab.encode_iter(text).collect::<Vec<_>>(), ids);
+    let expected: Vec<u32> = vocab
+        .tokenize(text)
+        .iter()
+        .map(|token| vocab.token_to_id(token).unwrap())
+        .collect();
+    assert_eq!(ids, expected);
+
+    // Special tokens missing from the vocabulary can be given reserved IDs.
+    let vocab = BytePairEncoder::new_from_str("▁the\t1\n▁cat\t1")
+        .unwrap()
+        .with_options(EncoderOptions {
+            special_tokens: SpecialTokenOptions {
+                missing: MissingSpecialTokenPolicy::Register,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    assert_eq!(vocab.encode("the dog"), vec![2, 0, 4, 3]);
+}
+
+#[test]
+fn test_new_with_overlay() {
+    let base = BytePairEncoder::new_from_str("#@name: base\n▁the\t1\n▁cat\t1\n▁ca\t0\nt\t0")
+        .unwrap()
+        .with_options(EncoderOptions {
+            mode: TextMode::Code,
+            ..Default::default()
+        });
+    let overlay = "#@name: custom\n▁cat\t-5\n▁catalog\t2\n▁the\t1";
+    let vocab = BytePairEncoder::new_with_overlay_str(&base, overlay).unwrap();
+
+    // Existing tokens keep their IDs, new ones follow.
+    for token in ["▁the", "▁cat", "▁ca", "t"] {
+        assert_eq!(vocab.token_to_id(token), base.token_to_id(token));
+    }
+    assert_eq!(vocab.token_to_id("▁catalog"), Some(4));
+    assert_eq!(vocab.vocab_size(), 5);
+    assert_eq!(vocab.score_of("▁cat"), Some(-5));
+    assert_eq!(vocab.metadata().name.as_deref(), Some("custom"));
+    assert_eq!(vocab.options(), base.options());
+    assert_eq!(
+        vocab.tokenize("cat catalog"),
+        vec!["<s>", "▁cat", "▁catalog", "</s>"]
+    );
+
+    let path = "test_overlay.txt";
+    fs::write(path, overlay).unwrap();
+    assert_eq!(
+        BytePairEncoder::new_with_overlay(&base, path).unwrap(),
+        vocab
+    );
+    fs::remove_file(path).unwrap();
+
+    assert!(matches!(
+        BytePairEncoder::new_with_overlay_str(&base, "▁dog\tx"),
+        Err(BytePairEncoderError::InvalidScore { line: 1, .. })
+    ));
+    assert!(matches!(
+        BytePairEncoder::new_with_overlay(&base, "missing_overlay.txt"),
+        Err(BytePairEncoderError::InvalidFile { .. })
+    ));
+}
+
+#[test]
+fn test_decode() {
+    let vocab = BytePairEncoder::new_from_str(
+        "<unk>\t0\n<s>\t0\n</s>\t0\n<pad>\t0\n▁the\t1\n▁cat\t1\n.\t1",
+    )
+    .unwrap();
+    let text = "The cat. The dog.";
+    let ids = vocab.encode(text);
+    assert_eq!(vocab.decode(&ids).unwrap(), "the cat the<unk>");
+    assert_eq!(
+        vocab.decode_with_special_tokens(&ids).unwrap(),
+        "<s> the cat</s><s> the<unk></s>"
+    );
+    assert_eq!(vocab.decode(&[3, 4, 5, 3]).unwrap(), "the cat");
+    assert_eq!(vocab.decode(&[]).unwrap(), "");
+    assert_eq!(vocab.decode(&[4, 6, 5]).unwrap(), "the. cat");
+    assert!(matches!(
+        vocab.decode(&[4, 99]),
+        Err(BytePairEncoderError::InvalidTokenId { id: 99, index: 1 })
+    ));
+
+    // Lossless IDs decode to the original text.
+    let lossless = vocab.with_options(EncoderOptions::lossless());
+    let text = "The CAT sat.  Done!";
+    assert_eq!(lossless.decode(&lossless.encode(text)).unwrap(), text);
+}
+
+#[cfg(feature = "huggingface")]
+#[test]
+fn test_huggingface_tokenizer_conversion() {
+    use std::convert::TryFrom;
+
+    use tokenizers::Tokenizer;
+
+    let vocab = BytePairEncoder::new_from_str(
+        "<unk>\t0\n<s>\t0\n</s>\t0\n▁\t0\nh\t-1\ni\t-2\nd\t-3\n▁h\t-4\n▁hi\t-5",
+    )
+    .unwrap();
+
+    // Natural mode: lowercased, punctuation dropped, sentence markers added.
+    let tokenizer = Tokenizer::try_from(&vocab).unwrap();
+    for text in ["Hi, hi!", "hi... (hid)", "HI   hid"] {
+        let encoding = tokenizer.encode(text, true).unwrap();
+        assert_eq!(encoding.get_tokens(), vocab.tokenize(text), "{}", text);
+        let ids: Vec<u32> = vocab.encode(text);
+        assert_eq!(encoding.get_ids(), ids.as_slice(), "{}", text);
+    }
+    let saved = tokenizer.to_string(false).unwrap();
+    assert!(saved.contains("\"Lowercase\""));
+    assert!(saved.contains("\"TemplateProcessing\""));
+    assert!(saved.contains("\"byte_fallback\":false"));
+
+    // Code mode keeps case and symbols.
+    let code = vocab.clone().with_options(EncoderOptions {
+        mode: TextMode::Code,
+        ..Default::default()
+    });
+    let tokenizer = Tokenizer::try_from(&code).unwrap();
+    assert!(!tokenizer
+        .to_string(false)
+        .unwrap()
+        .contains("\"Lowercase\""));
+    let encoding = tokenizer.encode("hi(h)", false).unwrap();
+    assert_eq!(
+        encoding.get_tokens(),
+        ["▁hi", "▁", "<unk>", "▁h", "▁", "<unk>"]
+    );
+
+    // Lossless mode falls back to bytes and decodes them.
+    let lossless = vocab.with_options(EncoderOptions::lossless());
+    let tokenizer = Tokenizer::try_from(&lossless).unwrap();
+    assert!(tokenizer
+        .to_string(false)
+        .unwrap()
+        .contains("\"byte_fallback\":true"));
+    let encoding = tokenizer.encode("hi!", false).unwrap();
+    assert_eq!(encoding.get_tokens(), ["h", "i", "<0x21>"]);
+    assert_eq!(tokenizer.decode(encoding.get_ids(), false).unwrap(), "hi!");
+
+    // Numbers, contractions, abbreviations, and ideographs split into the same words.
+    let vocab = BytePairEncoder::new_from_str(
+        "<unk>\t0\n<s>\t0\n</s>\t0\n▁\t0\n3\t-1\n.\t-1\n,\t-1\n1\t-1\n4\t-1\n東\t-1\n京\t-1\n\
+         d\t-1\no\t-1\nn\t-1\n'\t-1\nt\t-1\nu\t-1\ns\t-1\n▁3\t-2\n▁東\t-2\n▁京\t-2\n▁d\t-2\n▁u\t-2",
+    )
+    .unwrap();
+    let tokenizer = Tokenizer::try_from(&vocab).unwrap();
+    for (text, expected) in [
+        ("3.14", vec!["<s>", "▁3", ".", "1", "4", "</s>"]),
+        ("東京", vec!["<s>", "▁東", "▁京", "</s>"]),
+        (
+            "Don't 3,14 u.s.",
+            vec![
+                "<s>", "▁d", "o", "n", "'", "t", "▁3", ",", "1", "4", "▁u", ".", "s", "</s>",
+            ],
+        ),
+    ] {
+        assert_eq!(vocab.tokenize(text), expected, "{}", text);
+        let encoding = tokenizer.encode(text, true).unwrap();
+        assert_eq!(encoding.get_tokens(), expected, "{}", text);
+    }
+}
+
+#[test]
+fn test_detokenize() {
+    let vocab = BytePairEncoder::new_from_str("<pad>\t0\n▁the\t1\n▁cat\t1\n▁sat\t1\ns\t1").unwrap();
+    let tokens = vocab.tokenize("The cats sat. The dog!");
+    assert_eq!(vocab.detokenize(&tokens), "the cats sat the<unk>");
+
+    let tokens: Vec<String> = ["<s>", "▁the", "▁cat", "</s>", "<pad>", "<pad>"]
+        .iter()
+        .map(|token| token.to_string())
+        .collect();
+    assert_eq!(vocab.detokenize(&tokens), "the cat");
+    assert_eq!(vocab.detokenize(&[]), "");
+
+    let lossless = vocab.with_options(EncoderOptions::lossless());
+    let text = "The CAT sat.\n\nDone!";
+    assert_eq!(lossless.detokenize(&lossless.tokenize(text)), text);
+}
+
+#[test]
+fn test_id_stream() {
+    let vocab = BytePairEncoder::new_from_str("▁the\t1\n▁cat\t1\n▁sat\t1").unwrap();
+    let documents = [
+        vocab.encode("The cat sat."),
+        vec![],
+        vec![0, u32::MAX, 5, 5, 4, 0],
+    ];
+    for delta in [false, true] {
+        let options = IdStreamOptions { delta };
+        let mut bytes = vec![];
+        for ids in &documents {
+            write_ids(&mut bytes, ids, &options).unwrap();
+        }
+        let mut reader = bytes.as_slice();
+        for ids in &documents {
+            assert_eq!(&read_ids(&mut reader).unwrap(), ids);
+        }
+        let end = read_ids(&mut reader).unwrap_err();
+        assert_eq!(end.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    // Small IDs take a byte each, after the 7-byte header.
+    let mut bytes = vec![];
+    write_ids(&mut bytes, &[1, 2, 3, 127], &IdStreamOptions::default()).unwrap();
+    assert_eq!(bytes.len(), 7 + 4);
+
+    // Delta coding shrinks sorted IDs.
+    let sorted: Vec<u32> = (100_000..100_100).collect();
+    let (mut plain, mut delta) = (vec![], vec![]);
+    write_ids(&mut plain, &sorted, &IdStreamOptions::default()).unwrap();
+    write_ids(&mut delta, &sorted, &IdStreamOptions { delta: true }).unwrap();
+    assert!(delta.len() * 2 < plain.len());
+
+    // Corrupt input is rejected.
+    let error = read_ids(&b"BPEX\x01\x00\x00"[..]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    let error = read_ids(&b"BPEI\x01\x00\x01\xff\xff\xff\xff\x7f"[..]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    let error = read_ids(&b"BPEI\x01\x00\x02\x01"[..]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[cfg(feature = "language")]
+#[test]
+fn test_tokenize_sentences_tagged() {
+    use crate::language::{tokenize_sentences_tagged, Script};
+
+    let vocab = BytePairEncoder::new_from_str("▁the\t0\n▁cat\t0\n▁кот\t0").unwrap();
+    let text = "The cat. Кот спит! 123 456.\n日本語です。";
+    let sentences = tokenize_sentences_tagged(&vocab, text);
+    let scripts: Vec<Option<Script>> = sentences.iter().map(|s| s.script).collect();
+    assert_eq!(
+        scripts,
+        vec![
+            Some(Script::Latin),
+            Some(Script::Cyrillic),
+            None,
+            Some(Script::Kana)
+        ]
+    );
+    let tokens: Vec<Vec<String>> = sentences.iter().map(|s| s.tokens.clone()).collect();
+    assert_eq!(tokens, vocab.tokenize_sentences(text));
+    assert_eq!(&text[sentences[1].range.clone()], "Кот спит! ");
+
+    // Split sentences keep the tag of the whole sentence.
+    let capped = vocab.with_options(EncoderOptions {
+        max_sentence_tokens: Some(3),
+        ..Default::default()
+    });
+    let sentences = tokenize_sentences_tagged(&capped, "The cat кот.");
+    assert_eq!(sentences.len(), 3);
+    assert!(sentences
+        .iter()
+        .all(|s| s.script == Some(Script::Latin) && s.range == (0..15)));
+}
+
+#[test]
+fn test_builder() {
+    let input = "[CLS]\t0\n[SEP]\t0\n[UNK]\t0\n[PAD]\t0\n#the\t1\n#cat\t1\n#\t1\na\t1\nt\t1";
+    let vocab = BytePairEncoder::builder()
+        .sentence_start("[CLS]")
+        .sentence_end("[SEP]")
+        .unknown("[UNK]")
+        .pad("[PAD]")
+        .mask("[MASK]")
+        .build_from_str(input)
+        .unwrap();
+    let special = vocab.special_tokens();
+    assert_eq!(special.pad(), Some("[PAD]"));
+    assert_eq!(special.mask(), Some("[MASK]"));
+    assert_eq!(special.word_break(), "▁");
+    let tokens = vocab.tokenize("The cat.");
+    assert_eq!(tokens.first().map(String::as_str), Some("[CLS]"));
+    assert!(!tokens.iter().any(|token| token.starts_with('#')));
+
+    // A different word break character, used for matching and decoding alike.
+    let vocab = BytePairEncoder::builder()
+        .word_break('#')
+        .build_from_str(input)
+        .unwrap();
+    assert_eq!(vocab.special_tokens().word_break(), "#");
+    assert_eq!(vocab.special_tokens().sentence_start(), "[CLS]");
+    let tokens = vocab.tokenize("The cat sat.");
+    assert_eq!(
+        tokens,
+        vec!["[CLS]", "#the", "#cat", "#", "[UNK]", "a", "t", "[SEP]"]
+    );
+    assert_eq!(vocab.detokenize(&tokens), "the cat [UNK]at");
+    assert_eq!(
+        vocab.merge_word_pieces(&tokens),
+        vec!["[CLS]", "the", "cat", "[UNK]", "at", "[SEP]"]
+    );
+
+    let lossless = BytePairEncoder::builder()
+        .options(EncoderOptions::lossless())
+        .word_break('#')
+        .build_from(vocab)
+        .unwrap();
+    let text = "The  cat sat!";
+    assert_eq!(lossless.detokenize(&lossless.tokenize(text)), text);
+    assert!(lossless.tokenize(text).contains(&"#cat".to_string()));
+
+    assert!(BytePairEncoder::builder()
+        .build_from_file("no-such-vocab.txt")
+        .is_err());
+}
+
+#[test]
+fn test_tokenize_stream() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let vocab = BytePairEncoder::new_from_str("▁the\t1\n▁cat\t1\n▁sat\t1").unwrap();
+    let texts: Vec<String> = (0..50)
+        .map(|i| format!("The cat sat {}. The cat! Sat.", i))
+        .collect();
+    let expected: Vec<(usize, Vec<String>)> = texts
+        .iter()
+        .enumerate()
+        .flat_map(|(i, text)| {
+            vocab
+                .tokenize_sentences(text)
+                .into_iter()
+                .map(move |s| (i, s))
+        })
+        .collect();
+
+    // A slow consumer sees everything in order, while the producer is held back.
+    let pulled = AtomicUsize::new(0);
+    let limits = StreamLimits {
+        max_buffered_sentences: 2,
+        max_buffered_tokens: Some(6),
+    };
+    let mut received = vec![];
+    let mut max_ahead = 0;
+    vocab
+        .tokenize_stream(
+            texts.iter().inspect(|_| {
+                pulled.fetch_add(1, Ordering::SeqCst);
+            }),
+            &limits,
+            |i, tokens| {
+                max_ahead = max_ahead.max(pulled.load(Ordering::SeqCst) - i);
+                thread::sleep(Duration::from_micros(200));
+                received.push((i, tokens));
+                Ok::<_, ()>(())
+            },
+        )
+        .unwrap();
+    assert_eq!(received, expected);
+    assert!(max_ahead <= 3, "producer ran {} texts ahead", max_ahead);
+
+    // An error from the consumer stops the stream.
+    let mut count = 0;
+    let result = vocab.tokenize_stream(&texts, &StreamLimits::default(), |_, _| {
+        count += 1;
+        if count == 5 {
+            Err("stop")
+        } else {
+            Ok(())
+        }
+    });
+    assert_eq!(result, Err("stop"));
+    assert_eq!(count, 5);
+
+    let result = vocab.tokenize_stream(Vec::<String>::new(), &limits, |_, _| Err(()));
+    assert_eq!(result, Ok(()));
+
+    // A panicking consumer unblocks the producer waiting on a full queue, and the panic
+    // propagates instead of deadlocking.
+    let limits = StreamLimits {
+        max_buffered_sentences: 1,
+        max_buffered_tokens: None,
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        vocab.tokenize_stream(&texts, &limits, |_, _| -> Result<(), ()> {
+            thread::sleep(Duration::from_millis(20));
+            panic!("consumer failed");
+        })
+    }));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_verify_default_vocabs() {
+    #[cfg(not(any(
+        feature = "default-small",
+        feature = "default-medium",
+        feature = "default-large"
+    )))]
+    assert!(matches!(
+        BytePairEncoder::verify_default_vocabs(),
+        Err(BytePairEncoderError::NoDefaultVocabFeature)
+    ));
+
+    #[cfg(feature = "default-small")]
+    {
+        use crate::default_vocabs::{decode_tokens, DefaultVocab};
+
+        assert!(BytePairEncoder::verify_default_vocabs().is_ok());
+
+        // Errors name the vocabulary and what was recorded about it at build time.
+        let data = DefaultVocab::Small.data();
+        let error = decode_tokens(DefaultVocab::Small, &data[..data.len() / 2]).unwrap_err();
+        match &error {
+            BytePairEncoderError::DecompressionError {
+                vocab,
+                expected_size,
+                checksum,
+                ..
+            } => {
+                assert_eq!(*vocab, "default-small");
+                assert!(*expected_size > 0);
+                assert_ne!(*checksum, 0);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(error.to_string().contains("`default-small`"));
+        assert!(std::error::Error::source(&error).is_some());
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn test_default_vocab_memory_bytes() {
+    use crate::default_vocabs::{new_default, DefaultVocab};
+
+    // The sizes `best_default_for_budget` chooses by must match what loading actually takes.
+    for vocab in DefaultVocab::compiled() {
+        assert_eq!(
+            vocab.memory_bytes(),
+            new_default(vocab).unwrap().memory_bytes(),
+            "{}",
+            vocab.feature()
+        );
+    }
+}
+
+#[test]
+fn test_punctuation_policy() {
+    let vocab =
+        BytePairEncoder::new_from_str("▁hello\t1\n▁world\t1\n▁,\t1\n▁\t0\n!\t1\n-\t1").unwrap();
+    assert_eq!(
+        vocab.tokenize("Hello, world!"),
+        vec!["<s>", "▁hello", "▁world", "</s>"]
+    );
+
+    let vocab = vocab.with_options(EncoderOptions {
+        punctuation: PunctuationPolicy::Keep,
+        ..Default::default()
+    });
+    assert_eq!(
+        vocab.tokenize("Hello, world!"),
+        vec!["<s>", "▁hello", "▁,", "▁world", "▁", "!", "</s>"]
+    );
+    // Punctuation-only sentences are kept, whitespace-only ones are not.
+    assert_eq!(
+        vocab.tokenize_sentences("Hello!\n---\n   "),
+        vec![
+            vec!["<s>", "▁hello", "▁", "!", "</s>"],
+            vec!["<s>", "▁", "-", "▁", "-", "▁", "-", "</s>"],
+        ]
+    );
+    // Emoji still follow the emoji policy.
+    assert_eq!(vocab.tokenize("Hello 😀"), vec!["<s>", "▁hello", "</s>"]);
+    assert_eq!(
+        vocab.detokenize(&vocab.tokenize("Hello, world!")),
+        "hello , world !"
+    );
+}
+
+#[test]
+fn test_vocab_store() {
+    let input = (0..1000)
+        .map(|i| format!("▁w{}\t{}\n", i, -i))
+        .chain(["▁hello\t1\n▁wor\t1\nld\t1\n".to_string()])
+        .collect::<String>();
+    let vocab = BytePairEncoder::new_from_str(&input).unwrap();
+    let compact = vocab.clone().with_vocab_store(CompactStore::new());
+    assert_eq!(compact, vocab);
+    let text = "Hello world, w1 w999 w1000.";
+    assert_eq!(compact.tokenize(text), vocab.tokenize(text));
+    assert_eq!(compact.encode(text), vocab.encode(text));
+    assert_eq!(compact.token_to_id("▁w999"), Some(999));
+    assert_eq!(compact.token_to_id("▁w1000"), None);
+    assert!(compact.vocab_store().heap_bytes() * 2 < vocab.vocab_store().heap_bytes());
+
+    // Tokens added later go into the same store.
+    let extended = BytePairEncoder::new_with_overlay_str(&compact, "▁w1000\t5").unwrap();
+    assert_eq!(extended.token_to_id("▁w1000"), Some(1003));
+    assert_eq!(extended.token_to_id("▁w999"), Some(999));
+
+    let built = BytePairEncoder::builder()
+        .vocab_store(CompactStore::new())
+        .build_from_str(&input)
+        .unwrap();
+    assert_eq!(built.tokenize(text), vocab.tokenize(text));
+    let back = built.with_vocab_store(HashMapStore::new());
+    assert_eq!(
+        back.vocab_store().heap_bytes(),
+        vocab.vocab_store().heap_bytes()
+    );
+
+    // A trie finds the same tokens, and the same prefixes as the default lookups, in one walk.
+    let trie = vocab.clone().with_vocab_store(TrieStore::new());
+    assert_eq!(trie, vocab);
+    assert_eq!(trie.token_to_id("▁w99"), Some(99));
+    assert_eq!(trie.token_to_id("▁w1000"), None);
+    assert_eq!(trie.token_to_id("▁w"), None);
+    let prefixes = |encoder: &BytePairEncoder| {
+        let mut found = vec![];
+        encoder.tokens.prefixes("▁w123x", |end, token, _| {
+            found.push((end, token.to_string()));
+            true
+        });
+        found
+    };
+    assert_eq!(
+        prefixes(&trie),
+        [
+            (7, "▁w123".to_string()),
+            (6, "▁w12".to_string()),
+            (5, "▁w1".to_string())
+        ]
+    );
+    assert_eq!(prefixes(&trie), prefixes(&vocab));
+    for segmentation in [
+        SegmentationStrategy::Greedy,
+        SegmentationStrategy::Viterbi,
+        SegmentationStrategy::WordPiece,
+    ] {
+        let options = EncoderOptions {
+            segmentation,
+            ..Default::default()
+        };
+        let (trie, vocab) = (
+            trie.clone().with_options(options.clone()),
+            vocab.clone().with_options(options),
+        );
+        assert_eq!(
+            trie.tokenize(text),
+            vocab.tokenize(text),
+            "{:?}",
+            segmentation
+        );
+    }
+    let wordpiece =
+        BytePairEncoder::new_from_wordpiece_str("[UNK]\nun\n#\n##a\n##ff\n##aff\n##able");
+    let trie = wordpiece.clone().with_vocab_store(TrieStore::new());
+    assert_eq!(trie.tokenize("unaffable"), wordpiece.tokenize("unaffable"));
+    assert_eq!(trie.tokenize("unaffable")[1..4], ["un", "##aff", "##able"]);
+}
+
+#[test]
+fn test_tokenizer_config() {
+    let options = EncoderOptions {
+        line_breaks: LineBreakPolicy::Normalize,
+        numbers: NumberPolicy::Magnitude,
+        emoji: EmojiPolicy::Replace("<emoji \"x\">".to_string()),
+        mode: TextMode::Code,
+        max_word_length: Some(100),
+        single_char_margin: Some(-2),
+        priors: Some(TokenPriors::new([("▁get", 3.0), ("é\n", -1.0)], 2.0)),
+        empty_sentences: EmptySentencePolicy::EmitToken("<empty>".to_string()),
+        cache_capacity: Some(64),
+        padding: Some(Padding {
+            length: 16,
+            side: PaddingSide::Left,
+        }),
+        special_tokens: SpecialTokenOptions {
+            sentence_start: Some("[CLS]".to_string()),
+            word_break: Some('Ġ'),
+            missing: MissingSpecialTokenPolicy::Register,
+            ..Default::default()
+        },
+        compat: CompatLevel::V0_1,
+        ..Default::default()
+    };
+    let json = options.to_json();
+    assert!(json.starts_with("{\n  \"version\": 1,\n  \"normalizer\": {\n"));
+    assert_eq!(EncoderOptions::from_json(&json).unwrap(), options);
+    assert_eq!(
+        EncoderOptions::from_json(&EncoderOptions::default().to_json()).unwrap(),
+        EncoderOptions::default()
+    );
+    assert_eq!(
+        EncoderOptions::from_json("{}").unwrap(),
+        EncoderOptions::default()
+    );
+
+    for invalid in [
+        "",
+        "[]",
+        r#"{"version": 2}"#,
+        r#"{"normalizer": {"numbers": "zero"}}"#,
+        r#"{"special_tokens": {"word_break": "ab"}}"#,
+        r#"{"runtime": {"cache_capacity": -1}}"#,
+        r#"{"pre_tokenizer": {"emoji": {"replace": 1}}}"#,
+        r#"{"truncation": {"max_tokens": 10}}"#,
+        r#"{"padding": {"side": "left"}}"#,
+        r#"{"padding": {"length": 8, "side": "top"}}"#,
+        r#"{"compat": "latest",}"#,
+    ] {
+        assert!(
+            matches!(
+                EncoderOptions::from_json(invalid),
+                Err(BytePairEncoderError::InvalidConfig { .. })
+            ),
+            "{}",
+            invalid
+        );
+    }
+
+    let vocab_path = "test_config_vocab.txt";
+    let config_path = "test_tokenizer_config.json";
+    let vocab = BytePairEncoder::new_from_str("[CLS]\t0\n</s>\t0\nĠget\t1\nValue\t1")
+        .unwrap()
+        .with_options(options);
+    vocab
+        .write_vocab(File::create(vocab_path).unwrap(), VocabOrder::Id)
+        .unwrap();
+    vocab
+        .write_config(File::create(config_path).unwrap())
+        .unwrap();
+    let loaded = BytePairEncoder::new_from_file_with_config(vocab_path, config_path).unwrap();
+    fs::remove_file(vocab_path).unwrap();
+    fs::remove_file(config_path).unwrap();
+    assert_eq!(loaded, vocab);
+    assert_eq!(loaded.tokenize("getValue"), vocab.tokenize("getValue"));
+    assert_eq!(
+        loaded.encode_full("getValue"),
+        vocab.encode_full("getValue")
+    );
+}
+
+#[test]
+fn test_tokenize_with_offsets() {
+    let vocab =
+        BytePairEncoder::new_from_str("▁hello\t1\n▁wor\t1\nld\t1\n▁caf\t1\ne\u{301}\t1").unwrap();
+    let text = "Hello,  world!\nCAFE\u{301} hello";
+    let tokens = vocab.tokenize_with_offsets(text);
+    assert_eq!(
+        tokens
+            .iter()
+            .map(|(token, _)| token.clone())
+            .collect::<Vec<_>>(),
+        vocab.tokenize(text)
+    );
+    let covered: Vec<(&str, &str)> = tokens
+        .iter()
+        .map(|(token, range)| (token.as_str(), &text[range.clone()]))
+        .collect();
+    assert_eq!(
+        covered,
+        vec![
+            ("<s>", ""),
+            ("▁hello", "Hello"),
+            ("▁wor", "wor"),
+            ("ld", "ld"),
+            ("</s>", ""),
+            ("<s>", ""),
+            ("▁caf", "CAF"),
+            ("e\u{301}", "E\u{301}"),
+            ("▁hello", "hello"),
+            ("</s>", ""),
+        ]
+    );
+    assert_eq!(tokens[4].1, 15..15);
+    assert!(vocab.tokenize_with_offsets("").is_empty());
+
+    // Offsets follow the text through options that reshape it.
+    let vocab = vocab.with_options(EncoderOptions {
+        max_word_length: Some(2),
+        invisible_chars: InvisibleCharPolicy::Strip,
+        ..Default::default()
+    });
+    let text = "he\u{200b}llo";
+    let covered: Vec<&str> = vocab
+        .tokenize_with_offsets(text)
+        .into_iter()
+        .map(|(_, range)| &text[range])
+        .collect();
+    assert_eq!(vocab.tokenize(text).len(), covered.len());
+    // The stripped zero-width space is not covered by any token.
+    assert_eq!(covered.concat(), "hello");
+}
+
+#[test]
+fn test_capabilities() {
+    let vocabs = capabilities::default_vocabs();
+    assert_eq!(capabilities::has_default_vocab(), !vocabs.is_empty());
+    assert_eq!(
+        vocabs.contains(&"default-small"),
+        cfg!(feature = "default-small")
+    );
+    for vocab in &vocabs {
+        assert!(capabilities::has_feature(vocab));
+    }
+    match BytePairEncoder::best_default_for_budget(usize::MAX) {
+        Ok((_, info)) => assert_eq!(Some(&info.feature), vocabs.last()),
+        Err(BytePairEncoderError::NoDefaultVocabFeature) => assert!(vocabs.is_empty()),
+        Err(e) => panic!("{}", e),
+    }
+
+    assert_eq!(
+        capabilities::has_feature("huggingface"),
+        cfg!(feature = "huggingface")
+    );
+    assert!(!capabilities::has_feature("no-such-feature"));
+    let features = capabilities::features();
+    assert!(features
+        .iter()
+        .all(|feature| capabilities::has_feature(feature)));
+    assert_eq!(features.contains(&"abi"), cfg!(feature = "abi"));
+
+    assert!(capabilities::parallelism() >= 1);
+    assert_eq!(
+        capabilities::supports_parallel(),
+        capabilities::parallelism() > 1
+    );
+}
+
+#[test]
+fn test_encode_full() {
+    let vocab = BytePairEncoder::new_from_str(
+        "<s>\t0\n</s>\t0\n<pad>\t0\n▁naïve\t1\n▁caf\t1\né\t1\n▁日\t1\n▁本\t1",
+    )
+    .unwrap();
+    let text = "Naïve café. 日本!";
+    let encoding = vocab.encode_full(text);
+    assert_eq!(encoding.tokens, vocab.tokenize(text));
+    let ids: Vec<u32> = encoding.ids.iter().flatten().copied().collect();
+    assert_eq!(ids, vec![0, 3, 4, 5, 1, 0, 6, 7, 1]);
+    for (range, chars) in encoding.offsets.iter().zip(&encoding.char_offsets) {
+        let covered: String = text.chars().skip(chars.start).take(chars.len()).collect();
+        assert_eq!(text[range.clone()], covered);
+    }
+    assert_eq!(encoding.offsets[3], 10..12);
+    assert_eq!(encoding.char_offsets[3], 9..10);
+    assert_eq!(encoding.char_offsets[7], 13..14);
+    let special: Vec<usize> = (0..encoding.len())
+        .filter(|&i| encoding.special_tokens_mask[i])
+        .collect();
+    assert_eq!(special, vec![0, 4, 5, 8]);
+    assert_eq!(encoding.attention_mask, vec![1; 9]);
+    assert_eq!(vocab.mask_spans(text, &[]), encoding);
+
+    let mut padded = encoding.clone();
+    padded.pad(11, "<pad>", Some(2));
+    assert_eq!(padded.len(), 11);
+    assert_eq!(padded.ids[9..], [Some(2), Some(2)]);
+    assert_eq!(padded.offsets[10], text.len()..text.len());
+    assert_eq!(padded.char_offsets[10], 15..15);
+    assert_eq!(padded.attention_mask[8..], [1, 0, 0]);
+    assert!(padded.special_tokens_mask[10]);
+    padded.pad(4, "<pad>", Some(2));
+    assert_eq!(padded.len(), 11);
+
+    let mut left = encoding.clone();
+    left.pad_left(11, "<pad>", Some(2));
+    assert_eq!(left.tokens[2..], encoding.tokens[..]);
+    assert_eq!(left.offsets[..3], [0..0, 0..0, 0..0]);
+    assert_eq!(left.attention_mask[..3], [0, 0, 1]);
+    assert!(left.special_tokens_mask[1]);
+
+    // The default padding of the options applies to every encoding.
+    for (side, expected) in [(PaddingSide::Right, padded), (PaddingSide::Left, left)] {
+        let vocab = vocab.clone().with_options(EncoderOptions {
+            padding: Some(Padding { length: 11, side }),
+            ..Default::default()
+        });
+        assert_eq!(vocab.encode_full(text), expected);
+    }
+
+    assert_eq!(vocab.encode_full(""), Encoding::default());
+}
+
+#[test]
+fn test_tokenizer_pool() {
+    let loads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let pool = TokenizerPool::new().with_cache_capacity(100);
+    pool.insert(
+        "en",
+        BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap(),
+    );
+    let counter = Arc::clone(&loads);
+    pool.register("de", move || {
+        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        BytePairEncoder::new_from_str("▁hallo\t1\n▁welt\t2")
+    });
+    pool.register("broken", || BytePairEncoder::new_from_str("▁hello\tone"));
+
+    assert_eq!(pool.names(), vec!["broken", "de", "en"]);
+    assert!(pool.is_loaded("en"));
+    assert!(!pool.is_loaded("de"));
+
+    // The same word is cached separately for each encoder.
+    assert_eq!(pool.encode_with("en", "hello").unwrap(), vec![0]);
+    assert_eq!(
+        pool.tokenize_with("de", "hello").unwrap(),
+        vec!["<s>", "<unk>", "</s>"]
+    );
+    assert_eq!(
+        pool.tokenize_with("de", "Hallo Welt").unwrap()[1..3],
+        ["▁hallo", "▁welt"]
+    );
+    assert_eq!(loads.load(std::sync::atomic::Ordering::SeqCst), 1);
+    assert!(pool.is_loaded("de"));
+
+    assert!(matches!(
+        pool.encode_with("fr", "Bonjour"),
+        Err(BytePairEncoderError::UnknownEncoder { name }) if name == "fr"
+    ));
+    assert!(pool.get("broken").is_err());
+    assert!(!pool.is_loaded("broken"));
+
+    // A limit below the pinned encoder unloads every registered encoder but the one in use.
+    let en_bytes = pool.get("en").unwrap().memory_bytes();
+    let limited = TokenizerPool::new().with_memory_limit(en_bytes);
+    limited.insert(
+        "en",
+        BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap(),
+    );
+    let counter = Arc::clone(&loads);
+    limited.register("de", move || {
+        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        BytePairEncoder::new_from_str("▁hallo\t1\n▁welt\t2")
+    });
+    limited.register("nl", || {
+        BytePairEncoder::new_from_str("▁hallo\t1\n▁wereld\t2")
+    });
+    limited.get("de").unwrap();
+    assert!(limited.is_loaded("de"));
+    limited.get("nl").unwrap();
+    assert!(limited.is_loaded("nl"));
+    assert!(!limited.is_loaded("de"));
+    assert!(limited.is_loaded("en"));
+    limited.get("de").unwrap();
+    assert_eq!(loads.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+    assert!(pool.remove("de"));
+    assert!(!pool.remove("de"));
+    assert_eq!(pool.names(), vec!["broken", "en"]);
+}
+
+#[test]
+fn test_conformance_fingerprint() {
+    use crate::conformance;
+
+    assert_eq!(
+        conformance::fingerprint(),
+        conformance::REFERENCE_FINGERPRINT
+    );
+    assert_eq!(conformance::fingerprint(), conformance::fingerprint());
+
+    let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    let other = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\n▁the\t3").unwrap();
+    assert_eq!(
+        conformance::fingerprint_with(&vocab),
+        conformance::fingerprint_with(&vocab.clone())
+    );
+    assert_ne!(
+        conformance::fingerprint_with(&vocab),
+        conformance::fingerprint_with(&other)
+    );
+
+    let mut options = EncoderOptions::default();
+    options.special_tokens.sentence_start = Some("[CLS]".to_string());
+    assert_ne!(
+        conformance::fingerprint_with(&vocab),
+        conformance::fingerprint_with(&vocab.clone().with_options(options))
+    );
+}
+
+#[test]
+fn test_trainer_builder() {
+    let builder = Trainer::builder()
+        .vocab_size(40)
+        .special_tokens(["<unk>", "<s>", "</s>", "<sep>"])
+        .min_pair_frequency(3)
+        .max_token_length(3)
+        .character_coverage(0.99)
+        .max_distinct_words(1_000)
+        .threads(2);
+    let expected = TrainerOptions {
+        vocab_size: 40,
+        special_tokens: vec!["<unk>", "<s>", "</s>", "<sep>"]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        min_pair_frequency: 3,
+        max_token_length: Some(3),
+        character_coverage: 0.99,
+        max_distinct_words: Some(1_000),
+        threads: 2,
+    };
+    assert_eq!(builder.options(), &expected);
+
+    let mut trainer = builder.build();
+    trainer.feed("low lower lowest low low newest");
+    let mut reference = Trainer::new(expected);
+    reference.feed("low lower lowest low low newest");
+    let encoder = trainer.train();
+    assert_eq!(
+        encoder.tokenize("lowest newer"),
+        reference.train().tokenize("lowest newer")
+    );
+    assert_eq!(encoder.token_to_id("<sep>"), Some(3));
+
+    assert_eq!(
+        TrainerBuilder::preset(TrainerPreset::Large32k).options(),
+        &TrainerOptions::preset(TrainerPreset::Large32k)
+    );
+}
+
+#[test]
+fn test_redact() {
+    let vocab = BytePairEncoder::new_from_str(
+        "<unk>\t0\n<s>\t0\n</s>\t0\n[MASK]\t0\n▁my\t1\n▁pin\t1\n▁is\t1\n▁12\t1\n34\t1\n▁bob\t1",
+    )
+    .unwrap();
+    let text = "My PIN is 1234. Bob";
+    let plain = vocab.encode_full(text);
+
+    // Tokens partly covered by a span are redacted whole, and alignment is kept.
+    let digits = 12..13;
+    let redaction = vocab.redact(text, &[digits]);
+    assert_eq!(redaction.redacted, 1);
+    assert_eq!(redaction.encoding.len(), plain.len());
+    assert_eq!(redaction.encoding.offsets, plain.offsets);
+    assert_eq!(
+        redaction.encoding.tokens,
+        vec!["<s>", "▁my", "▁pin", "▁is", "▁12", "[MASK]", "</s>", "<s>", "▁bob", "</s>"]
+    );
+    assert_eq!(redaction.encoding.ids[5], Some(3));
+    assert_eq!(redaction.text, "my pin is 12[MASK] bob");
+
+    let spans = vec![10..14, 16..19];
+    let redaction = vocab.redact(text, &spans);
+    assert_eq!(redaction.redacted, 3);
+    assert_eq!(redaction.text, "my pin is [MASK][MASK] [MASK]");
+
+    let redaction = vocab.redact(text, &spans[..1]);
+    assert_eq!(redaction.redacted, 2);
+
+    let redaction = vocab.redact(text, |token: &str| token.eq_ignore_ascii_case("bob"));
+    assert_eq!(redaction.text, "my pin is 1234 [MASK]");
+
+    let none: &[std::ops::Range<usize>] = &[];
+    assert_eq!(vocab.redact(text, none).encoding, plain);
+    assert_eq!(vocab.redact("", |_: &str| true).redacted, 0);
+}
+
+#[test]
+fn test_viterbi_segmentation() {
+    let vocab = BytePairEncoder::new_from_str(
+        "<unk>\t0\n▁ab\t-1\ncd\t-1\n▁abc\t-2\nd\t-10\n▁\t-5\na\t-5\nb\t-5\nc\t-5",
+    )
+    .unwrap();
+    let viterbi = vocab.clone().with_options(EncoderOptions {
+        segmentation: SegmentationStrategy::Viterbi,
+        ..Default::default()
+    });
+
+    // Greedy takes the longest token; Viterbi the highest total score.
+    assert_eq!(vocab.tokenize("abcd")[1..3], ["▁abc", "d"]);
+    assert_eq!(viterbi.tokenize("abcd")[1..3], ["▁ab", "cd"]);
+
+    // Unknown characters are kept to a minimum and merged, with their offsets.
+    assert_eq!(
+        viterbi.tokenize_with_offsets("abxyzd"),
+        vec![
+            ("<s>".to_string(), 0..0),
+            ("▁ab".to_string(), 0..2),
+            ("<unk>".to_string(), 2..5),
+            ("d".to_string(), 5..6),
+            ("</s>".to_string(), 6..6),
+        ]
+    );
+    assert_eq!(viterbi.tokenize("xyz"), vec!["<s>", "▁", "<unk>", "</s>"]);
+
+    // Ties on score go to the segmentation with fewer tokens.
+    let tied = BytePairEncoder::new_from_str("▁ab\t-2\n▁a\t-1\nb\t-1")
+        .unwrap()
+        .with_options(viterbi.options().clone());
+    assert_eq!(tied.tokenize("ab")[1..2], ["▁ab"]);
+
+    let config = viterbi.options().to_json();
+    assert!(config.contains("\"segmentation\": \"viterbi\""));
+    assert_eq!(
+        &EncoderOptions::from_json(&config).unwrap(),
+        viterbi.options()
+    );
+}
+
+#[test]
+fn test_cache_persistence() {
+    let vocab = BytePairEncoder::new_from_str("<unk>\t0\n▁hello\t1\n▁wor\t2\nld\t3")
+        .unwrap()
+        .with_options(EncoderOptions {
+            cache_capacity: Some(100),
+            ..Default::default()
+        });
+    let text = "Hello world! Hello xyz.";
+    let expected = vocab.tokenize(text);
+
+    let path = "test_word_cache.txt";
+    vocab.save_cache(path).unwrap();
+    let saved = fs::read_to_string(path).unwrap();
+    assert!(saved.contains("▁world\t▁wor\tld\n"));
+    assert!(saved.contains("▁xyz\t<unk>\n"));
+
+    // A fresh encoder starts with the saved words, and tokenizes the same.
+    let mut options = vocab.options().clone();
+    options.collect_metrics = true;
+    let next = vocab.clone().with_options(options);
+    assert_eq!(next.load_cache(path).unwrap(), 3);
+    assert_eq!(next.tokenize(text), expected);
+    assert_eq!(next.metrics().cache_misses, 0);
+
+    // Caches of other vocabularies or tokenization options are ignored.
+    let other = BytePairEncoder::new_from_str("▁hello\t1")
+        .unwrap()
+        .with_options(vocab.options().clone());
+    assert_eq!(other.load_cache(path).unwrap(), 0);
+    let mut options = vocab.options().clone();
+    options.mode = TextMode::Code;
+    assert_eq!(
+        vocab
+            .clone()
+            .with_options(options)
+            .load_cache(path)
+            .unwrap(),
+        0
+    );
+    let uncached = BytePairEncoder::new_from_str("<unk>\t0\n▁hello\t1\n▁wor\t2\nld\t3").unwrap();
+    assert_eq!(uncached.load_cache(path).unwrap(), 0);
+    fs::remove_file(path).unwrap();
+
+    let header = saved.lines().take(2).collect::<Vec<_>>().join("\n");
+    assert!(matches!(
+        vocab.load_cache_from_str("not a cache"),
+        Err(BytePairEncoderError::InvalidCache { line: 1 })
+    ));
+    assert!(matches!(
+        vocab.load_cache_from_str(&format!("{}\n▁hello\t▁nope", header)),
+        Err(BytePairEncoderError::InvalidCache { line: 3 })
+    ));
+    assert!(matches!(
+        vocab.load_cache("test_missing_word_cache.txt"),
+        Err(BytePairEncoderError::InvalidFile { .. })
+    ));
+
+    // The graphemes of `UnknownStrategy::Graphemes` are cached as tokens of their own.
+    let vocab = BytePairEncoder::new_from_str("<unk>\t0\n▁a\t1\nb\t2")
+        .unwrap()
+        .with_options(EncoderOptions {
+            cache_capacity: Some(100),
+            unknown: UnknownStrategy::Graphemes,
+            ..Default::default()
+        });
+    let text = "a e\u{301}b xyz";
+    let expected = vocab.tokenize(text);
+    assert!(expected.contains(&"▁e\u{301}".to_string()));
+    let mut saved = vec![];
+    vocab.write_cache(&mut saved).unwrap();
+    let saved = String::from_utf8(saved).unwrap();
+    assert!(saved.contains("▁xyz\t▁x\ty\tz\n"));
+    let next = vocab.clone();
+    assert_eq!(next.load_cache_from_str(&saved).unwrap(), 3);
+    assert_eq!(next.tokenize(text), expected);
+    let header = saved.lines().take(2).collect::<Vec<_>>().join("\n");
+    assert!(matches!(
+        next.load_cache_from_str(&format!("{}\n▁xyz\t▁x\tyz", header)),
+        Err(BytePairEncoderError::InvalidCache { line: 3 })
+    ));
+}
+
+#[test]
+fn test_wordpiece() {
+    let vocab = BytePairEncoder::new_from_wordpiece_str(
+        "[PAD]\n[UNK]\n[CLS]\n[SEP]\n[MASK]\n\nthe\nun\n##aff\n##able\n##a\n.\nplay\n##ing\n",
+    );
+    assert_eq!(vocab.vocab_size(), 13);
+    assert_eq!(vocab.token_to_id("[UNK]"), Some(1));
+    assert_eq!(vocab.token_to_id("##ing"), Some(12));
+    assert_eq!(
+        vocab.options().segmentation,
+        SegmentationStrategy::WordPiece
+    );
+
+    // Longest match from the left, continued with `##` tokens, and punctuation on its own.
+    let tokens = vocab.tokenize("The unaffable playing.");
+    assert_eq!(
+        tokens,
+        vec!["[CLS]", "the", "un", "##aff", "##able", "play", "##ing", ".", "[SEP]"]
+    );
+    assert_eq!(vocab.detokenize(&tokens), "the unaffable playing .");
+
+    // The first token covers the whole start of the word, and a word that cannot be covered
+    // completely is a single unknown token.
+    assert_eq!(
+        vocab.tokenize_with_offsets("unaffable playx"),
+        vec![
+            ("[CLS]".to_string(), 0..0),
+            ("un".to_string(), 0..2),
+            ("##aff".to_string(), 2..5),
+            ("##able".to_string(), 5..9),
+            ("[UNK]".to_string(), 10..15),
+            ("[SEP]".to_string(), 15..15),
+        ]
+    );
+
+    // Words are split around every punctuation character, as BERT's basic tokenizer does.
+    let bert = BytePairEncoder::new_from_wordpiece_str(
+        "[UNK]\n[CLS]\n[SEP]\ndon\n'\nt\n3\n.\n14\nu\ns\n東\n京\n$\n##s",
+    );
+    assert_eq!(
+        bert.tokenize("Don't 3.14 U.S. 東京 $s"),
+        vec![
+            "[CLS]", "don", "'", "t", "3", ".", "14", "u", ".", "s", ".", "[SEP]", "[CLS]", "東",
+            "京", "$", "s", "[SEP]"
+        ]
+    );
+
+    // Words longer than 100 characters are unknown, like BERT's `max_input_chars_per_word`.
+    let long = BytePairEncoder::new_from_wordpiece_str("[UNK]\nu\n##u");
+    assert_eq!(long.tokenize(&"u".repeat(100)).len(), 102);
+    assert_eq!(long.tokenize(&"u".repeat(101))[1..], ["[UNK]", "</s>"]);
+
+    // The greedy strategy does not know about continuation tokens.
+    let greedy = vocab.clone().with_options(EncoderOptions {
+        punctuation: PunctuationPolicy::Keep,
+        ..Default::default()
+    });
+    assert_eq!(greedy.tokenize("playing")[1..2], ["[UNK]"]);
+
+    let config = vocab.options().to_json();
+    assert!(config.contains("\"segmentation\": \"wordpiece\""));
+    assert_eq!(
+        &EncoderOptions::from_json(&config).unwrap(),
+        vocab.options()
+    );
+
+    fs::write("test_wordpiece_vocab.txt", "[UNK]\nhello\n##s\n").unwrap();
+    let from_file = BytePairEncoder::new_from_wordpiece_file("test_wordpiece_vocab.txt").unwrap();
+    fs::remove_file("test_wordpiece_vocab.txt").unwrap();
+    assert_eq!(from_file.tokenize("hellos")[1..3], ["hello", "##s"]);
+    assert!(matches!(
+        BytePairEncoder::new_from_wordpiece_file("test_missing_wordpiece_vocab.txt"),
+        Err(BytePairEncoderError::InvalidFile { .. })
+    ));
+}
+
+#[test]
+fn test_tokenize_pretokenized() {
+    let vocab = BytePairEncoder::new_from_str(
+        "<unk>\t0\n▁hello\t1\n▁world\t1\n▁,\t1\n▁e-mail\t3\n▁e\t1\n▁mail\t1",
+    )
+    .unwrap();
+
+    // Each word is normalized and matched on its own, and punctuation is kept.
+    assert_eq!(
+        vocab.tokenize_pretokenized(&["Hello", ",", "World"]),
+        vec!["<s>", "▁hello", "▁,", "▁world", "</s>"]
+    );
+    assert_eq!(
+        vocab.tokenize_pretokenized(&["Hello", "World"]),
+        vocab.tokenize("Hello world")
+    );
+
+    // The caller's segmentation wins over the encoder's.
+    assert_eq!(vocab.tokenize("e-mail")[1..3], ["▁e", "▁mail"]);
+    assert_eq!(
+        vocab.tokenize_pretokenized(&["e-mail".to_string()]),
+        vec!["<s>", "▁e-mail", "</s>"]
+    );
+
+    // Words with nothing left after normalization produce no tokens, and no words no sentence.
+    let stripped = vocab.clone().with_options(EncoderOptions {
+        invisible_chars: InvisibleCharPolicy::Strip,
+        ..Default::default()
+    });
+    assert_eq!(
+        stripped.tokenize_pretokenized(&["\u{200b}", "hello"]),
+        vec!["<s>", "▁hello", "</s>"]
+    );
+    assert!(vocab.tokenize_pretokenized::<&str>(&[]).is_empty());
+
+    let capped = vocab.clone().with_options(EncoderOptions {
+        max_sentence_tokens: Some(4),
+        ..Default::default()
+    });
+    assert_eq!(
+        capped.tokenize_pretokenized(&["hello", "hello", "hello"]),
+        vec!["<s>", "▁hello", "▁hello", "</s>", "<s>", "▁hello", "</s>"]
+    );
+}
+
+#[test]
+fn test_byte_fallback() {
+    let vocab = BytePairEncoder::new_from_str("<unk>\t0\n▁hello\t1\n▁\t-1").unwrap();
+    let bytes = vocab.clone().with_options(EncoderOptions {
+        unknown: UnknownStrategy::Bytes,
+        emoji: EmojiPolicy::Keep,
+        ..Default::default()
+    });
+
+    assert_eq!(vocab.tokenize("hello éx")[2..], ["▁", "<unk>", "</s>"]);
+    let tokens = bytes.tokenize("hello éx");
+    assert_eq!(tokens[2..], ["▁", "<0xC3>", "<0xA9>", "<0x78>", "</s>"]);
+    assert_eq!(bytes.detokenize(&tokens), "hello éx");
+
+    // Each byte token covers its whole character, and the word break becomes a space.
+    let offsets = bytes.tokenize_with_offsets("hello éx");
+    assert_eq!(offsets[3], ("<0xC3>".to_string(), 6..8));
+    assert_eq!(offsets[4], ("<0xA9>".to_string(), 6..8));
+    assert_eq!(offsets[5], ("<0x78>".to_string(), 8..9));
+    let unspaced = BytePairEncoder::new_from_str("<unk>\t0\n▁hello\t1")
+        .unwrap()
+        .with_options(bytes.options().clone());
+    assert_eq!(
+        unspaced.tokenize("hi")[1..4],
+        ["<0x20>", "<0x68>", "<0x69>"]
+    );
+    assert_eq!(
+        unspaced.detokenize(&unspaced.tokenize("hello hi")),
+        "hello hi"
+    );
+
+    // Missing emoji fall back to bytes too, and no unknown token is emitted, though the text is
+    // still reported as uncovered.
+    assert_eq!(
+        bytes.tokenize("😀")[1..],
+        ["▁", "<0xF0>", "<0x9F>", "<0x98>", "<0x80>", "</s>"]
+    );
+    assert!(!bytes.tokenize("hello éx").contains(&"<unk>".to_string()));
+    assert_eq!(bytes.uncovered_spans("hello éx"), vec![6..9]);
+
+    // Registered byte tokens get IDs, so no content is lost in the IDs either.
+    let registered = bytes.clone().with_options(EncoderOptions {
+        unknown: UnknownStrategy::Bytes,
+        special_tokens: SpecialTokenOptions {
+            missing: MissingSpecialTokenPolicy::Register,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let ids = registered.encode("hello éx");
+    assert_eq!(registered.decode(&ids).unwrap(), "hello éx");
+
+    let config = bytes.options().to_json();
+    assert!(config.contains("\"unknown\": \"bytes\""));
+    assert_eq!(
+        &EncoderOptions::from_json(&config).unwrap(),
+        bytes.options()
+    );
+}
+
+#[test]
+fn test_grapheme_fallback() {
+    let vocab = BytePairEncoder::new_from_str("<unk>\t0\n▁hello\t1").unwrap();
+    let graphemes = vocab.clone().with_options(EncoderOptions {
+        unknown: UnknownStrategy::Graphemes,
+        emoji: EmojiPolicy::Keep,
+        collect_metrics: true,
+        ..Default::default()
+    });
+
+    // Each grapheme cluster is a token of its own, the first one with the word break.
+    let tokens = graphemes.tokenize("hello ne\u{301}x 👍🏽");
+    assert_eq!(tokens[2..], ["▁n", "e\u{301}", "x", "▁", "👍🏽", "</s>"][..]);
+    assert_eq!(graphemes.detokenize(&tokens), "hello ne\u{301}x 👍🏽");
+    assert_eq!(graphemes.metrics().unknown_tokens, 5);
+
+    // The graphemes are marked unknown by their IDs, and keep their offsets.
+    let encoding = graphemes.encode_full("hello ne\u{301}x");
+    assert_eq!(encoding.tokens[3], "e\u{301}");
+    assert_eq!(encoding.ids[2..5], [Some(0); 3]);
+    assert_eq!(encoding.offsets[2..5], [6..7, 7..10, 10..11]);
+    assert_eq!(graphemes.encode("hello nex")[1..], [1, 0, 0, 0, 0]);
+
+    // IDs match those of `encode`, also for reserved IDs and byte tokens without an ID.
+    let registered = BytePairEncoder::new_from_str("▁hello\t1")
+        .unwrap()
+        .with_options(EncoderOptions {
+            special_tokens: SpecialTokenOptions {
+                missing: MissingSpecialTokenPolicy::Register,
+                ..Default::default()
+            },
+            ..graphemes.options().clone()
+        });
+    let bytes = vocab.clone().with_options(EncoderOptions {
+        unknown: UnknownStrategy::Bytes,
+        ..Default::default()
+    });
+    for encoder in [&registered, &bytes] {
+        let ids: Vec<u32> = encoder
+            .encode_full("hello xyz")
+            .ids
+            .into_iter()
+            .flatten()
+            .collect();
+        assert_eq!(ids, encoder.encode("hello xyz"));
+    }
+    assert_eq!(
+        registered.encode_full("hello xyz").ids,
+        [Some(1), Some(0), Some(3), Some(3), Some(3), Some(2)]
+    );
+
+    // They are not stored in the encoder, so the borrowing methods yield them owned, also when
+    // cached, and decode them like the owned tokens.
+    let tokens = graphemes.tokenize_ref("nex");
+    assert_eq!(tokens[1..4], ["▁n", "e", "x"]);
+    assert!(matches!(tokens[2], Cow::Owned(_)));
+    assert!(graphemes.is_lossless_for("nex"));
+    let cached = graphemes.clone().with_options(EncoderOptions {
+        cache_capacity: Some(8),
+        ..graphemes.options().clone()
+    });
+    for _ in 0..2 {
+        assert_eq!(cached.tokenize_ref("nex"), cached.tokenize("nex"));
+        assert_eq!(cached.tokenize("nex")[1..4], ["▁n", "e", "x"]);
+    }
+
+    let config = graphemes.options().to_json();
+    assert!(config.contains("\"unknown\": \"graphemes\""));
+    assert_eq!(
+        &EncoderOptions::from_json(&config).unwrap(),
+        graphemes.options()
+    );
+}
+
+#[test]
+fn test_unknown_strategy() {
+    let vocab = BytePairEncoder::new_from_str("<unk>\t0\n▁hello\t1\n▁wor\t1\nd\t1").unwrap();
+    let with = |unknown| {
+        vocab.clone().with_options(EncoderOptions {
+            unknown,
+            emoji: EmojiPolicy::Keep,
+            ..Default::default()
+        })
+    };
+    let text = "Hello, world 😀!";
+
+    let skip = with(UnknownStrategy::Skip);
+    assert_eq!(
+        skip.tokenize(text),
+        vec!["<s>", "▁hello", "▁wor", "d", "</s>"]
+    );
+    assert_eq!(skip.encode_full(text).offsets[2..4], [7..10, 11..12]);
+    assert!(skip.try_tokenize(text).is_ok());
+
+    // Only `try_tokenize` fails; the infallible methods behave like `Unk`.
+    let strict = with(UnknownStrategy::Error);
+    assert_eq!(
+        strict.tokenize(text),
+        with(UnknownStrategy::Unk).tokenize(text)
+    );
+    match strict.try_tokenize(text) {
+        Err(BytePairEncoderError::UnknownToken { span }) => assert_eq!(&text[span], "l"),
+        other => panic!("expected an unknown token error, got {:?}", other),
+    }
+    match strict.try_tokenize("word 😀") {
+        Err(BytePairEncoderError::UnknownToken { span }) => assert_eq!(span, 5..9),
+        other => panic!("expected an unknown token error, got {:?}", other),
+    }
+    assert_eq!(
+        strict.try_tokenize("Hello word").unwrap(),
+        vec!["<s>", "▁hello", "▁wor", "d", "</s>"]
+    );
+    assert!(with(UnknownStrategy::Unk).try_tokenize(text).is_ok());
+
+    for unknown in [UnknownStrategy::Skip, UnknownStrategy::Error] {
+        let config = with(unknown).options().to_json();
+        assert_eq!(EncoderOptions::from_json(&config).unwrap().unknown, unknown);
+    }
+}
+
+#[test]
+fn test_gpt2() {
+    let vocab_json = r#"{"<s>": 0, "</s>": 1, "a": 2, "b": 3, "c": 4, "Ġ": 5, "Ã": 6, "©": 7,
+        "Ċ": 8, "A": 9, "±": 10, "ab": 11, "bc": 12, "Ã©": 13, "Ġbc": 14}"#;
+    let merges = "#version: 0.2\nb c\na b\nĠ bc\nÃ ©\n";
+    let vocab = BytePairEncoder::new_from_gpt2_str(vocab_json, merges).unwrap();
+    assert_eq!(vocab.vocab_size(), 15);
+    assert_eq!(vocab.options().mode, TextMode::Lossless);
+    assert_eq!(vocab.options().segmentation, SegmentationStrategy::Merges);
+
+    // Merges are applied by rank, not by length: `b c` is merged before `a b` can be.
+    let tokens = vocab.tokenize("abc bc");
+    assert_eq!(tokens, vec!["<s>", "a", "bc", "Ġbc", "</s>"]);
+    assert_eq!(vocab.encode("abc bc"), vec![0, 2, 12, 14, 1]);
+
+    // Case is kept, and bytes map to the byte-level alphabet, whether merged or not. The line
+    // break does not end a sentence, as GPT-2 has none.
+    let text = "Ab é ñ\nab";
+    let tokens = vocab.tokenize(text);
+    assert_eq!(
+        tokens,
+        vec!["<s>", "A", "b", "Ġ", "Ã©", "Ġ", "Ã", "±", "Ċ", "ab", "</s>"]
+    );
+    assert_eq!(vocab.detokenize(&tokens), text);
+    assert_eq!(
+        vocab.tokenize_with_offsets("ñ")[1..3],
+        [("Ã".to_string(), 0..2), ("±".to_string(), 0..2)]
+    );
+
+    // Errors in either file are reported.
+    assert!(matches!(
+        BytePairEncoder::new_from_gpt2_str(vocab_json, "a b\nab\n"),
+        Err(BytePairEncoderError::InvalidMerges { line: 2 })
+    ));
+    assert!(matches!(
+        BytePairEncoder::new_from_gpt2_str(r#"{"a": 0, "b": 2}"#, ""),
+        Err(BytePairEncoderError::ConversionError { .. })
+    ));
+    assert!(matches!(
+        BytePairEncoder::new_from_gpt2_str(r#"{"a": "0"}"#, ""),
+        Err(BytePairEncoderError::ConversionError { .. })
+    ));
+
+    // The files load the same encoder, and the merges take part in equality.
+    let dir = std::env::temp_dir().join(format!("bpe-gpt2-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (vocab_path, merges_path) = (dir.join("vocab.json"), dir.join("merges.txt"));
+    fs::write(&vocab_path, vocab_json).unwrap();
+    fs::write(&merges_path, merges).unwrap();
+    let loaded = BytePairEncoder::new_from_gpt2_files(
+        vocab_path.to_str().unwrap(),
+        merges_path.to_str().unwrap(),
+    )
+    .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(loaded, vocab);
+    assert_ne!(
+        BytePairEncoder::new_from_gpt2_str(vocab_json, "a b").unwrap(),
+        vocab
+    );
+    assert!(
+        vocab.memory_bytes()
+            > BytePairEncoder::new_from_gpt2_str(vocab_json, "")
+                .unwrap()
+                .memory_bytes()
+    );
+
+    let config = vocab.options().to_json();
+    assert_eq!(
+        EncoderOptions::from_json(&config).unwrap().segmentation,
+        SegmentationStrategy::Merges
+    );
+
+    // The native files don't hold the merges, so loading them with the configuration fails.
+    let dir = std::env::temp_dir().join(format!("bpe-gpt2-config-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (vocab_path, config_path) = (dir.join("vocab.txt"), dir.join("config.json"));
+    vocab
+        .write_vocab(fs::File::create(&vocab_path).unwrap(), VocabOrder::Id)
+        .unwrap();
+    vocab
+        .write_config(fs::File::create(&config_path).unwrap())
+        .unwrap();
+    let loaded = BytePairEncoder::new_from_file_with_config(
+        vocab_path.to_str().unwrap(),
+        config_path.to_str().unwrap(),
+    );
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(
+        loaded,
+        Err(BytePairEncoderError::InvalidConfig { .. })
+    ));
+}
+
+#[test]
+fn test_gpt2_pretokenization() {
+    // A byte-level vocabulary of the 256 byte symbols, in byte order, and a few merges.
+    let merges = "#version: 0.2\nd o\nĠ s\nĠs t\no p\nĠst op\n! !\ndo n\n' t\nn '\n!! !\n";
+    let symbols = (0..=255).map(|byte| crate::gpt2::byte_char(byte).to_string());
+    let merged = merges.lines().skip(1).map(|line| line.replace(' ', ""));
+    let entries: Vec<String> = symbols
+        .into_iter()
+        .chain(merged)
+        .enumerate()
+        .map(|(id, token)| format!("{}: {}", crate::json::json_string(&token), id))
+        .collect();
+    let vocab_json = format!("{{{}}}", entries.join(", "));
+    let vocab = BytePairEncoder::new_from_gpt2_str(&vocab_json, merges).unwrap();
+
+    // Contractions and runs of symbols are pieces of their own, so `n '` never applies and
+    // `!! !` does. There are no sentence markers, since the vocabulary has none.
+    let text = "don't stop!!!";
+    assert_eq!(vocab.tokenize(text), vec!["don", "'t", "Ġstop", "!!!"]);
+    assert_eq!(vocab.encode(text), vec![262, 263, 260, 265]);
+
+    // Whitespace runs leave their last space to the next word, and line breaks don't split.
+    let text = "stop.  Don't\n\nstop 42x ";
+    let pieces: Vec<&str> = crate::gpt2::pretokenize(text).collect();
+    assert_eq!(
+        pieces,
+        vec!["stop", ".", " ", " Don", "'t", "\n", "\n", "stop", " 42", "x", " "]
+    );
+    assert_eq!(vocab.detokenize(&vocab.tokenize(text)), text);
+
+    // A literal word break character is spelled by its UTF-8 bytes, like any other character.
+    assert_eq!(vocab.tokenize("Ġe"), vec!["Ä", "ł", "e"]);
+    for text in ["Ġe", "aĠb Ġ", " ĠĠ"] {
+        assert_eq!(vocab.decode(&vocab.encode(text)).unwrap(), text);
+    }
+
+    #[cfg(feature = "huggingface")]
+    {
+        use tokenizers::{models::bpe::BPE, pre_tokenizers::byte_level::ByteLevel, Tokenizer};
+
+        let (vocab_path, merges_path) = (
+            std::env::temp_dir().join(format!("bpe-gpt2-hf-vocab-{}", std::process::id())),
+            std::env::temp_dir().join(format!("bpe-gpt2-hf-merges-{}", std::process::id())),
+        );
+        fs::write(&vocab_path, &vocab_json).unwrap();
+        fs::write(&merges_path, merges).unwrap();
+        let model = BPE::from_file(vocab_path.to_str().unwrap(), merges_path.to_str().unwrap())
+            .build()
+            .unwrap();
+        fs::remove_file(&vocab_path).unwrap();
+        fs::remove_file(&merges_path).unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(ByteLevel::new(false, true, true)));
+        for text in [
+            "don't stop!!!",
+            "stop.  Don't\n\nstop 42x ",
+            "it's   done'd !! ",
+            "é ñ!",
+            "Ġe aĠb Ġ",
+        ] {
+            let encoding = tokenizer.encode(text, false).unwrap();
+            let ids: Vec<u32> = vocab.encode(text);
+            assert_eq!(encoding.get_ids(), ids.as_slice(), "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// # The length of a token's text, in several units.
+///
+/// Downstream constraints are expressed in different units: database columns in bytes, string
+/// APIs in characters, and user interfaces in graphemes (what a reader perceives as a single
+/// character, such as `é` written as `e` plus a combining accent). Lengths are measured on the
+/// token text as emitted, including any word break character (`▁`).
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::TokenLengths;
+///
+/// let lengths = TokenLengths::of("▁cafe\u{301}");
+/// assert_eq!((lengths.bytes, lengths.chars, lengths.graphemes), (9, 6, 5));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct TokenLengths {
+    /// The length in bytes, as UTF-8.
+    pub bytes: usize,
+
+    /// The length in Unicode scalar values (`char`s).
+    pub chars: usize,
+
+    /// The length in extended grapheme clusters.
+    pub graphemes: usize,
+}
+
+impl TokenLengths {
+    /// Measures `token`.
+    pub fn of(token: &str) -> Self {
+        TokenLengths {
+            bytes: token.len(),
+            chars: token.chars().count(),
+            graphemes: token.graphemes(true).count(),
+        }
+    }
+}
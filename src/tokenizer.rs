@@ -1,13 +1,103 @@
-use std::{collections::HashMap, fs, iter};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    convert::Infallible,
+    fmt, fs,
+    hash::{Hash, Hasher},
+    io, iter, mem,
+    ops::Range,
+    rc::Rc,
+    str::FromStr,
+    sync::Arc,
+    thread,
+};
 
 use unicode_segmentation::UnicodeSegmentation;
 
+#[cfg(feature = "encryption")]
+use crate::encryption;
 use crate::{
+    bounded_queue::{BoundedQueue, CloseGuard, FinishGuard},
+    builder::BytePairEncoderBuilder,
+    cache::{CacheStore, WordCache},
+    confusables::fold_confusables,
     constants::*,
-    default_vocabs::{new_default, DefaultVocab},
-    BytePairEncoderError,
+    default_vocabs::{
+        best_default_for_budget, new_default, verify_default_vocabs, DefaultVocab, DefaultVocabInfo,
+    },
+    distance::{closest_tokens, ClosestTokens},
+    either::Either,
+    encoding::Encoding,
+    fnv::Fnv1a,
+    gpt2::{byte_char, char_byte, parse_vocab_json, pretokenize, Merges},
+    json::json_string,
+    metadata::Metadata,
+    metrics::{Metrics, MetricsCounters},
+    normalized::NormalizedText,
+    normalizer::{
+        attach_spaces, byte_token, carry_trailing_whitespace, escape_patterns, fold_case,
+        hyphenation_breaks, invisible_breaks, is_emoji, is_invisible, is_line_break,
+        normalize_numbers, push_cased, rejoin, split_around, split_identifier, split_punctuation,
+        utf8_len, CaseMarker, SOFT_HYPHEN,
+    },
+    options::{
+        ConfusablePolicy, EmojiPolicy, EmptySentencePolicy, HyphenationPolicy, InvisibleCharPolicy,
+        LineBreakPolicy, MissingSpecialTokenPolicy, NumberPolicy, PaddingSide, PunctuationPolicy,
+        SegmentationStrategy, SpecialTextPolicy, SpecialTokenOptions, StreamLimits, TextMode,
+        UnknownStrategy, VocabOrder,
+    },
+    parse_report::ParseReport,
+    preview::VocabPreview,
+    sentencepiece,
+    special_tokens::SpecialTokens,
+    test_vectors::TestVector,
+    token_lengths::TokenLengths,
+    vocab::Vocab,
+    vocab_store::VocabStore,
+    word_tokens::WordTokens,
+    BytePairEncoderError, EncoderOptions,
 };
 
+/// A token with the byte range of the text it covers, and whether it was inserted by the encoder
+/// rather than matched against the text. Tokens are borrowed from the encoder, except the
+/// graphemes of `UnknownStrategy::Graphemes`.
+type TokenSpan<'a> = (Cow<'a, str>, Range<usize>, bool);
+
+/// # A token of the sentence pipeline, with or without the text it covers.
+///
+/// [`BytePairEncoder::sentence_tokens`] assembles sentences from the tokens of their words, both
+/// for plain tokens and for [`TokenSpan`]s, so the two never disagree on the tokens.
+trait SentenceToken<'a> {
+    /// Builds a token inserted by the encoder, covering the given byte range of the sentence.
+    fn inserted(token: &'a str, range: Range<usize>) -> Self;
+
+    /// Returns the byte range of the sentence covered by the token, if it is tracked.
+    fn range(&self) -> Option<&Range<usize>>;
+}
+
+impl<'a> SentenceToken<'a> for Cow<'a, str> {
+    fn inserted(token: &'a str, _: Range<usize>) -> Self {
+        Cow::Borrowed(token)
+    }
+
+    fn range(&self) -> Option<&Range<usize>> {
+        None
+    }
+}
+
+impl<'a> SentenceToken<'a> for TokenSpan<'a> {
+    fn inserted(token: &'a str, range: Range<usize>) -> Self {
+        (Cow::Borrowed(token), range, true)
+    }
+
+    fn range(&self) -> Option<&Range<usize>> {
+        Some(&self.1)
+    }
+}
+
+/// The first line of a word cache file, identifying the format and its version.
+const CACHE_HEADER: &str = "#@bpe-tokenizer word cache: 1";
+
 /// # Represents a Byte Pair Encoding (BPE) vocabulary used for tokenization.
 ///
 /// This struct holds the mapping of tokens to their respective scores and provides methods for
@@ -24,13 +114,75 @@ use crate::{
 /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
 /// let tokenized = vocab.tokenize("Hello, world!");
 /// ```
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct BytePairEncoder {
-    /// # A mapping of tokens to their respective scores.
+    /// # A mapping of tokens to their respective scores and IDs.
     ///
     /// In BPE, tokens with higher scores are typically more common and are preferred during the
     /// tokenization process.
-    pub(crate) tokens: HashMap<String, isize>,
+    pub(crate) tokens: Vocab,
+
+    /// # Options controlling how text is prepared before tokenization.
+    pub(crate) options: EncoderOptions,
+
+    /// # Runtime counters, updated while `EncoderOptions::collect_metrics` is enabled.
+    pub(crate) metrics: MetricsCounters,
+
+    /// # The special tokens, resolved from `EncoderOptions::special_tokens` and the vocabulary.
+    pub(crate) special_tokens: SpecialTokens,
+
+    /// # The cache of tokenized words, present while `EncoderOptions::cache_capacity` is set.
+    pub(crate) cache: Option<WordCache>,
+
+    /// # Descriptive information about the encoder, persisted with the vocabulary.
+    pub(crate) metadata: Metadata,
+
+    /// # The fingerprint of `tokens`, kept up to date whenever they change.
+    pub(crate) fingerprint: u64,
+
+    /// # The ranked merges used by `SegmentationStrategy::Merges`, if the encoder has any.
+    pub(crate) merges: Option<Arc<Merges>>,
+}
+
+/// Two encoders are equal when they have the same vocabulary (tokens, scores, and IDs) and the
+/// same options, i.e. when they tokenize every input identically. Metadata, runtime counters, and
+/// cache contents are not taken into account.
+///
+/// Vocabularies are compared by [`BytePairEncoder::fingerprint`] first, so encoders with
+/// different vocabularies compare in constant time.
+impl PartialEq for BytePairEncoder {
+    fn eq(&self, other: &Self) -> bool {
+        self.fingerprint == other.fingerprint
+            && self.options == other.options
+            && self.tokens == other.tokens
+            && self.merges == other.merges
+    }
+}
+
+impl Eq for BytePairEncoder {}
+
+/// Hashes the vocabulary fingerprint and the options, consistently with `PartialEq`, so encoders
+/// can be used as keys for per-tokenizer resources. Hashing takes constant time in the size of the
+/// vocabulary.
+///
+/// ## Example
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use bpe_tokenizer::BytePairEncoder;
+///
+/// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+///
+/// let mut resources = HashMap::new();
+/// resources.insert(vocab.clone(), "embeddings-v1");
+/// assert_eq!(resources.get(&vocab), Some(&"embeddings-v1"));
+/// ```
+impl Hash for BytePairEncoder {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.fingerprint.hash(state);
+        self.options.hash(state);
+    }
 }
 
 impl BytePairEncoder {
@@ -73,7 +225,8 @@ impl BytePairEncoder {
     ///
     /// This function will return an error if:
     /// * The file cannot be read (returns `BytePairEncoderError::InvalidFile`)
-    /// * The file contents are not in the expected format (returns `BytePairEncoderError::InvalidVocabularyInput`)
+    /// * The file contents are not in the expected format (returns
+    ///   `BytePairEncoderError::InvalidVocabularyInput` or `BytePairEncoderError::InvalidScore`)
     ///
     /// ## Example
     ///
@@ -85,11 +238,135 @@ impl BytePairEncoder {
     pub fn new_from_file(file_path: &str) -> Result<Self, BytePairEncoderError> {
         Self::new_from_str(
             fs::read_to_string(file_path)
-                .map_err(|_| BytePairEncoderError::InvalidFile(file_path.to_string()))?
+                .map_err(|e| BytePairEncoderError::InvalidFile {
+                    path: file_path.to_string(),
+                    source: e,
+                })?
                 .as_ref(),
         )
     }
 
+    /// # Creates a new `BytePairEncoder` from an encrypted vocabulary file.
+    ///
+    /// Proprietary vocabularies can be shipped encrypted with AES-256-GCM, so the file on disk
+    /// does not reveal them. Files are written by [`BytePairEncoder::write_encrypted_vocab`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `file_path` - The path to the encrypted vocabulary file.
+    /// * `key` - The 256-bit key the file was encrypted with.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result<Self, BytePairEncoderError>`, like [`BytePairEncoder::new_from_file`].
+    ///
+    /// ## Errors
+    ///
+    /// In addition to the errors of [`BytePairEncoder::new_from_file`], this returns
+    /// `BytePairEncoderError::DecryptionError` if the file is not an encrypted vocabulary, the
+    /// key is wrong, or the file was modified.
+    ///
+    /// ## Note
+    ///
+    /// This is only enabled when the `encryption` feature is enabled in Cargo.toml.
+    #[cfg(feature = "encryption")]
+    pub fn new_from_encrypted_file(
+        file_path: &str,
+        key: &[u8; 32],
+    ) -> Result<Self, BytePairEncoderError> {
+        let container = fs::read(file_path).map_err(|e| BytePairEncoderError::InvalidFile {
+            path: file_path.to_string(),
+            source: e,
+        })?;
+        Self::new_from_encrypted_bytes(&container, key)
+    }
+
+    /// # Creates a new `BytePairEncoder` from an encrypted vocabulary in memory.
+    ///
+    /// This is [`BytePairEncoder::new_from_encrypted_file`] for vocabularies that are not read
+    /// from a file, e.g. ones embedded with `include_bytes!`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, VocabOrder};
+    ///
+    /// let key = [7; 32];
+    /// let vocab = BytePairEncoder::new_from_str("▁secret\t1\n▁sauce\t2").unwrap();
+    /// let mut encrypted = Vec::new();
+    /// vocab
+    ///     .write_encrypted_vocab(&mut encrypted, VocabOrder::Id, &key)
+    ///     .unwrap();
+    ///
+    /// let loaded = BytePairEncoder::new_from_encrypted_bytes(&encrypted, &key).unwrap();
+    /// assert_eq!(loaded, vocab);
+    /// assert!(BytePairEncoder::new_from_encrypted_bytes(&encrypted, &[8; 32]).is_err());
+    /// ```
+    ///
+    /// ## Note
+    ///
+    /// This is only enabled when the `encryption` feature is enabled in Cargo.toml.
+    #[cfg(feature = "encryption")]
+    pub fn new_from_encrypted_bytes(
+        container: &[u8],
+        key: &[u8; 32],
+    ) -> Result<Self, BytePairEncoderError> {
+        let plaintext = encryption::open(container, key)?;
+        let input =
+            String::from_utf8(plaintext).map_err(|_| BytePairEncoderError::DecryptionError {
+                reason: "decrypted vocabulary is not valid UTF-8".to_string(),
+            })?;
+        Self::new_from_str(&input)
+    }
+
+    /// # Reads the first tokens of a vocabulary file without loading all of it.
+    ///
+    /// Only the first `n` tokens (and any metadata before them) are read, so tooling can quickly
+    /// check a user-supplied file, and show what it contains, before committing to a full load
+    /// with [`BytePairEncoder::new_from_file`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `file_path` - The path to the vocabulary file.
+    /// * `n` - The maximum number of tokens to read.
+    ///
+    /// ## Returns
+    ///
+    /// A [`VocabPreview`] with the tokens read, the metadata, and the [`ScoreConvention`] their
+    /// scores follow.
+    ///
+    /// [`ScoreConvention`]: crate::ScoreConvention
+    ///
+    /// ## Errors
+    ///
+    /// This returns the same errors as [`BytePairEncoder::new_from_file`] for the lines it reads,
+    /// except that a file whose first token line is not in the expected format at all (e.g. a JSON
+    /// vocabulary) is reported as `BytePairEncoderError::UnrecognizedFormat`, with the reason.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use bpe_tokenizer::{BytePairEncoder, ScoreConvention};
+    ///
+    /// let preview = BytePairEncoder::peek_vocab_file("path/to/vocabulary/file.txt", 10).unwrap();
+    /// for (token, score) in &preview.entries {
+    ///     println!("{}\t{}", token, score);
+    /// }
+    /// if preview.scores == ScoreConvention::Unordered {
+    ///     println!("warning: scores are not sorted");
+    /// }
+    /// ```
+    pub fn peek_vocab_file(
+        file_path: &str,
+        n: usize,
+    ) -> Result<VocabPreview, BytePairEncoderError> {
+        let file = fs::File::open(file_path).map_err(|e| BytePairEncoderError::InvalidFile {
+            path: file_path.to_string(),
+            source: e,
+        })?;
+        VocabPreview::read(io::BufReader::new(file), n, file_path)
+    }
+
     /// # Creates a new `BytePairEncoder` from a string containing token-score pairs.
     ///
     /// This function parses the input string to construct a `BytePairEncoder`. The input should
@@ -108,6 +385,8 @@ impl BytePairEncoder {
     /// * A token (a string) followed by a tab character (`\t`)
     /// * A score (an integer) as either a positive or negative value.
     ///
+    /// Lines of the form `#@<key>: <value>` (without a tab) are read as [`Metadata`] instead.
+    ///
     /// For example:
     ///
     /// ```text
@@ -127,9 +406,10 @@ impl BytePairEncoder {
     ///
     /// ## Errors
     ///
-    /// This function will return `BytePairEncoderError::InvalidVocabularyInput` if:
-    /// * A line doesn't contain a tab character to separate token and score.
-    /// * The score cannot be parsed as an `isize`.
+    /// This function will return an error if:
+    /// * A line doesn't contain a tab character to separate token and score (returns
+    ///   `BytePairEncoderError::InvalidVocabularyInput`)
+    /// * The score cannot be parsed as an `isize` (returns `BytePairEncoderError::InvalidScore`)
     ///
     /// ## Example
     ///
@@ -140,21 +420,387 @@ impl BytePairEncoder {
     /// let vocab = BytePairEncoder::new_from_str(input).unwrap();
     /// ```
     pub fn new_from_str(input: &str) -> Result<Self, BytePairEncoderError> {
-        let mut tokens = HashMap::new();
+        Self::parse(input)
+    }
 
-        for line in input.lines() {
-            let (token, score_str) = match line.split_once('\t') {
-                Some(pair) => pair,
-                None => return Err(BytePairEncoderError::InvalidVocabularyInput),
-            };
-            let score = match score_str.parse::<isize>() {
-                Ok(score) => score,
-                Err(_) => return Err(BytePairEncoderError::InvalidVocabularyInput),
+    /// # Creates a new `BytePairEncoder` from a file, skipping malformed lines.
+    ///
+    /// This is [`BytePairEncoder::new_from_str_lenient`] for the contents of a file.
+    ///
+    /// ## Arguments
+    ///
+    /// * `file_path` - A string slice that holds the path to the vocabulary file.
+    ///
+    /// ## Returns
+    ///
+    /// The `BytePairEncoder` and a report of the skipped lines, or
+    /// `BytePairEncoderError::InvalidFile` if the file cannot be read.
+    pub fn new_from_file_lenient(
+        file_path: &str,
+    ) -> Result<(Self, ParseReport), BytePairEncoderError> {
+        let input =
+            fs::read_to_string(file_path).map_err(|e| BytePairEncoderError::InvalidFile {
+                path: file_path.to_string(),
+                source: e,
+            })?;
+        Ok(Self::new_from_str_lenient(&input))
+    }
+
+    /// # Creates a new `BytePairEncoder` from a string, skipping malformed lines.
+    ///
+    /// Community vocabulary files are often messy, and failing on the one bad line out of a
+    /// million is rarely helpful. This parses the same format as
+    /// [`BytePairEncoder::new_from_str`], but skips the lines that would make it fail, and
+    /// reports how many were skipped along with the first few of them.
+    ///
+    /// ## Arguments
+    ///
+    /// * `input` - A string slice containing the token-score pairs.
+    ///
+    /// ## Returns
+    ///
+    /// The `BytePairEncoder` built from the valid lines, and a [`ParseReport`] of the skipped
+    /// ones.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let (vocab, report) = BytePairEncoder::new_from_str_lenient("hello\t1\nbroken\nworld\tx");
+    /// assert_eq!(vocab.vocab_size(), 1);
+    /// assert_eq!(report.skipped, 2);
+    /// assert_eq!(report.samples[0].line, 2);
+    /// assert_eq!(report.samples[1].text, "world\tx");
+    /// ```
+    pub fn new_from_str_lenient(input: &str) -> (Self, ParseReport) {
+        let mut report = ParseReport::default();
+        let skip = |line: usize, text: &str, error: BytePairEncoderError| {
+            report.skip(line, text, error.to_string());
+            Ok::<(), Infallible>(())
+        };
+        let (mut tokens, mut metadata) = (Vocab::default(), Metadata::default());
+        if let Err(never) = Self::parse_onto(
+            &mut tokens,
+            &mut metadata,
+            input.lines().map(Ok),
+            None,
+            skip,
+        ) {
+            match never {}
+        }
+        (Self::from_vocab(tokens).with_metadata(metadata), report)
+    }
+
+    /// # Creates a new `BytePairEncoder` from a WordPiece vocabulary file.
+    ///
+    /// See [`BytePairEncoder::new_from_wordpiece_str`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `file_path` - A string slice that holds the path to the vocabulary file, such as BERT's
+    ///   `vocab.txt`.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the `BytePairEncoder`, or `BytePairEncoderError::InvalidFile` if the file
+    /// cannot be read.
+    pub fn new_from_wordpiece_file(file_path: &str) -> Result<Self, BytePairEncoderError> {
+        let input =
+            fs::read_to_string(file_path).map_err(|e| BytePairEncoderError::InvalidFile {
+                path: file_path.to_string(),
+                source: e,
+            })?;
+        Ok(Self::new_from_wordpiece_str(&input))
+    }
+
+    /// # Creates a new `BytePairEncoder` from a WordPiece vocabulary.
+    ///
+    /// WordPiece vocabularies, as used by BERT and its descendants, list one token per line, in
+    /// ID order and without scores. Tokens that continue a word have the `##` prefix, and tokens
+    /// that start one have no prefix at all. The encoder uses
+    /// `SegmentationStrategy::WordPiece` and keeps punctuation, so like BERT's tokenizer, it
+    /// splits text into words at whitespace and around every punctuation character (`don't`
+    /// becomes `don`, `'`, and `t`), and each word into pieces by greedy longest match. It detects
+    /// special tokens such as `[UNK]`, `[CLS]`, and `[SEP]` from the vocabulary. Empty lines are
+    /// skipped.
+    ///
+    /// ## Arguments
+    ///
+    /// * `input` - A string slice containing the tokens, one per line.
+    ///
+    /// ## Returns
+    ///
+    /// The `BytePairEncoder`, with IDs in the order of the lines.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab =
+    ///     BytePairEncoder::new_from_wordpiece_str("[UNK]\n[CLS]\n[SEP]\nun\n##aff\n##able\n!");
+    /// let tokens = vocab.tokenize("Unaffable!");
+    /// assert_eq!(tokens, vec!["[CLS]", "un", "##aff", "##able", "!", "[SEP]"]);
+    /// assert_eq!(vocab.detokenize(&tokens), "unaffable !");
+    /// ```
+    pub fn new_from_wordpiece_str(input: &str) -> Self {
+        let mut tokens = Vocab::default();
+        for token in input.lines().filter(|line| !line.is_empty()) {
+            tokens.insert(token, -(tokens.len() as isize));
+        }
+        Self::from_vocab(tokens).with_options(EncoderOptions {
+            segmentation: SegmentationStrategy::WordPiece,
+            punctuation: PunctuationPolicy::Keep,
+            ..Default::default()
+        })
+    }
+
+    /// # Creates a new `BytePairEncoder` from GPT-2 style `vocab.json` and `merges.txt` files.
+    ///
+    /// See [`BytePairEncoder::new_from_gpt2_str`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `vocab_path` - A string slice that holds the path to the `vocab.json` file.
+    /// * `merges_path` - A string slice that holds the path to the `merges.txt` file.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the `BytePairEncoder`, or `BytePairEncoderError::InvalidFile` if either
+    /// file cannot be read, or any error of [`BytePairEncoder::new_from_gpt2_str`].
+    pub fn new_from_gpt2_files(
+        vocab_path: &str,
+        merges_path: &str,
+    ) -> Result<Self, BytePairEncoderError> {
+        let read = |path: &str| {
+            fs::read_to_string(path).map_err(|e| BytePairEncoderError::InvalidFile {
+                path: path.to_string(),
+                source: e,
+            })
+        };
+        Self::new_from_gpt2_str(&read(vocab_path)?, &read(merges_path)?)
+    }
+
+    /// # Creates a new `BytePairEncoder` from a GPT-2 style vocabulary and merges.
+    ///
+    /// GPT-2, RoBERTa, and their descendants ship two files: `vocab.json`, an object mapping each
+    /// token to its ID, and `merges.txt`, the merges learned during training, one pair of symbols
+    /// per line, most frequent first. Their tokens are spelled in a byte-level alphabet, in which
+    /// every byte stands for a printable character (`Ġ` for a space), so any text can be
+    /// tokenized without an unknown token.
+    ///
+    /// The encoder uses `TextMode::Lossless` and `SegmentationStrategy::Merges`, with `Ġ` as the
+    /// word break character, and pre-tokenizes like the original tokenizer: the text is not split
+    /// into sentences, but into the pieces GPT-2's regular expression matches (contractions, runs
+    /// of letters, numbers, or other symbols with one leading space, and runs of whitespace).
+    /// Pieces keep their case, and are segmented by applying the merges in rank order. Sentence
+    /// markers are only added if both are in the vocabulary, such as RoBERTa's `<s>` and `</s>`,
+    /// which are then detected as usual. Tokens keep their IDs and are scored by negated ID.
+    /// Decoding maps the byte-level alphabet back to the original text.
+    ///
+    /// The merges are not part of the native vocabulary format or the configuration, so
+    /// vocabularies written with [`BytePairEncoder::write_vocab`] do not keep them, and loading
+    /// them with a configuration selecting `SegmentationStrategy::Merges` fails (see
+    /// [`BytePairEncoder::new_from_file_with_config`]).
+    ///
+    /// ## Arguments
+    ///
+    /// * `vocab_json` - A string slice containing the `vocab.json` object.
+    /// * `merges` - A string slice containing the merges, one per line, optionally after a
+    ///   `#version` header.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the `BytePairEncoder`, or an error if:
+    /// * `vocab_json` is not an object of non-negative integer IDs forming the contiguous range
+    ///   `0..n` (returns `BytePairEncoderError::ConversionError`)
+    /// * A line of `merges` is not two symbols separated by a space (returns
+    ///   `BytePairEncoderError::InvalidMerges`)
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab_json = r#"{"H": 0, "e": 1, "l": 2, "o": 3, "Ġ": 4, "w": 5, "r": 6, "d": 7,
+    ///     "!": 8, "ll": 9, "llo": 10, "Hello": 11, "Ġw": 12, "or": 13, "Ġworld": 14}"#;
+    /// let merges = "#version: 0.2\nl l\nll o\nH e\nHe llo\nĠ w\no r\nor l\nĠw orl\nĠworl d";
+    /// let vocab = BytePairEncoder::new_from_gpt2_str(vocab_json, merges).unwrap();
+    /// let tokens = vocab.tokenize("Hello world!");
+    /// assert_eq!(tokens, vec!["Hello", "Ġworld", "!"]);
+    /// assert_eq!(vocab.detokenize(&tokens), "Hello world!");
+    /// ```
+    pub fn new_from_gpt2_str(vocab_json: &str, merges: &str) -> Result<Self, BytePairEncoderError> {
+        let tokens = parse_vocab_json(vocab_json)?;
+        let mut encoder = Self::from_vocab(tokens).with_options(EncoderOptions {
+            mode: TextMode::Lossless,
+            segmentation: SegmentationStrategy::Merges,
+            special_tokens: SpecialTokenOptions {
+                word_break: Some(byte_char(b' ')),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        encoder.merges = Some(Arc::new(Merges::parse(merges)?));
+        Ok(encoder)
+    }
+
+    /// # Parses the native vocabulary format, returning the first malformed line as an error.
+    fn parse(input: &str) -> Result<Self, BytePairEncoderError> {
+        let (mut tokens, mut metadata) = (Vocab::default(), Metadata::default());
+        Self::parse_onto(
+            &mut tokens,
+            &mut metadata,
+            input.lines().map(Ok),
+            None,
+            |_, _, e| Err(e),
+        )?;
+        Ok(Self::from_vocab(tokens).with_metadata(metadata))
+    }
+
+    /// # Parses the native vocabulary format on top of existing tokens and metadata.
+    ///
+    /// Tokens already in `tokens` get the score from `input` and keep their ID, and new tokens
+    /// are added after them. Metadata keys in `lines` replace those in `metadata`.
+    ///
+    /// Each malformed line is passed to `on_error` with its number, its text, and the error. The
+    /// line is skipped if `on_error` returns `Ok`, and parsing stops with its error otherwise.
+    /// Errors reading `lines` stop parsing too.
+    ///
+    /// With a `limit`, parsing stops once `tokens` holds that many tokens, without reading the
+    /// rest of `lines`. This is the dry run behind [`BytePairEncoder::peek_vocab_file`].
+    pub(crate) fn parse_onto<L: AsRef<str>, E>(
+        tokens: &mut Vocab,
+        metadata: &mut Metadata,
+        lines: impl IntoIterator<Item = Result<L, E>>,
+        limit: Option<usize>,
+        mut on_error: impl FnMut(usize, &str, BytePairEncoderError) -> Result<(), E>,
+    ) -> Result<(), E> {
+        for (i, line) in lines.into_iter().enumerate() {
+            if limit.map_or(false, |limit| tokens.len() >= limit) {
+                break;
+            }
+            let line = line?;
+            let line = line.as_ref();
+            if let Some((key, value)) = Metadata::parse_line(line) {
+                metadata.set(&key, &value);
+                continue;
+            }
+            let parsed = match line.split_once('\t') {
+                Some((token, score_str)) => match score_str.parse::<isize>() {
+                    Ok(score) => Ok((token, score)),
+                    Err(e) => Err(BytePairEncoderError::InvalidScore {
+                        line: i + 1,
+                        source: e,
+                    }),
+                },
+                None => Err(BytePairEncoderError::InvalidVocabularyInput { line: i + 1 }),
             };
-            tokens.insert(token.to_string(), score);
+            match parsed {
+                Ok((token, score)) => {
+                    tokens.insert(token, score);
+                }
+                Err(e) => on_error(i + 1, line, e)?,
+            }
         }
 
-        Ok(BytePairEncoder { tokens })
+        Ok(())
+    }
+
+    /// # Creates a new `BytePairEncoder` from a base encoder and an overlay file.
+    ///
+    /// See [`BytePairEncoder::new_with_overlay_str`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `base` - The encoder whose vocabulary the overlay applies to.
+    /// * `overlay_path` - A string slice that holds the path to the overlay file.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the combined `BytePairEncoder`, or an error if the file cannot be read or
+    /// parsed.
+    pub fn new_with_overlay(
+        base: &BytePairEncoder,
+        overlay_path: &str,
+    ) -> Result<Self, BytePairEncoderError> {
+        Self::new_with_overlay_str(
+            base,
+            fs::read_to_string(overlay_path)
+                .map_err(|e| BytePairEncoderError::InvalidFile {
+                    path: overlay_path.to_string(),
+                    source: e,
+                })?
+                .as_ref(),
+        )
+    }
+
+    /// # Creates a new `BytePairEncoder` from a base encoder and an overlay of changes.
+    ///
+    /// Domain customizations can ship as a small overlay instead of a full copy of a large
+    /// vocabulary. The overlay uses the native vocabulary format: tokens already in `base` get the
+    /// overlay's score and keep their ID, and new tokens are added with IDs following the last ID
+    /// of `base`, in overlay order. Metadata lines in the overlay replace those of `base` with
+    /// the same key. The options of `base` are kept.
+    ///
+    /// ## Arguments
+    ///
+    /// * `base` - The encoder whose vocabulary the overlay applies to.
+    /// * `overlay` - A string slice containing the token-score pairs to add or override.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the combined `BytePairEncoder`, or an error if the overlay cannot be
+    /// parsed.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let base = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\n▁wor\t3\nld\t3").unwrap();
+    /// let overlay = "▁world\t9\n▁kubernetes\t5";
+    /// let vocab = BytePairEncoder::new_with_overlay_str(&base, overlay).unwrap();
+    /// assert_eq!(vocab.token_to_id("▁world"), Some(1));
+    /// assert_eq!(vocab.token_to_id("▁kubernetes"), Some(4));
+    /// assert_eq!(vocab.tokenize("world kubernetes"), vec!["<s>", "▁world", "▁kubernetes", "</s>"]);
+    /// ```
+    pub fn new_with_overlay_str(
+        base: &BytePairEncoder,
+        overlay: &str,
+    ) -> Result<Self, BytePairEncoderError> {
+        let (mut tokens, mut metadata) = (base.tokens.clone(), base.metadata.clone());
+        Self::parse_onto(
+            &mut tokens,
+            &mut metadata,
+            overlay.lines().map(Ok),
+            None,
+            |_, _, e| Err(e),
+        )?;
+        Ok(Self::from_vocab(tokens)
+            .with_options(base.options.clone())
+            .with_metadata(metadata))
+    }
+
+    /// # Creates a new `BytePairEncoder` from a token table, with default options.
+    ///
+    /// All constructors end up here, so this is where anything derived from the vocabulary (such
+    /// as the unknown token) is resolved.
+    pub(crate) fn from_vocab(tokens: Vocab) -> Self {
+        let options = EncoderOptions::default();
+        let special_tokens = SpecialTokens::resolve(&options, &tokens);
+        let fingerprint = tokens.fingerprint();
+        BytePairEncoder {
+            tokens,
+            options,
+            metrics: MetricsCounters::default(),
+            special_tokens,
+            cache: None,
+            metadata: Metadata::default(),
+            fingerprint,
+            merges: None,
+        }
     }
 
     /// # Creates a new `BytePairEncoder` with a default small vocabulary size (100,000 tokens).
@@ -192,6 +838,72 @@ impl BytePairEncoder {
         new_default(DefaultVocab::Small)
     }
 
+    /// # Checks that every compiled default vocabulary loads correctly.
+    ///
+    /// Each default vocabulary compiled in by the `default-small`, `default-medium`, and
+    /// `default-large` features is checked against the checksum recorded when it was built, then
+    /// fully decompressed and deserialized. Applications can call this at startup to fail fast,
+    /// with a diagnostic naming the vocabulary, instead of when a vocabulary is first needed. This
+    /// takes as long as loading each vocabulary once.
+    ///
+    /// ## Returns
+    ///
+    /// `Ok(())` if every compiled vocabulary is intact. Otherwise, fails with
+    /// [`BytePairEncoderError::DefaultVocabChecksumMismatch`] if the embedded data was altered,
+    /// with [`BytePairEncoderError::DecompressionError`] or
+    /// [`BytePairEncoderError::DeserializationError`] if it cannot be decoded, or with
+    /// [`BytePairEncoderError::NoDefaultVocabFeature`] if no default vocabulary is compiled in.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "default-small")] {
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// BytePairEncoder::verify_default_vocabs().expect("default vocabularies are corrupted");
+    /// # }
+    /// ```
+    pub fn verify_default_vocabs() -> Result<(), BytePairEncoderError> {
+        verify_default_vocabs()
+    }
+
+    /// # Creates a new `BytePairEncoder` with the largest default vocabulary that fits a budget.
+    ///
+    /// This picks among the default vocabularies compiled in by the `default-small`,
+    /// `default-medium`, and `default-large` features, so one codebase can deploy to devices with
+    /// very different memory (e.g. servers and phones) by enabling several of them and choosing at
+    /// runtime. Larger vocabularies split fewer words into pieces, which gives shorter token
+    /// sequences.
+    ///
+    /// ## Arguments
+    ///
+    /// * `bytes` - The heap memory the encoder may use, in bytes.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the encoder and a [`DefaultVocabInfo`] describing the chosen vocabulary,
+    /// including its measured coverage. Fails with
+    /// [`BytePairEncoderError::MemoryBudgetTooSmall`] if no compiled vocabulary fits, or with
+    /// [`BytePairEncoderError::NoDefaultVocabFeature`] if none is compiled in.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "default-small")] {
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let (encoder, info) = BytePairEncoder::best_default_for_budget(64 << 20).unwrap();
+    /// assert!(info.memory_bytes <= 64 << 20);
+    /// assert_eq!(encoder.vocab_size(), info.vocab_size);
+    /// println!("using {} ({:.0}% of words as one token)", info.feature, info.coverage * 100.0);
+    /// # }
+    /// ```
+    pub fn best_default_for_budget(
+        bytes: usize,
+    ) -> Result<(Self, DefaultVocabInfo), BytePairEncoderError> {
+        best_default_for_budget(bytes)
+    }
+
     /// # Creates a new `BytePairEncoder` with a default medium vocabulary size (320,000 tokens).
     ///
     /// This function constructs a `BytePairEncoder` using a pre-trained multilingual vocabulary
@@ -262,261 +974,3920 @@ impl BytePairEncoder {
         new_default(DefaultVocab::Large)
     }
 
-    /// # Tokenizes a text into sentences, then words, and finally into BPE tokens.
+    /// # Replaces the options used by this `BytePairEncoder`.
     ///
-    /// This function takes a string of text and returns an iterator that yields
-    /// vectors of tokens, where each vector represents a tokenized sentence.
+    /// Options control how text is prepared before it is matched against the vocabulary. The
+    /// vocabulary itself is unchanged. The options are applied as they are, without validation;
+    /// use [`BytePairEncoder::try_with_options`] to have invalid ones reported.
     ///
     /// ## Arguments
     ///
-    /// * `text` - A string slice containing the text to be tokenized.
+    /// * `options` - The `EncoderOptions` to use from now on.
     ///
     /// ## Returns
     ///
-    /// An iterator that yields `Vec<String>`, where each `Vec<String>` represents
-    /// a tokenized sentence.
+    /// The `BytePairEncoder` with the new options applied.
     ///
     /// ## Example
     ///
     /// ```
-    /// use bpe_tokenizer::BytePairEncoder;
+    /// use bpe_tokenizer::{BytePairEncoder, EncoderOptions, LineBreakPolicy};
     ///
-    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
-    /// let text = "Hello, world! How are you?";
-    /// let tokenized: Vec<Vec<String>> = vocab
-    ///     .tokenize_sentences_iter(text)
-    ///     .map(|sentence_iter| sentence_iter.collect())  // Collect each inner iterator into a Vec<String>
-    ///     .collect();  // Then collect everything into Vec<Vec<String>>
-    /// ```
-    ///
-    /// ## Notes
+    /// let mut options = EncoderOptions::default();
+    /// options.line_breaks = LineBreakPolicy::Normalize;
     ///
-    /// - This function uses Unicode-aware sentence and word segmentation.
-    /// - Each sentence is wrapped with sentence start (`<s>`) and end (`</s>`) tokens.
-    /// - Words are prefixed with the word break character (`▁`).
-    /// - Unknown tokens are replaced with the `<unk>` token.
-    pub fn tokenize_sentences_iter<'a>(
-        &'a self,
-        text: &'a str,
-    ) -> impl Iterator<Item = impl Iterator<Item = String> + 'a> + 'a {
-        UnicodeSegmentation::unicode_sentences(text)
-            .map(move |sentence| self.tokenize_with_sentence_markers_iter(sentence))
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2")
+    ///     .unwrap()
+    ///     .with_options(options);
+    /// assert_eq!(vocab.options().line_breaks, LineBreakPolicy::Normalize);
+    /// ```
+    pub fn with_options(mut self, options: EncoderOptions) -> Self {
+        self.options = options;
+        self.special_tokens = SpecialTokens::resolve(&self.options, &self.tokens);
+        self.cache = self
+            .options
+            .cache_capacity
+            .filter(|&capacity| capacity > 0)
+            .map(WordCache::new);
+        self
     }
 
-    /// # Tokenizes a text into a flat sequence of BPE tokens.
+    /// # Replaces the options used by this `BytePairEncoder`, checking that they can be honored.
     ///
-    /// This function takes a string of text and returns an iterator that yields
-    /// individual tokens. It first tokenizes the text into sentences, then words,
-    /// and finally into BPE tokens, flattening the result into a single sequence.
+    /// This is [`BytePairEncoder::with_options`] with validation, so invalid options are reported
+    /// when they are applied instead of showing up as surprising tokens later. The options are
+    /// checked on their own with [`EncoderOptions::validate`], and against this encoder.
     ///
     /// ## Arguments
     ///
-    /// * `text` - A string slice containing the text to be tokenized.
+    /// * `options` - The `EncoderOptions` to use from now on.
     ///
     /// ## Returns
     ///
-    /// An iterator that yields `String`, where each `String` represents a token.
+    /// The `BytePairEncoder` with the new options applied, or else:
+    ///
+    /// * The errors of [`EncoderOptions::validate`].
+    /// * `BytePairEncoderError::InvalidConfig` if `SegmentationStrategy::Merges` is selected for
+    ///   an encoder without merges, which only the GPT-2 constructors load.
+    /// * `BytePairEncoderError::MissingSpecialToken` if a special token is not in the vocabulary
+    ///   and the options use [`MissingSpecialTokenPolicy::Error`].
     ///
     /// ## Example
     ///
     /// ```
-    /// use bpe_tokenizer::BytePairEncoder;
+    /// use bpe_tokenizer::{BytePairEncoder, BytePairEncoderError, EncoderOptions};
+    /// use bpe_tokenizer::MissingSpecialTokenPolicy;
     ///
-    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
-    /// let text = "Hello, world! How are you?";
-    /// let tokenized: Vec<String> = vocab.tokenize_iter(text).collect();
+    /// let vocab = BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\nhello\t1").unwrap();
+    /// let mut options = EncoderOptions::default();
+    /// options.special_tokens.missing = MissingSpecialTokenPolicy::Error;
+    /// assert!(matches!(
+    ///     vocab.try_with_options(options),
+    ///     Err(BytePairEncoderError::MissingSpecialToken { token }) if token == "<unk>"
+    /// ));
     /// ```
+    pub fn try_with_options(self, options: EncoderOptions) -> Result<Self, BytePairEncoderError> {
+        options.validate()?;
+        if options.segmentation == SegmentationStrategy::Merges && self.merges.is_none() {
+            return Err(BytePairEncoderError::InvalidConfig {
+                reason: "`merges` segmentation requires an encoder with merges, such as one \
+                         loaded with `new_from_gpt2_files`"
+                    .to_string(),
+            });
+        }
+        let encoder = self.with_options(options);
+        if encoder.options.special_tokens.missing == MissingSpecialTokenPolicy::Error {
+            encoder.validate_special_tokens()?;
+        }
+        Ok(encoder)
+    }
+
+    /// # Configures this `BytePairEncoder` like another one.
     ///
-    /// ## Notes
+    /// This copies the options of `other` (normalization, matching, limits, caching, and so on),
+    /// and pins the special tokens to the ones `other` emits, whether they were overridden or
+    /// detected from its vocabulary. If `other` has no padding token, it is still detected from
+    /// this encoder's vocabulary. Rotating the vocabulary of a running service this way keeps
+    /// its configuration from drifting. Metadata is not copied, since it describes the vocabulary.
     ///
-    /// - This function uses Unicode-aware sentence and word segmentation.
-    /// - Each sentence is wrapped with sentence start (`<s>`) and end (`</s>`) tokens.
-    /// - Words are prefixed with the word break character (`▁`).
-    /// - Unknown tokens are replaced with the `<unk>` token.
-    pub fn tokenize_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = String> + 'a {
-        self.tokenize_sentences_iter(text).flatten()
+    /// ## Arguments
+    ///
+    /// * `other` - The encoder whose configuration is copied.
+    ///
+    /// ## Returns
+    ///
+    /// The `BytePairEncoder` configured like `other`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, EncoderOptions};
+    ///
+    /// let mut options = EncoderOptions::default();
+    /// options.max_word_length = Some(64);
+    /// let current = BytePairEncoder::new_from_str("[CLS]\t0\n[SEP]\t0\n▁hi\t1")
+    ///     .unwrap()
+    ///     .with_options(options);
+    ///
+    /// let next = BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\n▁hi\t1\n▁there\t1")
+    ///     .unwrap()
+    ///     .with_config_from(&current);
+    /// assert_eq!(next.options().max_word_length, Some(64));
+    /// assert_eq!(next.tokenize("Hi"), vec!["[CLS]", "▁hi", "[SEP]"]);
+    /// ```
+    pub fn with_config_from(self, other: &BytePairEncoder) -> Self {
+        let mut options = other.options.clone();
+        let special = &other.special_tokens;
+        options.special_tokens.sentence_start = Some(special.sentence_start().to_string());
+        options.special_tokens.sentence_end = Some(special.sentence_end().to_string());
+        options.special_tokens.unknown = Some(special.unknown().to_string());
+        if let Some(pad) = special.pad() {
+            options.special_tokens.pad = Some(pad.to_string());
+        }
+        self.with_options(options)
     }
 
-    /// # Tokenizes a text into sentences, then words, and finally into BPE tokens.
+    /// # Sets the sentence start and end markers emitted by this `BytePairEncoder`.
     ///
-    /// This function takes a string of text and returns a vector of tokenized sentences,
-    /// where each sentence is represented as a vector of tokens.
+    /// This is a shortcut for overriding [`SpecialTokenOptions::sentence_start`] and
+    /// [`SpecialTokenOptions::sentence_end`] through [`BytePairEncoder::with_options`], for models
+    /// that expect markers such as `<bos>` and `<eos>`. The markers get the IDs they have in the
+    /// vocabulary, or reserved IDs with [`MissingSpecialTokenPolicy::Register`].
+    ///
+    /// [`SpecialTokenOptions::sentence_start`]: crate::SpecialTokenOptions::sentence_start
+    /// [`SpecialTokenOptions::sentence_end`]: crate::SpecialTokenOptions::sentence_end
     ///
     /// ## Arguments
     ///
-    /// * `text` - A string slice containing the text to be tokenized.
+    /// * `start` - The token marking the start of a sentence.
+    /// * `end` - The token marking the end of a sentence.
     ///
     /// ## Returns
     ///
-    /// A `Vec<Vec<String>>`, where each inner `Vec<String>` represents a tokenized sentence.
+    /// The `BytePairEncoder` with the given sentence markers.
     ///
     /// ## Example
     ///
     /// ```
     /// use bpe_tokenizer::BytePairEncoder;
     ///
-    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
-    /// let text = "Hello, world! How are you?";
-    /// let tokenized = vocab.tokenize_sentences(text);
+    /// let vocab = BytePairEncoder::new_from_str("<bos>\t0\n<eos>\t0\n<s>\t0\n▁hi\t1")
+    ///     .unwrap()
+    ///     .with_sentence_markers("<bos>", "<eos>");
+    /// assert_eq!(vocab.tokenize("Hi"), vec!["<bos>", "▁hi", "<eos>"]);
+    /// assert_eq!(vocab.token_to_id("<eos>"), Some(1));
     /// ```
+    pub fn with_sentence_markers(self, start: &str, end: &str) -> Self {
+        let mut options = self.options.clone();
+        options.special_tokens.sentence_start = Some(start.to_string());
+        options.special_tokens.sentence_end = Some(end.to_string());
+        self.with_options(options)
+    }
+
+    /// # Returns a builder for a `BytePairEncoder` with custom special tokens.
     ///
-    /// ## Notes
+    /// See [`BytePairEncoderBuilder`].
+    pub fn builder() -> BytePairEncoderBuilder {
+        BytePairEncoderBuilder::new()
+    }
+
+    /// # Moves the vocabulary into another [`VocabStore`].
     ///
-    /// - This function uses Unicode-aware sentence and word segmentation.
-    /// - Each sentence is wrapped with sentence start (`<s>`) and end (`</s>`) tokens.
-    /// - Words are prefixed with the word break character (`▁`).
-    /// - Unknown tokens are replaced with the `<unk>` token.
-    pub fn tokenize_sentences(&self, text: &str) -> Vec<Vec<String>> {
-        self.tokenize_sentences_iter(text)
-            .map(|sentence_iter| sentence_iter.collect())
-            .collect()
+    /// The store maps tokens to IDs while tokenizing, and decides the speed and memory use of the
+    /// lookups. Tokens, IDs, and tokenization are unaffected, so stores can be swapped and
+    /// benchmarked against each other freely. Encoders derived from this one (e.g. with
+    /// [`BytePairEncoder::with_options`] or an overlay) keep the store, and newly loaded ones use
+    /// a [`HashMapStore`](crate::HashMapStore).
+    ///
+    /// ## Arguments
+    ///
+    /// * `store` - An empty store, which all tokens are added to.
+    ///
+    /// ## Returns
+    ///
+    /// The `BytePairEncoder` using `store`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, CompactStore};
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let compact = vocab.clone().with_vocab_store(CompactStore::new());
+    /// assert_eq!(compact.tokenize("Hello world"), vocab.tokenize("Hello world"));
+    /// assert!(compact.vocab_store().heap_bytes() < vocab.vocab_store().heap_bytes());
+    /// ```
+    pub fn with_vocab_store<S: VocabStore + 'static>(mut self, store: S) -> Self {
+        self.tokens.set_store(Box::new(store));
+        self
     }
 
-    /// # Tokenizes a text into a flat sequence of BPE tokens.
+    /// # Returns the [`VocabStore`] that maps tokens to IDs.
+    pub fn vocab_store(&self) -> &dyn VocabStore {
+        self.tokens.store()
+    }
+
+    /// # Attaches metadata to this `BytePairEncoder`, replacing any it had.
     ///
-    /// This function takes a string of text and returns a vector of tokens.
-    /// It first tokenizes the text into sentences, then words, and finally into BPE tokens,
-    /// flattening the result into a single sequence.
+    /// See [`Metadata`] for how it is persisted.
     ///
     /// ## Arguments
     ///
-    /// * `text` - A string slice containing the text to be tokenized.
+    /// * `metadata` - The metadata to attach.
     ///
     /// ## Returns
     ///
-    /// A `Vec<String>`, where each `String` represents a token.
+    /// The `BytePairEncoder` with the given metadata.
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// # Returns a fingerprint of the vocabulary.
+    ///
+    /// The fingerprint is a 64-bit FNV-1a hash over every token, its score, and its ID. It does
+    /// not depend on options or metadata, and it is stable across runs, platforms, and versions of
+    /// this crate, so it can identify a vocabulary in logs, caches, or persisted artifacts.
     ///
     /// ## Example
     ///
     /// ```
     /// use bpe_tokenizer::BytePairEncoder;
     ///
-    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
-    /// let text = "Hello, world! How are you?";
-    /// let tokenized = vocab.tokenize(text);
+    /// let a = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    /// let b = BytePairEncoder::new_from_str("world\t2\nhello\t1").unwrap();
+    /// assert_ne!(a.fingerprint(), b.fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /// # Returns the metadata attached to this `BytePairEncoder`.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// # Returns the options used by this `BytePairEncoder`.
+    pub fn options(&self) -> &EncoderOptions {
+        &self.options
+    }
+
+    /// # Returns a snapshot of the runtime counters of this `BytePairEncoder`.
+    ///
+    /// Counters are only updated while `EncoderOptions::collect_metrics` is enabled, so they stay
+    /// at zero otherwise. See [`Metrics`] for the available counters.
+    ///
+    /// ## Example
+    ///
     /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, EncoderOptions};
     ///
-    /// ## Notes
+    /// let mut options = EncoderOptions::default();
+    /// options.collect_metrics = true;
     ///
-    /// - This function uses Unicode-aware sentence and word segmentation.
-    /// - Each sentence is wrapped with sentence start (`<s>`) and end (`</s>`) tokens.
-    /// - Words are prefixed with the word break character (`▁`).
-    /// - Unknown tokens are replaced with the `<unk>` token.
-    pub fn tokenize(&self, text: &str) -> Vec<String> {
-        self.tokenize_iter(text).collect()
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2\n▁\t3")
+    ///     .unwrap()
+    ///     .with_options(options);
+    /// vocab.tokenize("Hello, world! How are you?");
+    ///
+    /// let metrics = vocab.metrics();
+    /// assert_eq!(metrics.words_tokenized, 5);
+    /// assert_eq!(metrics.unknown_tokens, 3);
+    /// ```
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.snapshot()
     }
 
-    /// # Tokenizes a single sentence, adding sentence start and end markers.
+    /// # Resets all runtime counters of this `BytePairEncoder` to zero.
+    pub fn reset_metrics(&self) {
+        self.metrics.reset();
+    }
+
+    /// # Removes all words from the word cache of this `BytePairEncoder`.
     ///
-    /// This function breaks down the tokenization process for a single sentence:
-    /// 1. Adds a sentence start token.
-    /// 2. Splits the sentence into words using Unicode-aware word segmentation.
-    /// 3. Prepends each word with the word break character.
-    /// 4. Tokenizes each word using the BPE vocabulary.
-    /// 5. Adds a sentence end token.
+    /// Does nothing unless `EncoderOptions::cache_capacity` is set.
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    /// # Writes the words in the word cache to a file, so a later run can start with them.
+    ///
+    /// Short-lived jobs over similar text spend much of their time tokenizing the same frequent
+    /// words; loading the cache of a previous run with [`BytePairEncoder::load_cache`] skips that.
+    /// See [`BytePairEncoder::write_cache`] for the format.
     ///
     /// ## Arguments
     ///
-    /// * `sentence` - A string slice containing a single sentence to be tokenized.
+    /// * `file_path` - The path of the file to write.
     ///
     /// ## Returns
     ///
-    /// An iterator that yields `String`s representing the tokenized sentence,
-    /// including start and end markers.
+    /// An `io::Result` indicating whether writing succeeded.
+    pub fn save_cache(&self, file_path: &str) -> io::Result<()> {
+        self.write_cache(io::BufWriter::new(fs::File::create(file_path)?))
+    }
+
+    /// # Writes the words in the word cache.
     ///
-    /// ## Implementation Notes
+    /// The output is a text file with a header line, a line identifying the vocabulary and the
+    /// options the words were tokenized with, and one `word<TAB>token<TAB>token...` line per
+    /// word, least recently used first. Nothing but the header is written unless
+    /// `EncoderOptions::cache_capacity` is set.
     ///
-    /// - Uses `unicode_words` for word segmentation to handle various Unicode scripts correctly.
-    /// - Converts words to lowercase before tokenization to match the vocabulary.
-    /// - Returns an iterator instead of a fully collected `Vec<String>` to allow for
-    ///   more efficient tokenization and processing.
-    pub(crate) fn tokenize_with_sentence_markers_iter<'a>(
-        &'a self,
-        sentence: &'a str,
-    ) -> impl Iterator<Item = String> + 'a {
-        iter::once(SENTENCE_START_TOKEN.to_string())
-            .chain(sentence.unicode_words().flat_map(move |word| {
-                self.tokenize_word(&format!("{}{}", WORD_BREAK_CHAR, word.to_lowercase()))
-            }))
-            .chain(iter::once(SENTENCE_END_TOKEN.to_string()))
+    /// ## Arguments
+    ///
+    /// * `writer` - Where to write the cache.
+    ///
+    /// ## Returns
+    ///
+    /// An `io::Result` indicating whether writing succeeded.
+    pub fn write_cache<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", CACHE_HEADER)?;
+        writeln!(
+            writer,
+            "{:016x}\t{:016x}",
+            self.fingerprint,
+            self.options_fingerprint()
+        )?;
+        for (word, tokens) in self.cache.iter().flat_map(WordCache::entries) {
+            writeln!(writer, "{}\t{}", word, tokens.join("\t"))?;
+        }
+        writer.flush()
     }
 
-    /// # Tokenizes a single word using the Byte Pair Encoding (BPE) algorithm.
+    /// # Loads words into the word cache from a file written by [`BytePairEncoder::save_cache`].
     ///
-    /// This function implements the core BPE tokenization logic:
-    /// 1. If the word is empty, return an empty vector.
-    /// 2. Convert the word to a vector of Unicode characters.
-    /// 3. Iterate through possible substrings of the word, from longest to shortest.
-    /// 4. For each substring length, find all matching tokens in the vocabulary.
-    /// 5. Choose the matching token with the highest score in the vocabulary.
-    /// 6. Split the word at the chosen token and recursively tokenize the parts before and after.
-    /// 7. If no match is found, return the unknown token.
+    /// The cached tokens of a word only hold for the vocabulary and options they were produced
+    /// with, so a file written by an encoder with another vocabulary or other options is ignored.
+    /// Options that do not affect tokenization, such as the cache capacity itself, may differ.
+    /// Words beyond the capacity of the cache evict the least recently used ones, as usual.
     ///
     /// ## Arguments
     ///
-    /// * `text` - A string slice containing a single word to be tokenized.
+    /// * `file_path` - The path of the file to load.
     ///
     /// ## Returns
     ///
-    /// A `Vec<String>` containing the BPE tokens for the input word.
+    /// A `Result` with the number of words loaded, which is `0` for an ignored file or unless
+    /// `EncoderOptions::cache_capacity` is set, or an error if the file cannot be read or is
+    /// malformed.
     ///
-    /// ## Implementation Notes
+    /// ## Example
     ///
-    /// - The algorithm prioritizes longer matches over shorter ones.
-    /// - In case of multiple matches of the same length, it chooses the one with the highest score.
-    /// - The function is recursive, handling subwords created by splitting at a matched token.
-    /// - If no match is found in the vocabulary, it returns the unknown token.
-    pub(crate) fn tokenize_word(&self, text: &str) -> Vec<String> {
-        // Base case: If the input is empty, return an empty vector
-        if text.is_empty() {
-            return vec![];
-        }
-
-        // Convert the `text` to a Vec of `char`s to index by character rather than byte
-        let word: Vec<char> = text.chars().collect();
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, EncoderOptions};
+    ///
+    /// let mut options = EncoderOptions::default();
+    /// options.cache_capacity = Some(1_000);
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2")
+    ///     .unwrap()
+    ///     .with_options(options);
+    /// vocab.tokenize("Hello world");
+    ///
+    /// let mut saved = vec![];
+    /// vocab.write_cache(&mut saved).unwrap();
+    ///
+    /// let next_run = vocab.clone();
+    /// let loaded = next_run.load_cache_from_str(std::str::from_utf8(&saved).unwrap());
+    /// assert_eq!(loaded.unwrap(), 2);
+    /// ```
+    pub fn load_cache(&self, file_path: &str) -> Result<usize, BytePairEncoderError> {
+        self.load_cache_from_str(&fs::read_to_string(file_path).map_err(|e| {
+            BytePairEncoderError::InvalidFile {
+                path: file_path.to_string(),
+                source: e,
+            }
+        })?)
+    }
 
-        // Look for the longest matching token in the vocabulary
-        for len in (1..=word.len()).rev() {
-            let mut matches = vec![];
-            // Iterate over each possible start position for substrings of length `len`
-            for start in 0..=(word.len() - len) {
-                let end = start + len;
+    /// # Loads words into the word cache from the output of [`BytePairEncoder::write_cache`].
+    ///
+    /// See [`BytePairEncoder::load_cache`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `input` - A word cache, as written by [`BytePairEncoder::write_cache`].
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the number of words loaded, or an error if `input` is malformed.
+    pub fn load_cache_from_str(&self, input: &str) -> Result<usize, BytePairEncoderError> {
+        let mut lines = input.lines();
+        if lines.next() != Some(CACHE_HEADER) {
+            return Err(BytePairEncoderError::InvalidCache { line: 1 });
+        }
+        let fingerprints = lines
+            .next()
+            .and_then(|line| line.split_once('\t'))
+            .and_then(|(vocab, options)| {
+                let vocab = u64::from_str_radix(vocab, 16).ok()?;
+                let options = u64::from_str_radix(options, 16).ok()?;
+                Some((vocab, options))
+            })
+            .ok_or(BytePairEncoderError::InvalidCache { line: 2 })?;
 
-                // Extract candidate substring (convert chars[start..end] back to a &str)
-                let candidate = &word[start..end].iter().collect::<String>();
+        let cache = match &self.cache {
+            Some(cache) if fingerprints == (self.fingerprint, self.options_fingerprint()) => cache,
+            _ => return Ok(0),
+        };
 
-                // If we have an exact match, just store it for now
-                if self.tokens.contains_key(candidate) {
-                    matches.push((candidate.to_string(), start, end));
+        // With `UnknownStrategy::Graphemes`, the graphemes of the word that are not covered are
+        // cached as tokens of their own, with the word break character in front of a word.
+        let graphemes = self.options.unknown == UnknownStrategy::Graphemes;
+        let is_grapheme_of = |word: &str, token: &str| {
+            let grapheme = token
+                .strip_prefix(self.special_tokens.word_break())
+                .unwrap_or(token);
+            word.contains(token) && grapheme.graphemes(true).count() == 1
+        };
+        let mut entries = vec![];
+        for (i, line) in lines.enumerate() {
+            let mut fields = line.split('\t');
+            let word = fields.next().filter(|word| !word.is_empty());
+            let tokens: Vec<&str> = fields.collect();
+            // Every cached token was emitted by an encoder with this vocabulary and options.
+            match word {
+                Some(word)
+                    if !tokens.is_empty()
+                        && tokens.iter().all(|token| {
+                            self.stored_token(token) == *token
+                                || (graphemes && is_grapheme_of(word, token))
+                        }) =>
+                {
+                    entries.push((word, tokens))
                 }
+                _ => return Err(BytePairEncoderError::InvalidCache { line: i + 3 }),
             }
+        }
+        for (word, tokens) in &entries {
+            cache.insert(word, tokens);
+        }
+        Ok(entries.len())
+    }
 
-            // If we got matches, choose the one with the highest score
-            if !matches.is_empty() {
-                let (candidate, start, end) = matches
-                    .into_iter()
-                    .max_by_key(|(candidate, _, _)| {
-                        self.tokens.get(candidate).copied().unwrap_or(isize::MIN)
-                    })
-                    .unwrap();
+    /// # Returns a fingerprint of the options that affect tokenization.
+    fn options_fingerprint(&self) -> u64 {
+        let mut options = self.options.clone();
+        options.collect_metrics = false;
+        options.cache_capacity = None;
+        let mut hash = Fnv1a::new();
+        hash.write_str(&options.to_json());
+        hash.finish()
+    }
 
-                // Recursively process the left part (before the match)
-                let left: String = word[..start].iter().collect();
-                let left_tokens = self.tokenize_word(&left);
+    /// # Returns an estimate of the heap memory used by this `BytePairEncoder`, in bytes.
+    ///
+    /// This covers the vocabulary: the token table, the token strings, and the
+    /// [`VocabStore`], as well as the merges of a GPT-2 style vocabulary. The word cache is not
+    /// included, since it grows with use; it takes roughly the length of a word plus its tokens
+    /// per cached word.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let small = BytePairEncoder::new_from_str("▁a\t1").unwrap();
+    /// let large = BytePairEncoder::new_from_str("▁a\t1\n▁b\t1\n▁c\t1").unwrap();
+    /// assert!(small.memory_bytes() < large.memory_bytes());
+    /// ```
+    pub fn memory_bytes(&self) -> usize {
+        self.tokens.heap_bytes() + self.merges.as_ref().map_or(0, |merges| merges.heap_bytes())
+    }
 
-                // The middle part is the matched token
-                let middle = vec![candidate];
+    /// # Makes this encoder use a word cache shared with other encoders.
+    ///
+    /// The namespace keeps the words cached by this encoder apart from those of the others, so it
+    /// must be unique among the encoders sharing `store`.
+    pub(crate) fn with_shared_cache(mut self, store: &Arc<CacheStore>, namespace: u64) -> Self {
+        self.cache = Some(WordCache::shared(store, namespace));
+        self
+    }
 
-                // Recursively process the right part (after the match)
-                let right: String = word[end..].iter().collect();
-                let right_tokens = self.tokenize_word(&right);
+    /// # Returns the number of tokens in the vocabulary.
+    ///
+    /// This includes special tokens registered with reserved IDs (see
+    /// [`MissingSpecialTokenPolicy::Register`]), so every ID is below this number.
+    pub fn vocab_size(&self) -> usize {
+        self.tokens.len() + self.special_tokens.registered().len()
+    }
 
-                // Concatenate the result of left, middle, and right
-                return [left_tokens, middle, right_tokens].concat();
-            }
-        }
+    /// # Returns the ID of a token.
+    ///
+    /// IDs are stable: a token's ID is its position in the vocabulary source, starting at zero
+    /// (i.e. the line number in a vocabulary file, or the order of the default vocabularies'
+    /// source files).
+    ///
+    /// ## Arguments
+    ///
+    /// * `token` - The token to look up.
+    ///
+    /// ## Returns
+    ///
+    /// The ID of `token`, or `None` if it is not in the vocabulary.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    /// assert_eq!(vocab.token_to_id("world"), Some(1));
+    /// assert_eq!(vocab.token_to_id("missing"), None);
+    /// ```
+    pub fn token_to_id(&self, token: &str) -> Option<u32> {
+        self.tokens.id(token).or_else(|| {
+            self.special_tokens
+                .registered()
+                .iter()
+                .position(|registered| registered == token)
+                .map(|index| (self.tokens.len() + index) as u32)
+        })
+    }
+
+    /// # Returns `true` if a token is in the vocabulary.
+    ///
+    /// This never allocates, so it is suitable for hot paths such as filters and embedding
+    /// lookups. Special tokens registered with reserved IDs count as present.
+    ///
+    /// ## Arguments
+    ///
+    /// * `token` - The token to look up.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if [`BytePairEncoder::token_to_id`] finds an ID for `token`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    /// assert!(vocab.contains_token("world"));
+    /// assert!(!vocab.contains_token("missing"));
+    /// ```
+    pub fn contains_token(&self, token: &str) -> bool {
+        self.tokens.contains_key(token)
+            || self
+                .special_tokens
+                .registered()
+                .iter()
+                .any(|registered| registered == token)
+    }
+
+    /// # Returns the score of a token.
+    ///
+    /// Like [`BytePairEncoder::contains_token`], this never allocates.
+    ///
+    /// ## Arguments
+    ///
+    /// * `token` - The token to look up.
+    ///
+    /// ## Returns
+    ///
+    /// The score of `token`, or `None` if it is not in the vocabulary. Special tokens registered
+    /// with reserved IDs have no score.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    /// assert_eq!(vocab.score_of("world"), Some(2));
+    /// assert_eq!(vocab.score_of("missing"), None);
+    /// ```
+    pub fn score_of(&self, token: &str) -> Option<isize> {
+        self.tokens.get(token).copied()
+    }
+
+    /// # Returns the token with a given ID.
+    ///
+    /// This is the inverse of [`BytePairEncoder::token_to_id`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID to look up.
+    ///
+    /// ## Returns
+    ///
+    /// The token with ID `id`, or `None` if there is no such token.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    /// assert_eq!(vocab.id_to_token(0), Some("hello"));
+    /// assert_eq!(vocab.id_to_token(2), None);
+    /// ```
+    pub fn id_to_token(&self, id: u32) -> Option<&str> {
+        self.tokens.token(id).or_else(|| {
+            let index = (id as usize).checked_sub(self.tokens.len())?;
+            self.special_tokens
+                .registered()
+                .get(index)
+                .map(String::as_str)
+        })
+    }
 
-        // If no match is found, return <unk> for the whole text
-        vec![UNKNOWN_TOKEN.to_string()]
+    /// # Returns the full ID to token table.
+    ///
+    /// The table is indexed by ID, so `table[id]` is the token [`BytePairEncoder::id_to_token`]
+    /// returns for `id`, and its length is [`BytePairEncoder::vocab_size`]. Building an embedding
+    /// matrix with one row per entry, in order, keeps it aligned with the IDs produced here.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec` with every token, in ID order, including registered special tokens.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    /// assert_eq!(vocab.export_id_table(), vec!["hello", "world"]);
+    /// ```
+    pub fn export_id_table(&self) -> Vec<&str> {
+        self.tokens
+            .iter()
+            .map(|(token, _)| token)
+            .chain(self.special_tokens.registered().iter().map(String::as_str))
+            .collect()
+    }
+
+    /// # Checks that every special token emitted by this `BytePairEncoder` has an ID.
+    ///
+    /// Special tokens that fall back to their defaults, or are overridden, may name tokens that
+    /// are not in the vocabulary. Tokens registered with [`MissingSpecialTokenPolicy::Register`]
+    /// count as present.
+    ///
+    /// ## Returns
+    ///
+    /// `Ok(())` if every special token has an ID, or
+    /// [`BytePairEncoderError::MissingSpecialToken`] naming the first one that does not.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, BytePairEncoderError};
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\nhello\t1").unwrap();
+    /// assert!(matches!(
+    ///     vocab.validate_special_tokens(),
+    ///     Err(BytePairEncoderError::MissingSpecialToken { token }) if token == "<unk>"
+    /// ));
+    /// ```
+    pub fn validate_special_tokens(&self) -> Result<(), BytePairEncoderError> {
+        match self
+            .special_tokens
+            .iter()
+            .find(|token| self.token_to_id(token).is_none())
+        {
+            Some(token) => Err(BytePairEncoderError::MissingSpecialToken {
+                token: token.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// # Writes the vocabulary in the native token-score format, in a deterministic order.
+    ///
+    /// Each token is written as a `<token>\t<score>` line, so the output can be parsed back with
+    /// [`BytePairEncoder::new_from_str`]. Exporting the same vocabulary always produces identical
+    /// output, which keeps exported files diffable and reproducible.
+    ///
+    /// ## Arguments
+    ///
+    /// * `writer` - Where to write the vocabulary.
+    /// * `order` - The order in which to write the tokens. See [`VocabOrder`].
+    ///
+    /// ## Returns
+    ///
+    /// An `io::Result` indicating whether writing succeeded.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, VocabOrder};
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("b\t1\nc\t2\na\t1").unwrap();
+    /// let mut exported = Vec::new();
+    /// vocab.write_vocab(&mut exported, VocabOrder::Score).unwrap();
+    /// assert_eq!(exported, b"c\t2\na\t1\nb\t1\n");
+    /// ```
+    pub fn write_vocab<W: io::Write>(&self, mut writer: W, order: VocabOrder) -> io::Result<()> {
+        for (key, value) in self.metadata.entries() {
+            writeln!(writer, "{}", Metadata::format_line(key, value))?;
+        }
+        for (token, score) in self.tokens.sorted(order) {
+            writeln!(writer, "{}\t{}", token, score)?;
+        }
+        Ok(())
+    }
+
+    /// # Writes the vocabulary encrypted, for loading with the encrypted constructors.
+    ///
+    /// The output of [`BytePairEncoder::write_vocab`] is encrypted with AES-256-GCM, which also
+    /// detects any modification of the file. A random nonce is generated for each write and
+    /// stored in the output, so the same key can safely encrypt any number of files. See
+    /// [`BytePairEncoder::new_from_encrypted_bytes`] for an example.
+    ///
+    /// ## Arguments
+    ///
+    /// * `writer` - Where to write the encrypted vocabulary.
+    /// * `order` - The order in which to write the tokens. See [`VocabOrder`].
+    /// * `key` - The 256-bit key to encrypt with.
+    ///
+    /// ## Returns
+    ///
+    /// An `io::Result` indicating whether writing succeeded.
+    ///
+    /// ## Note
+    ///
+    /// This is only enabled when the `encryption` feature is enabled in Cargo.toml.
+    #[cfg(feature = "encryption")]
+    pub fn write_encrypted_vocab<W: io::Write>(
+        &self,
+        mut writer: W,
+        order: VocabOrder,
+        key: &[u8; 32],
+    ) -> io::Result<()> {
+        let mut plaintext = Vec::new();
+        self.write_vocab(&mut plaintext, order)?;
+        writer.write_all(&encryption::seal(&plaintext, key))
+    }
+
+    /// # Exports the vocabulary and merges for the onnxruntime-extensions BPE tokenizer op.
+    ///
+    /// The `vocab` and `merges` attributes of the onnxruntime-extensions BPE tokenizer ops (e.g.
+    /// `GPT2Tokenizer`) take a JSON object mapping tokens to IDs and a GPT-2 style `merges.txt`.
+    /// This writes both, so a model exported to ONNX maps tokens to the same IDs as this crate:
+    ///
+    /// * The vocabulary is the ID table (see [`BytePairEncoder::export_id_table`]), including
+    ///   registered special tokens, as a single-line JSON object in ID order.
+    /// * The merges are reconstructed from the vocabulary: every way of splitting a token into two
+    ///   tokens of the vocabulary is a merge, ranked by the score of the merged token, after a
+    ///   `#version: 0.2` header.
+    ///
+    /// The op only replaces the matching of words against the vocabulary. Lowercasing, the `▁`
+    /// word prefix, and sentence markers still have to be applied around it, and merge-based BPE
+    /// does not always split a word the same way as this crate's longest-match search, so check
+    /// the exported model against [`BytePairEncoder::generate_test_vectors`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `vocab` - Where to write the vocabulary JSON.
+    /// * `merges` - Where to write the merges.
+    ///
+    /// ## Returns
+    ///
+    /// An `io::Result` indicating whether writing succeeded. Fails with
+    /// `io::ErrorKind::InvalidData` if a merge has a token containing whitespace, which the
+    /// merges format cannot represent.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁\t0\nh\t-1\ni\t-2\n▁h\t-3\n▁hi\t-4").unwrap();
+    /// let (mut ids, mut merges) = (Vec::new(), Vec::new());
+    /// vocab.export_onnx_bpe(&mut ids, &mut merges).unwrap();
+    ///
+    /// let (ids, merges) = (String::from_utf8(ids).unwrap(), String::from_utf8(merges).unwrap());
+    /// assert_eq!(ids.trim_end(), r#"{"▁":0,"h":1,"i":2,"▁h":3,"▁hi":4}"#);
+    /// assert_eq!(merges, "#version: 0.2\n▁ h\n▁h i\n");
+    /// ```
+    pub fn export_onnx_bpe<V, M>(&self, mut vocab: V, mut merges: M) -> io::Result<()>
+    where
+        V: io::Write,
+        M: io::Write,
+    {
+        let entries: Vec<String> = self
+            .export_id_table()
+            .into_iter()
+            .enumerate()
+            .map(|(id, token)| format!("{}:{}", json_string(token), id))
+            .collect();
+        writeln!(vocab, "{{{}}}", entries.join(","))?;
+
+        writeln!(merges, "#version: 0.2")?;
+        for (left, right) in self.tokens.merges() {
+            if left.contains(char::is_whitespace) || right.contains(char::is_whitespace) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "cannot write merge `{}` + `{}` with whitespace",
+                        left, right
+                    ),
+                ));
+            }
+            writeln!(merges, "{} {}", left, right)?;
+        }
+        Ok(())
+    }
+
+    /// # Writes the vocabulary as a SentencePiece BPE model file.
+    ///
+    /// The output is a serialized `ModelProto`, loadable by SentencePiece (and converters built on
+    /// it) as a `.model` file. The pieces are the ID table (see
+    /// [`BytePairEncoder::export_id_table`]), so IDs agree, with scores from the vocabulary, and
+    /// the encoder's options are translated into the model's specs:
+    ///
+    /// * Lowercasing, for `TextMode::Natural`, becomes a normalizer lowercasing each character.
+    /// * The `▁` word prefix becomes `add_dummy_prefix`, which `TextMode::Lossless` leaves off.
+    /// * The sentence markers become the control pieces `bos_id` and `eos_id`, along with the
+    ///   other special tokens, and the unknown token becomes `unk_id`.
+    /// * Byte fallback is enabled for `TextMode::Lossless` if the vocabulary has all 256 byte
+    ///   tokens, which become byte pieces.
+    ///
+    /// SentencePiece only splits input on whitespace and merges pieces by score, so sentence
+    /// splitting, dropping punctuation, the case markers of `TextMode::Lossless`, and the text
+    /// normalization options are not exported, and the markers are only added when requested at
+    /// encoding time (`add_bos`, `add_eos`). Check the exported model against
+    /// [`BytePairEncoder::generate_test_vectors`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `writer` - Where to write the model.
+    ///
+    /// ## Returns
+    ///
+    /// An `io::Result` indicating whether writing succeeded. Fails with
+    /// `io::ErrorKind::InvalidData` if the unknown token has no ID, or the word break is not `▁`,
+    /// since SentencePiece requires both.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("<unk>\t0\n<s>\t0\n</s>\t0\n▁hi\t-1").unwrap();
+    /// let mut model = Vec::new();
+    /// vocab.write_sentencepiece_model(&mut model).unwrap();
+    /// assert!(model.starts_with(b"\n\x0e\n\x05<unk>"));
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hi\t-1").unwrap();
+    /// assert!(vocab.write_sentencepiece_model(&mut Vec::new()).is_err());
+    /// ```
+    pub fn write_sentencepiece_model<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&sentencepiece::model_proto(self)?)
+    }
+
+    /// # Generates test vectors pinning the current tokenization of some texts.
+    ///
+    /// ## Arguments
+    ///
+    /// * `texts` - The texts to pin, e.g. representative samples of production input.
+    ///
+    /// ## Returns
+    ///
+    /// One [`TestVector`] per text, with its tokens and their IDs.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let vectors = vocab.generate_test_vectors(["Hello, world!"]);
+    /// assert_eq!(vectors[0].tokens, vec!["<s>", "▁hello", "▁world", "</s>"]);
+    /// assert_eq!(vectors[0].ids, vec![None, Some(0), Some(1), None]);
+    ///
+    /// assert!(vocab.verify_test_vectors(&vectors).is_ok());
+    /// ```
+    pub fn generate_test_vectors<I, S>(&self, texts: I) -> Vec<TestVector>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        texts
+            .into_iter()
+            .map(|text| {
+                let tokens = self.tokenize(text.as_ref());
+                let ids = tokens.iter().map(|token| self.token_to_id(token)).collect();
+                TestVector {
+                    text: text.as_ref().to_string(),
+                    tokens,
+                    ids,
+                }
+            })
+            .collect()
+    }
+
+    /// # Checks that test vectors still match the tokenization of their texts.
+    ///
+    /// Both the tokens and their IDs must match, so a vocabulary whose IDs shifted fails even if
+    /// it produces the same tokens.
+    ///
+    /// ## Arguments
+    ///
+    /// * `vectors` - The test vectors to check, as produced by
+    ///   [`BytePairEncoder::generate_test_vectors`].
+    ///
+    /// ## Returns
+    ///
+    /// `Ok(())` if every test vector matches, or [`BytePairEncoderError::TestVectorMismatch`] for
+    /// the first one that does not.
+    pub fn verify_test_vectors(&self, vectors: &[TestVector]) -> Result<(), BytePairEncoderError> {
+        for (index, vector) in vectors.iter().enumerate() {
+            let actual = self.generate_test_vectors([&vector.text]).remove(0);
+            if actual != *vector {
+                return Err(BytePairEncoderError::TestVectorMismatch {
+                    index,
+                    expected: Box::new(vector.clone()),
+                    actual: Box::new(actual),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// # Derives a reduced `BytePairEncoder` containing only the tokens matching a predicate.
+    ///
+    /// The kept tokens keep their scores and relative order, but are renumbered from zero, so the
+    /// new encoder's token IDs generally differ from this one's. Options and metadata are carried
+    /// over, and special tokens are detected again from the reduced vocabulary. The merges of a
+    /// GPT-2 style vocabulary are carried over too, except those whose merged token is dropped.
+    /// Note that the predicate also sees special tokens such as `<unk>`, and tokens starting with
+    /// the word break character `▁`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `predicate` - Called with each token and its score; returns `true` to keep the token.
+    ///
+    /// ## Returns
+    ///
+    /// A new `BytePairEncoder` with the kept tokens.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁a\t0\n▁1\t-1\n▁b\t-2\n2\t-3").unwrap();
+    /// let no_numerals = vocab.filter_tokens(|token, _| !token.chars().any(char::is_numeric));
+    /// assert_eq!(no_numerals.to_string(), "▁a\t0\n▁b\t-2\n");
+    /// ```
+    pub fn filter_tokens<F>(&self, mut predicate: F) -> BytePairEncoder
+    where
+        F: FnMut(&str, isize) -> bool,
+    {
+        let tokens: Vocab = self
+            .tokens
+            .iter()
+            .filter(|&(token, score)| predicate(token, score))
+            .collect();
+        let merges = self
+            .merges
+            .as_ref()
+            .map(|merges| Arc::new(merges.filter(|merged| tokens.contains_key(merged))));
+        let mut encoder = BytePairEncoder::from_vocab(tokens)
+            .with_options(self.options.clone())
+            .with_metadata(self.metadata.clone());
+        encoder.merges = merges;
+        encoder
+    }
+
+    /// # Recomputes token scores from the token frequencies observed in a corpus.
+    ///
+    /// The corpus is tokenized with the current scores, and every vocabulary token is then
+    /// re-scored by rank: the most frequent token scores `0`, the next `-1`, and so on, following
+    /// the convention of the BPEmb vocabularies. Ties (including tokens never seen in the corpus)
+    /// keep their previous relative order. The token set and token IDs are unchanged, but the
+    /// segmentation of ambiguous words adapts to the domain of the corpus.
+    ///
+    /// The corpus is tokenized with metrics and the word cache disabled, so rescoring does not
+    /// show up in [`BytePairEncoder::metrics`], and the cache is cleared afterwards since the
+    /// segmentations it holds may have changed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `corpus` - The documents of the corpus.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let mut vocab =
+    ///     BytePairEncoder::new_from_str("▁\t0\n▁a\t-1\nb\t-2\n▁ab\t-3\nab\t-4").unwrap();
+    /// vocab.rescore_from_corpus(["ab ab ab", "a"]);
+    /// assert_eq!(vocab.to_string(), "▁\t-2\n▁a\t-1\nb\t-3\n▁ab\t0\nab\t-4\n");
+    /// ```
+    pub fn rescore_from_corpus<I, S>(&mut self, corpus: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let cache = self.cache.take();
+        let collect_metrics = mem::replace(&mut self.options.collect_metrics, false);
+        let mut counts = vec![0u64; self.tokens.len()];
+        for document in corpus {
+            for sentence in self.split_sentences(document.as_ref()) {
+                for word in self.split_words(sentence) {
+                    for token in self.tokenize_segment(word) {
+                        if let Some(id) = self.tokens.id(&token) {
+                            counts[id as usize] += 1;
+                        }
+                    }
+                }
+            }
+        }
+        self.options.collect_metrics = collect_metrics;
+        self.cache = cache;
+
+        let mut ranked: Vec<(u32, isize)> = self
+            .tokens
+            .iter()
+            .enumerate()
+            .map(|(id, (_, score))| (id as u32, score))
+            .collect();
+        ranked.sort_by(|(a, a_score), (b, b_score)| {
+            counts[*b as usize]
+                .cmp(&counts[*a as usize])
+                .then(b_score.cmp(a_score))
+                .then(a.cmp(b))
+        });
+        for (rank, (id, _)) in ranked.into_iter().enumerate() {
+            self.tokens.set_score(id, -(rank as isize));
+        }
+        self.fingerprint = self.tokens.fingerprint();
+
+        self.clear_cache();
+    }
+
+    /// # Returns the special tokens emitted by this `BytePairEncoder`.
+    ///
+    /// See [`SpecialTokens`] for how they are detected from the vocabulary.
+    pub fn special_tokens(&self) -> &SpecialTokens {
+        &self.special_tokens
+    }
+
+    /// # Returns the token emitted for input that the vocabulary cannot cover.
+    ///
+    /// This is `SpecialTokenOptions::unknown` when set. Otherwise it is detected from the
+    /// vocabulary: the first of `<unk>`, `[UNK]`, `<UNK>`, and `⁇` that the vocabulary contains,
+    /// or `<unk>` if it contains none of them.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("[UNK]\t0\nhello\t-1").unwrap();
+    /// assert_eq!(vocab.unknown_token(), "[UNK]");
+    /// assert_eq!(vocab.unknown_token_id(), Some(0));
+    /// ```
+    pub fn unknown_token(&self) -> &str {
+        self.special_tokens.unknown()
+    }
+
+    /// # Returns the ID of the unknown token.
+    ///
+    /// ## Returns
+    ///
+    /// The ID of [`BytePairEncoder::unknown_token`], as [`BytePairEncoder::token_to_id`] gives
+    /// it, or `None` if the vocabulary does not contain it and it was not given a reserved ID.
+    pub fn unknown_token_id(&self) -> Option<u32> {
+        self.token_to_id(self.special_tokens.unknown())
+    }
+
+    /// # Returns the parts of a text that the vocabulary cannot cover.
+    ///
+    /// These are the parts that no vocabulary token covers, as byte ranges of the original text,
+    /// whatever the configured `UnknownStrategy` makes of them in [`BytePairEncoder::tokenize`]:
+    /// the unknown token by default, byte tokens, graphemes, or nothing (for emoji, the
+    /// replacement of `EmojiPolicy::Replace`). They point at exactly what needs a cleanup rule or
+    /// new vocabulary tokens, and can be passed to [`BytePairEncoder::suggest_alternatives`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to check.
+    ///
+    /// ## Returns
+    ///
+    /// The byte ranges of `text` not covered by the vocabulary, in order. Adjacent uncovered parts
+    /// are merged into a single range. Each range covers whole characters of `text`, including any
+    /// that normalization removed or expanded.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁wor\t1\nd\t1").unwrap();
+    /// let text = "Hello, world!";
+    /// assert_eq!(vocab.tokenize(text), vec!["<s>", "▁hello", "▁wor", "<unk>", "d", "</s>"]);
+    /// assert_eq!(vocab.uncovered_spans(text), vec![10..11]);
+    /// assert_eq!(&text[10..11], "l");
+    /// ```
+    pub fn uncovered_spans(&self, text: &str) -> Vec<Range<usize>> {
+        let offset = |slice: &str| slice.as_ptr() as usize - text.as_ptr() as usize;
+        let lowercase = self.options.mode == TextMode::Natural;
+        let mut spans: Vec<Range<usize>> = vec![];
+        let mut push = |span: Range<usize>| match spans.last_mut() {
+            Some(last) if last.end >= span.start => last.end = last.end.max(span.end),
+            _ => spans.push(span),
+        };
+
+        for segment in self
+            .split_sentences(text)
+            .flat_map(|sentence| self.split_words(sentence))
+        {
+            let start = offset(segment);
+            if self.options.emoji != EmojiPolicy::Drop && is_emoji(segment) {
+                let prefixed = format!("{}{}", self.special_tokens.word_break(), segment);
+                if !self.tokens.contains_key(&prefixed) && !self.tokens.contains_key(segment) {
+                    push(start..start + segment.len());
+                }
+                continue;
+            }
+
+            let normalized =
+                NormalizedText::new(segment, self.normalized_chars(segment), lowercase);
+            if normalized.as_str().is_empty() {
+                continue;
+            }
+            let word = format!(
+                "{}{}",
+                self.special_tokens.word_break(),
+                normalized.as_str()
+            );
+            for piece in self.word_pieces(&word) {
+                let mut tokens = WordTokens::new(self, word[piece.clone()].to_string());
+                while let Some((range, token)) = tokens.next_match() {
+                    if token.is_some() {
+                        continue;
+                    }
+                    // Map the range back to the normalized text, leaving out the word break
+                    // character, which is not part of the input.
+                    let range = piece.start + range.start..piece.start + range.end;
+                    let prefix = self.special_tokens.word_break().len();
+                    let range = range.start.max(prefix) - prefix..range.end.max(prefix) - prefix;
+                    if !range.is_empty() {
+                        let source = normalized.source_range(range);
+                        push(start + source.start..start + source.end);
+                    }
+                }
+            }
+        }
+        spans
+    }
+
+    /// # Suggests the vocabulary tokens closest to a span of text.
+    ///
+    /// This is meant for spell-correction style applications: given a span that tokenized to the
+    /// unknown token, it finds the tokens it was most likely meant to be. The span is normalized
+    /// like a word (see [`BytePairEncoder::normalize`]) and compared to every token, ignoring the
+    /// word break character.
+    ///
+    /// Suggestions are ranked by edit distance, then by the length of the prefix shared with the
+    /// span, then by score, and finally by token ID. Special tokens are never suggested.
+    ///
+    /// ## Performance
+    ///
+    /// There is no index: every call scans the whole vocabulary, so it takes time proportional to
+    /// the number of tokens times the length of the span. The edit distance computation stops as
+    /// soon as a token is known to be farther away than the `limit` best found so far, which
+    /// keeps small limits cheap, but a call still takes tens of milliseconds on the million
+    /// tokens of the large default vocabulary. This suits suggesting corrections for the unknown
+    /// spans of a text, not running once per token of a large corpus.
+    ///
+    /// ## Arguments
+    ///
+    /// * `span` - The text to find alternatives for.
+    /// * `limit` - The maximum number of suggestions to return.
+    ///
+    /// ## Returns
+    ///
+    /// Up to `limit` `(token, distance)` pairs, best first, where `distance` is the edit distance
+    /// between the normalized span and the token.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁world\t1\n▁word\t2\n▁hello\t3").unwrap();
+    /// assert_eq!(
+    ///     vocab.suggest_alternatives("Wrld", 2),
+    ///     vec![("▁world", 1), ("▁word", 2)]
+    /// );
+    /// ```
+    pub fn suggest_alternatives(&self, span: &str, limit: usize) -> Vec<(&str, usize)> {
+        let target: Vec<char> = self.normalize(span).as_str().chars().collect();
+        let candidates = self
+            .tokens
+            .iter()
+            .filter(|(token, _)| !self.special_tokens.iter().any(|s| s == *token))
+            .map(|(token, score)| {
+                let compared = token
+                    .strip_prefix(self.special_tokens.word_break())
+                    .unwrap_or(token);
+                (compared, token, score)
+            });
+        closest_tokens(&target, candidates, limit)
+    }
+
+    /// # Finds the tokens closest to a query string.
+    ///
+    /// Unlike [`BytePairEncoder::suggest_alternatives`], the query is compared to tokens exactly
+    /// as they are stored, including the word break character, and special tokens (including
+    /// registered ones) are candidates too. This suits vocabulary exploration tools, and
+    /// resolving user-specified special tokens that contain typos (`<unk` finds `<unk>`).
+    ///
+    /// With a [`TrieStore`](crate::TrieStore), the trie is searched directly, skipping every
+    /// subtree whose prefix is already farther from the query than the `k` best tokens found so
+    /// far. Other stores compare every token, stopping each edit distance computation as soon as
+    /// the token is known to be farther away. Either way, results are ranked by edit distance,
+    /// then by the length of the prefix shared with the query, then by score, and finally by
+    /// token ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `query` - The string to search for.
+    /// * `k` - The maximum number of tokens to return.
+    ///
+    /// ## Returns
+    ///
+    /// Up to `k` `(token, distance)` pairs, best first, where `distance` is the edit distance
+    /// between the query and the token.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("<unk>\t0\n▁hello\t1\n▁help\t2").unwrap();
+    /// assert_eq!(vocab.find_similar_tokens("<unk", 1), vec![("<unk>", 1)]);
+    /// assert_eq!(
+    ///     vocab.find_similar_tokens("▁hel", 2),
+    ///     vec![("▁help", 1), ("▁hello", 2)]
+    /// );
+    /// ```
+    pub fn find_similar_tokens(&self, query: &str, k: usize) -> Vec<(&str, usize)> {
+        let query: Vec<char> = query.chars().collect();
+        let mut closest = ClosestTokens::new(&query, k);
+        let searched = self.tokens.similar(&query, |id, token, score, distance| {
+            closest.offer(distance, token, score, id as usize);
+            closest.bound()
+        });
+        if !searched {
+            for (position, (token, score)) in self.tokens.iter().enumerate() {
+                closest.compare(token, token, score, position);
+            }
+        }
+        for (i, token) in self.special_tokens.registered().iter().enumerate() {
+            closest.compare(token, token, isize::MIN, self.tokens.len() + i);
+        }
+        closest.into_tokens()
+    }
+
+    /// # Tokenizes a text into sentences, then words, and finally into BPE tokens.
+    ///
+    /// This function takes a string of text and returns an iterator that yields
+    /// vectors of tokens, where each vector represents a tokenized sentence.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator that yields `Vec<String>`, where each `Vec<String>` represents
+    /// a tokenized sentence.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    /// let text = "Hello, world! How are you?";
+    /// let tokenized: Vec<Vec<String>> = vocab
+    ///     .tokenize_sentences_iter(text)
+    ///     .map(|sentence_iter| sentence_iter.collect())  // Collect each inner iterator into a Vec<String>
+    ///     .collect();  // Then collect everything into Vec<Vec<String>>
+    /// ```
+    ///
+    /// ## Notes
+    ///
+    /// - This function uses Unicode-aware sentence and word segmentation.
+    /// - Each sentence is wrapped with sentence start (`<s>`) and end (`</s>`) tokens, or the
+    ///   markers detected from the vocabulary (see [`SpecialTokens`]).
+    /// - Words are prefixed with the word break character (`▁`).
+    /// - Unknown tokens are replaced with the `<unk>` token.
+    /// - Sentences longer than `EncoderOptions::max_sentence_tokens` are yielded as several
+    ///   sentences.
+    pub fn tokenize_sentences_iter<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> impl Iterator<Item = impl Iterator<Item = String> + 'a> + 'a {
+        self.tokenize_sentences_ref_iter(text)
+            .map(|sentence| sentence.map(Cow::into_owned))
+    }
+
+    /// # Tokenizes a text into a flat sequence of BPE tokens.
+    ///
+    /// This function takes a string of text and returns an iterator that yields
+    /// individual tokens. It first tokenizes the text into sentences, then words,
+    /// and finally into BPE tokens, flattening the result into a single sequence.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator that yields `String`, where each `String` represents a token.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    /// let text = "Hello, world! How are you?";
+    /// let tokenized: Vec<String> = vocab.tokenize_iter(text).collect();
+    /// ```
+    ///
+    /// ## Notes
+    ///
+    /// - This function uses Unicode-aware sentence and word segmentation.
+    /// - Each sentence is wrapped with sentence start (`<s>`) and end (`</s>`) tokens, or the
+    ///   markers detected from the vocabulary (see [`SpecialTokens`]).
+    /// - Words are prefixed with the word break character (`▁`).
+    /// - Unknown tokens are replaced with the `<unk>` token.
+    pub fn tokenize_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = String> + 'a {
+        self.tokenize_sentences_iter(text).flatten()
+    }
+
+    /// # Tokenizes a text into tokens borrowed from this `BytePairEncoder` where possible.
+    ///
+    /// This yields the same tokens as [`BytePairEncoder::tokenize_iter`], but tokens stored in the
+    /// vocabulary (or the special tokens) are borrowed instead of freshly allocated, which saves
+    /// an allocation per token for read-only consumers. Only tokens the encoder does not store,
+    /// such as the graphemes of `UnknownStrategy::Graphemes`, and tokens of words taken from the
+    /// word cache are owned.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over the tokens of `text`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let tokens: Vec<_> = vocab.tokenize_ref_iter("Hello, world!").collect();
+    /// assert_eq!(tokens, vec!["<s>", "▁hello", "▁world", "</s>"]);
+    /// ```
+    pub fn tokenize_ref_iter<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> impl Iterator<Item = Cow<'a, str>> + 'a {
+        self.tokenize_sentences_ref_iter(text).flatten()
+    }
+
+    /// # Tokenizes a text into sentences of tokens borrowed from this `BytePairEncoder`.
+    ///
+    /// This is [`BytePairEncoder::tokenize_sentences_iter`] without allocating a `String` per
+    /// stored token, like [`BytePairEncoder::tokenize_ref_iter`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over the sentences of `text`, each an iterator over its tokens.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let sentences: Vec<Vec<_>> = vocab
+    ///     .tokenize_sentences_ref_iter("Hello! World!")
+    ///     .map(Iterator::collect)
+    ///     .collect();
+    /// assert_eq!(sentences, vec![vec!["<s>", "▁hello", "</s>"], vec!["<s>", "▁world", "</s>"]]);
+    /// ```
+    pub fn tokenize_sentences_ref_iter<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> impl Iterator<Item = impl Iterator<Item = Cow<'a, str>> + 'a> + 'a {
+        self.split_sentences(text)
+            .flat_map(move |sentence| self.tokenize_sentence(sentence))
+    }
+
+    /// # Tokenizes a text into sentences of token IDs.
+    ///
+    /// Like [`BytePairEncoder::tokenize_sentences_ref_iter`], but yields the ID of each token,
+    /// so IDs can be streamed per sentence without ever materializing token strings. Tokens
+    /// without an ID get the ID of the unknown token, and are left out if it has none either (see
+    /// [`MissingSpecialTokenPolicy::Register`] to give special tokens IDs).
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over the sentences of `text`, each an iterator over its token IDs.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\n▁hello\t1\n▁world\t2").unwrap();
+    /// let sentences: Vec<Vec<u32>> = vocab
+    ///     .tokenize_sentences_ids_iter("Hello! World!")
+    ///     .map(Iterator::collect)
+    ///     .collect();
+    /// assert_eq!(sentences, vec![vec![0, 2, 1], vec![0, 3, 1]]);
+    /// ```
+    pub fn tokenize_sentences_ids_iter<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> impl Iterator<Item = impl Iterator<Item = u32> + 'a> + 'a {
+        let unknown = self.token_to_id(self.special_tokens.unknown());
+        self.tokenize_sentences_ref_iter(text).map(move |sentence| {
+            sentence.filter_map(move |token| self.token_to_id(&token).or(unknown))
+        })
+    }
+
+    /// # Tokenizes a text into a `Vec` of tokens borrowed from this `BytePairEncoder`.
+    ///
+    /// See [`BytePairEncoder::tokenize_ref_iter`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec` with the tokens of `text`, borrowed from this `BytePairEncoder` where possible.
+    pub fn tokenize_ref<'a>(&'a self, text: &'a str) -> Vec<Cow<'a, str>> {
+        self.tokenize_ref_iter(text).collect()
+    }
+
+    /// # Tokenizes a text, replacing the tokens of the given spans with the mask token.
+    ///
+    /// This builds masked language model training data directly from raw text: every token that
+    /// covers part of a span is replaced by the mask token, one for one, so the tokens and offsets
+    /// of the rest of the text stay exactly as [`BytePairEncoder::tokenize`] would produce them.
+    /// Tokens inserted by the encoder, such as sentence markers, are never masked.
+    ///
+    /// Spans are character ranges, as in [`Encoding::char_offsets`], so they can come straight
+    /// from annotation tools that count characters. Offsets past the end of `text` stand for its
+    /// end.
+    ///
+    /// The mask token is the one configured in `SpecialTokenOptions::mask` or detected from the
+    /// vocabulary (see [`SpecialTokens`]), and `<mask>` otherwise.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    /// * `spans` - The character ranges of `text` to mask.
+    ///
+    /// ## Returns
+    ///
+    /// An [`Encoding`] of `text`, with the masked tokens replaced.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁héllo\t1\n▁wör\t1\nld\t1\n<mask>\t0").unwrap();
+    /// let text = "Héllo, wörld!";
+    /// let encoding = vocab.mask_spans(text, &[10..11]);
+    /// assert_eq!(encoding.tokens, vec!["<s>", "▁héllo", "▁wör", "<mask>", "</s>"]);
+    /// assert_eq!(encoding.offsets, vec![0..0, 0..6, 8..12, 12..14, 15..15]);
+    /// assert_eq!(encoding.char_offsets[3], 10..12);
+    /// assert_eq!(encoding.ids[3], Some(3));
+    /// ```
+    pub fn mask_spans(&self, text: &str, spans: &[Range<usize>]) -> Encoding {
+        let mask = self.special_tokens.mask().unwrap_or(MASK_TOKEN);
+        let char_starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        let byte_offset = |offset: usize| char_starts.get(offset).copied().unwrap_or(text.len());
+        let spans: Vec<Range<usize>> = spans
+            .iter()
+            .map(|span| byte_offset(span.start)..byte_offset(span.end))
+            .collect();
+        let tokens = self
+            .token_spans(text)
+            .into_iter()
+            .map(|(token, range, inserted)| {
+                let masked = !inserted
+                    && spans
+                        .iter()
+                        .any(|span| span.start < range.end && range.start < span.end);
+                (
+                    if masked { Cow::Borrowed(mask) } else { token },
+                    range,
+                    inserted,
+                )
+            });
+        self.encoding(text, tokens)
+    }
+
+    /// # Tokenizes a text into an [`Encoding`], with everything a model pipeline needs.
+    ///
+    /// In one pass, this produces the tokens of [`BytePairEncoder::tokenize`] with their IDs,
+    /// their byte and character offsets into `text`, which of them were inserted by the encoder
+    /// (such as sentence markers), and an attention mask, like the `Encoding` of HuggingFace's
+    /// `tokenizers`. It is padded as configured by [`EncoderOptions::padding`], or can be padded
+    /// to a fixed length with [`Encoding::pad`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be encoded.
+    ///
+    /// ## Returns
+    ///
+    /// The [`Encoding`] of `text`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\n▁hello\t1\n▁wörld\t2").unwrap();
+    /// let encoding = vocab.encode_full("Hello wörld");
+    /// assert_eq!(encoding.ids, vec![Some(0), Some(2), Some(3), Some(1)]);
+    /// assert_eq!(encoding.offsets[2], 6..12);
+    /// assert_eq!(encoding.char_offsets[2], 6..11);
+    /// ```
+    pub fn encode_full(&self, text: &str) -> Encoding {
+        self.encoding(text, self.token_spans(text))
+    }
+
+    /// # Builds the [`Encoding`] of tokens paired with byte ranges of `text`.
+    pub(crate) fn encoding<'a, I>(&self, text: &str, tokens: I) -> Encoding
+    where
+        I: IntoIterator<Item = TokenSpan<'a>>,
+    {
+        let char_starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        let char_offset = |byte: usize| char_starts.partition_point(|&start| start < byte);
+        let mut encoding = Encoding::default();
+        for span in tokens {
+            let id = self.span_id(&span);
+            let (token, range, inserted) = span;
+            let chars = char_offset(range.start)..char_offset(range.end);
+            encoding.push(&token, id, range, chars, inserted);
+        }
+        if let (Some(padding), Some(pad)) = (self.options.padding, self.special_tokens.pad()) {
+            let id = self.token_to_id(pad);
+            match padding.side {
+                PaddingSide::Right => encoding.pad(padding.length, pad, id),
+                PaddingSide::Left => encoding.pad_left(padding.length, pad, id),
+            }
+        }
+        encoding
+    }
+
+    /// # Returns the ID of the token of a span produced by [`BytePairEncoder::token_spans`].
+    ///
+    /// IDs are resolved as by [`BytePairEncoder::encode`]: tokens without an ID, such as the
+    /// graphemes of `UnknownStrategy::Graphemes` or byte tokens missing from the vocabulary, get
+    /// the ID of the unknown token.
+    pub(crate) fn span_id(&self, span: &TokenSpan<'_>) -> Option<u32> {
+        self.token_to_id(&span.0)
+            .or_else(|| self.unknown_token_id())
+    }
+
+    /// # Tokenizes a text into token IDs.
+    ///
+    /// IDs are the positions of the tokens in the vocabulary source, which makes them stable for a
+    /// given vocabulary (see [`BytePairEncoder::token_to_id`]). The sentence markers and the
+    /// unknown token have IDs when they are in the vocabulary, as in the default vocabularies, or
+    /// when they were given reserved IDs with [`MissingSpecialTokenPolicy::Register`]. Any other
+    /// token without an ID gets the ID of the unknown token, and is left out if it has none either.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be encoded.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<u32>` with the token IDs of `text`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab =
+    ///     BytePairEncoder::new_from_str("<unk>\t0\n<s>\t0\n</s>\t0\n▁hello\t1\n▁world\t2").unwrap();
+    /// assert_eq!(vocab.encode("Hello, world! Hi!"), vec![1, 3, 4, 2, 1, 0, 2]);
+    /// ```
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        self.encode_iter(text).collect()
+    }
+
+    /// # Lazily tokenizes a text into token IDs.
+    ///
+    /// See [`BytePairEncoder::encode`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be encoded.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over the token IDs of `text`.
+    pub fn encode_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = u32> + 'a {
+        self.tokenize_sentences_ids_iter(text).flatten()
+    }
+
+    /// # Tokenizes a stream of texts into a consumer, with bounded buffering.
+    ///
+    /// The texts are tokenized on a background thread while `consume` runs on the calling thread,
+    /// receiving each sentence as soon as it is ready. At most `limits` worth of sentences are
+    /// buffered between the two: once the buffer is full, tokenization waits for `consume` to
+    /// catch up, so memory use stays bounded whatever the relative speeds of the input, the
+    /// tokenizer, and the consumer. The texts are pulled from `texts` lazily, so they can be read
+    /// from a file or socket as needed.
+    ///
+    /// If `consume` returns an error, tokenization stops, buffered sentences are dropped, and the
+    /// error is returned. If it panics, tokenization stops the same way before the panic resumes.
+    ///
+    /// ## Arguments
+    ///
+    /// * `texts` - The texts to tokenize, e.g. lines read from a file.
+    /// * `limits` - The bounds on the buffered sentences.
+    /// * `consume` - Called with the index of the text and the tokens of each sentence, in order.
+    ///
+    /// ## Returns
+    ///
+    /// `Ok(())` once every sentence was consumed, or the first error returned by `consume`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, StreamLimits};
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let mut limits = StreamLimits::default();
+    /// limits.max_buffered_sentences = 2;
+    ///
+    /// let mut sentences = vec![];
+    /// vocab
+    ///     .tokenize_stream(["Hello!", "Hello world. World!"], &limits, |i, tokens| {
+    ///         sentences.push((i, tokens));
+    ///         Ok::<_, ()>(())
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(sentences.len(), 3);
+    /// assert_eq!(sentences[2], (1, vec!["<s>".to_string(), "▁world".to_string(), "</s>".to_string()]));
+    /// ```
+    pub fn tokenize_stream<I, S, F, E>(
+        &self,
+        texts: I,
+        limits: &StreamLimits,
+        mut consume: F,
+    ) -> Result<(), E>
+    where
+        I: IntoIterator<Item = S>,
+        I::IntoIter: Send,
+        S: AsRef<str>,
+        F: FnMut(usize, Vec<String>) -> Result<(), E>,
+    {
+        let queue = BoundedQueue::new(limits.clone());
+        let texts = texts.into_iter();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let _finish = FinishGuard(&queue);
+                'texts: for (i, text) in texts.enumerate() {
+                    for sentence in self.split_sentences(text.as_ref()) {
+                        for tokens in self.tokenize_sentence(sentence) {
+                            if !queue.push(i, tokens.map(Cow::into_owned).collect()) {
+                                break 'texts;
+                            }
+                        }
+                    }
+                }
+            });
+
+            let _close = CloseGuard(&queue);
+            while let Some((i, tokens)) = queue.pop() {
+                consume(i, tokens)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// # Tokenizes a batch of texts in parallel.
+    ///
+    /// The texts are split into sentences, and the sentences of all texts are divided into
+    /// contiguous runs of roughly equal size, one per available CPU, which are tokenized on
+    /// separate threads. This parallelizes across the sentences of long texts as well as across
+    /// texts, so a batch dominated by one huge text still uses every core. Results are reassembled
+    /// in order.
+    ///
+    /// ## Arguments
+    ///
+    /// * `texts` - The texts to tokenize.
+    ///
+    /// ## Returns
+    ///
+    /// One `Vec<String>` per text, identical to what [`BytePairEncoder::tokenize`] returns for it.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let tokenized = vocab.tokenize_batch(&["Hello!", "Hello world. World!"]);
+    /// assert_eq!(tokenized[0], vocab.tokenize("Hello!"));
+    /// assert_eq!(tokenized[1], vocab.tokenize("Hello world. World!"));
+    /// ```
+    pub fn tokenize_batch<S: AsRef<str> + Sync>(&self, texts: &[S]) -> Vec<Vec<String>> {
+        let sentences: Vec<(usize, &str)> = texts
+            .iter()
+            .enumerate()
+            .flat_map(|(i, text)| self.split_sentences(text.as_ref()).map(move |s| (i, s)))
+            .collect();
+
+        // Divide the sentences into contiguous runs of roughly equal total length.
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let total: usize = sentences.iter().map(|(_, s)| s.len()).sum();
+        let target = total / threads + 1;
+        let mut runs: Vec<&[(usize, &str)]> = vec![];
+        let (mut start, mut size) = (0, 0);
+        for (i, (_, sentence)) in sentences.iter().enumerate() {
+            size += sentence.len();
+            if size >= target {
+                runs.push(&sentences[start..=i]);
+                start = i + 1;
+                size = 0;
+            }
+        }
+        runs.push(&sentences[start..]);
+
+        let tokenized: Vec<Vec<(usize, Vec<String>)>> = thread::scope(|scope| {
+            let handles: Vec<_> = runs
+                .into_iter()
+                .map(|run| {
+                    scope.spawn(move || {
+                        run.iter()
+                            .flat_map(|&(i, sentence)| {
+                                self.tokenize_sentence(sentence)
+                                    .map(move |tokens| (i, tokens.map(Cow::into_owned).collect()))
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("tokenization thread panicked"))
+                .collect()
+        });
+
+        let mut output = vec![vec![]; texts.len()];
+        for (i, tokens) in tokenized.into_iter().flatten() {
+            output[i].extend(tokens);
+        }
+        output
+    }
+
+    /// # Decodes token IDs back into text.
+    ///
+    /// The IDs are looked up with [`BytePairEncoder::id_to_token`], and the tokens are joined into
+    /// text: word break characters become spaces, without the space that would otherwise start
+    /// the text, and sentence markers and the padding token are skipped. Case markers and byte
+    /// tokens are applied, so the IDs of `TextMode::Lossless` decode to the original text. Use
+    /// [`BytePairEncoder::decode_with_special_tokens`] to keep the special tokens in the text.
+    ///
+    /// ## Arguments
+    ///
+    /// * `ids` - The token IDs to decode, e.g. from [`BytePairEncoder::encode`].
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the decoded text, or [`BytePairEncoderError::InvalidTokenId`] for the first
+    /// ID that no token has.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, BytePairEncoderError};
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("<s>\t0\n</s>\t0\n▁hello\t1\n▁wor\t2\nld\t3").unwrap();
+    /// let ids = vocab.encode("Hello world");
+    /// assert_eq!(ids, vec![0, 2, 3, 4, 1]);
+    /// assert_eq!(vocab.decode(&ids).unwrap(), "hello world");
+    /// assert_eq!(vocab.decode_with_special_tokens(&ids).unwrap(), "<s> hello world</s>");
+    /// assert!(matches!(
+    ///     vocab.decode(&[2, 7]),
+    ///     Err(BytePairEncoderError::InvalidTokenId { id: 7, index: 1 })
+    /// ));
+    /// ```
+    pub fn decode(&self, ids: &[u32]) -> Result<String, BytePairEncoderError> {
+        self.decode_checked(ids, true)
+    }
+
+    /// # Decodes token IDs back into text, keeping special tokens.
+    ///
+    /// This is [`BytePairEncoder::decode`], except that sentence markers and the padding token
+    /// are kept in the text as they are. With `SpecialTextPolicy::Escape`, text spelling a special
+    /// token that was decoded from other tokens is escaped with a backslash (`\<s>`), and
+    /// backslashes are doubled, so the special tokens in the text are exactly those in `ids`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `ids` - The token IDs to decode.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the decoded text, or [`BytePairEncoderError::InvalidTokenId`] for the first
+    /// ID that no token has.
+    pub fn decode_with_special_tokens(&self, ids: &[u32]) -> Result<String, BytePairEncoderError> {
+        self.decode_checked(ids, false)
+    }
+
+    /// # Reconstructs text from tokens.
+    ///
+    /// This is the inverse of [`BytePairEncoder::tokenize`]: word break characters become spaces,
+    /// without the space that would otherwise start the text, and sentence markers and the
+    /// padding token are dropped, so consecutive sentences are joined by the space their first
+    /// word starts with. Case markers and byte tokens are applied as in
+    /// [`BytePairEncoder::decode`], and all other tokens, including the unknown token, are kept.
+    ///
+    /// ## Arguments
+    ///
+    /// * `tokens` - The tokens to join, e.g. from [`BytePairEncoder::tokenize`].
+    ///
+    /// ## Returns
+    ///
+    /// The reconstructed text.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁wor\t2\nld\t3").unwrap();
+    /// let tokens = vocab.tokenize("Hello world! Hello.");
+    /// assert_eq!(vocab.detokenize(&tokens), "hello world hello");
+    /// ```
+    pub fn detokenize(&self, tokens: &[String]) -> String {
+        let mut text = String::new();
+        self.decode_into(tokens.iter().map(String::as_str), true, &mut text);
+        text
+    }
+
+    /// Decodes `ids`, failing on the first ID that no token has.
+    fn decode_checked(
+        &self,
+        ids: &[u32],
+        skip_special: bool,
+    ) -> Result<String, BytePairEncoderError> {
+        let tokens = ids
+            .iter()
+            .enumerate()
+            .map(|(index, &id)| {
+                self.id_to_token(id)
+                    .ok_or(BytePairEncoderError::InvalidTokenId { id, index })
+            })
+            .collect::<Result<Vec<&str>, _>>()?;
+        let mut text = String::new();
+        self.decode_into(tokens, skip_special, &mut text);
+        Ok(text)
+    }
+
+    /// # Decodes a batch of token ID sequences back into text.
+    ///
+    /// Each sequence is decoded by looking up its IDs (see [`BytePairEncoder::id_to_token`]),
+    /// dropping sentence markers and the padding token, and turning word break characters into
+    /// spaces, without the space that would otherwise start the text. Case markers and byte
+    /// tokens are applied, so tokens of `TextMode::Lossless` decode to the original text. IDs that
+    /// are not in the vocabulary decode as the unknown token, like words the vocabulary cannot
+    /// cover do.
+    ///
+    /// Sequences are decoded into a buffer that is reused across the whole batch, and each output
+    /// `String` is allocated once, at its exact length. Use
+    /// [`BytePairEncoder::decode_batch_parallel`] to spread large batches across threads.
+    ///
+    /// ## Arguments
+    ///
+    /// * `batch` - The ID sequences to decode.
+    ///
+    /// ## Returns
+    ///
+    /// One `String` per sequence, in order.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2\n!\t3").unwrap();
+    /// let ids = vec![vec![0, 1, 2], vec![1, 0]];
+    /// assert_eq!(vocab.decode_batch(&ids), vec!["hello world!", "world hello"]);
+    /// ```
+    pub fn decode_batch(&self, batch: &[Vec<u32>]) -> Vec<String> {
+        let mut buffer = String::new();
+        batch
+            .iter()
+            .map(|ids| self.decode_with(ids, &mut buffer))
+            .collect()
+    }
+
+    /// # Decodes a batch of token ID sequences back into text, in parallel.
+    ///
+    /// This returns the same strings as [`BytePairEncoder::decode_batch`], but divides the batch
+    /// into contiguous runs with roughly equal numbers of IDs, one per available CPU, and decodes
+    /// them on separate threads, each with its own buffer. Thread startup costs more than
+    /// decoding a few short sequences, so this pays off for large batches only.
+    ///
+    /// ## Arguments
+    ///
+    /// * `batch` - The ID sequences to decode.
+    ///
+    /// ## Returns
+    ///
+    /// One `String` per sequence, in order.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let ids = vec![vec![0, 1]; 1000];
+    /// assert_eq!(vocab.decode_batch_parallel(&ids), vocab.decode_batch(&ids));
+    /// ```
+    pub fn decode_batch_parallel(&self, batch: &[Vec<u32>]) -> Vec<String> {
+        // Divide the sequences into contiguous runs of roughly equal total length.
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let total: usize = batch.iter().map(Vec::len).sum();
+        let target = total / threads + 1;
+        let mut runs: Vec<&[Vec<u32>]> = vec![];
+        let (mut start, mut size) = (0, 0);
+        for (i, ids) in batch.iter().enumerate() {
+            size += ids.len();
+            if size >= target {
+                runs.push(&batch[start..=i]);
+                start = i + 1;
+                size = 0;
+            }
+        }
+        runs.push(&batch[start..]);
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = runs
+                .into_iter()
+                .map(|run| scope.spawn(move || self.decode_batch(run)))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("decoding thread panicked"))
+                .collect()
+        })
+    }
+
+    /// Decodes one ID sequence, as described in [`BytePairEncoder::decode_batch`], using
+    /// `buffer` to hold the text until its final length is known.
+    fn decode_with(&self, ids: &[u32], buffer: &mut String) -> String {
+        let unknown = self.special_tokens.unknown();
+        buffer.clear();
+        self.decode_into(
+            ids.iter()
+                .map(|&id| self.id_to_token(id).unwrap_or(unknown)),
+            true,
+            buffer,
+        );
+        buffer.as_str().to_string()
+    }
+
+    /// # Appends the text that a sequence of tokens decodes to.
+    ///
+    /// Sentence markers and the padding token are dropped if `skip_special` is set, and word break
+    /// characters become spaces. Outside of `TextMode::Lossless`, the space that would start the
+    /// text is dropped as well. Case markers and byte tokens (see `TextMode::Lossless`) are
+    /// applied, and byte sequences that are not valid UTF-8 decode to `U+FFFD`. All other tokens,
+    /// including the unknown token, are kept as they are.
+    ///
+    /// If special tokens are kept and the `SpecialTextPolicy` is `SpecialTextPolicy::Escape`,
+    /// special token text spelled by other tokens is escaped with a backslash, and backslashes are
+    /// doubled, so it cannot be mistaken for the special tokens themselves.
+    pub(crate) fn decode_into<I, S>(&self, tokens: I, skip_special: bool, out: &mut String)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let start = out.len();
+        let escape = !skip_special && self.options.special_text == SpecialTextPolicy::Escape;
+        // The ranges of `out` holding special tokens, which are not escaped.
+        let mut kept: Vec<Range<usize>> = vec![];
+        let skipped = [
+            Some(self.special_tokens.sentence_start()),
+            Some(self.special_tokens.sentence_end()),
+            self.special_tokens.pad(),
+        ];
+        let skipped = if skip_special { &skipped[..] } else { &[] };
+        let mut case = None;
+        let mut bytes: Vec<u8> = vec![];
+
+        for token in tokens {
+            let token = token.as_ref();
+            if let Some(byte) = byte_token(token) {
+                push_byte(out, &mut bytes, byte, &mut case);
+                continue;
+            }
+            if escape && self.special_tokens.iter().any(|special| special == token) {
+                flush_bytes(out, &mut bytes, &mut case);
+                if self.options.segmentation == SegmentationStrategy::WordPiece {
+                    out.push(' ');
+                }
+                let at = out.len();
+                out.push_str(token);
+                kept.push(at..out.len());
+                continue;
+            }
+            if self.options.segmentation == SegmentationStrategy::Merges
+                && !skipped.contains(&Some(token))
+            {
+                // Byte-level tokens spell UTF-8 bytes, and a character may span several tokens.
+                for c in token.chars() {
+                    match char_byte(c) {
+                        Some(byte) => push_byte(out, &mut bytes, byte, &mut case),
+                        None => {
+                            flush_bytes(out, &mut bytes, &mut case);
+                            out.push(c);
+                        }
+                    }
+                }
+                continue;
+            }
+            flush_bytes(out, &mut bytes, &mut case);
+            if skipped.contains(&Some(token)) {
+                continue;
+            } else if token == CAPITALIZED_TOKEN {
+                case = Some(CaseMarker::Capitalized);
+            } else if token == UPPERCASE_TOKEN {
+                case = Some(CaseMarker::Uppercase);
+            } else if self.options.segmentation == SegmentationStrategy::WordPiece {
+                // WordPiece tokens start a word unless they have the continuation prefix.
+                match token.strip_prefix(WORDPIECE_PREFIX) {
+                    Some(piece) => push_cased(out, piece, &mut case),
+                    None => {
+                        out.push(' ');
+                        push_cased(out, token, &mut case);
+                    }
+                }
+            } else {
+                push_cased(
+                    out,
+                    &token.replace(self.special_tokens.word_break(), " "),
+                    &mut case,
+                );
+            }
+        }
+        flush_bytes(out, &mut bytes, &mut case);
+
+        if escape {
+            let decoded = out.split_off(start);
+            let escaped = self.control_tokens();
+            let mut end = 0;
+            for range in kept {
+                escape_patterns(&decoded[end..range.start], &escaped, out);
+                out.push_str(&decoded[range.clone()]);
+                end = range.end;
+            }
+            escape_patterns(&decoded[end..], &escaped, out);
+        }
+
+        if self.options.mode != TextMode::Lossless && out[start..].starts_with(' ') {
+            out.remove(start);
+        }
+    }
+
+    /// # Checks whether a text survives tokenization and decoding unchanged.
+    ///
+    /// The text is tokenized, the tokens are decoded as by [`BytePairEncoder::decode_batch`], and
+    /// the result is compared with the text. With [`EncoderOptions::lossless`] this holds for all
+    /// but a few pathological texts (see there), and the check can be used to guard reversible
+    /// transformations. With the default options, it only holds for text that is already
+    /// lowercase words separated by single spaces, and covered by the vocabulary.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - The text to check.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if decoding the tokens of `text` gives back `text` exactly.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, EncoderOptions};
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// assert!(vocab.is_lossless_for("hello world"));
+    /// assert!(!vocab.is_lossless_for("Hello, world!"));
+    ///
+    /// let vocab = vocab.with_options(EncoderOptions::lossless());
+    /// assert!(vocab.is_lossless_for("Hello, world!"));
+    /// ```
+    pub fn is_lossless_for(&self, text: &str) -> bool {
+        let mut decoded = String::with_capacity(text.len());
+        self.decode_into(self.tokenize_ref_iter(text), true, &mut decoded);
+        decoded == text
+    }
+
+    /// # Tokenizes only the last `n` tokens of a text.
+    ///
+    /// This returns the same tokens as the end of [`BytePairEncoder::tokenize`], but works
+    /// backwards from the end of the text and stops as soon as `n` tokens are found, so keeping
+    /// the most recent context of a long text (e.g. a chat history) does not require tokenizing
+    /// all of it.
+    ///
+    /// The text is walked backwards one line at a time, which never changes where sentences
+    /// begin or end, and the sentences of each line are tokenized from last to first. Line breaks
+    /// are tokens of their own in `TextMode::Lossless`, GPT-2's pre-tokenizer attaches them to
+    /// neighbouring whitespace with `SegmentationStrategy::Merges`, and `HyphenationPolicy::Rejoin`
+    /// joins words across them, so with any of these the whole text is tokenized instead.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    /// * `n` - The maximum number of tokens to return.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` with the last `n` tokens of the text (or all of them, if there are fewer).
+    /// The first token may fall in the middle of a sentence.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let text = "Hello world.\nHello. World!";
+    /// assert_eq!(vocab.tokenize_last_n(text, 4), vec!["</s>", "<s>", "▁world", "</s>"]);
+    /// ```
+    pub fn tokenize_last_n(&self, text: &str, n: usize) -> Vec<String> {
+        self.last_n(text, n, Some)
+    }
+
+    /// # Encodes only the last `n` tokens of a text into token IDs.
+    ///
+    /// This returns the same IDs as the end of [`BytePairEncoder::encode`], working backwards from
+    /// the end of the text like [`BytePairEncoder::tokenize_last_n`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be encoded.
+    /// * `n` - The maximum number of token IDs to return.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<u32>` with the last `n` token IDs of the text (or all of them, if there are fewer).
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab =
+    ///     BytePairEncoder::new_from_str("<unk>\t0\n<s>\t0\n</s>\t0\n▁hello\t1\n▁world\t2").unwrap();
+    /// assert_eq!(vocab.encode_last_n("Hello, world! Hi!", 3), vec![1, 0, 2]);
+    /// ```
+    pub fn encode_last_n(&self, text: &str, n: usize) -> Vec<u32> {
+        let unknown = self.token_to_id(self.special_tokens.unknown());
+        self.last_n(text, n, |token| self.token_to_id(&token).or(unknown))
+    }
+
+    /// Collects the last `n` tokens of `text` that `map` keeps, working backwards from the end of
+    /// the text one line at a time, unless lines cannot be tokenized on their own.
+    fn last_n<T>(&self, text: &str, n: usize, map: impl Fn(String) -> Option<T>) -> Vec<T> {
+        let line_local = self.options.mode != TextMode::Lossless
+            && self.options.segmentation != SegmentationStrategy::Merges
+            && self.options.hyphenation == HyphenationPolicy::Keep;
+        if !line_local {
+            let tokens: Vec<T> = self.tokenize_iter(text).filter_map(map).collect();
+            let skip = tokens.len().saturating_sub(n);
+            return tokens.into_iter().skip(skip).collect();
+        }
+
+        let mut sentences: Vec<Vec<T>> = vec![];
+        let mut count = 0;
+        let mut end = text.len();
+
+        'lines: while count < n && end > 0 {
+            // Sentences always end after a line feed, so each line can be segmented on its own.
+            let before = &text[..end];
+            let start = before
+                .strip_suffix('\n')
+                .unwrap_or(before)
+                .rfind('\n')
+                .map_or(0, |i| i + 1);
+
+            let line: Vec<&str> = self.split_sentences(&text[start..end]).collect();
+            for sentence in line.into_iter().rev() {
+                let segments: Vec<Vec<T>> = self
+                    .tokenize_sentence(sentence)
+                    .map(|segment| segment.map(Cow::into_owned).filter_map(&map).collect())
+                    .collect();
+                for segment in segments.into_iter().rev() {
+                    count += segment.len();
+                    sentences.push(segment);
+                    if count >= n {
+                        break 'lines;
+                    }
+                }
+            }
+            end = start;
+        }
+
+        let tokens: Vec<T> = sentences.into_iter().rev().flatten().collect();
+        let skip = tokens.len().saturating_sub(n);
+        tokens.into_iter().skip(skip).collect()
+    }
+
+    /// # Tokenizes the beginning of a text, up to a limit on its decoded length in bytes.
+    ///
+    /// Storage fields are often limited in bytes rather than tokens. This keeps as many tokens as
+    /// fit into `max_bytes` once decoded, measuring each one while tokenizing so the rest of the
+    /// text is never processed. Tokens are measured as [`BytePairEncoder::detokenize`] decodes
+    /// them: each word break character counts as a single space, except for the space that would
+    /// start the text, sentence markers and case markers count as nothing, and all other tokens,
+    /// including the unknown token, count as their text in UTF-8.
+    ///
+    /// A sentence cut short is still closed with a sentence end marker, and a sentence start
+    /// marker without any tokens after it is dropped.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    /// * `max_bytes` - The maximum decoded length of the tokens, in bytes.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` with the leading tokens of the text that fit into `max_bytes`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// // "hello world" decodes to 11 bytes, and " hello" would add 6 more.
+    /// assert_eq!(
+    ///     vocab.tokenize_max_bytes("Hello world. Hello!", 14),
+    ///     vec!["<s>", "▁hello", "▁world", "</s>"]
+    /// );
+    /// ```
+    pub fn tokenize_max_bytes(&self, text: &str, max_bytes: usize) -> Vec<String> {
+        let mut tokens = vec![];
+        let mut used = 0;
+        for token in self.tokenize_iter(text) {
+            let (mut len, leading_space) = self.decoded_len(&token);
+            if used == 0 && leading_space && self.options.mode != TextMode::Lossless {
+                len -= 1;
+            }
+            if used + len > max_bytes {
+                break;
+            }
+            used += len;
+            tokens.push(token);
+        }
+
+        if let Some((start, end)) = self.sentence_markers() {
+            match tokens.last() {
+                Some(last) if last == start => {
+                    tokens.pop();
+                }
+                Some(last) if last != end => tokens.push(end.to_string()),
+                _ => {}
+            }
+        }
+        tokens
+    }
+
+    /// Returns the length in bytes of the text `token` decodes to on its own, as in
+    /// [`BytePairEncoder::decode_into`], and whether that text starts with a space.
+    fn decoded_len(&self, token: &str) -> (usize, bool) {
+        if let Some(byte) = byte_token(token) {
+            return (1, byte == b' ');
+        }
+        let markers = [
+            Some(self.special_tokens.sentence_start()),
+            Some(self.special_tokens.sentence_end()),
+            self.special_tokens.pad(),
+            Some(CAPITALIZED_TOKEN),
+            Some(UPPERCASE_TOKEN),
+        ];
+        if markers.contains(&Some(token)) {
+            return (0, false);
+        }
+        match self.options.segmentation {
+            SegmentationStrategy::Merges => {
+                let len = token
+                    .chars()
+                    .map(|c| char_byte(c).map_or(c.len_utf8(), |_| 1))
+                    .sum();
+                (len, token.chars().next().and_then(char_byte) == Some(b' '))
+            }
+            SegmentationStrategy::WordPiece => match token.strip_prefix(WORDPIECE_PREFIX) {
+                Some(piece) => (piece.len(), false),
+                None => (token.len() + 1, true),
+            },
+            _ => {
+                let text = token.replace(self.special_tokens.word_break(), " ");
+                (text.len(), text.starts_with(' '))
+            }
+        }
+    }
+
+    /// # Splits a text into consecutive slices that each fit into a token budget.
+    ///
+    /// This is meant for feeding long documents to models with a limited context: every slice
+    /// tokenizes (with [`BytePairEncoder::tokenize`]) to at most `budget` tokens. Slices are cut
+    /// at sentence boundaries where possible, and sentences too long for the budget on their own
+    /// are cut at word boundaries. Each slice then counts its own sentence start and end markers.
+    ///
+    /// Each candidate slice is checked by tokenizing it on its own, since a slice can tokenize
+    /// differently than the same text in context (e.g. in `TextMode::Lossless`, where the space
+    /// before a word is part of its first token). The last fitting cut is found by a binary
+    /// search, so every part of the text is only tokenized a few times.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be split.
+    /// * `budget` - The maximum number of tokens per slice.
+    ///
+    /// ## Returns
+    ///
+    /// Slices of `text`, in order, which together cover all of it (none for an empty text). A
+    /// single word with too many tokens to fit into the budget (with its sentence markers) is
+    /// returned as a slice of its own, which then exceeds the budget.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let text = "Hello world. Hello world, hello world!";
+    /// assert_eq!(
+    ///     vocab.split_by_token_budget(text, 5),
+    ///     vec!["Hello world. ", "Hello world, hello ", "world!"]
+    /// );
+    /// ```
+    pub fn split_by_token_budget<'a>(&self, text: &'a str, budget: usize) -> Vec<&'a str> {
+        let offset = |slice: &str| slice.as_ptr() as usize - text.as_ptr() as usize;
+        let fits = |range: Range<usize>| {
+            self.tokenize_ref_iter(&text[range])
+                .take(budget + 1)
+                .count()
+                <= budget
+        };
+
+        // Slices end where a sentence starts, or where a word starts in a sentence that does not
+        // fit into the budget on its own. Each sentence runs up to the start of the next one.
+        let sentences: Vec<&str> = self.split_sentences(text).collect();
+        let mut cuts = vec![];
+        for (i, sentence) in sentences.iter().enumerate() {
+            let start = offset(sentence);
+            let end = sentences.get(i + 1).map_or(text.len(), |next| offset(next));
+            if start > 0 {
+                cuts.push(start);
+            }
+            if !fits(start..end) {
+                cuts.extend(
+                    self.split_words(sentence)
+                        .map(offset)
+                        .filter(|&word| word > start),
+                );
+            }
+        }
+        if !text.is_empty() {
+            cuts.push(text.len());
+        }
+
+        let mut slices = vec![];
+        let (mut start, mut next) = (0, 0);
+        while next < cuts.len() {
+            if !fits(start..cuts[next]) {
+                // A single word too long for the budget is returned on its own.
+                slices.push(&text[start..cuts[next]]);
+                start = cuts[next];
+                next += 1;
+                continue;
+            }
+            // Gallop to a cut that does not fit, then bisect for the last one that does.
+            let (mut fit, mut over, mut step) = (next, cuts.len(), 1);
+            while fit + step < cuts.len() {
+                if !fits(start..cuts[fit + step]) {
+                    over = fit + step;
+                    break;
+                }
+                fit += step;
+                step *= 2;
+            }
+            while over - fit > 1 {
+                let middle = (fit + over) / 2;
+                if fits(start..cuts[middle]) {
+                    fit = middle;
+                } else {
+                    over = middle;
+                }
+            }
+            slices.push(&text[start..cuts[fit]]);
+            start = cuts[fit];
+            next = fit + 1;
+        }
+        slices
+    }
+
+    /// # Hashes the tokens of each sentence of a text.
+    ///
+    /// Each hash is a 64-bit FNV-1a hash over the tokens of one sentence (as returned by
+    /// [`BytePairEncoder::tokenize_sentences`]), in order, so sentences that tokenize identically
+    /// hash identically, even if their text differs in case or punctuation. The hashes are
+    /// identical across runs and platforms, so they can be stored to deduplicate a corpus
+    /// incrementally.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be hashed.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<u64>` with one hash per sentence.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let hashes = vocab.sentence_hashes("Hello world! hello, WORLD. Hello.");
+    /// assert_eq!(hashes[0], hashes[1]);
+    /// assert_ne!(hashes[0], hashes[2]);
+    /// ```
+    pub fn sentence_hashes(&self, text: &str) -> Vec<u64> {
+        self.tokenize_sentences_iter(text)
+            .map(|sentence| {
+                let mut hash = Fnv1a::new();
+                sentence.for_each(|token| hash.write_str(&token));
+                hash.finish()
+            })
+            .collect()
+    }
+
+    /// # Tokenizes the sentences of a text that have not been seen before.
+    ///
+    /// Sentences are identified by the hash of their tokens, as computed by
+    /// [`BytePairEncoder::sentence_hashes`]. The hashes of new sentences are added to `seen`, so
+    /// passing the same set for every document of a corpus deduplicates the whole corpus while
+    /// keeping only 8 bytes per distinct sentence in memory.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    /// * `seen` - The hashes of the sentences seen so far.
+    ///
+    /// ## Returns
+    ///
+    /// The tokenized sentences of `text` whose hash was not in `seen`, in order.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    ///
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁world\t2").unwrap();
+    /// let mut seen = HashSet::new();
+    /// assert_eq!(vocab.dedup_sentences("Hello. World. Hello!", &mut seen).len(), 2);
+    /// assert!(vocab.dedup_sentences("World.", &mut seen).is_empty());
+    /// ```
+    pub fn dedup_sentences(&self, text: &str, seen: &mut HashSet<u64>) -> Vec<Vec<String>> {
+        self.tokenize_sentences_iter(text)
+            .map(|sentence| sentence.collect::<Vec<String>>())
+            .filter(|sentence| {
+                let mut hash = Fnv1a::new();
+                sentence.iter().for_each(|token| hash.write_str(token));
+                seen.insert(hash.finish())
+            })
+            .collect()
+    }
+
+    /// # Tokenizes a text into sentences, then words, and finally into BPE tokens.
+    ///
+    /// This function takes a string of text and returns a vector of tokenized sentences,
+    /// where each sentence is represented as a vector of tokens.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<Vec<String>>`, where each inner `Vec<String>` represents a tokenized sentence.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    /// let text = "Hello, world! How are you?";
+    /// let tokenized = vocab.tokenize_sentences(text);
+    /// ```
+    ///
+    /// ## Notes
+    ///
+    /// - This function uses Unicode-aware sentence and word segmentation.
+    /// - Each sentence is wrapped with sentence start (`<s>`) and end (`</s>`) tokens, or the
+    ///   markers detected from the vocabulary (see [`SpecialTokens`]).
+    /// - Words are prefixed with the word break character (`▁`).
+    /// - Unknown tokens are replaced with the `<unk>` token.
+    pub fn tokenize_sentences(&self, text: &str) -> Vec<Vec<String>> {
+        self.tokenize_sentences_iter(text)
+            .map(|sentence_iter| sentence_iter.collect())
+            .collect()
+    }
+
+    /// # Tokenizes a text into a flat sequence of BPE tokens.
+    ///
+    /// This function takes a string of text and returns a vector of tokens.
+    /// It first tokenizes the text into sentences, then words, and finally into BPE tokens,
+    /// flattening the result into a single sequence.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>`, where each `String` represents a token.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+    /// let text = "Hello, world! How are you?";
+    /// let tokenized = vocab.tokenize(text);
+    /// ```
+    ///
+    /// ## Notes
+    ///
+    /// - This function uses Unicode-aware sentence and word segmentation.
+    /// - Each sentence is wrapped with sentence start (`<s>`) and end (`</s>`) tokens, or the
+    ///   markers detected from the vocabulary (see [`SpecialTokens`]).
+    /// - Words are prefixed with the word break character (`▁`).
+    /// - Unknown tokens are replaced with the `<unk>` token, unless the [`UnknownStrategy`] says
+    ///   otherwise.
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        self.tokenize_iter(text).collect()
+    }
+
+    /// # Tokenizes a text, failing on text the vocabulary does not cover.
+    ///
+    /// With `UnknownStrategy::Error`, this is [`BytePairEncoder::tokenize`] for strict validation
+    /// pipelines: instead of emitting the unknown token, it fails with the first part of the text
+    /// that no vocabulary token covers (the first of [`BytePairEncoder::uncovered_spans`]). With
+    /// any other strategy, and in `TextMode::Lossless`, it never fails.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// The tokens of `text`, or `BytePairEncoderError::UnknownToken` with the byte range of the
+    /// uncovered text.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, BytePairEncoderError, EncoderOptions, UnknownStrategy};
+    ///
+    /// let mut options = EncoderOptions::default();
+    /// options.unknown = UnknownStrategy::Error;
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁wor\t1\nd\t1")
+    ///     .unwrap()
+    ///     .with_options(options);
+    /// assert_eq!(vocab.try_tokenize("Hello!").unwrap(), vec!["<s>", "▁hello", "</s>"]);
+    /// assert!(matches!(
+    ///     vocab.try_tokenize("Hello, world!"),
+    ///     Err(BytePairEncoderError::UnknownToken { span }) if span == (10..11)
+    /// ));
+    /// ```
+    pub fn try_tokenize(&self, text: &str) -> Result<Vec<String>, BytePairEncoderError> {
+        if self.options.unknown == UnknownStrategy::Error && self.options.mode != TextMode::Lossless
+        {
+            if let Some(span) = self.uncovered_spans(text).into_iter().next() {
+                return Err(BytePairEncoderError::UnknownToken { span });
+            }
+        }
+        Ok(self.tokenize(text))
+    }
+
+    /// # Tokenizes a text, measuring the length of each token.
+    ///
+    /// This returns the same tokens as [`BytePairEncoder::tokenize`], each with its length in
+    /// bytes, characters, and graphemes, for enforcing constraints on token text downstream.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec` of tokens and their [`TokenLengths`].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1").unwrap();
+    /// let tokens = vocab.tokenize_with_lengths("Hello");
+    /// assert_eq!(tokens[1].0, "▁hello");
+    /// assert_eq!(tokens[1].1.bytes, 8);
+    /// assert_eq!(tokens[1].1.chars, 6);
+    /// ```
+    pub fn tokenize_with_lengths(&self, text: &str) -> Vec<(String, TokenLengths)> {
+        self.tokenize_iter(text)
+            .map(|token| {
+                let lengths = TokenLengths::of(&token);
+                (token, lengths)
+            })
+            .collect()
+    }
+
+    /// # Tokenizes a text, pairing each token with the byte range of the text it came from.
+    ///
+    /// This returns the same tokens as [`BytePairEncoder::tokenize`], each with a byte range of
+    /// `text`, for highlighting tokens or aligning them with annotations such as named entities.
+    /// The ranges are tracked through sentence splitting, word splitting, and matching, and
+    /// through normalization (a token covering part of a character that normalization expanded
+    /// covers the whole character). The word break character covers no text, and sentence markers
+    /// get an empty range at the start or end of their sentence. [`BytePairEncoder::encode_full`]
+    /// returns the same offsets along with token IDs and character offsets, as an [`Encoding`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec` of tokens and the byte ranges of `text` they cover.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁hello\t1\n▁wor\t1\nld\t1").unwrap();
+    /// let text = "Hello, world!";
+    /// let tokens = vocab.tokenize_with_offsets(text);
+    /// assert_eq!(tokens[1], ("▁hello".to_string(), 0..5));
+    /// assert_eq!(tokens[3], ("ld".to_string(), 10..12));
+    /// assert_eq!(&text[tokens[2].1.clone()], "wor");
+    /// assert_eq!(tokens[4], ("</s>".to_string(), 13..13));
+    /// ```
+    pub fn tokenize_with_offsets(&self, text: &str) -> Vec<(String, Range<usize>)> {
+        self.token_spans(text)
+            .into_iter()
+            .map(|(token, range, _)| (token.into_owned(), range))
+            .collect()
+    }
+
+    /// # Merges BPE tokens back into whole words.
+    ///
+    /// Every token starting with the word break character (`▁`) begins a new word, and the tokens
+    /// following it are appended to that word until the next one begins. The word break character
+    /// is removed, so `["▁sent", "ence"]` becomes `["sentence"]`. Special tokens (sentence markers,
+    /// the unknown token, and the padding token) are kept as units of their own, and end the word
+    /// in progress.
+    ///
+    /// This is useful when word-level units are wanted, with BPE only used to segment words missing
+    /// from the vocabulary.
+    ///
+    /// ## Arguments
+    ///
+    /// * `tokens` - The tokens to merge, as produced by [`BytePairEncoder::tokenize`].
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` with the words and special tokens, in order.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁sent\t1\nence\t1\n▁a\t1").unwrap();
+    /// let tokens = vocab.tokenize("A sentence");
+    /// assert_eq!(tokens, vec!["<s>", "▁a", "▁sent", "ence", "</s>"]);
+    /// assert_eq!(vocab.merge_word_pieces(&tokens), vec!["<s>", "a", "sentence", "</s>"]);
+    /// ```
+    pub fn merge_word_pieces<S: AsRef<str>>(&self, tokens: &[S]) -> Vec<String> {
+        let mut merged = vec![];
+        let mut word: Option<String> = None;
+
+        for token in tokens.iter().map(AsRef::as_ref) {
+            if self.special_tokens.iter().any(|special| special == token) {
+                merged.extend(word.take().filter(|word| !word.is_empty()));
+                merged.push(token.to_string());
+            } else if let Some(piece) = token.strip_prefix(self.special_tokens.word_break()) {
+                merged.extend(
+                    word.replace(piece.to_string())
+                        .filter(|word| !word.is_empty()),
+                );
+            } else {
+                word.get_or_insert_with(String::new).push_str(token);
+            }
+        }
+        merged.extend(word.filter(|word| !word.is_empty()));
+        merged
+    }
+
+    /// # Tokenizes a text without letting any token span the given boundaries.
+    ///
+    /// This works like [`BytePairEncoder::tokenize`], but a word containing one of `boundaries` is
+    /// cut there before matching, and each piece is tokenized on its own. Only the first piece of
+    /// a word starts with the word break character, so the pieces still read as one word. This
+    /// lets upstream annotations (entity spans, embedded code) constrain the segmentation.
+    ///
+    /// Boundaries between words already separate tokens and have no effect, and neither do
+    /// boundaries that are not character boundaries of `text` or that fall inside an emoji
+    /// sequence, which is always matched as a whole.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    /// * `boundaries` - Byte offsets into `text`, in any order, that no token may span.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` with the tokens of the text.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁newyork\t5\n▁new\t1\nyork\t1").unwrap();
+    /// assert_eq!(vocab.tokenize("NewYork"), vec!["<s>", "▁newyork", "</s>"]);
+    /// assert_eq!(
+    ///     vocab.tokenize_with_boundaries("NewYork", &[3]),
+    ///     vec!["<s>", "▁new", "york", "</s>"]
+    /// );
+    /// ```
+    pub fn tokenize_with_boundaries(&self, text: &str, boundaries: &[usize]) -> Vec<String> {
+        let mut cuts: Vec<usize> = boundaries
+            .iter()
+            .copied()
+            .filter(|&cut| text.is_char_boundary(cut))
+            .collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        // Sentences and words are slices of `text`, so their offsets follow from their addresses.
+        let offset = |slice: &str| slice.as_ptr() as usize - text.as_ptr() as usize;
+        let cuts_within = |slice: &str| {
+            let start = offset(slice);
+            let from = cuts.partition_point(|&cut| cut <= start);
+            let to = cuts.partition_point(|&cut| cut < start + slice.len());
+            cuts[from..to].iter().map(move |&cut| cut - start)
+        };
+
+        let mut tokens = vec![];
+        for sentence in self.split_sentences(text) {
+            if cuts_within(sentence).next().is_none() {
+                tokens.extend(
+                    self.tokenize_sentence(sentence)
+                        .flatten()
+                        .map(Cow::into_owned),
+                );
+                continue;
+            }
+
+            let words = self.split_words(sentence);
+            let segments = self.sentence_tokens(words, sentence.len(), |word| {
+                let mut pieces = vec![];
+                let mut start = 0;
+                if self.options.emoji == EmojiPolicy::Drop || !is_emoji(word) {
+                    for cut in cuts_within(word) {
+                        pieces.push(&word[start..cut]);
+                        start = cut;
+                    }
+                }
+                pieces.push(&word[start..]);
+
+                // Only the first piece that is not normalized away starts the word.
+                let mut word_tokens: Vec<Cow<'_, str>> = vec![];
+                for piece in pieces {
+                    let word_start = word_tokens.is_empty();
+                    word_tokens.extend(self.piece_tokens(piece, word_start));
+                }
+                word_tokens.into_iter()
+            });
+            tokens.extend(segments.flatten().map(Cow::into_owned));
+        }
+        tokens
+    }
+
+    /// # Tokenizes words that were already segmented by the caller.
+    ///
+    /// Upstream NLP pipelines often split text into words with their own rules. This skips
+    /// sentence and word segmentation, and tokenizes each of `words` as one word, exactly as
+    /// [`BytePairEncoder::tokenize`] tokenizes the words it finds: normalized according to the
+    /// [`EncoderOptions`], prefixed with the word break character, and matched against the
+    /// vocabulary. The words are taken as a single sentence, wrapped in sentence markers (and
+    /// split into several if `EncoderOptions::max_sentence_tokens` is set). Words with nothing
+    /// left after normalization produce no tokens. Every other word is tokenized, including
+    /// punctuation and words containing spaces, whatever the `PunctuationPolicy`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `words` - The words of the sentence, in order.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` with the tokens of the words, or an empty `Vec` if `words` is empty.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let vocab = BytePairEncoder::new_from_str("▁new\t1\n▁york\t1\n▁new york\t2").unwrap();
+    /// assert_eq!(vocab.tokenize("New York"), vec!["<s>", "▁new", "▁york", "</s>"]);
+    /// assert_eq!(
+    ///     vocab.tokenize_pretokenized(&["New York", "!"]),
+    ///     vec!["<s>", "▁new york", "<unk>", "</s>"]
+    /// );
+    /// ```
+    pub fn tokenize_pretokenized<S: AsRef<str>>(&self, words: &[S]) -> Vec<String> {
+        if words.is_empty() {
+            return vec![];
+        }
+
+        let words = words.iter().map(AsRef::as_ref);
+        self.sentence_tokens(words, 0, |word| self.segment_tokens(word))
+            .flatten()
+            .map(Cow::into_owned)
+            .collect()
+    }
+
+    /// # Collapses long runs of the same token into the token and a count.
+    ///
+    /// Degenerate input (a wall of `!`, a binary blob full of `<unk>`) can produce thousands of
+    /// identical tokens in a row. For logging and analysis, such runs can be stored as a single
+    /// entry instead. Runs shorter than `min_run` are left alone, as one entry per token, so
+    /// ordinary output is unaffected. [`BytePairEncoder::expand_runs`] reverses this.
+    ///
+    /// ## Arguments
+    ///
+    /// * `tokens` - The tokens to compress, as produced by [`BytePairEncoder::tokenize`].
+    /// * `min_run` - The shortest run that is collapsed.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec` of `(token, count)` pairs, in order.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::BytePairEncoder;
+    ///
+    /// let tokens = ["▁wow", "!", "!", "!", "!", "▁ok", "!"];
+    /// let runs = BytePairEncoder::collapse_runs(&tokens, 3);
+    /// assert_eq!(
+    ///     runs,
+    ///     vec![("▁wow".into(), 1), ("!".into(), 4), ("▁ok".into(), 1), ("!".into(), 1)]
+    /// );
+    /// assert_eq!(BytePairEncoder::expand_runs(&runs), tokens);
+    /// ```
+    pub fn collapse_runs<S: AsRef<str>>(tokens: &[S], min_run: usize) -> Vec<(String, usize)> {
+        let mut runs: Vec<(String, usize)> = vec![];
+        let mut rest = tokens;
+        while let Some(first) = rest.first() {
+            let token = first.as_ref();
+            let len = rest.iter().take_while(|t| t.as_ref() == token).count();
+            if len >= min_run {
+                runs.push((token.to_string(), len));
+            } else {
+                runs.extend((0..len).map(|_| (token.to_string(), 1)));
+            }
+            rest = &rest[len..];
+        }
+        runs
+    }
+
+    /// # Expands runs collapsed by [`BytePairEncoder::collapse_runs`] back into tokens.
+    ///
+    /// ## Arguments
+    ///
+    /// * `runs` - The `(token, count)` pairs to expand.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` with each token repeated `count` times, in order.
+    pub fn expand_runs(runs: &[(String, usize)]) -> Vec<String> {
+        runs.iter()
+            .flat_map(|(token, count)| iter::repeat(token.clone()).take(*count))
+            .collect()
+    }
+
+    /// # Normalizes text the way words are prepared for matching, keeping track of offsets.
+    ///
+    /// This applies the configured `HyphenationPolicy`, `InvisibleCharPolicy`,
+    /// `ConfusablePolicy`, and `NumberPolicy`, then lowercases the text in `TextMode::Natural`,
+    /// like [`BytePairEncoder::tokenize`] does for each word. The result comes with a map from its
+    /// byte offsets back to the original text, which stays correct when normalization changes the
+    /// length of the text, as lowercasing does for some characters.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be normalized.
+    ///
+    /// ## Returns
+    ///
+    /// The normalized text, as a [`NormalizedText`].
+    pub fn normalize(&self, text: &str) -> NormalizedText {
+        NormalizedText::new(
+            text,
+            self.normalized_chars(text),
+            self.options.mode == TextMode::Natural,
+        )
+    }
+
+    /// # Applies the character-level normalization options to a text, before lowercasing.
+    ///
+    /// This removes soft hyphens and hyphenated line breaks (`HyphenationPolicy`) and invisible
+    /// characters (`InvisibleCharPolicy`), folds confusable characters (`ConfusablePolicy`), and
+    /// then rewrites numbers (`NumberPolicy`).
+    ///
+    /// ## Returns
+    ///
+    /// The remaining characters, each with the byte offset in `text` of the character it came
+    /// from.
+    pub(crate) fn normalized_chars(&self, text: &str) -> Vec<(usize, char)> {
+        let rejoin = self.options.hyphenation == HyphenationPolicy::Rejoin;
+        let strip = self.options.invisible_chars == InvisibleCharPolicy::Strip;
+        let breaks = if rejoin {
+            hyphenation_breaks(text)
+        } else {
+            vec![]
+        };
+        let chars = text
+            .char_indices()
+            .filter(|&(i, c)| {
+                !(rejoin && (c == SOFT_HYPHEN || breaks.iter().any(|b| b.contains(&i))))
+                    && !(strip && is_invisible(c))
+            })
+            .collect();
+        let chars = match self.options.confusables {
+            ConfusablePolicy::Keep => chars,
+            ConfusablePolicy::Fold => fold_confusables(chars),
+        };
+        normalize_numbers(chars, self.options.numbers)
+    }
+
+    /// # Splits a text into sentences, honoring the configured `LineBreakPolicy`.
+    ///
+    /// With `LineBreakPolicy::Normalize`, the text is first cut at every line terminator, and each
+    /// resulting line is split into sentences independently. With `LineBreakPolicy::Preserve`,
+    /// the whole text is handed to the Unicode sentence splitter as-is.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be split.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over the sentences of `text`. With `EmptySentencePolicy::Skip`, sentences
+    /// without any alphanumeric characters (or emoji, when the configured `EmojiPolicy` keeps
+    /// them, or punctuation, when the configured `PunctuationPolicy` keeps it) are skipped;
+    /// otherwise only empty ones are. In `TextMode::Lossless`, only empty
+    /// sentences are skipped, and whitespace between sentences starts the next sentence. With
+    /// `SpecialTextPolicy::Strip`, sentences are checked without their special token text, which
+    /// [`BytePairEncoder::split_words`] removes. With `SegmentationStrategy::Merges`, the whole
+    /// text is one sentence, as in GPT-2.
+    pub(crate) fn split_sentences<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        if self.options.segmentation == SegmentationStrategy::Merges {
+            return Either::Left(iter::once(text).filter(|text| !text.is_empty()));
+        }
+        let normalize = self.options.line_breaks == LineBreakPolicy::Normalize;
+        let keep_emoji = self.options.emoji != EmojiPolicy::Drop;
+        let skip_empty = self.options.empty_sentences == EmptySentencePolicy::Skip;
+        let keep_punctuation = self.options.punctuation == PunctuationPolicy::Keep;
+        let code = self.options.mode == TextMode::Code;
+        let lossless = self.options.mode == TextMode::Lossless;
+        let stripped = match self.options.special_text {
+            SpecialTextPolicy::Strip => self.control_tokens(),
+            _ => vec![],
+        };
+        let sentences = text
+            .split(move |c| normalize && is_line_break(c))
+            .flat_map(UnicodeSegmentation::split_sentence_bounds);
+        let sentences = match self.options.hyphenation {
+            HyphenationPolicy::Keep => Either::Left(sentences),
+            HyphenationPolicy::Rejoin => {
+                Either::Right(rejoin(text, hyphenation_breaks(text), sentences))
+            }
+        };
+        let sentences = if lossless {
+            Either::Left(carry_trailing_whitespace(text, sentences))
+        } else {
+            Either::Right(sentences)
+        };
+        let keep = move |sentence: &str| {
+            if !skip_empty || lossless {
+                return !sentence.is_empty();
+            }
+            if code {
+                return !sentence.trim().is_empty();
+            }
+            if keep_punctuation {
+                return sentence.split_word_bounds().any(|segment| {
+                    !segment.trim().is_empty() && (keep_emoji || !is_emoji(segment))
+                });
+            }
+            sentence.chars().any(char::is_alphanumeric)
+                || (keep_emoji && sentence.split_word_bounds().any(is_emoji))
+        };
+        Either::Right(sentences.filter(move |sentence| {
+            if stripped.is_empty() {
+                keep(sentence)
+            } else {
+                split_around(sentence, &stripped).into_iter().any(keep)
+            }
+        }))
+    }
+
+    /// # Tokenizes a single sentence, as produced by [`BytePairEncoder::split_sentences`].
+    ///
+    /// This function breaks down the tokenization process for a single sentence:
+    /// 1. Adds a sentence start token.
+    /// 2. Splits the sentence into words using Unicode-aware word segmentation.
+    /// 3. Prepends each word with the word break character.
+    /// 4. Tokenizes each word using the BPE vocabulary.
+    /// 5. Adds a sentence end token.
+    ///
+    /// ## Arguments
+    ///
+    /// * `sentence` - A string slice containing a single sentence to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over the tokenized sentence, with markers. Unless
+    /// `EncoderOptions::max_sentence_tokens` splits it, it yields exactly one item.
+    pub(crate) fn tokenize_sentence<'a>(
+        &'a self,
+        sentence: &'a str,
+    ) -> impl Iterator<Item = impl Iterator<Item = Cow<'a, str>> + 'a> + 'a {
+        let words = self.split_words(sentence);
+        self.sentence_tokens(words, sentence.len(), move |word| self.segment_tokens(word))
+    }
+
+    /// # Returns the sentence start and end markers, if sentences are wrapped in them.
+    ///
+    /// GPT-2 style vocabularies (`SegmentationStrategy::Merges`) only get the markers when both
+    /// are in the vocabulary, as RoBERTa's `<s>` and `</s>` are, since GPT-2 itself has none.
+    fn sentence_markers(&self) -> Option<(&str, &str)> {
+        let start = self.special_tokens.sentence_start();
+        let end = self.special_tokens.sentence_end();
+        let missing = self.token_to_id(start).is_none() || self.token_to_id(end).is_none();
+        if self.options.segmentation == SegmentationStrategy::Merges && missing {
+            return None;
+        }
+        Some((start, end))
+    }
+
+    /// # Tokenizes the words of a sentence with the given word tokenizer, adding the inserted
+    /// tokens.
+    ///
+    /// This is the sentence pipeline shared by [`BytePairEncoder::tokenize_sentence`],
+    /// [`BytePairEncoder::token_spans`], and [`BytePairEncoder::tokenize_pretokenized`]: it
+    /// applies `EmptySentencePolicy::EmitToken`, wraps the tokens of the words in sentence
+    /// markers, and splits the sentence into segments of at most
+    /// `EncoderOptions::max_sentence_tokens` tokens. Words are added to the current segment until
+    /// the next word no longer fits, and a word that does not fit even into an empty segment is
+    /// placed in a segment of its own.
+    ///
+    /// ## Arguments
+    ///
+    /// * `words` - The word segments of the sentence, e.g. from [`BytePairEncoder::split_words`].
+    /// * `end` - The length of the sentence in bytes, where the end marker is placed.
+    /// * `word` - Tokenizes a word segment of the sentence, with ranges relative to the sentence.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over the segments of the sentence, each an iterator over its tokens. Sentence
+    /// markers cover an empty range at the start or end of their segment, and the token of
+    /// `EmptySentencePolicy::EmitToken` covers the whole sentence.
+    fn sentence_tokens<'a, T, S, W, I>(
+        &'a self,
+        words: S,
+        end: usize,
+        mut word: W,
+    ) -> impl Iterator<Item = impl Iterator<Item = T> + 'a> + 'a
+    where
+        T: SentenceToken<'a> + 'a,
+        S: Iterator<Item = &'a str> + 'a,
+        W: FnMut(&'a str) -> I + 'a,
+        I: Iterator<Item = T> + 'a,
+    {
+        let markers = self.sentence_markers();
+        let start_marker = move |at: usize| markers.map(|(start, _)| T::inserted(start, at..at));
+        let end_marker = move |at: usize| markers.map(|(_, end)| T::inserted(end, at..at));
+        let mut words = words.peekable();
+        if let EmptySentencePolicy::EmitToken(token) = &self.options.empty_sentences {
+            if words.peek().is_none() {
+                let tokens: Vec<T> = start_marker(0)
+                    .into_iter()
+                    .chain(iter::once(T::inserted(token, 0..end)))
+                    .chain(end_marker(end))
+                    .collect();
+                return Either::Left(iter::once(Either::Right(tokens.into_iter())));
+            }
+        }
+
+        let max = match self.options.max_sentence_tokens {
+            Some(max) => max,
+            None => {
+                return Either::Left(iter::once(Either::Left(
+                    start_marker(0)
+                        .into_iter()
+                        .chain(words.flat_map(word))
+                        .chain(end_marker(end)),
+                )))
+            }
+        };
+        let words = words.map(|w| word(w).collect());
+        let reserved = if markers.is_some() { 2 } else { 0 };
+        let segments = pack_words(words, max.saturating_sub(reserved));
+        let count = segments.len();
+        Either::Right(segments.into_iter().enumerate().map(move |(i, segment)| {
+            let first = match segment.first().and_then(T::range) {
+                Some(range) if i > 0 => range.start,
+                _ => 0,
+            };
+            let last = match segment.last().and_then(T::range) {
+                Some(range) if i + 1 < count => range.end,
+                _ => end,
+            };
+            let segment: Vec<T> = start_marker(first)
+                .into_iter()
+                .chain(segment)
+                .chain(end_marker(last))
+                .collect();
+            Either::Right(segment.into_iter())
+        }))
+    }
+
+    /// # Splits a sentence into the word segments that take part in tokenization.
+    ///
+    /// Segments are produced by Unicode-aware word segmentation. By default only segments with at
+    /// least one alphanumeric character are kept, exactly like `unicode_words`. When the
+    /// configured `EmojiPolicy` keeps emoji, emoji segments are kept as well, and when the
+    /// configured `PunctuationPolicy` keeps punctuation, so are all other segments that are not
+    /// whitespace. In `TextMode::Lossless`, every segment is kept, and a space before a word is
+    /// attached to it. Segments separated only by a hyphenated line break
+    /// (`HyphenationPolicy::Rejoin`) or by invisible characters (`InvisibleCharPolicy::Strip`)
+    /// are merged into one. With `SegmentationStrategy::WordPiece`, every punctuation character is
+    /// a segment of its own, as in BERT's basic tokenizer. With `SegmentationStrategy::Merges`,
+    /// the segments are the pieces of GPT-2's pre-tokenizer instead (see [`pretokenize`]). With
+    /// `SpecialTextPolicy::Strip`, special token text is removed from the sentence first, and the
+    /// text on either side of it is segmented separately, so words around it are not joined.
+    ///
+    /// ## Arguments
+    ///
+    /// * `sentence` - A string slice containing a single sentence.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over the word segments of `sentence`.
+    pub(crate) fn split_words<'a>(
+        &'a self,
+        sentence: &'a str,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        if self.options.special_text != SpecialTextPolicy::Strip {
+            return Either::Left(self.word_segments(sentence));
+        }
+        let stripped = self.control_tokens();
+        Either::Right(
+            split_around(sentence, &stripped)
+                .into_iter()
+                .flat_map(move |piece| self.word_segments(piece)),
+        )
+    }
+
+    /// Splits text into word segments, as described in [`BytePairEncoder::split_words`], without
+    /// removing special token text.
+    fn word_segments<'a>(&'a self, sentence: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        if self.options.segmentation == SegmentationStrategy::Merges {
+            return Either::Left(pretokenize(sentence));
+        }
+        let keep_emoji = self.options.emoji != EmojiPolicy::Drop;
+        let keep_punctuation = self.options.punctuation == PunctuationPolicy::Keep;
+        let code = self.options.mode == TextMode::Code;
+        let lossless = self.options.mode == TextMode::Lossless;
+        let mut breaks = vec![];
+        if self.options.hyphenation == HyphenationPolicy::Rejoin {
+            breaks.extend(hyphenation_breaks(sentence));
+        }
+        if self.options.invisible_chars == InvisibleCharPolicy::Strip {
+            breaks.extend(invisible_breaks(sentence));
+        }
+        let segments = if breaks.is_empty() {
+            Either::Left(sentence.split_word_bounds())
+        } else {
+            Either::Right(rejoin(sentence, breaks, sentence.split_word_bounds()))
+        };
+        let segments = if self.options.segmentation == SegmentationStrategy::WordPiece {
+            Either::Left(segments.flat_map(split_punctuation))
+        } else {
+            Either::Right(segments)
+        };
+        let segments = if lossless {
+            Either::Left(attach_spaces(sentence, segments))
+        } else {
+            Either::Right(segments)
+        };
+        Either::Right(segments.filter(move |segment| {
+            if lossless {
+                return !segment.is_empty();
+            }
+            if code {
+                return !segment.trim().is_empty();
+            }
+            if segment.chars().any(char::is_alphanumeric) {
+                return true;
+            }
+            if is_emoji(segment) {
+                return keep_emoji;
+            }
+            keep_punctuation && !segment.trim().is_empty()
+        }))
+    }
+
+    /// # Tokenizes a single word segment, as produced by [`BytePairEncoder::split_words`].
+    ///
+    /// Emoji segments are matched atomically with [`BytePairEncoder::tokenize_emoji`], all other
+    /// segments are normalized and tokenized with the BPE algorithm. Words longer than the
+    /// configured `max_word_length` are split into chunks first, and each chunk is tokenized on
+    /// its own.
+    ///
+    /// ## Arguments
+    ///
+    /// * `segment` - A string slice containing a single word segment.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` containing the BPE tokens for the segment.
+    pub(crate) fn tokenize_segment(&self, segment: &str) -> Vec<String> {
+        self.segment_tokens(segment).map(Cow::into_owned).collect()
+    }
+
+    /// # Lazily tokenizes a single word segment, like [`BytePairEncoder::tokenize_segment`].
+    ///
+    /// See [`BytePairEncoder::piece_tokens`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `segment` - A string slice containing a single word segment.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over the BPE tokens for the segment.
+    pub(crate) fn segment_tokens<'a>(
+        &'a self,
+        segment: &str,
+    ) -> impl Iterator<Item = Cow<'a, str>> + 'a {
+        self.piece_tokens(segment, true)
+    }
+
+    /// # Lazily tokenizes a word segment, or a piece of one.
+    ///
+    /// These are the tokens of [`BytePairEncoder::segment_spans`], looked up in the word cache
+    /// first if it is enabled. Without the cache, tokens are yielded as soon as they are matched,
+    /// without collecting the tokens of the whole word first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `segment` - A string slice containing a word segment, or a piece of one.
+    /// * `word_start` - Whether `segment` starts its word, and so gets the word break character.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over the BPE tokens for the segment.
+    fn piece_tokens<'a>(
+        &'a self,
+        segment: &str,
+        word_start: bool,
+    ) -> impl Iterator<Item = Cow<'a, str>> + 'a {
+        // Lossless words and emoji keep their case and are cheap to match, so they are not cached.
+        let cached = match &self.cache {
+            Some(cache)
+                if self.options.mode != TextMode::Lossless
+                    && !(self.options.emoji != EmojiPolicy::Drop && is_emoji(segment)) =>
+            {
+                self.prepare_word(segment).map(|word| match word_start {
+                    true => (cache, word),
+                    false => (
+                        cache,
+                        word[self.special_tokens.word_break().len()..].to_string(),
+                    ),
+                })
+            }
+            _ => None,
+        };
+        let (cache, word) = match cached {
+            Some(cached) => cached,
+            None => {
+                return Either::Right(
+                    self.segment_spans(segment, word_start)
+                        .map(|(token, _, _)| token),
+                )
+            }
+        };
+
+        if let Some(tokens) = cache.get(&word) {
+            if self.options.collect_metrics {
+                self.metrics.add_words_tokenized(u64::from(word_start));
+                self.metrics.add_cache_hits(1);
+                self.metrics
+                    .add_unknown_tokens(self.cached_unknown_tokens(&tokens));
+            }
+            let tokens: Vec<Cow<'a, str>> = tokens.into_iter().map(Cow::Owned).collect();
+            return Either::Left(tokens.into_iter());
+        }
+
+        if self.options.collect_metrics {
+            self.metrics.add_cache_misses(1);
+        }
+        let tokens: Vec<Cow<'a, str>> = self
+            .segment_spans(segment, word_start)
+            .map(|(token, _, _)| token)
+            .collect();
+        cache.insert(&word, &tokens);
+        Either::Left(tokens.into_iter())
+    }
+
+    /// Returns the number of unknown tokens among the cached tokens of a word, as counted by the
+    /// `unknown_tokens` metric when the word was tokenized.
+    fn cached_unknown_tokens(&self, tokens: &[String]) -> u64 {
+        let unknown = tokens.iter().filter(|token| match self.options.unknown {
+            UnknownStrategy::Unk | UnknownStrategy::Error => {
+                token.as_str() == self.special_tokens.unknown()
+            }
+            // The graphemes that are not covered are the only tokens not in the vocabulary.
+            UnknownStrategy::Graphemes => self.tokens.entry(token).is_none(),
+            UnknownStrategy::Bytes | UnknownStrategy::Skip => false,
+        });
+        unknown.count() as u64
+    }
+
+    /// # Tokenizes a text, pairing each token with the byte range of the text it covers.
+    ///
+    /// This runs the same sentence and word pipeline as [`BytePairEncoder::tokenize_iter`], with
+    /// the tokens of each word taken from [`BytePairEncoder::segment_spans`] instead of the word
+    /// cache. Sentence markers get an empty range at the start or end of their sentence (or
+    /// segment, with `EncoderOptions::max_sentence_tokens`), and the token of
+    /// `EmptySentencePolicy::EmitToken` covers its whole sentence.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing the text to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec` of `(token, range, inserted)` triples, where `range` is a byte range of `text` and
+    /// `inserted` is `true` for tokens that do not come from matching the text.
+    pub(crate) fn token_spans<'a>(&'a self, text: &'a str) -> Vec<TokenSpan<'a>> {
+        // Sentences and words are slices of `text`, so their offsets follow from their addresses.
+        let offset = |outer: &str, slice: &str| slice.as_ptr() as usize - outer.as_ptr() as usize;
+        self.split_sentences(text)
+            .flat_map(move |sentence| {
+                let base = offset(text, sentence);
+                let words = self.split_words(sentence);
+                self.sentence_tokens(words, sentence.len(), move |word| {
+                    let start = offset(sentence, word);
+                    self.segment_spans(word, true)
+                        .map(move |(token, range, inserted)| {
+                            (token, start + range.start..start + range.end, inserted)
+                        })
+                })
+                .flatten()
+                .map(move |(token, range, inserted)| {
+                    (token, base + range.start..base + range.end, inserted)
+                })
+            })
+            .collect()
+    }
+
+    /// # Lazily tokenizes a word segment, or a piece of one, pairing each token with its text.
+    ///
+    /// This is the word pipeline behind both [`BytePairEncoder::piece_tokens`] and
+    /// [`BytePairEncoder::token_spans`]. Emoji segments are matched atomically with
+    /// [`BytePairEncoder::tokenize_emoji`], and all other segments are normalized and tokenized
+    /// with the BPE algorithm, piece by piece (see [`BytePairEncoder::word_pieces`]). The word
+    /// break character in front of the word covers no text, and a token covering part of a
+    /// character that normalization expanded covers the whole character.
+    ///
+    /// ## Arguments
+    ///
+    /// * `segment` - A string slice containing a word segment, or a piece of one.
+    /// * `word_start` - Whether `segment` starts its word, and so gets the word break character.
+    ///   Only word starts count as words in the metrics.
+    ///
+    /// ## Returns
+    ///
+    /// An iterator over `(token, range, inserted)` triples, where `range` is a byte range of
+    /// `segment`.
+    fn segment_spans<'a>(
+        &'a self,
+        segment: &str,
+        word_start: bool,
+    ) -> impl Iterator<Item = TokenSpan<'a>> + 'a {
+        if self.options.collect_metrics && word_start {
+            self.metrics.add_words_tokenized(1);
+        }
+
+        if self.options.mode == TextMode::Lossless {
+            let spans: Vec<TokenSpan<'a>> = self
+                .lossless_spans(segment)
+                .into_iter()
+                .map(|(token, range, inserted)| (token.into(), range, inserted))
+                .collect();
+            return Either::Left(spans.into_iter());
+        }
+
+        if self.options.emoji != EmojiPolicy::Drop && is_emoji(segment) {
+            let tokens = self.tokenize_emoji(segment);
+            let count = tokens.len();
+            // Only the last token stands for the emoji; any others are the word break character.
+            let spans: Vec<TokenSpan<'a>> = tokens
+                .into_iter()
+                .enumerate()
+                .map(|(i, token)| {
+                    let end = if i + 1 == count { segment.len() } else { 0 };
+                    let stored = self.stored_token(&token);
+                    let token = if stored == token {
+                        Cow::Borrowed(stored)
+                    } else {
+                        Cow::Owned(token)
+                    };
+                    (token, 0..end, false)
+                })
+                .collect();
+            return Either::Left(spans.into_iter());
+        }
+
+        let lowercase = self.options.mode == TextMode::Natural;
+        let normalized = NormalizedText::new(segment, self.normalized_chars(segment), lowercase);
+        if normalized.as_str().is_empty() {
+            return Either::Left(vec![].into_iter());
+        }
+        let prefix = match word_start {
+            true => self.special_tokens.word_break(),
+            false => "",
+        };
+        let word = format!("{}{}", prefix, normalized.as_str());
+        let prefix = prefix.len();
+        let pieces = self.word_pieces(&word);
+        let normalized = Rc::new(normalized);
+        Either::Right(pieces.into_iter().flat_map(move |piece| {
+            let normalized = Rc::clone(&normalized);
+            let mut matches = WordTokens::new(self, word[piece.clone()].to_string());
+            iter::from_fn(move || {
+                let (range, token) = matches.next_token()?;
+                let range = piece.start + range.start..piece.start + range.end;
+                let range = range.start.max(prefix) - prefix..range.end.max(prefix) - prefix;
+                Some((token, normalized.source_range(range), false))
+            })
+        }))
+    }
+
+    /// # Returns the special tokens that act as control tokens in the output.
+    ///
+    /// These are the tokens covered by `SpecialTextPolicy`: the special tokens, in
+    /// `TextMode::Lossless` the case markers and byte tokens, and with `UnknownStrategy::Bytes`
+    /// the byte tokens.
+    fn control_tokens(&self) -> Vec<&str> {
+        let lossless = self.options.mode == TextMode::Lossless;
+        let bytes = lossless || self.options.unknown == UnknownStrategy::Bytes;
+        self.special_tokens
+            .iter()
+            .chain(
+                [CAPITALIZED_TOKEN, UPPERCASE_TOKEN]
+                    .into_iter()
+                    .filter(|_| lossless),
+            )
+            .chain(BYTE_TOKENS.into_iter().filter(|_| bytes))
+            .collect()
+    }
+
+    /// # Returns `true` if `token` is one of the [`BytePairEncoder::control_tokens`].
+    pub(crate) fn is_control_token(&self, token: &str) -> bool {
+        let lossless = self.options.mode == TextMode::Lossless;
+        self.special_tokens.iter().any(|special| special == token)
+            || (lossless && (token == CAPITALIZED_TOKEN || token == UPPERCASE_TOKEN))
+            || ((lossless || self.options.unknown == UnknownStrategy::Bytes)
+                && byte_token(token).is_some())
+    }
+
+    /// # Returns the string stored by this encoder for a token it emits.
+    ///
+    /// Every emitted token is either a vocabulary token, a special token, a replacement token
+    /// from the options, all of which are stored in the encoder, or a case marker or byte token,
+    /// which are constants.
+    fn stored_token(&self, token: &str) -> &str {
+        if let Some((stored, _)) = self.tokens.entry(token) {
+            return stored;
+        }
+        let replacements = [
+            match &self.options.emoji {
+                EmojiPolicy::Replace(replacement) => Some(replacement.as_str()),
+                _ => None,
+            },
+            match &self.options.empty_sentences {
+                EmptySentencePolicy::EmitToken(replacement) => Some(replacement.as_str()),
+                _ => None,
+            },
+        ];
+        self.special_tokens
+            .iter()
+            .chain(self.special_tokens.registered().iter().map(String::as_str))
+            .chain(replacements.into_iter().flatten())
+            .chain([CAPITALIZED_TOKEN, UPPERCASE_TOKEN])
+            .chain(BYTE_TOKENS)
+            .find(|stored| *stored == token)
+            .unwrap_or_else(|| self.special_tokens.unknown())
+    }
+
+    /// # Splits a prepared word into the pieces that are matched independently.
+    ///
+    /// In `TextMode::Code`, identifiers are split into their parts. Words (or parts) longer than
+    /// `EncoderOptions::max_word_length` are split into chunks. The word break character stays in
+    /// front of the first piece only.
+    ///
+    /// ## Arguments
+    ///
+    /// * `word` - A prepared word.
+    ///
+    /// ## Returns
+    ///
+    /// The byte ranges of the pieces in `word`, in order.
+    fn word_pieces(&self, word: &str) -> Vec<Range<usize>> {
+        let prefix = if word.starts_with(self.special_tokens.word_break()) {
+            self.special_tokens.word_break().len()
+        } else {
+            0
+        };
+        let body = &word[prefix..];
+        let max = self.options.max_word_length;
+        let code = self.options.mode == TextMode::Code;
+        if !code && max.map_or(true, |max| body.chars().count() <= max) {
+            return iter::once(0..word.len()).collect();
+        }
+
+        let parts = if code {
+            split_identifier(body)
+        } else {
+            vec![body]
+        };
+        let mut pieces = vec![];
+        for part in parts {
+            let start = part.as_ptr() as usize - word.as_ptr() as usize;
+            let mut bounds: Vec<usize> = part.char_indices().map(|(i, _)| start + i).collect();
+            bounds.push(start + part.len());
+            let size = max.map_or(bounds.len(), |max| max.max(1));
+            let mut chunk_start = 0;
+            while chunk_start + 1 < bounds.len() {
+                let chunk_end = (chunk_start + size).min(bounds.len() - 1);
+                pieces.push(bounds[chunk_start]..bounds[chunk_end]);
+                chunk_start = chunk_end;
+            }
+        }
+        if let Some(first) = pieces.first_mut() {
+            first.start = 0;
+        }
+        pieces
+    }
+
+    /// # Tokenizes a single word segment in `TextMode::Lossless`, with the text of each token.
+    ///
+    /// A leading space becomes the word break character, and any other spaces become word break
+    /// characters of their own, except with `SegmentationStrategy::Merges`, whose spaces stay
+    /// spaces. Capitalized and all-uppercase words are lowercased and marked with [`fold_case`],
+    /// and parts of the segment that no vocabulary token covers become one byte token per UTF-8
+    /// byte, with a space for each word break character. `<upper>` precedes every token of a
+    /// word, counting the byte tokens of a character as one. Case markers get an empty range at
+    /// the start of the text they apply to, and the byte tokens of a character each cover the
+    /// whole character.
+    ///
+    /// ## Arguments
+    ///
+    /// * `segment` - A string slice containing a single word segment, as produced by
+    ///   [`BytePairEncoder::split_words`].
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec` of `(token, range, inserted)` triples, where `range` is the byte range of
+    /// `segment` covered by the token and `inserted` is `true` for case markers.
+    fn lossless_spans(&self, segment: &str) -> Vec<(&str, Range<usize>, bool)> {
+        let body = segment.strip_prefix(' ').unwrap_or(segment);
+        // Byte-level vocabularies have tokens of both cases, so their words keep theirs, and
+        // spell spaces as bytes like any other character, so a literal word break stays one.
+        let merges = self.options.segmentation == SegmentationStrategy::Merges;
+        let case = match merges {
+            true => None,
+            false => fold_case(body).0,
+        };
+        let word_break = match merges {
+            true => ' ',
+            false => self
+                .special_tokens
+                .word_break()
+                .chars()
+                .next()
+                .unwrap_or(' '),
+        };
+        let kept = segment
+            .char_indices()
+            .map(|(i, c)| (i, if c == ' ' { word_break } else { c }))
+            .collect();
+        // Folding the case of a marked word is the same as lowercasing all of it.
+        let normalized = NormalizedText::new(segment, kept, case.is_some());
+        let word = normalized.as_str();
+
+        let mut spans = vec![];
+        if case == Some(CaseMarker::Capitalized) {
+            let start = segment.len() - body.len();
+            spans.push((CAPITALIZED_TOKEN, start..start, true));
+        }
+        let upper = case == Some(CaseMarker::Uppercase);
+        for piece in self.word_pieces(word) {
+            let mut matches = WordTokens::new(self, word[piece.clone()].to_string());
+            while let Some((range, token)) = matches.next_match() {
+                let range = piece.start + range.start..piece.start + range.end;
+                if let Some(token) = token {
+                    let source = normalized.source_range(range);
+                    if upper {
+                        spans.push((UPPERCASE_TOKEN, source.start..source.start, true));
+                    }
+                    spans.push((token, source, false));
+                    continue;
+                }
+                for (i, c) in word[range.clone()].char_indices() {
+                    let start = range.start + i;
+                    let source = normalized.source_range(start..start + c.len_utf8());
+                    if upper {
+                        spans.push((UPPERCASE_TOKEN, source.start..source.start, true));
+                    }
+                    let c = if c == word_break { ' ' } else { c };
+                    let mut buffer = [0; 4];
+                    for &byte in c.encode_utf8(&mut buffer).as_bytes() {
+                        spans.push((BYTE_TOKENS[byte as usize], source.clone(), false));
+                    }
+                }
+            }
+        }
+        spans
+    }
+
+    /// # Tokenizes a single emoji sequence without splitting it apart.
+    ///
+    /// Emoji sequences (skin tone modifiers, ZWJ sequences, flags) are only ever matched as a
+    /// whole:
+    /// 1. If the vocabulary contains the sequence prefixed with the word break character, that
+    ///    token is returned.
+    /// 2. Otherwise the word break character is tokenized on its own, followed by the sequence
+    ///    itself if the vocabulary contains it.
+    /// 3. If the vocabulary does not contain the sequence, the replacement token of
+    ///    `EmojiPolicy::Replace` (or for `EmojiPolicy::Keep`, the unknown token, the byte tokens of
+    ///    the sequence with `UnknownStrategy::Bytes`, or the sequence itself with
+    ///    `UnknownStrategy::Graphemes`) is used instead. With `UnknownStrategy::Skip`, the emoji
+    ///    produces no tokens at all.
+    ///
+    /// ## Arguments
+    ///
+    /// * `emoji` - A string slice containing a single emoji sequence.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` containing the tokens for the emoji sequence.
+    pub(crate) fn tokenize_emoji(&self, emoji: &str) -> Vec<String> {
+        let word = format!("{}{}", self.special_tokens.word_break(), emoji);
+        if self.tokens.contains_key(&word) {
+            return vec![word];
+        }
+
+        let mut tokens = self.tokenize_word(self.special_tokens.word_break());
+        if self.tokens.contains_key(emoji) {
+            tokens.push(emoji.to_string());
+        } else if let EmojiPolicy::Replace(replacement) = &self.options.emoji {
+            tokens.push(replacement.clone());
+        } else {
+            match self.options.unknown {
+                UnknownStrategy::Unk | UnknownStrategy::Error => {
+                    if self.options.collect_metrics {
+                        self.metrics.add_unknown_tokens(1);
+                    }
+                    tokens.push(self.special_tokens.unknown().to_string());
+                }
+                UnknownStrategy::Bytes => tokens.extend(
+                    emoji
+                        .bytes()
+                        .map(|byte| BYTE_TOKENS[byte as usize].to_string()),
+                ),
+                UnknownStrategy::Graphemes => {
+                    if self.options.collect_metrics {
+                        self.metrics.add_unknown_tokens(1);
+                    }
+                    tokens.push(emoji.to_string());
+                }
+                UnknownStrategy::Skip => return vec![],
+            }
+        }
+        tokens
+    }
+
+    /// # Normalizes a single word and prefixes it with the word break character.
+    ///
+    /// This applies the configured `HyphenationPolicy`, `InvisibleCharPolicy`,
+    /// `ConfusablePolicy`, and `NumberPolicy`, then converts the word to lowercase in
+    /// `TextMode::Natural`, to match the vocabulary.
+    ///
+    /// ## Arguments
+    ///
+    /// * `word` - A string slice containing a single word, as produced by word segmentation.
+    ///
+    /// ## Returns
+    ///
+    /// The prepared word, or `None` if nothing is left of it after normalization.
+    pub(crate) fn prepare_word(&self, word: &str) -> Option<String> {
+        // Skip the character pipeline in the common case where it would not change anything.
+        let unchanged = self.options.hyphenation == HyphenationPolicy::Keep
+            && self.options.invisible_chars == InvisibleCharPolicy::Preserve
+            && self.options.numbers == NumberPolicy::Keep
+            && self.options.confusables == ConfusablePolicy::Keep;
+        let word: String = if unchanged {
+            word.to_string()
+        } else {
+            self.normalized_chars(word)
+                .into_iter()
+                .map(|(_, c)| c)
+                .collect()
+        };
+        let word = match self.options.mode {
+            TextMode::Natural => word.to_lowercase(),
+            TextMode::Code | TextMode::Lossless => word,
+        };
+
+        if word.is_empty() {
+            None
+        } else {
+            Some(format!("{}{}", self.special_tokens.word_break(), word))
+        }
+    }
+
+    /// # Tokenizes a single word using the Byte Pair Encoding (BPE) algorithm.
+    ///
+    /// See [`WordTokens`] for the algorithm.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - A string slice containing a single word to be tokenized.
+    ///
+    /// ## Returns
+    ///
+    /// A `Vec<String>` containing the BPE tokens for the input word.
+    pub(crate) fn tokenize_word(&self, text: &str) -> Vec<String> {
+        WordTokens::new(self, text.to_string())
+            .map(Cow::into_owned)
+            .collect()
+    }
+}
+
+/// # Appends a decoded byte, flushing the pending bytes once they form a complete character.
+fn push_byte(out: &mut String, bytes: &mut Vec<u8>, byte: u8, case: &mut Option<CaseMarker>) {
+    bytes.push(byte);
+    if bytes.len() >= utf8_len(bytes[0]) {
+        flush_bytes(out, bytes, case);
+    }
+}
+
+/// # Appends the pending decoded bytes, replacing an incomplete character with `U+FFFD`.
+fn flush_bytes(out: &mut String, bytes: &mut Vec<u8>, case: &mut Option<CaseMarker>) {
+    if !bytes.is_empty() {
+        push_cased(out, &String::from_utf8_lossy(bytes), case);
+        bytes.clear();
+    }
+}
+
+/// # Packs the tokens of a sentence's words into segments of at most `budget` items.
+///
+/// Words are added to the current segment until the next word no longer fits, at which point a
+/// new segment is started. A word that does not fit even into an empty segment is placed in a
+/// segment of its own.
+///
+/// ## Arguments
+///
+/// * `words` - The tokens of each word of the sentence, in order.
+/// * `budget` - The maximum number of tokens per segment.
+///
+/// ## Returns
+///
+/// A `Vec` of segments. There is always at least one segment.
+fn pack_words<T, I>(words: I, budget: usize) -> Vec<Vec<T>>
+where
+    I: IntoIterator<Item = Vec<T>>,
+{
+    let mut segments = vec![];
+    let mut current: Vec<T> = vec![];
+
+    for tokens in words {
+        if !current.is_empty() && current.len() + tokens.len() > budget {
+            segments.push(std::mem::take(&mut current));
+        }
+        current.extend(tokens);
+    }
+    segments.push(current);
+    segments
+}
+
+/// Parses a `BytePairEncoder` from the native token-score format, exactly like
+/// [`BytePairEncoder::new_from_str`].
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{BytePairEncoder, BytePairEncoderError};
+///
+/// let vocab: BytePairEncoder = "hello\t1\nworld\t2".parse()?;
+/// # Ok::<(), BytePairEncoderError>(())
+/// ```
+impl FromStr for BytePairEncoder {
+    type Err = BytePairEncoderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new_from_str(s)
+    }
+}
+
+/// Formats the vocabulary in the native token-score format, one `<token>\t<score>` line per
+/// token, so that the output can be parsed back with [`BytePairEncoder::new_from_str`].
+///
+/// [`Metadata`] is written first. Tokens are written in ID order, so parsing the output yields the
+/// same token IDs. Use
+/// [`BytePairEncoder::write_vocab`] to choose a different order.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::BytePairEncoder;
+///
+/// let vocab = BytePairEncoder::new_from_str("hello\t1\nworld\t2").unwrap();
+/// assert_eq!(vocab.to_string(), "hello\t1\nworld\t2\n");
+/// ```
+impl fmt::Display for BytePairEncoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in self.metadata.entries() {
+            writeln!(f, "{}", Metadata::format_line(key, value))?;
+        }
+        for (token, score) in self.tokens.iter() {
+            writeln!(f, "{}\t{}", token, score)?;
+        }
+        Ok(())
     }
 }
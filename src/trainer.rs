@@ -0,0 +1,667 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    thread,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    constants::WORD_BREAK_CHAR,
+    count_min::CountMinSketch,
+    options::{TrainerOptions, TrainerPreset},
+    vocab::Vocab,
+    BytePairEncoder, BytePairEncoderError, TrainerBuilder,
+};
+
+/// The first line of a trainer checkpoint, identifying the format and its version.
+const CHECKPOINT_HEADER: &str = "#@bpe-tokenizer trainer checkpoint: 1";
+
+/// # Learns a BPE vocabulary from a text corpus.
+///
+/// Text is fed to the trainer with [`Trainer::feed`], which splits it into words the same way a
+/// `BytePairEncoder` does (lowercased, prefixed with the word break character `▁`) and counts
+/// them. Training then starts from the individual characters of those words and repeatedly merges
+/// the most frequent pair of adjacent tokens into a new token, until the vocabulary reaches
+/// [`TrainerOptions::vocab_size`] or no pairs are left.
+///
+/// Token IDs follow the order in which tokens are added, and each token's score is lower than
+/// that of every token added before it, so earlier (more frequent) merges are preferred.
+///
+/// ## Example
+///
+/// ```
+/// use bpe_tokenizer::{Trainer, TrainerOptions};
+///
+/// let mut options = TrainerOptions::default();
+/// options.vocab_size = 25;
+///
+/// let mut trainer = Trainer::new(options);
+/// trainer.feed("low lower lowest");
+/// trainer.feed("new newer newest");
+///
+/// let encoder = trainer.train();
+/// assert_eq!(encoder.vocab_size(), 25);
+/// assert_eq!(encoder.tokenize("lowest"), vec!["<s>", "▁lowest", "</s>"]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Trainer {
+    /// Options controlling training.
+    options: TrainerOptions,
+
+    /// The number of occurrences of each prepared word fed to the trainer.
+    word_counts: HashMap<String, u64>,
+
+    /// The estimated counts of the words discarded from `word_counts` to stay within
+    /// [`TrainerOptions::max_distinct_words`], created when words are first discarded.
+    discarded: Option<CountMinSketch>,
+
+    /// The merges of an interrupted training run, replayed before learning new ones.
+    merges: Vec<(String, String)>,
+}
+
+impl Trainer {
+    /// # Creates a new `Trainer` with the given options and no training data.
+    pub fn new(options: TrainerOptions) -> Self {
+        Trainer {
+            options,
+            word_counts: HashMap::new(),
+            discarded: None,
+            merges: Vec::new(),
+        }
+    }
+
+    /// # Returns a builder for a `Trainer`, to set its options one at a time.
+    ///
+    /// See [`TrainerBuilder`].
+    pub fn builder() -> TrainerBuilder {
+        TrainerBuilder::new()
+    }
+
+    /// # Creates a `Trainer` from a checkpoint file, to resume an interrupted training run.
+    ///
+    /// See [`Trainer::train_with_checkpoints`]. The resumed trainer has the training data of the
+    /// checkpoint, and training replays the merges learned before the interruption instead of
+    /// counting pairs for them again. More text can still be fed before training, in which case
+    /// the replayed merges are kept, but learning continues on the combined data.
+    ///
+    /// ## Arguments
+    ///
+    /// * `options` - The options to train with. To get the vocabulary the interrupted run would
+    ///   have produced, pass the same options.
+    /// * `file_path` - The path of the checkpoint file.
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the resumed `Trainer`, or an error if the file cannot be read or is not a
+    /// valid checkpoint.
+    pub fn resume_from_file(
+        options: TrainerOptions,
+        file_path: &str,
+    ) -> Result<Self, BytePairEncoderError> {
+        Self::resume_from_str(
+            options,
+            fs::read_to_string(file_path)
+                .map_err(|e| BytePairEncoderError::InvalidFile {
+                    path: file_path.to_string(),
+                    source: e,
+                })?
+                .as_ref(),
+        )
+    }
+
+    /// # Creates a `Trainer` from the contents of a checkpoint.
+    ///
+    /// See [`Trainer::resume_from_file`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `options` - The options to train with.
+    /// * `input` - A checkpoint, as written by [`Trainer::write_checkpoint`].
+    ///
+    /// ## Returns
+    ///
+    /// A `Result` with the resumed `Trainer`, or an error if `input` is not a valid checkpoint.
+    pub fn resume_from_str(
+        options: TrainerOptions,
+        input: &str,
+    ) -> Result<Self, BytePairEncoderError> {
+        let mut trainer = Trainer::new(options);
+        for (i, line) in input.lines().enumerate() {
+            let invalid = || BytePairEncoderError::InvalidCheckpoint { line: i + 1 };
+            if i == 0 {
+                if line != CHECKPOINT_HEADER {
+                    return Err(invalid());
+                }
+                continue;
+            }
+            let mut fields = line.split('\t');
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some("w"), Some(count), Some(word), None) => {
+                    let count = count.parse().map_err(|_| invalid())?;
+                    trainer.word_counts.insert(word.to_string(), count);
+                }
+                (Some("m"), Some(left), Some(right), None) => {
+                    trainer.merges.push((left.to_string(), right.to_string()));
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        if input.is_empty() {
+            return Err(BytePairEncoderError::InvalidCheckpoint { line: 1 });
+        }
+        Ok(trainer)
+    }
+
+    /// # Writes a checkpoint of the training data and of the merges of a resumed run.
+    ///
+    /// Checkpoints written during training are written by [`Trainer::train_with_checkpoints`];
+    /// this writes the state before training, e.g. to save the word counts of a long feeding
+    /// phase. The checkpoint is a text file with a header line, followed by one
+    /// `w<TAB>count<TAB>word` line per word and one `m<TAB>left<TAB>right` line per merge, in
+    /// order.
+    ///
+    /// ## Arguments
+    ///
+    /// * `writer` - Where to write the checkpoint.
+    ///
+    /// ## Returns
+    ///
+    /// An `io::Result` indicating whether writing succeeded.
+    pub fn write_checkpoint<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        self.write_state(writer, &self.merges)
+    }
+
+    /// Writes the word counts and `merges` as a checkpoint.
+    fn write_state<W: io::Write>(&self, writer: W, merges: &[(String, String)]) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        writeln!(writer, "{}", CHECKPOINT_HEADER)?;
+        let mut words: Vec<(&String, &u64)> = self.word_counts.iter().collect();
+        words.sort();
+        for (word, count) in words {
+            writeln!(writer, "w\t{}\t{}", count, word)?;
+        }
+        for (left, right) in merges {
+            writeln!(writer, "m\t{}\t{}", left, right)?;
+        }
+        writer.flush()
+    }
+
+    /// # Creates a new `Trainer` with the options of a [`TrainerPreset`].
+    ///
+    /// See [`TrainerOptions::preset`].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{Trainer, TrainerPreset};
+    ///
+    /// let mut trainer = Trainer::preset(TrainerPreset::Small8k);
+    /// trainer.feed("low lower lowest");
+    /// let encoder = trainer.train();
+    /// assert_eq!(encoder.special_tokens().pad(), Some("<pad>"));
+    /// ```
+    pub fn preset(preset: TrainerPreset) -> Self {
+        Trainer::new(TrainerOptions::preset(preset))
+    }
+
+    /// # Adds text to the training data.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - The text to learn from. It is split into words, which are counted.
+    pub fn feed(&mut self, text: &str) {
+        for word in text.unicode_words() {
+            let word = format!("{}{}", WORD_BREAK_CHAR, word.to_lowercase());
+            if let Some(count) = self.word_counts.get_mut(&word) {
+                *count += 1;
+                continue;
+            }
+            // A discarded word resumes from its estimated count.
+            let discarded = self.discarded.as_ref().map_or(0, |d| d.estimate(&word));
+            self.word_counts.insert(word, discarded + 1);
+            if let Some(max) = self.options.max_distinct_words {
+                if self.word_counts.len() > max {
+                    self.prune(max);
+                }
+            }
+        }
+    }
+
+    /// # Adds a stream of documents to the training data.
+    ///
+    /// Documents are consumed one at a time, so the corpus never has to be held in memory. Set
+    /// [`TrainerOptions::max_distinct_words`] to bound the memory used by the word counts too.
+    ///
+    /// ## Arguments
+    ///
+    /// * `documents` - The documents to learn from.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{Trainer, TrainerOptions};
+    ///
+    /// let mut options = TrainerOptions::default();
+    /// options.max_distinct_words = Some(1_000);
+    ///
+    /// let mut trainer = Trainer::new(options);
+    /// trainer.feed_iter((0..10_000).map(|i| format!("document number {}", i)));
+    /// let encoder = trainer.train();
+    /// ```
+    pub fn feed_iter<I, S>(&mut self, documents: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for document in documents {
+            self.feed(document.as_ref());
+        }
+    }
+
+    /// # Adds the lines of a reader (e.g. a corpus file) to the training data.
+    ///
+    /// The reader is consumed one line at a time, like [`Trainer::feed_iter`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `reader` - The reader to learn from.
+    ///
+    /// ## Returns
+    ///
+    /// An `io::Result` indicating whether reading succeeded. Lines read before a failure are kept.
+    pub fn feed_reader<R: io::BufRead>(&mut self, reader: R) -> io::Result<()> {
+        for line in reader.lines() {
+            self.feed(&line?);
+        }
+        Ok(())
+    }
+
+    /// Discards the least frequent half of the words, given the `max_distinct_words` option, and
+    /// adds their counts to the count-min sketch, which has `max` cells per row.
+    fn prune(&mut self, max: usize) {
+        let mut counts: Vec<(String, u64)> = self.word_counts.drain().collect();
+        counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        let discarded = self
+            .discarded
+            .get_or_insert_with(|| CountMinSketch::new(max));
+        for (word, count) in counts.drain(max / 2..) {
+            // The count already includes the estimate the word resumed from.
+            let estimate = discarded.estimate(&word);
+            discarded.add(&word, count.saturating_sub(estimate));
+        }
+        self.word_counts = counts.into_iter().collect();
+    }
+
+    /// # Trains a new vocabulary from the text fed so far.
+    ///
+    /// The vocabulary starts with [`TrainerOptions::special_tokens`] (by default `<unk>`, `<s>`,
+    /// and `</s>`, with IDs 0, 1, and 2), followed by the characters of the training data within
+    /// [`TrainerOptions::character_coverage`], most frequent first, and then the learned merges.
+    ///
+    /// ## Returns
+    ///
+    /// A `BytePairEncoder` using the trained vocabulary.
+    pub fn train(&self) -> BytePairEncoder {
+        self.learn(Vocab::default(), None)
+            .expect("training without checkpoints does no I/O")
+    }
+
+    /// # Trains a new vocabulary like [`Trainer::train`], writing checkpoints along the way.
+    ///
+    /// After every `every` merges, and once training is done, the training data and the merges
+    /// learned so far are written to `file_path`, replacing the previous checkpoint. Each
+    /// checkpoint is written to a temporary file next to it first, so an interruption never
+    /// leaves a partial checkpoint behind. If training is interrupted, it continues from the last
+    /// checkpoint with [`Trainer::resume_from_file`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `file_path` - The path of the checkpoint file.
+    /// * `every` - The number of merges between checkpoints.
+    ///
+    /// ## Returns
+    ///
+    /// An `io::Result` with a `BytePairEncoder` using the trained vocabulary, or the error of a
+    /// failed checkpoint.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use bpe_tokenizer::{Trainer, TrainerOptions};
+    ///
+    /// let options = TrainerOptions::default();
+    /// let checkpoint = "training.checkpoint";
+    /// let mut trainer = match Trainer::resume_from_file(options.clone(), checkpoint) {
+    ///     Ok(trainer) => trainer,
+    ///     Err(_) => {
+    ///         let mut trainer = Trainer::new(options);
+    ///         trainer.feed("low lower lowest");
+    ///         trainer
+    ///     }
+    /// };
+    /// let encoder = trainer.train_with_checkpoints(checkpoint, 1_000).unwrap();
+    /// ```
+    pub fn train_with_checkpoints(
+        &self,
+        file_path: &str,
+        every: usize,
+    ) -> io::Result<BytePairEncoder> {
+        self.learn(Vocab::default(), Some((file_path, every.max(1))))
+    }
+
+    /// # Extends an existing vocabulary with merges learned from the text fed so far.
+    ///
+    /// Every token of `base` keeps its ID and score, so the result still tokenizes the languages
+    /// `base` covers. The training data is first segmented with `base`, and new tokens are then
+    /// learned on top of that segmentation until the vocabulary reaches
+    /// [`TrainerOptions::vocab_size`]. Special tokens and characters of the training data missing
+    /// from `base` are added too. New tokens get IDs after the last ID of `base` and scores below
+    /// all of its scores.
+    ///
+    /// ## Arguments
+    ///
+    /// * `base` - The encoder whose vocabulary is extended. Its options are kept.
+    ///
+    /// ## Returns
+    ///
+    /// A `BytePairEncoder` using the extended vocabulary.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bpe_tokenizer::{BytePairEncoder, Trainer, TrainerOptions};
+    ///
+    /// let base = BytePairEncoder::new_from_str("▁\t0\n▁the\t-1\nt\t-2\nh\t-3\ne\t-4").unwrap();
+    ///
+    /// let mut options = TrainerOptions::default();
+    /// options.vocab_size = 20;
+    ///
+    /// let mut trainer = Trainer::new(options);
+    /// trainer.feed("the tokenizer tokenizes the tokens");
+    ///
+    /// let extended = trainer.extend(&base);
+    /// assert_eq!(extended.vocab_size(), 20);
+    /// assert_eq!(extended.token_to_id("▁the"), Some(1));
+    /// assert_eq!(extended.tokenize("tokens"), vec!["<s>", "▁token", "s", "</s>"]);
+    /// ```
+    pub fn extend(&self, base: &BytePairEncoder) -> BytePairEncoder {
+        self.learn(base.tokens.clone(), None)
+            .expect("training without checkpoints does no I/O")
+            .with_options(base.options().clone())
+    }
+
+    /// Learns new tokens on top of `vocab` until it reaches the target size, replaying the merges
+    /// of a resumed run first, and writing a checkpoint to the given path every given number of
+    /// merges.
+    fn learn(
+        &self,
+        mut vocab: Vocab,
+        checkpoint: Option<(&str, usize)>,
+    ) -> io::Result<BytePairEncoder> {
+        let mut next_score = vocab
+            .iter()
+            .map(|(_, score)| score)
+            .min()
+            .map_or(0, |s| s - 1);
+        let mut add = |vocab: &mut Vocab, token: &str| {
+            if !vocab.contains_key(token) {
+                vocab.insert(token, next_score);
+                next_score -= 1;
+            }
+        };
+
+        for token in &self.options.special_tokens {
+            add(&mut vocab, token);
+        }
+
+        // Characters within the coverage become tokens, so that words can be segmented into them.
+        // The remaining (rarest) characters are left to the unknown token.
+        let mut char_counts: HashMap<char, u64> = HashMap::new();
+        for (word, count) in &self.word_counts {
+            for c in word.chars() {
+                *char_counts.entry(c).or_insert(0) += count;
+            }
+        }
+        let mut chars: Vec<(char, u64)> = char_counts.into_iter().collect();
+        chars.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        let total: u64 = chars.iter().map(|(_, count)| count).sum();
+        let mut covered = 0;
+        for (c, count) in chars {
+            if covered as f64 >= self.options.character_coverage * total as f64 {
+                break;
+            }
+            covered += count;
+            add(&mut vocab, &c.to_string());
+        }
+
+        // Segment the training words with the vocabulary learned so far. Tokens are never merged
+        // across an unknown token, so words are split into pieces around them.
+        let segmenter = BytePairEncoder::from_vocab(vocab.clone());
+        let unknown = segmenter.unknown_token();
+        let mut symbols = Symbols::default();
+        let mut words: Vec<(Vec<u32>, u64)> = Vec::new();
+        for (word, count) in &self.word_counts {
+            for piece in segmenter
+                .tokenize_word(word)
+                .split(|token| token == unknown)
+            {
+                if piece.len() > 1 {
+                    let piece = piece.iter().map(|token| symbols.id(token)).collect();
+                    words.push((piece, *count));
+                }
+            }
+        }
+
+        let threads = self.options.threads.max(1);
+        let max_length = self.options.max_token_length;
+        let mut resumed = self.merges.iter();
+        let mut merges: Vec<(String, String)> = Vec::new();
+        while vocab.len() < self.options.vocab_size {
+            let (left, right) = if let Some((left, right)) = resumed.next() {
+                (symbols.id(left), symbols.id(right))
+            } else {
+                let best = if threads > 1 {
+                    best_pair_sharded(&words, &symbols, max_length, threads)
+                } else {
+                    best_pair(&words, &symbols, max_length)
+                };
+                match best {
+                    Some((pair, count)) if count >= self.options.min_pair_frequency => pair,
+                    _ => break,
+                }
+            };
+            merges.push((
+                symbols.token(left).to_string(),
+                symbols.token(right).to_string(),
+            ));
+
+            let merged = format!("{}{}", symbols.token(left), symbols.token(right));
+            add(&mut vocab, &merged);
+
+            let id = symbols.id(&merged);
+            if threads > 1 {
+                let chunk = words.len() / threads + 1;
+                thread::scope(|scope| {
+                    for words in words.chunks_mut(chunk) {
+                        scope.spawn(move || {
+                            for (word, _) in words {
+                                merge_pair(word, (left, right), id);
+                            }
+                        });
+                    }
+                });
+            } else {
+                for (word, _) in &mut words {
+                    merge_pair(word, (left, right), id);
+                }
+            }
+
+            if let Some((path, every)) = checkpoint {
+                if merges.len() % every == 0 {
+                    self.save_checkpoint(path, &merges)?;
+                }
+            }
+        }
+
+        if let Some((path, _)) = checkpoint {
+            self.save_checkpoint(path, &merges)?;
+        }
+        Ok(BytePairEncoder::from_vocab(vocab))
+    }
+
+    /// Writes a checkpoint with `merges` to `path`, through a temporary file.
+    fn save_checkpoint(&self, path: &str, merges: &[(String, String)]) -> io::Result<()> {
+        let temporary = format!("{}.tmp", path);
+        self.write_state(fs::File::create(&temporary)?, merges)?;
+        fs::rename(temporary, path)
+    }
+}
+
+/// Interns the tokens of the words being trained on, so pairs can be counted cheaply.
+#[derive(Debug, Default)]
+struct Symbols {
+    tokens: Vec<String>,
+    ids: HashMap<String, u32>,
+
+    /// The length of each token, in characters.
+    lengths: Vec<usize>,
+}
+
+impl Symbols {
+    /// Returns the ID of `token`, interning it if needed.
+    fn id(&mut self, token: &str) -> u32 {
+        if let Some(&id) = self.ids.get(token) {
+            return id;
+        }
+        let id = self.tokens.len() as u32;
+        self.tokens.push(token.to_string());
+        self.ids.insert(token.to_string(), id);
+        self.lengths.push(token.chars().count());
+        id
+    }
+
+    /// Returns the token with the given ID.
+    fn token(&self, id: u32) -> &str {
+        &self.tokens[id as usize]
+    }
+}
+
+/// Calls `f` with each pair of adjacent symbols in `words` and the count of the word it occurs
+/// in. Pairs that would merge into a token longer than `max_length` are skipped.
+fn for_each_pair<F>(
+    words: &[(Vec<u32>, u64)],
+    symbols: &Symbols,
+    max_length: Option<usize>,
+    mut f: F,
+) where
+    F: FnMut((u32, u32), u64),
+{
+    for (word, count) in words {
+        for pair in word.windows(2) {
+            let length = symbols.lengths[pair[0] as usize] + symbols.lengths[pair[1] as usize];
+            if max_length.map_or(true, |max| length <= max) {
+                f((pair[0], pair[1]), *count);
+            }
+        }
+    }
+}
+
+/// Returns the pair with the highest count, with ties broken by the symbols' text.
+fn most_frequent<I>(counts: I, symbols: &Symbols) -> Option<((u32, u32), u64)>
+where
+    I: IntoIterator<Item = ((u32, u32), u64)>,
+{
+    counts.into_iter().max_by(
+        |((a_left, a_right), a_count), ((b_left, b_right), b_count)| {
+            a_count.cmp(b_count).then_with(|| {
+                (symbols.token(*b_left), symbols.token(*b_right))
+                    .cmp(&(symbols.token(*a_left), symbols.token(*a_right)))
+            })
+        },
+    )
+}
+
+/// Returns the most frequent pair of adjacent symbols and its count, with ties broken by the
+/// symbols' text. Pairs that would merge into a token longer than `max_length` are skipped.
+fn best_pair(
+    words: &[(Vec<u32>, u64)],
+    symbols: &Symbols,
+    max_length: Option<usize>,
+) -> Option<((u32, u32), u64)> {
+    let mut counts: HashMap<(u32, u32), u64> = HashMap::new();
+    for_each_pair(words, symbols, max_length, |pair, count| {
+        *counts.entry(pair).or_insert(0) += count;
+    });
+    most_frequent(counts, symbols)
+}
+
+/// Like [`best_pair`], but counts pairs on `threads` threads.
+///
+/// Each thread counts the pairs of its share of the words into `threads` shards, by hash of the
+/// pair. The shards are then reduced in parallel, each by one thread, into the most frequent pair
+/// of the shard, so no single map ever holds every pair. The result is the same as that of
+/// [`best_pair`].
+fn best_pair_sharded(
+    words: &[(Vec<u32>, u64)],
+    symbols: &Symbols,
+    max_length: Option<usize>,
+    threads: usize,
+) -> Option<((u32, u32), u64)> {
+    let shard =
+        |(left, right): (u32, u32)| (left.wrapping_mul(0x9E37_79B9) ^ right) as usize % threads;
+    let chunk = words.len() / threads + 1;
+    let counted: Vec<Vec<HashMap<(u32, u32), u64>>> = thread::scope(|scope| {
+        let handles: Vec<_> = words
+            .chunks(chunk)
+            .map(|words| {
+                scope.spawn(move || {
+                    let mut shards = vec![HashMap::new(); threads];
+                    for_each_pair(words, symbols, max_length, |pair, count| {
+                        *shards[shard(pair)].entry(pair).or_insert(0) += count;
+                    });
+                    shards
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("pair counting thread panicked"))
+            .collect()
+    });
+
+    let counted = &counted;
+    let best: Vec<Option<((u32, u32), u64)>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|i| {
+                scope.spawn(move || {
+                    let mut counts: HashMap<(u32, u32), u64> = HashMap::new();
+                    for shards in counted {
+                        for (&pair, &count) in &shards[i] {
+                            *counts.entry(pair).or_insert(0) += count;
+                        }
+                    }
+                    most_frequent(counts, symbols)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("pair counting thread panicked"))
+            .collect()
+    });
+    most_frequent(best.into_iter().flatten(), symbols)
+}
+
+/// Replaces every occurrence of `pair` in `word` with the symbol `merged`.
+fn merge_pair(word: &mut Vec<u32>, pair: (u32, u32), merged: u32) {
+    let mut i = 0;
+    while i + 1 < word.len() {
+        if (word[i], word[i + 1]) == pair {
+            word[i] = merged;
+            word.remove(i + 1);
+        }
+        i += 1;
+    }
+}
@@ -0,0 +1,251 @@
+use std::{cmp::Reverse, collections::HashMap, mem, sync::Arc};
+
+use crate::{
+    fnv::Fnv1a,
+    options::VocabOrder,
+    vocab_store::{HashMapStore, TokenTable, VocabStore},
+};
+
+/// # The token table of a `BytePairEncoder`.
+///
+/// Each token has a score, used to choose between competing matches, and a stable ID: its position
+/// in the vocabulary source (e.g. the line number in a vocabulary file, starting at zero). Tokens
+/// are owned by the ID-ordered table, and found through a pluggable [`VocabStore`].
+#[derive(Debug, Clone)]
+pub(crate) struct Vocab {
+    /// Maps each token to its ID.
+    ids: Box<dyn VocabStore>,
+
+    /// The tokens and their scores, indexed by ID.
+    entries: Vec<(Arc<str>, isize)>,
+}
+
+impl Default for Vocab {
+    fn default() -> Self {
+        Vocab {
+            ids: Box::new(HashMapStore::new()),
+            entries: vec![],
+        }
+    }
+}
+
+impl Vocab {
+    /// Returns the store that maps tokens to IDs.
+    pub(crate) fn store(&self) -> &dyn VocabStore {
+        &*self.ids
+    }
+
+    /// Replaces the store that maps tokens to IDs, adding all tokens to `store` first.
+    pub(crate) fn set_store(&mut self, mut store: Box<dyn VocabStore>) {
+        let table = TokenTable {
+            entries: &self.entries,
+        };
+        for id in 0..self.entries.len() as u32 {
+            if store.get(table.token(id), table).is_none() {
+                store.insert(id, table);
+            }
+        }
+        self.ids = store;
+    }
+
+    /// Returns the ID of `token` in the store.
+    fn lookup(&self, token: &str) -> Option<u32> {
+        self.ids.get(
+            token,
+            TokenTable {
+                entries: &self.entries,
+            },
+        )
+    }
+
+    /// Calls `found` with the length in bytes, the token, and the score of each token that is a
+    /// prefix of `text`, longest first, until it returns `false`.
+    pub(crate) fn prefixes<'a>(
+        &'a self,
+        text: &str,
+        mut found: impl FnMut(usize, &'a str, isize) -> bool,
+    ) {
+        let table = TokenTable {
+            entries: &self.entries,
+        };
+        self.ids.prefixes(text, table, &mut |end, id| {
+            let (token, score) = &self.entries[id as usize];
+            found(end, token, *score)
+        });
+    }
+
+    /// Calls `found` with the ID, text, score, and edit distance of each token within a bounded
+    /// distance of `query`, as [`VocabStore::similar`] does, or returns `false` if the store
+    /// cannot search this way.
+    pub(crate) fn similar<'a>(
+        &'a self,
+        query: &[char],
+        mut found: impl FnMut(u32, &'a str, isize, usize) -> usize,
+    ) -> bool {
+        let table = TokenTable {
+            entries: &self.entries,
+        };
+        self.ids.similar(query, table, &mut |id, distance| {
+            let (token, score) = &self.entries[id as usize];
+            found(id, token, *score, distance)
+        })
+    }
+
+    /// Adds a token with the given score, assigning it the next free ID.
+    ///
+    /// If the token is already present, its score is replaced and it keeps its original ID.
+    pub(crate) fn insert(&mut self, token: &str, score: isize) -> u32 {
+        if let Some(id) = self.lookup(token) {
+            self.entries[id as usize].1 = score;
+            return id;
+        }
+
+        let id = self.entries.len() as u32;
+        self.entries.push((Arc::from(token), score));
+        self.ids.insert(
+            id,
+            TokenTable {
+                entries: &self.entries,
+            },
+        );
+        id
+    }
+
+    /// Replaces the score of the token with the given ID.
+    pub(crate) fn set_score(&mut self, id: u32, score: isize) {
+        self.entries[id as usize].1 = score;
+    }
+
+    /// Returns the score of `token`, if it is in the vocabulary.
+    pub(crate) fn get(&self, token: &str) -> Option<&isize> {
+        self.lookup(token).map(|id| &self.entries[id as usize].1)
+    }
+
+    /// Returns `token` as stored in the vocabulary, and its score, if it is in the vocabulary.
+    pub(crate) fn entry(&self, token: &str) -> Option<(&str, isize)> {
+        self.lookup(token).map(|id| {
+            let (token, score) = &self.entries[id as usize];
+            (&**token, *score)
+        })
+    }
+
+    /// Returns `true` if `token` is in the vocabulary.
+    pub(crate) fn contains_key(&self, token: &str) -> bool {
+        self.lookup(token).is_some()
+    }
+
+    /// Returns the ID of `token`, if it is in the vocabulary.
+    pub(crate) fn id(&self, token: &str) -> Option<u32> {
+        self.lookup(token)
+    }
+
+    /// Returns the token with the given ID, if there is one.
+    pub(crate) fn token(&self, id: u32) -> Option<&str> {
+        self.entries.get(id as usize).map(|(token, _)| &**token)
+    }
+
+    /// Returns the number of tokens in the vocabulary.
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the vocabulary has no tokens.
+    #[allow(dead_code)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an estimate of the heap memory used by the vocabulary, in bytes: the token table,
+    /// the token strings (with their reference counts), and the store.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        let strings: usize = self
+            .entries
+            .iter()
+            .map(|(token, _)| token.len() + 2 * mem::size_of::<usize>())
+            .sum();
+        self.entries.capacity() * mem::size_of::<(Arc<str>, isize)>()
+            + strings
+            + self.ids.heap_bytes()
+    }
+
+    /// Iterates over the tokens and their scores, in ID order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, isize)> + '_ {
+        self.entries.iter().map(|(token, score)| (&**token, *score))
+    }
+
+    /// Computes a fingerprint of the tokens, their scores, and their IDs.
+    ///
+    /// This is a 64-bit FNV-1a hash over each token's UTF-8 bytes and little-endian 64-bit score,
+    /// in ID order, so it is identical across runs, platforms, and crate versions.
+    pub(crate) fn fingerprint(&self) -> u64 {
+        let mut hash = Fnv1a::new();
+        for (token, score) in self.iter() {
+            hash.write_str(token);
+            hash.write(&(score as i64).to_le_bytes());
+        }
+        hash.finish()
+    }
+
+    /// Returns the tokens and their scores in the given order.
+    pub(crate) fn sorted(&self, order: VocabOrder) -> Vec<(&str, isize)> {
+        let mut entries: Vec<_> = self.iter().collect();
+        if order == VocabOrder::Score {
+            entries.sort_by(|(a, a_score), (b, b_score)| b_score.cmp(a_score).then(a.cmp(b)));
+        }
+        entries
+    }
+
+    /// Reconstructs BPE merges from the tokens, for exporting to merge-based tokenizers.
+    ///
+    /// This is how `tokenizers` converts SentencePiece BPE models: every way of splitting a token
+    /// into two tokens of the vocabulary becomes a merge. Merges are ranked by the rank of the
+    /// merged token, then by the ranks of its parts, where tokens are ranked by score (highest
+    /// first) and then by ID.
+    pub(crate) fn merges(&self) -> Vec<(&str, &str)> {
+        let mut ranked: Vec<(usize, &str, isize)> = self
+            .iter()
+            .enumerate()
+            .map(|(id, (token, score))| (id, token, score))
+            .collect();
+        ranked.sort_by_key(|&(id, _, score)| (Reverse(score), id));
+        let rank: HashMap<&str, usize> = ranked
+            .iter()
+            .enumerate()
+            .map(|(rank, &(_, token, _))| (token, rank))
+            .collect();
+
+        let mut merges = vec![];
+        for (merged, &merged_rank) in &rank {
+            for (split, _) in merged.char_indices().skip(1) {
+                let (left, right) = merged.split_at(split);
+                if let (Some(&left_rank), Some(&right_rank)) = (rank.get(left), rank.get(right)) {
+                    merges.push(((merged_rank, left_rank, right_rank), left, right));
+                }
+            }
+        }
+        merges.sort_unstable_by_key(|&(key, _, _)| key);
+        merges
+            .into_iter()
+            .map(|(_, left, right)| (left, right))
+            .collect()
+    }
+}
+
+/// Two vocabularies are equal when they have the same tokens, with the same scores and IDs.
+impl PartialEq for Vocab {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries == other.entries
+    }
+}
+
+impl Eq for Vocab {}
+
+impl<'a> FromIterator<(&'a str, isize)> for Vocab {
+    fn from_iter<I: IntoIterator<Item = (&'a str, isize)>>(iter: I) -> Self {
+        let mut vocab = Vocab::default();
+        for (token, score) in iter {
+            vocab.insert(token, score);
+        }
+        vocab
+    }
+}
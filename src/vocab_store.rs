@@ -0,0 +1,410 @@
+use std::{collections::HashMap, fmt, mem, sync::Arc};
+
+use crate::fnv::Fnv1a;
+
+/// # The tokens of a vocabulary, indexed by ID, as seen by a [`VocabStore`].
+///
+/// The token strings are owned by the vocabulary, so a store only needs to keep what it takes to
+/// find an ID from a token, and can read the token of an ID back from the table to confirm a
+/// match.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenTable<'a> {
+    pub(crate) entries: &'a [(Arc<str>, isize)],
+}
+
+impl<'a> TokenTable<'a> {
+    /// Returns the token with the given ID.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `id` is not less than [`TokenTable::len`].
+    pub fn token(&self, id: u32) -> &'a str {
+        &self.entries[id as usize].0
+    }
+
+    /// Returns the number of tokens.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no tokens.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// # The index that maps the tokens of a vocabulary to their IDs.
+///
+/// Every candidate substring of a word is looked up here while tokenizing, so this is where the
+/// speed and memory use of an encoder are decided. The store is chosen when the encoder is built
+/// (see [`BytePairEncoder::with_vocab_store`](crate::BytePairEncoder::with_vocab_store)), and
+/// the rest of the API is unaffected by the choice. Three stores are included:
+///
+/// - [`HashMapStore`]: the default, a `HashMap` from token to ID.
+/// - [`CompactStore`]: an open-addressing table of IDs only, which uses a fraction of the memory
+///   of `HashMapStore` for slightly slower lookups.
+/// - [`TrieStore`]: a byte trie, which finds all the tokens starting at a position of a word in a
+///   single walk, for more memory than `HashMapStore`.
+///
+/// Other backends, such as FSTs or memory-mapped tables, can be plugged in by implementing this
+/// trait.
+pub trait VocabStore: fmt::Debug + Send + Sync {
+    /// Returns the ID of `token`, if it is in the store.
+    fn get(&self, token: &str, table: TokenTable<'_>) -> Option<u32>;
+
+    /// Calls `found` with the length in bytes and the ID of each token that is a prefix of
+    /// `text`, longest first, until it returns `false`.
+    ///
+    /// This is how segmentation finds the tokens starting at a position of a word. The default
+    /// looks up each prefix ending on a character boundary with [`VocabStore::get`]; stores that
+    /// keep their tokens in a trie find them all in one walk instead.
+    fn prefixes(
+        &self,
+        text: &str,
+        table: TokenTable<'_>,
+        found: &mut dyn FnMut(usize, u32) -> bool,
+    ) {
+        let ends = text.char_indices().map(|(i, c)| i + c.len_utf8()).rev();
+        for end in ends {
+            if let Some(id) = self.get(&text[..end], table) {
+                if !found(end, id) {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Calls `found` with the ID of each token within a bounded edit distance of `query`, and
+    /// that distance, or returns `false` if the store cannot search this way.
+    ///
+    /// `found` returns the largest distance still of interest, and only tokens within it are
+    /// passed on from then on. This is how
+    /// [`BytePairEncoder::find_similar_tokens`](crate::BytePairEncoder::find_similar_tokens)
+    /// avoids comparing the query to every token. The default returns `false`, and every token
+    /// is compared instead.
+    fn similar(
+        &self,
+        query: &[char],
+        table: TokenTable<'_>,
+        found: &mut dyn FnMut(u32, usize) -> usize,
+    ) -> bool {
+        let _ = (query, table, found);
+        false
+    }
+
+    /// Adds the token with the given ID, which is already in `table` and not yet in the store.
+    fn insert(&mut self, id: u32, table: TokenTable<'_>);
+
+    /// Returns the heap memory used by the store, in bytes, excluding the token strings.
+    fn heap_bytes(&self) -> usize;
+
+    /// Returns a boxed copy of the store.
+    fn clone_box(&self) -> Box<dyn VocabStore>;
+}
+
+impl Clone for Box<dyn VocabStore> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// # A [`VocabStore`] backed by a `HashMap` from token to ID.
+///
+/// The keys share the token strings of the vocabulary, so each token is stored once.
+#[derive(Debug, Clone, Default)]
+pub struct HashMapStore {
+    ids: HashMap<Arc<str>, u32>,
+}
+
+impl HashMapStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl VocabStore for HashMapStore {
+    fn get(&self, token: &str, _table: TokenTable<'_>) -> Option<u32> {
+        self.ids.get(token).copied()
+    }
+
+    fn insert(&mut self, id: u32, table: TokenTable<'_>) {
+        self.ids
+            .insert(Arc::clone(&table.entries[id as usize].0), id);
+    }
+
+    fn heap_bytes(&self) -> usize {
+        // Each bucket holds a key and a value, plus one control byte.
+        self.ids.capacity() * (mem::size_of::<(Arc<str>, u32)>() + 1)
+    }
+
+    fn clone_box(&self) -> Box<dyn VocabStore> {
+        Box::new(self.clone())
+    }
+}
+
+/// # A [`VocabStore`] that keeps only token IDs, in an open-addressing hash table.
+///
+/// Each slot is a 4-byte ID, and the table is kept at most three quarters full, so the store
+/// takes 6 to 11 bytes per token instead of the 20 to 40 of [`HashMapStore`]. Lookups hash the
+/// candidate, probe linearly, and compare it with the tokens of the IDs found in the
+/// [`TokenTable`].
+#[derive(Debug, Clone, Default)]
+pub struct CompactStore {
+    slots: Vec<u32>,
+    len: usize,
+}
+
+impl CompactStore {
+    /// Marks a slot without an ID.
+    const EMPTY: u32 = u32::MAX;
+
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn hash(token: &str) -> usize {
+        let mut hash = Fnv1a::new();
+        hash.write(token.as_bytes());
+        hash.finish() as usize
+    }
+
+    /// Returns the slot holding `token`, or the empty slot where it would be inserted.
+    fn find(&self, token: &str, table: TokenTable<'_>) -> usize {
+        let mask = self.slots.len() - 1;
+        let mut slot = Self::hash(token) & mask;
+        loop {
+            let id = self.slots[slot];
+            if id == Self::EMPTY || table.token(id) == token {
+                return slot;
+            }
+            slot = (slot + 1) & mask;
+        }
+    }
+
+    /// Doubles the number of slots (starting at 16), and re-inserts the IDs.
+    fn grow(&mut self, table: TokenTable<'_>) {
+        let capacity = (self.slots.len() * 2).max(16);
+        let old = mem::replace(&mut self.slots, vec![Self::EMPTY; capacity]);
+        for id in old.into_iter().filter(|&id| id != Self::EMPTY) {
+            let slot = self.find(table.token(id), table);
+            self.slots[slot] = id;
+        }
+    }
+}
+
+impl VocabStore for CompactStore {
+    fn get(&self, token: &str, table: TokenTable<'_>) -> Option<u32> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        match self.slots[self.find(token, table)] {
+            Self::EMPTY => None,
+            id => Some(id),
+        }
+    }
+
+    fn insert(&mut self, id: u32, table: TokenTable<'_>) {
+        if (self.len + 1) * 4 > self.slots.len() * 3 {
+            self.grow(table);
+        }
+        let slot = self.find(table.token(id), table);
+        self.slots[slot] = id;
+        self.len += 1;
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.slots.capacity() * mem::size_of::<u32>()
+    }
+
+    fn clone_box(&self) -> Box<dyn VocabStore> {
+        Box::new(self.clone())
+    }
+}
+
+/// # A [`VocabStore`] that keeps the tokens in a byte trie.
+///
+/// Each node holds the ID of the token ending there, if any, and its children sorted by byte, so
+/// a lookup walks one node per byte of the token, and [`VocabStore::prefixes`] finds every token
+/// that starts a text in the same walk. This makes segmentation strategies that try every token
+/// at a position, such as `SegmentationStrategy::Viterbi` and `SegmentationStrategy::WordPiece`,
+/// do one walk per position instead of one lookup per candidate. [`VocabStore::similar`] walks
+/// the trie too, skipping every subtree whose prefix is already too far from the query. Nodes are
+/// shared by the tokens with a common prefix, but each takes more memory than a slot of
+/// [`HashMapStore`], so the store is usually larger.
+#[derive(Debug, Clone)]
+pub struct TrieStore {
+    nodes: Vec<TrieNode>,
+}
+
+/// A node of a [`TrieStore`].
+#[derive(Debug, Clone)]
+struct TrieNode {
+    /// The ID of the token ending at this node, or [`TrieStore::NONE`].
+    id: u32,
+
+    /// The byte leading to each child, and the child's index, sorted by byte.
+    children: Vec<(u8, u32)>,
+}
+
+impl TrieNode {
+    fn new() -> Self {
+        TrieNode {
+            id: TrieStore::NONE,
+            children: vec![],
+        }
+    }
+
+    fn child(&self, byte: u8) -> Option<u32> {
+        self.children
+            .binary_search_by_key(&byte, |&(b, _)| b)
+            .ok()
+            .map(|i| self.children[i].1)
+    }
+}
+
+impl Default for TrieStore {
+    fn default() -> Self {
+        TrieStore {
+            nodes: vec![TrieNode::new()],
+        }
+    }
+}
+
+impl TrieStore {
+    /// Marks a node without a token.
+    const NONE: u32 = u32::MAX;
+
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Visits the subtree of `node` for [`VocabStore::similar`].
+    ///
+    /// `row` holds the edit distances between the text leading to the node and each prefix of
+    /// the query, as in the Levenshtein algorithm. It only advances on whole characters, so the
+    /// bytes of a character still incomplete at the node are held in `pending`. A subtree is
+    /// skipped once every distance in the row exceeds `bound`, as no token in it can be closer.
+    fn similar_from(
+        &self,
+        node: usize,
+        query: &[char],
+        row: &[usize],
+        pending: &[u8],
+        bound: &mut usize,
+        found: &mut dyn FnMut(u32, usize) -> usize,
+    ) {
+        let id = self.nodes[node].id;
+        let distance = row[query.len()];
+        if pending.is_empty() && id != Self::NONE && distance <= *bound {
+            *bound = found(id, distance);
+        }
+
+        let mut bytes = pending.to_vec();
+        for &(byte, child) in &self.nodes[node].children {
+            bytes.truncate(pending.len());
+            bytes.push(byte);
+            let c = match std::str::from_utf8(&bytes) {
+                Ok(text) => text.chars().next(),
+                Err(_) => None,
+            };
+            let c = match c {
+                Some(c) => c,
+                None => {
+                    self.similar_from(child as usize, query, row, &bytes, bound, found);
+                    continue;
+                }
+            };
+
+            let mut next = Vec::with_capacity(row.len());
+            next.push(row[0] + 1);
+            for (j, &q) in query.iter().enumerate() {
+                let substitution = row[j] + usize::from(q != c);
+                next.push(substitution.min(row[j + 1] + 1).min(next[j] + 1));
+            }
+            if next.iter().min().map_or(false, |&lowest| lowest <= *bound) {
+                self.similar_from(child as usize, query, &next, &[], bound, found);
+            }
+        }
+    }
+}
+
+impl VocabStore for TrieStore {
+    fn get(&self, token: &str, _table: TokenTable<'_>) -> Option<u32> {
+        let mut node = 0;
+        for &byte in token.as_bytes() {
+            node = self.nodes[node].child(byte)? as usize;
+        }
+        Some(self.nodes[node].id).filter(|&id| id != Self::NONE)
+    }
+
+    fn prefixes(
+        &self,
+        text: &str,
+        _table: TokenTable<'_>,
+        found: &mut dyn FnMut(usize, u32) -> bool,
+    ) {
+        let mut matches = vec![];
+        let mut node = 0;
+        for (i, &byte) in text.as_bytes().iter().enumerate() {
+            node = match self.nodes[node].child(byte) {
+                Some(child) => child as usize,
+                None => break,
+            };
+            if self.nodes[node].id != Self::NONE {
+                matches.push((i + 1, self.nodes[node].id));
+            }
+        }
+        for (end, id) in matches.into_iter().rev() {
+            if !found(end, id) {
+                return;
+            }
+        }
+    }
+
+    fn similar(
+        &self,
+        query: &[char],
+        _table: TokenTable<'_>,
+        found: &mut dyn FnMut(u32, usize) -> usize,
+    ) -> bool {
+        let row: Vec<usize> = (0..=query.len()).collect();
+        let mut bound = usize::MAX;
+        self.similar_from(0, query, &row, &[], &mut bound, found);
+        true
+    }
+
+    fn insert(&mut self, id: u32, table: TokenTable<'_>) {
+        let mut node = 0;
+        for &byte in table.token(id).as_bytes() {
+            node = match self.nodes[node]
+                .children
+                .binary_search_by_key(&byte, |&(b, _)| b)
+            {
+                Ok(i) => self.nodes[node].children[i].1 as usize,
+                Err(i) => {
+                    let child = self.nodes.len();
+                    self.nodes.push(TrieNode::new());
+                    self.nodes[node].children.insert(i, (byte, child as u32));
+                    child
+                }
+            };
+        }
+        self.nodes[node].id = id;
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.nodes.capacity() * mem::size_of::<TrieNode>()
+            + self
+                .nodes
+                .iter()
+                .map(|node| node.children.capacity() * mem::size_of::<(u8, u32)>())
+                .sum::<usize>()
+    }
+
+    fn clone_box(&self) -> Box<dyn VocabStore> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,464 @@
+use std::{borrow::Cow, cmp::Reverse, ops::Range};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    constants::{BYTE_TOKENS, WORDPIECE_MAX_INPUT_CHARS, WORDPIECE_PREFIX},
+    gpt2::byte_char,
+    BytePairEncoder, SegmentationStrategy, SpecialTextPolicy, TextMode, UnknownStrategy,
+};
+
+/// # A lazy iterator over the BPE tokens of a single word.
+///
+/// This implements the core BPE tokenization logic without recursion:
+/// 1. Find the longest substring of the remaining text that is in the vocabulary, choosing the one
+///    with the highest score among substrings of that length.
+/// 2. Split the text at the chosen token, and process the part before it, the token itself, and
+///    the part after it, in that order.
+/// 3. If no substring is in the vocabulary, yield the unknown token for the whole text.
+///
+/// With `SegmentationStrategy::Viterbi`, `SegmentationStrategy::WordPiece`, or
+/// `SegmentationStrategy::Merges`, the whole word is segmented up front instead (see
+/// [`WordTokens::viterbi`], [`WordTokens::wordpiece`], and [`WordTokens::merges`]), and the
+/// resulting tokens are yielded from the same stack.
+///
+/// Parts of the word that no vocabulary token covers are yielded according to the
+/// `UnknownStrategy` (see [`WordTokens::next_token`]).
+///
+/// The parts still to be processed are kept on a stack, so the first token is yielded as soon as
+/// the leftmost part of the word is resolved, and memory use is bounded by the depth of the
+/// splits rather than the number of tokens. Candidates are looked up as slices of the word, and
+/// tokens are yielded as the strings stored in the vocabulary, so nothing is allocated per token.
+#[derive(Debug)]
+pub(crate) struct WordTokens<'a> {
+    /// The encoder whose vocabulary is used.
+    encoder: &'a BytePairEncoder,
+
+    /// The word being tokenized.
+    word: String,
+
+    /// The parts of the word still to be processed, last part first.
+    stack: Vec<Part<'a>>,
+
+    /// The fallback tokens still to be yielded for an unknown part, last token first.
+    fallback: Vec<(Range<usize>, Cow<'a, str>)>,
+}
+
+/// A part of the word still to be processed, as a byte range into it.
+#[derive(Debug)]
+enum Part<'a> {
+    /// Text that has yet to be matched against the vocabulary.
+    Text(Range<usize>),
+
+    /// A vocabulary token that has already been matched, as stored in the vocabulary, and the
+    /// byte range of the word it covers.
+    Token(Range<usize>, &'a str),
+
+    /// Text that no vocabulary token covers.
+    Unknown(Range<usize>),
+}
+
+/// How a segmentation of the start of a word ranks in [`WordTokens::viterbi`]: the number of
+/// characters left unknown, the total score, and the number of tokens.
+type Rank = (usize, isize, usize);
+
+impl<'a> WordTokens<'a> {
+    /// Creates an iterator over the tokens of `word`.
+    pub(crate) fn new(encoder: &'a BytePairEncoder, word: String) -> Self {
+        let stack = vec![Part::Text(0..word.len())];
+        let mut tokens = WordTokens {
+            encoder,
+            word,
+            stack,
+            fallback: vec![],
+        };
+        match encoder.options.segmentation {
+            SegmentationStrategy::Greedy => {}
+            SegmentationStrategy::Viterbi => tokens.stack = tokens.viterbi(),
+            SegmentationStrategy::WordPiece => tokens.stack = tokens.wordpiece(),
+            SegmentationStrategy::Merges => tokens.stack = tokens.merges(),
+        }
+        tokens
+    }
+
+    /// Returns the next token and the byte range of the word it covers, or `None` as the token
+    /// for a part of the word that no vocabulary token covers.
+    pub(crate) fn next_match(&mut self) -> Option<(Range<usize>, Option<&'a str>)> {
+        loop {
+            let text = match self.stack.pop()? {
+                Part::Token(range, token) => return Some((range, Some(token))),
+                Part::Unknown(range) => return Some((range, None)),
+                Part::Text(range) if range.is_empty() => continue,
+                Part::Text(range) => range,
+            };
+
+            match self.best_match(&self.word[text.clone()]) {
+                Some((found, token)) => {
+                    let found = text.start + found.start..text.start + found.end;
+                    self.stack.push(Part::Text(found.end..text.end));
+                    self.stack.push(Part::Token(found.clone(), token));
+                    self.stack.push(Part::Text(text.start..found.start));
+                }
+                None => return Some((text, None)),
+            }
+        }
+    }
+
+    /// Returns the next token and the byte range of the word it covers, with the parts of the word
+    /// that no vocabulary token covers replaced according to the `UnknownStrategy`.
+    ///
+    /// Tokens are borrowed from the encoder, except the graphemes of
+    /// `UnknownStrategy::Graphemes`, which are taken from the word. With `UnknownStrategy::Bytes`,
+    /// each byte token covers the whole character it is part of.
+    pub(crate) fn next_token(&mut self) -> Option<(Range<usize>, Cow<'a, str>)> {
+        loop {
+            if let Some(fallback) = self.fallback.pop() {
+                return Some(fallback);
+            }
+            let range = match self.next_match()? {
+                (range, Some(token)) => return Some((range, Cow::Borrowed(token))),
+                (range, None) => range,
+            };
+            match self.encoder.options.unknown {
+                UnknownStrategy::Unk | UnknownStrategy::Error => {
+                    if self.encoder.options.collect_metrics {
+                        self.encoder.metrics.add_unknown_tokens(1);
+                    }
+                    let unknown = self.encoder.special_tokens.unknown();
+                    return Some((range, Cow::Borrowed(unknown)));
+                }
+                UnknownStrategy::Bytes => self.fallback = self.byte_fallback(range),
+                UnknownStrategy::Graphemes => self.fallback = self.grapheme_fallback(range),
+                UnknownStrategy::Skip => {}
+            }
+        }
+    }
+
+    /// Returns the byte tokens of the given range of the word, last token first.
+    fn byte_fallback(&self, range: Range<usize>) -> Vec<(Range<usize>, Cow<'a, str>)> {
+        let word_break = self.encoder.special_tokens.word_break();
+        let mut tokens = vec![];
+        for (i, c) in self.word[range.clone()].char_indices() {
+            let covered = range.start + i..range.start + i + c.len_utf8();
+            let c = if word_break.starts_with(c) { ' ' } else { c };
+            let mut buffer = [0; 4];
+            for &byte in c.encode_utf8(&mut buffer).as_bytes() {
+                tokens.push((covered.clone(), Cow::Borrowed(BYTE_TOKENS[byte as usize])));
+            }
+        }
+        tokens.reverse();
+        tokens
+    }
+
+    /// Returns the grapheme clusters of the given range of the word as tokens of their own, last
+    /// token first. A word break character in front of them is kept with the first one.
+    fn grapheme_fallback(&self, range: Range<usize>) -> Vec<(Range<usize>, Cow<'a, str>)> {
+        let word_break = self.encoder.special_tokens.word_break();
+        let text = &self.word[range.clone()];
+        let (prefix, body) = match text.strip_prefix(word_break) {
+            Some(body) if !body.is_empty() => (word_break, body),
+            _ => ("", text),
+        };
+        let mut tokens: Vec<(Range<usize>, Cow<'a, str>)> = body
+            .grapheme_indices(true)
+            .map(|(i, grapheme)| {
+                let start = range.start + prefix.len() + i;
+                (
+                    start..start + grapheme.len(),
+                    Cow::Owned(grapheme.to_string()),
+                )
+            })
+            .collect();
+        if let Some((covered, grapheme)) = tokens.first_mut() {
+            covered.start = range.start;
+            *grapheme = Cow::Owned(format!("{}{}", prefix, grapheme));
+        }
+        if self.encoder.options.collect_metrics {
+            self.encoder.metrics.add_unknown_tokens(tokens.len() as u64);
+        }
+        tokens.reverse();
+        tokens
+    }
+
+    /// Returns the byte range (relative to `text`) of the best vocabulary token in `text`, and the
+    /// token as stored in the vocabulary.
+    ///
+    /// Longer tokens are preferred over shorter ones. Among tokens of the same length, the one
+    /// with the highest score (adjusted by [`TokenPriors`](crate::TokenPriors), if set) wins, and
+    /// among those, the last one. With
+    /// [`EncoderOptions::single_char_margin`](crate::EncoderOptions::single_char_margin) set, the
+    /// candidates within the margin are first ranked by the number of single-character fragments
+    /// they leave. Unless the `SpecialTextPolicy` is `SpecialTextPolicy::Text`, tokens that spell
+    /// a special token are not candidates.
+    fn best_match(&self, text: &str) -> Option<(Range<usize>, &'a str)> {
+        let margin = self.encoder.options.single_char_margin;
+        let boundaries: Vec<usize> = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(Some(text.len()))
+            .collect();
+        let chars = boundaries.len() - 1;
+
+        for len in (1..=chars).rev() {
+            if self.encoder.options.collect_metrics {
+                self.encoder
+                    .metrics
+                    .add_candidate_lookups((chars - len + 1) as u64);
+            }
+
+            let mut best: Option<(isize, Range<usize>, &'a str)> = None;
+            let mut candidates = vec![];
+            for start in 0..=(chars - len) {
+                let range = boundaries[start]..boundaries[start + len];
+                if let Some((token, score)) = self.candidate(&text[range.clone()]) {
+                    if margin.is_some() {
+                        candidates.push((score, start, range.clone(), token));
+                    }
+                    if best.as_ref().map_or(true, |(best, _, _)| score >= *best) {
+                        best = Some((score, range, token));
+                    }
+                }
+            }
+
+            if let Some((best_score, range, token)) = best {
+                if let Some(margin) = margin {
+                    let chars_after = |start: usize| chars - start - len;
+                    return candidates
+                        .into_iter()
+                        .filter(|(score, _, _, _)| *score >= best_score.saturating_sub(margin))
+                        .max_by_key(|(score, start, _, _)| {
+                            let fragments = (*start == 1) as u8 + (chars_after(*start) == 1) as u8;
+                            (Reverse(fragments), *score, *start)
+                        })
+                        .map(|(_, _, range, token)| (range, token));
+                }
+                return Some((range, token));
+            }
+        }
+
+        None
+    }
+
+    /// Returns the vocabulary token spelled by `text`, as stored in the vocabulary, and its
+    /// score adjusted by [`TokenPriors`](crate::TokenPriors), if set. Unless the
+    /// `SpecialTextPolicy` is `SpecialTextPolicy::Text`, tokens that spell a special token are not
+    /// candidates.
+    fn candidate(&self, text: &str) -> Option<(&'a str, isize)> {
+        let (token, score) = self.encoder.tokens.entry(text)?;
+        self.adjust(token, score)
+    }
+
+    /// Calls `found` with the length in bytes, the token, and the adjusted score of each
+    /// candidate (see [`WordTokens::candidate`]) that is a prefix of `text`, longest first, until
+    /// it returns `false`. The tokens are found with [`VocabStore::prefixes`], in one walk for
+    /// stores that keep a trie.
+    ///
+    /// [`VocabStore::prefixes`]: crate::VocabStore::prefixes
+    fn prefix_candidates(&self, text: &str, mut found: impl FnMut(usize, &'a str, isize) -> bool) {
+        self.encoder
+            .tokens
+            .prefixes(text, |end, token, score| match self.adjust(token, score) {
+                Some((token, score)) => found(end, token, score),
+                None => true,
+            });
+    }
+
+    /// Applies `SpecialTextPolicy` and [`TokenPriors`](crate::TokenPriors) to a token found in
+    /// the vocabulary. Unless the policy is `SpecialTextPolicy::Text`, control tokens are not
+    /// candidates: with `SpecialTextPolicy::Strip`, literal special token text is already gone,
+    /// and what is left may still spell one once normalized, such as `BOS` lowercased to `bos`.
+    fn adjust(&self, token: &'a str, score: isize) -> Option<(&'a str, isize)> {
+        if self.encoder.options.special_text != SpecialTextPolicy::Text
+            && self.encoder.is_control_token(token)
+        {
+            return None;
+        }
+        let priors = self.encoder.options.priors.as_ref();
+        Some((
+            token,
+            priors.map_or(score, |p| score.saturating_add(p.adjustment(token))),
+        ))
+    }
+
+    /// Segments the whole word with the Viterbi algorithm, and returns its parts, last part
+    /// first.
+    ///
+    /// For each character boundary, in order, this keeps the best segmentation of the word up to
+    /// there: the one with the fewest characters left unknown, then the highest total score, then
+    /// the fewest tokens. It is extended by every vocabulary token starting at the boundary, and
+    /// by a single unknown character, so every boundary is reachable. Consecutive unknown
+    /// characters are merged into one part, like the unknown text of the greedy strategy.
+    fn viterbi(&self) -> Vec<Part<'a>> {
+        let boundaries: Vec<usize> = self
+            .word
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(Some(self.word.len()))
+            .collect();
+        let chars = boundaries.len() - 1;
+        let better = |a: Rank, b: Rank| {
+            (Reverse(a.0), a.1, Reverse(a.2)) > (Reverse(b.0), b.1, Reverse(b.2))
+        };
+
+        // For each boundary, the rank of its best segmentation, and the start and token of its
+        // last part.
+        let mut best: Vec<Option<(Rank, usize, Option<&'a str>)>> = vec![None; chars + 1];
+        best[0] = Some(((0, 0, 0), 0, None));
+        for start in 0..chars {
+            let (rank, _, _) = best[start].expect("every boundary is reachable");
+            if self.encoder.options.collect_metrics {
+                self.encoder
+                    .metrics
+                    .add_candidate_lookups((chars - start) as u64);
+            }
+            let mut extend = |end: usize, next: Rank, token: Option<&'a str>| {
+                if best[end].map_or(true, |(current, _, _)| better(next, current)) {
+                    best[end] = Some((next, start, token));
+                }
+            };
+            extend(start + 1, (rank.0 + 1, rank.1, rank.2 + 1), None);
+            let offset = boundaries[start];
+            self.prefix_candidates(&self.word[offset..], |len, token, score| {
+                let end = boundaries.partition_point(|&boundary| boundary < offset + len);
+                let next = (rank.0, rank.1.saturating_add(score), rank.2 + 1);
+                extend(end, next, Some(token));
+                true
+            });
+        }
+
+        let mut parts = vec![];
+        let mut end = chars;
+        while end > 0 {
+            let (_, start, token) = best[end].expect("every boundary is reachable");
+            let range = boundaries[start]..boundaries[end];
+            match (token, parts.last_mut()) {
+                (Some(token), _) => parts.push(Part::Token(range, token)),
+                (None, Some(Part::Unknown(unknown))) => unknown.start = range.start,
+                (None, _) => parts.push(Part::Unknown(range)),
+            }
+            end = start;
+        }
+        parts
+    }
+
+    /// Segments the whole word the way WordPiece does, and returns its parts, last part first.
+    ///
+    /// The longest vocabulary token at the start of the remaining text is taken, until the word
+    /// is covered. The word break character in front of the word is not matched, but is covered
+    /// by the first token; all other tokens are looked up with the `##` prefix, including the
+    /// first one of a word that was split into pieces. If any part of the word cannot be matched,
+    /// or the word is longer than `WORDPIECE_MAX_INPUT_CHARS`, the whole word is unknown.
+    fn wordpiece(&self) -> Vec<Part<'a>> {
+        let word_break = self.encoder.special_tokens.word_break();
+        let (mut start, mut continuation) = match self.word.strip_prefix(word_break) {
+            Some(_) => (word_break.len(), false),
+            None => (0, true),
+        };
+        if self.word[start..]
+            .chars()
+            .nth(WORDPIECE_MAX_INPUT_CHARS)
+            .is_some()
+        {
+            return vec![Part::Unknown(0..self.word.len())];
+        }
+        let mut parts = vec![];
+        let mut key = String::new();
+        while start < self.word.len() {
+            let rest = &self.word[start..];
+            if self.encoder.options.collect_metrics {
+                self.encoder
+                    .metrics
+                    .add_candidate_lookups(rest.chars().count() as u64);
+            }
+
+            key.clear();
+            if continuation {
+                key.push_str(WORDPIECE_PREFIX);
+            }
+            let prefix = key.len();
+            key.push_str(rest);
+            let mut found = None;
+            self.prefix_candidates(&key, |len, token, _| {
+                if len <= prefix {
+                    return true;
+                }
+                found = Some((len - prefix, token));
+                false
+            });
+            let (end, token) = match found {
+                Some(found) => found,
+                None => return vec![Part::Unknown(0..self.word.len())],
+            };
+            let range_start = if parts.is_empty() { 0 } else { start };
+            parts.push(Part::Token(range_start..start + end, token));
+            start += end;
+            continuation = true;
+        }
+        parts.reverse();
+        parts
+    }
+
+    /// Segments the whole word with ranked merges, as GPT-2's byte-level BPE does, and returns
+    /// its parts, last part first.
+    ///
+    /// The word is spelled with one symbol of the byte-level alphabet per UTF-8 byte, each
+    /// covering the whole character the byte is part of, except that a leading word break
+    /// character, which only the modes other than `TextMode::Lossless` insert, is spelled as a
+    /// space. The adjacent pair of symbols with the lowest-ranked merge is joined (the
+    /// leftmost one, among equal ranks), until no pair has a merge. Symbols that are not in the
+    /// vocabulary are unknown, and consecutive ones are merged into one part.
+    fn merges(&self) -> Vec<Part<'a>> {
+        let word_break = self.encoder.special_tokens.word_break();
+        let inserted = self.encoder.options.mode != TextMode::Lossless;
+        let mut symbols: Vec<(Range<usize>, String)> = vec![];
+        for (i, c) in self.word.char_indices() {
+            let range = i..i + c.len_utf8();
+            if inserted && i == 0 && word_break.starts_with(c) {
+                symbols.push((range, byte_char(b' ').to_string()));
+                continue;
+            }
+            let mut buffer = [0; 4];
+            for &byte in c.encode_utf8(&mut buffer).as_bytes() {
+                symbols.push((range.clone(), byte_char(byte).to_string()));
+            }
+        }
+
+        if let Some(merges) = &self.encoder.merges {
+            while let Some((_, i)) = symbols
+                .windows(2)
+                .enumerate()
+                .filter_map(|(i, pair)| Some((merges.rank(&pair[0].1, &pair[1].1)?, i)))
+                .min()
+            {
+                let (right_range, right) = symbols.remove(i + 1);
+                let left = &mut symbols[i];
+                left.0.end = right_range.end;
+                left.1.push_str(&right);
+            }
+        }
+        if self.encoder.options.collect_metrics {
+            self.encoder
+                .metrics
+                .add_candidate_lookups(symbols.len() as u64);
+        }
+
+        let mut parts = vec![];
+        for (range, symbol) in symbols {
+            match (self.candidate(&symbol), parts.last_mut()) {
+                (Some((token, _)), _) => parts.push(Part::Token(range, token)),
+                (None, Some(Part::Unknown(unknown))) => unknown.end = range.end,
+                (None, _) => parts.push(Part::Unknown(range)),
+            }
+        }
+        parts.reverse();
+        parts
+    }
+}
+
+impl<'a> Iterator for WordTokens<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Cow<'a, str>> {
+        self.next_token().map(|(_, token)| token)
+    }
+}